@@ -1,4 +1,3 @@
-use async_executors::AsyncStd;
 use language_server::{async_trait::async_trait, types::*, *};
 use std::sync::Arc;
 
@@ -25,13 +24,14 @@ impl LanguageServer for Server {
 }
 
 fn main() {
-    AsyncStd::block_on(
-        LanguageService::builder()
-            .server(Arc::new(Server))
-            .input(async_std::io::stdin())
-            .output(async_std::io::stdout())
-            .executor(AsyncStd)
-            .build()
-            .listen(),
-    );
+    let service = LanguageService::async_std_stdio(Arc::new(Server));
+    let result = async_std::task::block_on(service.listen());
+
+    std::process::exit(match result {
+        Ok(reason) => reason.exit_code(),
+        Err(why) => {
+            eprintln!("{}", why);
+            1
+        }
+    });
 }
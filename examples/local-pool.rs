@@ -0,0 +1,47 @@
+use futures::executor::LocalPool;
+use language_server::{async_trait::async_trait, types::*, *};
+use std::sync::Arc;
+
+struct Server;
+
+#[async_trait]
+impl LanguageServer for Server {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn initialized(&self, _params: InitializedParams, client: Arc<dyn LanguageClient>) {
+        let params = ShowMessageParams {
+            typ: MessageType::Info,
+            message: "Hello World!".to_owned(),
+        };
+
+        client.show_message(params).await;
+    }
+}
+
+fn main() {
+    let mut executor = LocalPool::new();
+    let (input, output) = stdio();
+    let service = LanguageService::builder()
+        .server(Arc::new(Server))
+        .input(input)
+        .output(output)
+        .executor(executor.spawner())
+        .build()
+        .listen();
+
+    let result = executor.run_until(service);
+
+    std::process::exit(match result {
+        Ok(reason) => reason.exit_code(),
+        Err(why) => {
+            eprintln!("{}", why);
+            1
+        }
+    });
+}
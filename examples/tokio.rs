@@ -1,7 +1,5 @@
-use async_executors::TokioTp;
 use language_server::{async_trait::async_trait, types::*, *};
-use std::{convert::TryFrom, sync::Arc};
-use tokio_util::compat::*;
+use std::sync::Arc;
 
 struct Server;
 
@@ -26,16 +24,16 @@ impl LanguageServer for Server {
 }
 
 fn main() {
-    let executor = TokioTp::try_from(&mut tokio::runtime::Builder::new())
-        .expect("failed to create thread pool");
+    let service =
+        LanguageService::tokio_stdio(Arc::new(Server)).expect("failed to create thread pool");
 
-    executor.block_on(
-        LanguageService::builder()
-            .server(Arc::new(Server))
-            .input(tokio::io::stdin().compat())
-            .output(tokio::io::stdout().compat_write())
-            .executor(executor.clone())
-            .build()
-            .listen(),
-    );
+    let result = futures::executor::block_on(service.listen());
+
+    std::process::exit(match result {
+        Ok(reason) => reason.exit_code(),
+        Err(why) => {
+            eprintln!("{}", why);
+            1
+        }
+    });
 }
@@ -12,46 +12,156 @@ struct JsonRpcClientArgs {
     ident: Ident,
 }
 
-pub fn jsonrpc_client(attr: AttributeArgs, trait_: ItemTrait) -> Result<TokenStream> {
+pub fn jsonrpc_client(attr: AttributeArgs, mut trait_: ItemTrait) -> Result<TokenStream> {
     let args = JsonRpcClientArgs::from_list(&attr)?;
+    let stubs = generate_client_stubs(&trait_.items)?;
+    if extends_raw_client(&trait_) {
+        generate_default_methods(&mut trait_.items)?;
+    }
+
     let trait_ident = &trait_.ident;
     let struct_ident = args.ident;
-    let stubs = generate_client_stubs(&trait_.items)?;
     let tokens = quote! {
         #trait_
 
         #[derive(Debug)]
         pub struct #struct_ident {
-            client: Client
+            client: ::language_server::__private::Client
         }
 
         impl #struct_ident
         {
-            pub fn new(output: futures::channel::mpsc::Sender<Message>) -> Self {
+            pub fn new(
+                output: ::language_server::__private::mpsc::Sender<::language_server::jsonrpc::Message>,
+            ) -> Self {
+                Self {
+                    client: ::language_server::__private::Client::new(output),
+                }
+            }
+
+            pub fn with_timeout(self, timeout: ::std::time::Duration) -> Self {
+                Self {
+                    client: self.client.with_timeout(timeout),
+                }
+            }
+
+            pub fn with_timer(
+                self,
+                timer: ::std::sync::Arc<::language_server::__private::Timer>,
+            ) -> Self {
                 Self {
-                    client: Client::new(output),
+                    client: self.client.with_timer(timer),
                 }
             }
+
+            pub fn close(&self) {
+                self.client.close();
+            }
         }
 
-        #[async_trait::async_trait]
+        #[::language_server::async_trait::async_trait]
         impl #trait_ident for #struct_ident
         {
             #stubs
         }
 
-        #[async_trait::async_trait]
-        impl ResponseHandler for #struct_ident
+        #[::language_server::async_trait::async_trait]
+        impl ::language_server::RawClient for #struct_ident
+        {
+            async fn send_raw_request(
+                &self,
+                method: String,
+                params: ::language_server::__private::serde_json::Value,
+            ) -> ::language_server::jsonrpc::Result<::language_server::__private::serde_json::Value> {
+                self.client.send_request(method, params).await
+            }
+
+            async fn send_raw_request_with_timeout(
+                &self,
+                method: String,
+                params: ::language_server::__private::serde_json::Value,
+                timeout: ::std::time::Duration,
+            ) -> ::language_server::jsonrpc::Result<::language_server::__private::serde_json::Value> {
+                self.client
+                    .send_request_with_timeout(method, params, timeout)
+                    .await
+            }
+
+            async fn send_raw_notification(
+                &self,
+                method: String,
+                params: ::language_server::__private::serde_json::Value,
+            ) {
+                self.client.send_notification(method, params).await
+            }
+        }
+
+        #[::language_server::async_trait::async_trait]
+        impl ::language_server::__private::ResponseHandler for #struct_ident
         {
-            async fn handle(&self, response: Response) {
+            async fn handle(&self, response: ::language_server::jsonrpc::Response) {
                 self.client.handle(response).await;
             }
+
+            fn close(&self) {
+                self.client.close();
+            }
         }
     };
 
     Ok(tokens.into())
 }
 
+fn extends_raw_client(trait_: &ItemTrait) -> bool {
+    trait_.supertraits.iter().any(|bound| match bound {
+        TypeParamBound::Trait(bound) => bound
+            .path
+            .segments
+            .last()
+            .into_iter()
+            .any(|segment| segment.ident == "RawClient"),
+        TypeParamBound::Lifetime(_) => false,
+    })
+}
+
+// Implements the methods without a body on top of the `RawClient` trait,
+// so other types can implement the client trait by sending untyped messages.
+fn generate_default_methods(items: &mut Vec<TraitItem>) -> Result<()> {
+    for item in items {
+        let method = match item {
+            TraitItem::Method(method) if method.default.is_none() => method,
+            _ => continue,
+        };
+        let args = match JsonRpcMethodArgs::parse(method)? {
+            Some(args) => args,
+            None => continue,
+        };
+
+        let param_pat = match &method.sig.inputs[1] {
+            FnArg::Typed(param) => &param.pat,
+            FnArg::Receiver(_) => unreachable!(),
+        };
+        let name = args.name;
+        let body: Block = match args.kind {
+            MethodKind::Request => parse_quote!({
+                let params = ::language_server::__private::json!(#param_pat);
+                let result = ::language_server::RawClient::send_raw_request(self, #name.to_owned(), params).await?;
+                ::language_server::__private::serde_json::from_value(result)
+                    .map_err(|_| ::language_server::jsonrpc::Error::result_deserialize_error())
+            }),
+            MethodKind::Notification => parse_quote!({
+                let params = ::language_server::__private::json!(#param_pat);
+                ::language_server::RawClient::send_raw_notification(self, #name.to_owned(), params).await
+            }),
+        };
+
+        method.default = Some(body);
+        method.semi_token = None;
+    }
+
+    Ok(())
+}
+
 fn generate_client_stubs(items: &Vec<TraitItem>) -> Result<TokenStream2> {
     let mut stubs = Vec::new();
     for item in items {
@@ -78,7 +188,8 @@ fn generate_client_stubs(items: &Vec<TraitItem>) -> Result<TokenStream2> {
                 #(#attrs)*
                 async fn #ident(&self, #param) #output {
                     let result = self.client.send_request(#name.to_owned(), #param_pat).await?;
-                    serde_json::from_value(result).map_err(|_| Error::deserialize_error())
+                    ::language_server::__private::serde_json::from_value(result)
+                        .map_err(|_| ::language_server::jsonrpc::Error::result_deserialize_error())
                 }
             ),
             MethodKind::Notification => quote!(
@@ -6,20 +6,35 @@ mod server;
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, AttributeArgs, ItemTrait};
 
+/// Marks a method of a server or client trait as a JSON-RPC method,
+/// e.g. `#[jsonrpc_method(name = "foo/bar", kind = "request")]`.
 #[proc_macro_attribute]
 pub fn jsonrpc_method(_attr: TokenStream, item: TokenStream) -> TokenStream {
     item
 }
 
+/// Generates the dispatch of the incoming messages to the methods of a server trait.
+///
+/// The `client` argument names the trait of the client that is passed to the methods.
+/// If it is given, the handler is implemented for `dyn Trait + Send + Sync`.
+///
+/// The methods of the trait are listed by a crate-private function next to it,
+/// which is named after the trait, e.g. `calculator_supported_methods` for a trait `Calculator`.
 #[proc_macro_attribute]
-pub fn jsonrpc_server(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn jsonrpc_server(attr: TokenStream, item: TokenStream) -> TokenStream {
     let trait_: ItemTrait = parse_macro_input!(item);
-    match crate::server::jsonrpc_server(trait_) {
+    let attr: AttributeArgs = parse_macro_input!(attr);
+    match crate::server::jsonrpc_server(attr, trait_) {
         Ok(tokens) => tokens,
         Err(why) => why.into(),
     }
 }
 
+/// Generates a struct with the given `ident` that implements the client trait
+/// by sending the messages to the other side.
+///
+/// If the trait extends `RawClient`, the methods also get a default implementation
+/// that sends untyped messages with the `RawClient` methods.
 #[proc_macro_attribute]
 pub fn jsonrpc_client(attr: TokenStream, item: TokenStream) -> TokenStream {
     let trait_: ItemTrait = parse_macro_input!(item);
@@ -2,33 +2,107 @@ use crate::{
     error::Result,
     method::{JsonRpcMethodArgs, MethodKind},
 };
+use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{export::TokenStream2, *};
 
-pub fn jsonrpc_server(trait_: ItemTrait) -> Result<TokenStream> {
+#[derive(Debug, FromMeta)]
+struct JsonRpcServerArgs {
+    #[darling(default)]
+    client: Option<Path>,
+}
+
+pub fn jsonrpc_server(attr: AttributeArgs, trait_: ItemTrait) -> Result<TokenStream> {
+    let args = JsonRpcServerArgs::from_list(&attr)?;
+    let trait_ident = &trait_.ident;
+    let custom_protocol = args.client.is_some();
+    // The orphan rule forbids a blanket implementation for the servers of a foreign crate,
+    // so the handler of a custom protocol is implemented for the trait object instead.
+    let (header, server_bound, client_trait) = match args.client {
+        Some(client_trait) => (
+            quote!(impl<C> ::language_server::RequestHandler<C> for dyn #trait_ident + Send + Sync),
+            quote!(),
+            quote!(#client_trait),
+        ),
+        None => (
+            quote!(impl<S, C> ::language_server::RequestHandler<C> for S),
+            quote!(S: #trait_ident + Sync,),
+            quote!(::language_server::LanguageClient),
+        ),
+    };
     let (requests, notifications) = generate_server_skeletons(&trait_.items)?;
+    let methods = generate_method_list(&trait_.items)?;
+    // The name is derived from the trait, so several traits can be annotated in the same module.
+    let methods_ident = Ident::new(
+        &format!("{}_supported_methods", snake_case(&trait_ident.to_string())),
+        trait_ident.span(),
+    );
+    // Only the `LanguageServer` of this crate can be built with lenient defaults.
+    let empty_results = if custom_protocol {
+        quote!()
+    } else {
+        let results = generate_empty_results(&trait_.items)?;
+        let results_ident = Ident::new(
+            &format!("{}_empty_result", snake_case(&trait_ident.to_string())),
+            trait_ident.span(),
+        );
+        quote! {
+            // Returns the empty result of a request that the server does not implement.
+            #[allow(dead_code)]
+            pub(crate) fn #results_ident(
+                method: &str,
+                params: &::language_server::__private::serde_json::Value,
+            ) -> Option<::language_server::__private::serde_json::Value> {
+                match method {
+                    #results
+                    _ => None,
+                }
+            }
+        }
+    };
     let tokens = quote! {
         #trait_
 
-        #[async_trait::async_trait]
-        impl<S, C> RequestHandler<C> for S
+        // Lists the methods that are dispatched to the server.
+        #[allow(dead_code)]
+        pub(crate) fn #methods_ident() -> Vec<::language_server::SupportedMethod> {
+            vec![#methods]
+        }
+
+        #empty_results
+
+        #[::language_server::async_trait::async_trait]
+        #header
         where
-            S: LanguageServer + Sync,
-            C: LanguageClient,
+            #server_bound
+            C: #client_trait,
         {
-            async fn handle_request(&self, request: Request, client: Arc<C>) -> Response {
+            async fn handle_request(
+                &self,
+                request: ::language_server::jsonrpc::Request,
+                client: ::std::sync::Arc<C>,
+            ) -> ::language_server::jsonrpc::Response {
+                use ::language_server::jsonrpc::{Error, Response};
+                use ::language_server::__private::{json, params_error, serde_json};
+
                 match request.method.as_str() {
-                    #requests,
+                    #requests
                     _ => {
                         Response::error(Error::method_not_found_error(), Some(request.id))
                     }
                 }
             }
 
-            async fn handle_notification(&self, notification: Notification, client: Arc<C>) {
+            async fn handle_notification(
+                &self,
+                notification: ::language_server::jsonrpc::Notification,
+                client: ::std::sync::Arc<C>,
+            ) {
+                use ::language_server::__private::{log, notification_params_error, serde_json};
+
                 match notification.method.as_str() {
-                    #notifications,
+                    #notifications
                     _ => log::warn!("{}: {}", "Method not found", notification.method),
                 }
             }
@@ -62,12 +136,13 @@ fn generate_server_skeletons(items: &Vec<TraitItem>) -> Result<(TokenStream2, To
                 #(#cfg_attrs)*
                 #name => {
                     let handle = |json| async move {
-                        let params = serde_json::from_value(json).map_err(|_| Error::deserialize_error())?;
+                        let params = serde_json::from_value(json).map_err(|why| params_error(#name, why))?;
                         let result = self.#ident(params, client).await?;
                         Ok(result)
                     };
 
-                    match handle(request.params).await {
+                    // Missing parameters are treated as `null`, e.g. for `shutdown`.
+                    match handle(request.params.unwrap_or_default()).await {
                         Ok(result) => Response::result(json!(result), request.id),
                         Err(error) => Response::error(error, Some(request.id)),
                     }
@@ -76,9 +151,10 @@ fn generate_server_skeletons(items: &Vec<TraitItem>) -> Result<(TokenStream2, To
             MethodKind::Notification => notifications.push(quote!(
                 #(#cfg_attrs)*
                 #name => {
-                    let error = Error::deserialize_error().message;
-                    let params = serde_json::from_value(notification.params).expect(&error);
-                    self.#ident(params, client).await;
+                    match serde_json::from_value(notification.params.unwrap_or_default()) {
+                        Ok(params) => self.#ident(params, client).await,
+                        Err(why) => notification_params_error(#name, why),
+                    }
                 }
             )),
         };
@@ -86,3 +162,104 @@ fn generate_server_skeletons(items: &Vec<TraitItem>) -> Result<(TokenStream2, To
 
     Ok((quote! { #(#requests)* }, quote! { #(#notifications)* }))
 }
+
+fn generate_empty_results(items: &Vec<TraitItem>) -> Result<TokenStream2> {
+    let mut results = Vec::new();
+    for item in items {
+        let method = match item {
+            TraitItem::Method(method) => method,
+            _ => continue,
+        };
+
+        let args = match JsonRpcMethodArgs::parse(method)? {
+            Some(args) => args,
+            None => continue,
+        };
+
+        let result_type = match (&args.kind, result_type(&method.sig.output)) {
+            (MethodKind::Request, Some(result_type)) => result_type,
+            _ => continue,
+        };
+
+        let name = args.name;
+        let cfg_attrs = method.attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+        results.push(quote!(
+            #(#cfg_attrs)*
+            #name => ::language_server::__private::empty_result::<#result_type>(params),
+        ));
+    }
+
+    Ok(quote! { #(#results)* })
+}
+
+// Returns `T` of a request that returns `Result<T>`.
+fn result_type(output: &ReturnType) -> Option<&Type> {
+    let path = match output {
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Path(path) => &path.path,
+            _ => return None,
+        },
+        ReturnType::Default => return None,
+    };
+
+    match &path.segments.last()?.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn snake_case(ident: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn generate_method_list(items: &Vec<TraitItem>) -> Result<TokenStream2> {
+    let mut methods = Vec::new();
+    for item in items {
+        let method = match item {
+            TraitItem::Method(method) => method,
+            _ => continue,
+        };
+
+        let args = match JsonRpcMethodArgs::parse(method)? {
+            Some(args) => args,
+            None => continue,
+        };
+
+        let mut predicates = Vec::new();
+        for attr in method.attrs.iter().filter(|attr| attr.path.is_ident("cfg")) {
+            if let Meta::List(list) = attr.parse_meta()? {
+                predicates.extend(list.nested);
+            }
+        }
+
+        let name = args.name;
+        let kind = match args.kind {
+            MethodKind::Request => quote!(::language_server::MethodKind::Request),
+            MethodKind::Notification => quote!(::language_server::MethodKind::Notification),
+        };
+
+        methods.push(quote!(
+            ::language_server::SupportedMethod {
+                name: #name,
+                kind: #kind,
+                enabled: cfg!(all(#(#predicates),*)),
+            }
+        ));
+    }
+
+    Ok(quote! { #(#methods),* })
+}
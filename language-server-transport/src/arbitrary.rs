@@ -0,0 +1,172 @@
+//! Implementations of `quickcheck::Arbitrary` for the JSON-RPC types.
+use crate::jsonrpc::*;
+use quickcheck::{Arbitrary, Gen};
+use serde_json::{Map, Value};
+
+const MAX_DEPTH: usize = 3;
+
+const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode::ParseError,
+    ErrorCode::InvalidRequest,
+    ErrorCode::MethodNotFound,
+    ErrorCode::InvalidParams,
+    ErrorCode::InternalError,
+    ErrorCode::ServerNotInitialized,
+    ErrorCode::UnknownErrorCode,
+    ErrorCode::RequestCancelled,
+    ErrorCode::ContentModified,
+    ErrorCode::ServerCancelled,
+    ErrorCode::RequestFailed,
+    ErrorCode::ConnectionClosed,
+    ErrorCode::RequestTimeout,
+    ErrorCode::UnknownProtocolVersion,
+];
+
+fn choose<G: Gen>(g: &mut G, count: u32) -> u32 {
+    u32::arbitrary(g) % count
+}
+
+// Generates JSON values without floating point numbers, which do not survive a round trip in general.
+fn arbitrary_value<G: Gen>(g: &mut G, depth: usize) -> Value {
+    let kinds = if depth < MAX_DEPTH { 7 } else { 5 };
+    match choose(g, kinds) {
+        0 => Value::Null,
+        1 => Value::Bool(bool::arbitrary(g)),
+        2 => Value::from(u64::arbitrary(g)),
+        3 => Value::from(i64::arbitrary(g)),
+        4 => Value::String(String::arbitrary(g)),
+        5 => {
+            let len = choose(g, 4);
+            Value::Array((0..len).map(|_| arbitrary_value(g, depth + 1)).collect())
+        }
+        _ => {
+            let len = choose(g, 4);
+            let map: Map<String, Value> = (0..len)
+                .map(|_| (String::arbitrary(g), arbitrary_value(g, depth + 1)))
+                .collect();
+            Value::Object(map)
+        }
+    }
+}
+
+impl Arbitrary for Id {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        if bool::arbitrary(g) {
+            Id::Number(u64::arbitrary(g))
+        } else {
+            Id::String(String::arbitrary(g))
+        }
+    }
+}
+
+impl Arbitrary for ErrorCode {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        ERROR_CODES[choose(g, ERROR_CODES.len() as u32) as usize]
+    }
+}
+
+impl Arbitrary for Error {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Self {
+            code: ErrorCode::arbitrary(g),
+            message: String::arbitrary(g),
+            data: if bool::arbitrary(g) {
+                Some(arbitrary_value(g, 0))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl Arbitrary for Request {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Request::new(
+            String::arbitrary(g),
+            arbitrary_value(g, 0),
+            Id::arbitrary(g),
+        )
+    }
+}
+
+impl Arbitrary for Notification {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        Notification::new(String::arbitrary(g), arbitrary_value(g, 0))
+    }
+}
+
+impl Arbitrary for Response {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        if bool::arbitrary(g) {
+            Response::result(arbitrary_value(g, 0), Id::arbitrary(g))
+        } else {
+            Response::error(Error::arbitrary(g), Option::arbitrary(g))
+        }
+    }
+}
+
+impl Arbitrary for Message {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        match choose(g, 3) {
+            0 => Message::Request(Request::arbitrary(g)),
+            1 => Message::Notification(Notification::arbitrary(g)),
+            _ => Message::Response(Response::arbitrary(g)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    fn round_trip<T>(value: T) -> bool
+    where
+        T: Serialize + DeserializeOwned + PartialEq,
+    {
+        let json = serde_json::to_string(&value).unwrap();
+        serde_json::from_str::<T>(&json).ok() == Some(value)
+    }
+
+    #[test]
+    fn round_trip_id() {
+        quickcheck(round_trip as fn(Id) -> bool);
+    }
+
+    #[test]
+    fn round_trip_request() {
+        quickcheck(round_trip as fn(Request) -> bool);
+    }
+
+    #[test]
+    fn round_trip_notification() {
+        quickcheck(round_trip as fn(Notification) -> bool);
+    }
+
+    #[test]
+    fn round_trip_response() {
+        quickcheck(round_trip as fn(Response) -> bool);
+    }
+
+    #[test]
+    fn round_trip_message() {
+        quickcheck(round_trip as fn(Message) -> bool);
+    }
+
+    #[test]
+    fn classify_message() {
+        fn classify(message: Message) -> bool {
+            let json = serde_json::to_value(&message).unwrap();
+            let has_id = json.get("id").is_some();
+            let has_method = json.get("method").is_some();
+            match message {
+                Message::Request(_) => has_id && has_method,
+                Message::Notification(_) => !has_id && has_method,
+                Message::Response(_) => has_id && !has_method,
+            }
+        }
+
+        quickcheck(classify as fn(Message) -> bool);
+    }
+}
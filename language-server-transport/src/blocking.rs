@@ -0,0 +1,70 @@
+// `const` initializers of thread locals require a newer compiler than the supported one.
+#![allow(clippy::missing_const_for_thread_local)]
+
+use futures::task::{Context, Poll};
+use std::{cell::RefCell, future::Future, pin::Pin};
+
+thread_local! {
+    static CURRENT: RefCell<Option<(String, DeadlockPolicy)>> = RefCell::new(None);
+}
+
+/// Determines what happens if a request is sent from a section
+/// that prevents the response from being received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlockPolicy {
+    /// Logs the deadlock and sends the request anyway.
+    Report,
+
+    /// Logs the deadlock and fails the request without sending it.
+    Fail,
+}
+
+/// A future that blocks the processing of incoming messages while it runs,
+/// e.g. the handler of a notification that is processed inline.
+///
+/// Requests that are sent by the [`Client`](struct.Client.html) while the section is being polled
+/// can never receive their response, so they are handled according to the `DeadlockPolicy`.
+#[derive(Debug)]
+pub struct BlockingSection<F> {
+    label: String,
+    policy: DeadlockPolicy,
+    future: F,
+}
+
+impl<F> BlockingSection<F> {
+    /// Wraps the future into a section with the given label, which is included in the diagnostic.
+    pub fn new(label: String, policy: DeadlockPolicy, future: F) -> Self {
+        Self {
+            label,
+            policy,
+            future,
+        }
+    }
+}
+
+impl<F: Future + Unpin> Future for BlockingSection<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let section = Some((self.label.clone(), self.policy));
+        let previous = CURRENT.with(|current| current.replace(section));
+        let result = Pin::new(&mut self.future).poll(cx);
+        CURRENT.with(|current| current.replace(previous));
+        result
+    }
+}
+
+// Returns the policy of the section that is currently polled on this thread, if any.
+pub(crate) fn check(method: &str, id: u64) -> Option<DeadlockPolicy> {
+    CURRENT.with(|current| {
+        current.borrow().as_ref().map(|(label, policy)| {
+            log::error!(
+                "Deadlock: request {} ({}) has been sent while processing {}, which blocks its response",
+                id,
+                method,
+                label
+            );
+            *policy
+        })
+    })
+}
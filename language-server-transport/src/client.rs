@@ -0,0 +1,514 @@
+use crate::{
+    blocking::{self, DeadlockPolicy},
+    jsonrpc::*,
+    Delay,
+};
+use async_trait::async_trait;
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, select, BoxFuture, Either},
+    prelude::*,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Processes the responses to requests that have been sent to the other side.
+#[async_trait]
+pub trait ResponseHandler {
+    /// Handles a response that has been received from the other side.
+    async fn handle(&self, response: Response);
+
+    /// Resolves the pending requests once no more responses can be received.
+    /// The default implementation does nothing.
+    fn close(&self) {}
+}
+
+/// Creates a future that completes once the given duration has elapsed,
+/// e.g. to let the timeouts of a [`Client`](struct.Client.html) follow the clock of a service.
+pub type Timer = dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync;
+
+type ResultSender = oneshot::Sender<Result<serde_json::Value>>;
+
+/// Sends requests and notifications to the other side
+/// and correlates the received responses with the pending requests.
+pub struct Client {
+    output: mpsc::Sender<Message>,
+    request_id: AtomicU64,
+    // `None` once the client has been closed.
+    senders_by_id: Mutex<Option<HashMap<Id, ResultSender>>>,
+    timeout: Option<Duration>,
+    timer: Arc<Timer>,
+}
+
+impl Client {
+    /// Creates a new `Client` that writes its messages to the given output channel.
+    pub fn new(output: mpsc::Sender<Message>) -> Self {
+        Self {
+            output,
+            request_id: AtomicU64::new(0),
+            senders_by_id: Mutex::new(Some(HashMap::new())),
+            timeout: None,
+            timer: Arc::new(|duration| Delay::new(duration).boxed()),
+        }
+    }
+
+    /// Sets the default timeout of the requests that are sent by this client.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the timer that delays the timeouts of requests, which is a [`Delay`](struct.Delay.html) by default.
+    pub fn with_timer(self, timer: Arc<Timer>) -> Self {
+        Self { timer, ..self }
+    }
+
+    /// Sends a request and waits for the corresponding response.
+    ///
+    /// Requests that are sent from a [`BlockingSection`](struct.BlockingSection.html)
+    /// are handled according to the `DeadlockPolicy` of the section.
+    /// Requests fail with a [`ConnectionClosed`](jsonrpc/enum.ErrorCode.html#variant.ConnectionClosed)
+    /// error if the client is closed before the response arrives.
+    /// If the returned future is dropped before the response arrives,
+    /// the request is cancelled with a `$/cancelRequest` notification
+    /// unless it has not been sent to the other side yet.
+    /// The same happens once the default timeout of the client has elapsed.
+    pub async fn send_request<T: Serialize>(
+        &self,
+        method: String,
+        params: T,
+    ) -> Result<serde_json::Value> {
+        match self.timeout {
+            Some(timeout) => {
+                self.send_request_with_timeout(method, params, timeout)
+                    .await
+            }
+            None => self.send_request_without_timeout(method, params).await,
+        }
+    }
+
+    /// Sends a request and waits for the corresponding response for at most the given duration.
+    ///
+    /// Requests without a response in time are cancelled and fail with a
+    /// [`RequestTimeout`](jsonrpc/enum.ErrorCode.html#variant.RequestTimeout) error.
+    /// The timer is dropped as soon as the response arrives.
+    pub async fn send_request_with_timeout<T: Serialize>(
+        &self,
+        method: String,
+        params: T,
+        timeout: Duration,
+    ) -> Result<serde_json::Value> {
+        let error = Error::request_timeout(&method);
+        let request = Box::pin(self.send_request_without_timeout(method, params));
+        match select(request, (self.timer)(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right((_, _)) => Err(error),
+        }
+    }
+
+    async fn send_request_without_timeout<T: Serialize>(
+        &self,
+        method: String,
+        params: T,
+    ) -> Result<serde_json::Value> {
+        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        if blocking::check(&method, id) == Some(DeadlockPolicy::Fail) {
+            let message = format!("{} would never receive a response", method);
+            return Err(Error::internal_error(message));
+        }
+
+        let request = Request::new(method, json!(params), Id::Number(id));
+
+        let (result_tx, result_rx) = oneshot::channel();
+        match self.senders_by_id.lock().unwrap().as_mut() {
+            Some(senders_by_id) => senders_by_id.insert(request.id.clone(), result_tx),
+            None => return Err(Error::connection_closed()),
+        };
+
+        let mut pending = PendingRequest {
+            client: self,
+            id: Some(request.id.clone()),
+            sent: false,
+        };
+
+        // The request is only cancelled once it has been queued, so the other side knows its id.
+        let mut output = self.output.clone();
+        let sent = future::poll_fn(|cx| output.poll_ready_unpin(cx))
+            .await
+            .and_then(|()| output.start_send_unpin(Message::Request(request)));
+        match sent {
+            Ok(()) => pending.sent = true,
+            Err(_) => self.close(),
+        }
+
+        let result = result_rx
+            .await
+            .unwrap_or_else(|_| Err(Error::connection_closed()));
+        pending.id = None;
+        result
+    }
+
+    /// Sends a notification.
+    pub async fn send_notification<T: Serialize>(&self, method: String, params: T) {
+        let notification = Notification::new(method, json!(params));
+        let mut output = self.output.clone();
+        if output
+            .send(Message::Notification(notification))
+            .await
+            .is_err()
+        {
+            log::warn!("Dropped a notification because the connection has been closed");
+        }
+    }
+
+    /// Resolves all pending requests with a
+    /// [`ConnectionClosed`](jsonrpc/enum.ErrorCode.html#variant.ConnectionClosed) error
+    /// and rejects the requests that are sent afterwards.
+    pub fn close(&self) {
+        let senders_by_id = self.senders_by_id.lock().unwrap().take();
+        for (_, result_tx) in senders_by_id.into_iter().flatten() {
+            let _ = result_tx.send(Err(Error::connection_closed()));
+        }
+    }
+
+    // Forgets a pending request and returns `true` if it has not been answered yet.
+    fn forget(&self, id: &Id) -> bool {
+        match self.senders_by_id.lock().unwrap().as_mut() {
+            Some(senders_by_id) => senders_by_id.remove(id).is_some(),
+            None => false,
+        }
+    }
+
+    // Returns `true` if the id belongs to a request that has been sent by this client.
+    // The ids are assigned in ascending order, so no set of the forgotten requests needs to be kept.
+    fn is_issued(&self, id: &Id) -> bool {
+        match id {
+            Id::Number(id) => *id < self.request_id.load(Ordering::SeqCst),
+            Id::String(_) => false,
+        }
+    }
+
+    // Forgets a pending request and asks the other side to cancel it.
+    fn cancel(&self, id: Id) {
+        if self.forget(&id) {
+            let notification = Notification::new("$/cancelRequest".into(), json!({ "id": id }));
+            if self
+                .output
+                .clone()
+                .try_send(Message::Notification(notification))
+                .is_err()
+            {
+                log::warn!("Failed to cancel the request {:?}", id);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("output", &self.output)
+            .field("request_id", &self.request_id)
+            .field("senders_by_id", &self.senders_by_id)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+// Cancels the request if the future of `send_request` is dropped before the response has arrived.
+// A request that has not been sent yet is only forgotten.
+struct PendingRequest<'a> {
+    client: &'a Client,
+    id: Option<Id>,
+    sent: bool,
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        match self.id.take() {
+            Some(id) if self.sent => self.client.cancel(id),
+            Some(id) => {
+                self.client.forget(&id);
+            }
+            None => {}
+        }
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for Client {
+    async fn handle(&self, response: Response) {
+        let id = response.id.clone().expect("Expected response with id");
+        let result = response.into_result();
+
+        let result_tx = match self.senders_by_id.lock().unwrap().as_mut() {
+            Some(senders_by_id) => senders_by_id.remove(&id),
+            None => return,
+        };
+
+        match result_tx {
+            Some(result_tx) => {
+                let _ = result_tx.send(result);
+            }
+            // The other side still answers the requests that have been cancelled or have timed out.
+            None if self.is_issued(&id) => {}
+            None => panic!("Unexpected response received"),
+        }
+    }
+
+    fn close(&self) {
+        Client::close(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockingSection;
+    use futures::future::{join, join3};
+
+    #[tokio::test]
+    async fn notification() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let ((), output) = join(client.send_notification("foo".into(), 42u64), rx.next()).await;
+
+        assert_eq!(
+            output.unwrap(),
+            Message::Notification(Notification::new("foo".to_owned(), json!(42)))
+        );
+    }
+
+    #[tokio::test]
+    async fn request_success() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let (response, output, ()) = join3(
+            client.send_request("foo".into(), 42u64),
+            rx.next(),
+            client.handle(Response::result(
+                serde_json::to_value(1337u64).unwrap(),
+                Id::Number(0),
+            )),
+        )
+        .await;
+        assert_eq!(
+            output.unwrap(),
+            Message::Request(Request::new("foo".to_owned(), json!(42), Id::Number(0)))
+        );
+        assert_eq!(
+            serde_json::from_value::<u64>(response.unwrap()).unwrap(),
+            1337
+        );
+    }
+
+    #[tokio::test]
+    async fn request_failure() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let (response, output, ()) = join3(
+            client.send_request("foo".into(), 42u64),
+            rx.next(),
+            client.handle(Response::error(
+                Error::internal_error("bar".into()),
+                Some(Id::Number(0)),
+            )),
+        )
+        .await;
+        assert_eq!(
+            output.unwrap(),
+            Message::Request(Request::new("foo".to_owned(), json!(42), Id::Number(0)))
+        );
+        assert_eq!(response.unwrap_err(), Error::internal_error("bar".into()));
+    }
+
+    #[tokio::test]
+    async fn request_deadlock() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let request = client.send_request("foo".into(), 42u64).boxed();
+        let response = BlockingSection::new("bar".into(), DeadlockPolicy::Fail, request).await;
+        assert!(response.is_err());
+        drop(client);
+        assert_eq!(rx.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn request_connection_closed() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let (response, output) = join(client.send_request("foo".into(), 42u64), async {
+            let output = rx.next().await;
+            client.close();
+            output
+        })
+        .await;
+        assert!(output.is_some());
+        assert_eq!(response.unwrap_err(), Error::connection_closed());
+
+        let response = client.send_request("bar".into(), 42u64).await;
+        assert_eq!(response.unwrap_err(), Error::connection_closed());
+    }
+
+    #[tokio::test]
+    async fn request_output_dropped() {
+        let (tx, rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        drop(rx);
+        let response = client.send_request("foo".into(), 42u64).await;
+        assert_eq!(response.unwrap_err(), Error::connection_closed());
+        client.send_notification("bar".into(), 42u64).await;
+    }
+
+    #[tokio::test]
+    async fn request_dropped_is_cancelled() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let request = client.send_request("foo".into(), 42u64);
+        assert!(request.now_or_never().is_none());
+
+        assert_eq!(
+            rx.next().await.unwrap(),
+            Message::Request(Request::new("foo".into(), json!(42), Id::Number(0)))
+        );
+        assert_eq!(
+            rx.next().await.unwrap(),
+            Message::Notification(Notification::new(
+                "$/cancelRequest".into(),
+                json!({ "id": 0 })
+            ))
+        );
+        assert!(client
+            .senders_by_id
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_empty());
+
+        let error = Error {
+            code: ErrorCode::RequestCancelled,
+            message: "bar".into(),
+            data: None,
+        };
+        client
+            .handle(Response::error(error, Some(Id::Number(0))))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn request_dropped_before_sent() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx);
+        let (result_tx, _result_rx) = oneshot::channel();
+        let id = Id::Number(0);
+        client
+            .senders_by_id
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .insert(id.clone(), result_tx);
+
+        drop(PendingRequest {
+            client: &client,
+            id: Some(id),
+            sent: false,
+        });
+
+        assert!(client
+            .senders_by_id
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_empty());
+        drop(client);
+        assert_eq!(rx.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Client::new(tx)
+            .with_timeout(Duration::from_secs(60))
+            .with_timer(Arc::new(|_| future::ready(()).boxed()));
+        let (result, _) = join(client.send_request("foo".into(), 42u64), async {
+            let request = rx.next().await;
+            let cancel = rx.next().await;
+            (request, cancel)
+        })
+        .await;
+
+        assert_eq!(result, Err(Error::request_timeout("foo")));
+        assert!(client
+            .senders_by_id
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_empty());
+
+        // The late response is discarded.
+        client
+            .handle(Response::result(json!(1337), Id::Number(0)))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn request_timer_dropped() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let (timer_tx, timer_rx) = oneshot::channel::<()>();
+        let timer_rx = Mutex::new(Some(timer_rx));
+        let client = Client::new(tx).with_timer(Arc::new(move |_| {
+            let timer_rx = timer_rx.lock().unwrap().take().unwrap();
+            timer_rx.map(drop).boxed()
+        }));
+
+        let request =
+            client.send_request_with_timeout("foo".into(), 42u64, Duration::from_secs(60));
+        let (response, _, ()) = join3(
+            request,
+            rx.next(),
+            client.handle(Response::result(json!(1337), Id::Number(0))),
+        )
+        .await;
+
+        assert_eq!(response, Ok(json!(1337)));
+        assert!(timer_tx.is_canceled());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Unexpected response received")]
+    async fn request_unexpected_response() {
+        let (tx, _) = mpsc::channel(0);
+        let client = Client::new(tx);
+        client
+            .handle(Response::error(
+                Error::internal_error("bar".into()),
+                Some(Id::Number(42)),
+            ))
+            .await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Expected response with id")]
+    async fn request_response_without_id() {
+        let (tx, _) = mpsc::channel(0);
+        let client = Client::new(tx);
+        client
+            .handle(Response::error(Error::internal_error("bar".into()), None))
+            .await;
+    }
+}
@@ -1,7 +1,32 @@
+#[cfg(feature = "websocket")]
+use crate::WebSocketCodec;
 use bytes::{BufMut, BytesMut};
 use futures_codec::{Decoder, Encoder};
+use serde::Serialize;
 use std::io::{Error, ErrorKind};
 
+/// The format in which outgoing messages are serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Serializes messages without any whitespace.
+    Compact,
+
+    /// Serializes messages with indentation, e.g. to write human-readable transcripts while debugging.
+    Pretty,
+}
+
+impl OutputFormat {
+    /// Serializes the given message in this format.
+    pub fn serialize<T: Serialize>(self, message: &T) -> serde_json::Result<String> {
+        match self {
+            Self::Compact => serde_json::to_string(message),
+            Self::Pretty => serde_json::to_string_pretty(message),
+        }
+    }
+}
+
+/// Encodes and decodes messages that are prefixed with a `Content-Length` header.
+#[derive(Debug, Default, Clone, Copy)]
 pub struct LspCodec;
 
 impl Decoder for LspCodec {
@@ -38,6 +63,43 @@ impl Encoder for LspCodec {
     }
 }
 
+/// Determines how messages are delimited on the input and output streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Prefixes every message with a `Content-Length` header using the [`LspCodec`](struct.LspCodec.html).
+    ContentLength,
+
+    /// Sends every message as a WebSocket text frame using the [`WebSocketCodec`](struct.WebSocketCodec.html).
+    #[cfg(feature = "websocket")]
+    WebSocket,
+}
+
+impl Decoder for Framing {
+    type Item = String;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self {
+            Self::ContentLength => LspCodec.decode(src),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket => WebSocketCodec.decode(src),
+        }
+    }
+}
+
+impl Encoder for Framing {
+    type Item = String;
+    type Error = Error;
+
+    fn encode(&mut self, item: String, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match self {
+            Self::ContentLength => LspCodec.encode(item, dst),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket => WebSocketCodec.encode(item, dst),
+        }
+    }
+}
+
 mod parser {
     use nom::{
         bytes::streaming::{tag, take, take_while},
@@ -138,3 +200,18 @@ mod parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn output_format() {
+        let message = json!({ "jsonrpc": "2.0" });
+        let compact = OutputFormat::Compact.serialize(&message).unwrap();
+        assert_eq!(compact, r#"{"jsonrpc":"2.0"}"#);
+        let pretty = OutputFormat::Pretty.serialize(&message).unwrap();
+        assert_eq!(pretty, "{\n  \"jsonrpc\": \"2.0\"\n}");
+    }
+}
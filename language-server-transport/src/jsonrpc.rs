@@ -0,0 +1,552 @@
+//! Types for JSON-RPC messages.
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_repr::*;
+
+const PROTOCOL_VERSION: &str = "2.0";
+
+/// The identifier that is used in an JSON-RPC message.
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(u64),
+    String(String),
+}
+
+/// The JSON-RPC error codes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize_repr, Serialize_repr)]
+#[repr(i32)]
+pub enum ErrorCode {
+    ParseError = -32700,
+    InvalidRequest = -32600,
+    MethodNotFound = -32601,
+    InvalidParams = -32602,
+    InternalError = -32603,
+    ServerNotInitialized = -32002,
+    UnknownErrorCode = -32001,
+    RequestCancelled = -32800,
+    ContentModified = -32801,
+    ServerCancelled = -32802,
+    RequestFailed = -32803,
+    ConnectionClosed = -32097,
+    RequestTimeout = -32096,
+    UnknownProtocolVersion = 1,
+}
+
+impl ErrorCode {
+    /// The first error code that is reserved for the Language Server Protocol.
+    pub const LSP_RESERVED_ERROR_RANGE_START: i32 = -32899;
+
+    /// The last error code that is reserved for the Language Server Protocol.
+    pub const LSP_RESERVED_ERROR_RANGE_END: i32 = -32800;
+}
+
+/// The error type for JSON-RPC messages.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Error {
+    /// Returns an `Error` with the [`ParseError`](enum.ErrorCode.html#variant.ParseError) error code.
+    pub fn parse_error() -> Self {
+        Self {
+            code: ErrorCode::ParseError,
+            message: "Could not parse the input".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`MethodNotFound`](enum.ErrorCode.html#variant.MethodNotFound) error code.
+    pub fn method_not_found_error() -> Self {
+        Self {
+            code: ErrorCode::MethodNotFound,
+            message: "Method not found".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`InvalidParams`](enum.ErrorCode.html#variant.InvalidParams) error code.
+    pub fn deserialize_error() -> Self {
+        Self {
+            code: ErrorCode::InvalidParams,
+            message: "Could not deserialize parameter object".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`InternalError`](enum.ErrorCode.html#variant.InternalError) error code,
+    /// which indicates that the result of a response does not have the expected type.
+    pub fn result_deserialize_error() -> Self {
+        Self {
+            code: ErrorCode::InternalError,
+            message: "Could not deserialize result object".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`InvalidParams`](enum.ErrorCode.html#variant.InvalidParams) error code
+    /// and a custom message.
+    pub fn invalid_params(message: String) -> Self {
+        Self {
+            code: ErrorCode::InvalidParams,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`InvalidRequest`](enum.ErrorCode.html#variant.InvalidRequest) error code
+    /// and a custom message.
+    pub fn invalid_request(message: String) -> Self {
+        Self {
+            code: ErrorCode::InvalidRequest,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`ServerNotInitialized`](enum.ErrorCode.html#variant.ServerNotInitialized) error code,
+    /// which answers requests that are received before the `initialize` request.
+    pub fn server_not_initialized_error(message: String) -> Self {
+        Self {
+            code: ErrorCode::ServerNotInitialized,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns the error of a failed `initialize` request with the
+    /// [`RequestFailed`](enum.ErrorCode.html#variant.RequestFailed) error code.
+    ///
+    /// If `retry` is `true`, the client shows the message to the user and
+    /// sends the `initialize` request again once the user has chosen to retry.
+    /// A server that does not support the protocol version of the client can replace the code
+    /// with [`UnknownProtocolVersion`](enum.ErrorCode.html#variant.UnknownProtocolVersion).
+    pub fn initialize_error(message: String, retry: bool) -> Self {
+        Self {
+            code: ErrorCode::RequestFailed,
+            message,
+            data: Some(serde_json::json!({ "retry": retry })),
+        }
+    }
+
+    /// Returns an `Error` with the [`ContentModified`](enum.ErrorCode.html#variant.ContentModified) error code,
+    /// which tells the client that the request can be retried.
+    pub fn content_modified_error(message: String) -> Self {
+        Self {
+            code: ErrorCode::ContentModified,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`ServerCancelled`](enum.ErrorCode.html#variant.ServerCancelled) error code,
+    /// which tells the client that the server has cancelled the request and that it can be retried.
+    pub fn server_cancelled_error(message: String) -> Self {
+        Self {
+            code: ErrorCode::ServerCancelled,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`RequestFailed`](enum.ErrorCode.html#variant.RequestFailed) error code,
+    /// which tells the client that a syntactically valid request could not be processed.
+    pub fn request_failed_error(message: String) -> Self {
+        Self {
+            code: ErrorCode::RequestFailed,
+            message,
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`RequestFailed`](enum.ErrorCode.html#variant.RequestFailed) error code
+    /// and machine-readable details about the failure.
+    pub fn request_failed<T: Serialize>(message: String, data: T) -> Self {
+        Self::request_failed_error(message).with_data(data)
+    }
+
+    /// Attaches machine-readable details to the error, replacing the previous ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the details cannot be serialized, e.g. a map with non-string keys.
+    pub fn with_data<T: Serialize>(self, data: T) -> Self {
+        let data = serde_json::to_value(data).expect("failed to serialize error data");
+        Self {
+            data: Some(data),
+            ..self
+        }
+    }
+
+    /// Returns an `Error` with the [`RequestCancelled`](enum.ErrorCode.html#variant.RequestCancelled) error code,
+    /// which answers a request that has been cancelled by the client.
+    pub fn request_cancelled() -> Self {
+        Self {
+            code: ErrorCode::RequestCancelled,
+            message: "The request has been cancelled".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`ConnectionClosed`](enum.ErrorCode.html#variant.ConnectionClosed) error code,
+    /// which resolves requests that will never receive a response because the connection has been closed.
+    pub fn connection_closed() -> Self {
+        Self {
+            code: ErrorCode::ConnectionClosed,
+            message: "The connection has been closed".to_owned(),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`RequestTimeout`](enum.ErrorCode.html#variant.RequestTimeout) error code,
+    /// which resolves a request whose response has not arrived in time.
+    pub fn request_timeout(method: &str) -> Self {
+        Self {
+            code: ErrorCode::RequestTimeout,
+            message: format!("The request {} has timed out", method),
+            data: None,
+        }
+    }
+
+    /// Returns an `Error` with the [`internal_error`](enum.ErrorCode.html#variant.internal_error) error code.
+    pub fn internal_error(message: String) -> Self {
+        Self {
+            code: ErrorCode::InternalError,
+            message,
+            data: None,
+        }
+    }
+}
+
+/// A specialized Result type for JSON-RPC operations.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Distinguishes parameters that are `null` from parameters that have been omitted.
+fn deserialize_params<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<serde_json::Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+/// The request type for JSON-RPC messages.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub method: String,
+    /// The parameters of the request, which may be omitted according to the JSON-RPC specification.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_params",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub params: Option<serde_json::Value>,
+    pub id: Id,
+}
+
+impl Request {
+    // Creates a new `Request`.
+    pub fn new(method: String, params: serde_json::Value, id: Id) -> Self {
+        Self {
+            jsonrpc: PROTOCOL_VERSION.to_owned(),
+            method,
+            params: Some(params),
+            id,
+        }
+    }
+}
+
+/// The response type for JSON-RPC messages.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Response {
+    pub jsonrpc: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub result: Option<serde_json::Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+
+    pub id: Option<Id>,
+}
+
+impl Response {
+    /// Returns an `Response` with the given `result`.
+    pub fn result(result: serde_json::Value, id: Id) -> Self {
+        Self {
+            jsonrpc: PROTOCOL_VERSION.to_owned(),
+            result: Some(result),
+            error: None,
+            id: Some(id),
+        }
+    }
+
+    /// Returns an `Response` with the given `error`.
+    pub fn error(error: Error, id: Option<Id>) -> Self {
+        Self {
+            jsonrpc: PROTOCOL_VERSION.to_owned(),
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+
+    /// Returns `true` if the response does not contain an error.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns `true` if the response contains an error.
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Returns the id of the request that is answered by this response.
+    pub fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    /// Converts the response into a `Result`.
+    ///
+    /// A successful response without a result is treated as `null`.
+    pub fn into_result(self) -> Result<serde_json::Value> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or(serde_json::Value::Null)),
+        }
+    }
+
+    /// Returns the result of a successful response and discards the error.
+    pub fn ok(self) -> Option<serde_json::Value> {
+        self.into_result().ok()
+    }
+
+    /// Returns the error of a failed response and discards the result.
+    pub fn err(self) -> Option<Error> {
+        self.error
+    }
+
+    /// Deserializes the result of a successful response into the given type.
+    ///
+    /// Returns the error of the response or an [`InternalError`](enum.ErrorCode.html#variant.InternalError) error
+    /// if the result cannot be deserialized.
+    pub fn result_as<T: DeserializeOwned>(&self) -> Result<T> {
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => {
+                let result = self.result.clone().unwrap_or(serde_json::Value::Null);
+                serde_json::from_value(result).map_err(|_| Error::result_deserialize_error())
+            }
+        }
+    }
+}
+
+/// The notification type for JSON-RPC messages.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    /// The parameters of the notification, which may be omitted according to the JSON-RPC specification.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_params",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub params: Option<serde_json::Value>,
+}
+
+impl Notification {
+    // Creates a new `Notification`.
+    pub fn new(method: String, params: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: PROTOCOL_VERSION.to_owned(),
+            method,
+            params: Some(params),
+        }
+    }
+}
+
+/// Represents a JSON-RPC message.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Message {
+    Request(Request),
+    Notification(Notification),
+    Response(Response),
+}
+
+impl Message {
+    /// Returns the value of the `jsonrpc` field of the message.
+    pub fn version(&self) -> &str {
+        match self {
+            Self::Request(request) => &request.jsonrpc,
+            Self::Notification(notification) => &notification.jsonrpc,
+            Self::Response(response) => &response.jsonrpc,
+        }
+    }
+
+    /// Returns `true` if the message uses the version of the protocol that is implemented by this crate.
+    pub fn has_valid_version(&self) -> bool {
+        self.version() == PROTOCOL_VERSION
+    }
+}
+
+// Any value that is present is considered Some value, including null.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lsp_error_codes_are_reserved() {
+        let codes = [
+            ErrorCode::RequestCancelled,
+            ErrorCode::ContentModified,
+            ErrorCode::ServerCancelled,
+            ErrorCode::RequestFailed,
+        ];
+        for code in codes.iter() {
+            let code = *code as i32;
+            assert!(code >= ErrorCode::LSP_RESERVED_ERROR_RANGE_START);
+            assert!(code <= ErrorCode::LSP_RESERVED_ERROR_RANGE_END);
+        }
+    }
+
+    #[test]
+    fn error_with_data() {
+        #[derive(Serialize)]
+        struct Details {
+            line: u32,
+        }
+
+        let error = Error::request_failed("foo".into(), Details { line: 42 });
+        assert_eq!(error.code, ErrorCode::RequestFailed);
+        assert_eq!(error.data, Some(serde_json::json!({ "line": 42 })));
+
+        let error = error.with_data("bar");
+        assert_eq!(error.data, Some(serde_json::json!("bar")));
+    }
+
+    #[test]
+    fn serialize_response_success_null() {
+        let response = Response::result(serde_json::Value::Null, Id::Number(42));
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"jsonrpc":"2.0","result":null,"id":42}"#);
+    }
+
+    #[test]
+    fn deserialize_response_success_null() {
+        let json = r#"{"jsonrpc":"2.0","result":null,"id":42}"#;
+        let response: Response = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            response,
+            Response::result(serde_json::Value::Null, Id::Number(42))
+        );
+    }
+
+    #[test]
+    fn serialize_response_error_without_id() {
+        let response = Response::error(Error::deserialize_error(), None);
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            json,
+            r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Could not deserialize parameter object"},"id":null}"#
+        );
+    }
+
+    #[test]
+    fn deserialize_response_error_without_id() {
+        let json = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Could not deserialize parameter object"},"id":null}"#;
+        let response: Response = serde_json::from_str(json).unwrap();
+        assert_eq!(response, Response::error(Error::deserialize_error(), None));
+    }
+
+    #[test]
+    fn serialize_initialize_error() {
+        let error = Error::initialize_error("foo".into(), true);
+        assert_eq!(
+            serde_json::to_value(error).unwrap(),
+            serde_json::json!({ "code": -32803, "message": "foo", "data": { "retry": true } })
+        );
+    }
+
+    #[test]
+    fn message_version() {
+        let request = Request::new("foo".into(), serde_json::json!(null), Id::Number(0));
+        assert!(Message::Request(request.clone()).has_valid_version());
+
+        let request = Request {
+            jsonrpc: "1.0".into(),
+            ..request
+        };
+        let message = Message::Request(request);
+        assert_eq!(message.version(), "1.0");
+        assert!(!message.has_valid_version());
+    }
+
+    #[test]
+    fn message_without_params() {
+        let json = r#"{"jsonrpc":"2.0","method":"shutdown","id":0}"#;
+        let message: Message = serde_json::from_str(json).unwrap();
+        let request = match message {
+            Message::Request(request) => request,
+            _ => panic!("expected request"),
+        };
+        assert_eq!(request.params, None);
+        assert_eq!(serde_json::to_string(&request).unwrap(), json);
+
+        let json = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let message: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            message,
+            Message::Notification(Notification {
+                jsonrpc: "2.0".into(),
+                method: "exit".into(),
+                params: None,
+            })
+        );
+    }
+
+    #[test]
+    fn response_success_accessors() {
+        let response = Response::result(serde_json::json!(42), Id::Number(1));
+        assert!(response.is_success());
+        assert!(!response.is_error());
+        assert_eq!(response.id(), Some(&Id::Number(1)));
+        assert_eq!(response.result_as::<u64>(), Ok(42));
+        assert_eq!(
+            response.result_as::<String>(),
+            Err(Error::result_deserialize_error())
+        );
+        assert_eq!(response.clone().err(), None);
+        assert_eq!(response.into_result(), Ok(serde_json::json!(42)));
+    }
+
+    #[test]
+    fn response_error_accessors() {
+        let response = Response::error(Error::method_not_found_error(), None);
+        assert!(response.is_error());
+        assert_eq!(response.id(), None);
+        assert_eq!(
+            response.result_as::<u64>(),
+            Err(Error::method_not_found_error())
+        );
+        assert_eq!(response.clone().ok(), None);
+        assert_eq!(response.err(), Some(Error::method_not_found_error()));
+    }
+}
@@ -0,0 +1,25 @@
+//! The transport layer of the [`language-server`](https://crates.io/crates/language-server) crate.
+//!
+//! It implements the base protocol of the
+//! [Language Server Protocol](https://microsoft.github.io/language-server-protocol/specification#baseProtocol),
+//! which can be reused by any service that exchanges JSON-RPC messages with a `Content-Length` header.
+//! It does not depend on the types of the Language Server Protocol itself.
+//! With the `websocket` feature, messages can be exchanged over an upgraded WebSocket connection instead.
+#[cfg(any(test, feature = "quickcheck"))]
+mod arbitrary;
+mod blocking;
+mod client;
+mod codec;
+pub mod jsonrpc;
+mod multiplex;
+mod timer;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use blocking::{BlockingSection, DeadlockPolicy};
+pub use client::{Client, ResponseHandler, Timer};
+pub use codec::{Framing, LspCodec, OutputFormat};
+pub use multiplex::IdMultiplexer;
+pub use timer::Delay;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketCodec;
@@ -0,0 +1,143 @@
+use crate::jsonrpc::*;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Maps the requests of multiple sources into a single id space, e.g. in a proxy that forwards
+/// the requests of several clients to one server.
+///
+/// Forwarded requests get an id of the form `<source>:<sequence>`, which cannot collide with
+/// the numeric ids of a [`Client`](struct.Client.html). The responses are routed back to their source
+/// with the original id. Cloned multiplexers share their state.
+#[derive(Debug, Clone, Default)]
+pub struct IdMultiplexer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    forwarded: HashMap<(String, Id), Id>,
+    pending: HashMap<Id, (String, Id)>,
+}
+
+impl IdMultiplexer {
+    /// Creates a multiplexer without pending requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the id of a request that has been received from the given source.
+    pub fn forward(&self, source: &str, mut request: Request) -> Request {
+        let mut inner = self.inner.lock().unwrap();
+        let id = Id::String(format!("{}:{}", source, inner.next_id));
+        inner.next_id += 1;
+
+        let original = std::mem::replace(&mut request.id, id.clone());
+        inner
+            .forwarded
+            .insert((source.to_owned(), original.clone()), id.clone());
+        inner.pending.insert(id, (source.to_owned(), original));
+        request
+    }
+
+    /// Restores the original id of a response and returns the source of the request.
+    ///
+    /// Returns `None` if the response does not belong to a forwarded request.
+    pub fn route(&self, mut response: Response) -> Option<(String, Response)> {
+        let mut inner = self.inner.lock().unwrap();
+        let (source, original) = inner.pending.remove(response.id.as_ref()?)?;
+        inner.forwarded.remove(&(source.clone(), original.clone()));
+        response.id = Some(original);
+        Some((source, response))
+    }
+
+    /// Replaces the id in a `$/cancelRequest` notification that has been received from the given source.
+    ///
+    /// Other notifications and cancellations of unknown requests are returned unchanged.
+    pub fn forward_cancellation(
+        &self,
+        source: &str,
+        mut notification: Notification,
+    ) -> Notification {
+        if notification.method != "$/cancelRequest" {
+            return notification;
+        }
+
+        let original = notification
+            .params
+            .as_ref()
+            .and_then(|params| params.get("id"))
+            .cloned()
+            .and_then(|id| serde_json::from_value::<Id>(id).ok());
+
+        if let Some(original) = original {
+            let inner = self.inner.lock().unwrap();
+            if let (Some(id), Some(params)) = (
+                inner.forwarded.get(&(source.to_owned(), original)),
+                notification.params.as_mut(),
+            ) {
+                params["id"] = serde_json::to_value(id).unwrap_or(Value::Null);
+            }
+        }
+        notification
+    }
+
+    /// Returns the number of forwarded requests that have not been answered yet.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn route_mixed_ids() {
+        let multiplexer = IdMultiplexer::new();
+        let foo = multiplexer.forward("foo", Request::new("a".into(), json!(null), Id::Number(1)));
+        let bar = multiplexer.forward(
+            "bar",
+            Request::new("b".into(), json!(null), Id::String("1".into())),
+        );
+        let baz = multiplexer.forward("bar", Request::new("c".into(), json!(null), Id::Number(1)));
+
+        assert_ne!(foo.id, bar.id);
+        assert_ne!(bar.id, baz.id);
+        assert_eq!(multiplexer.pending(), 3);
+
+        let response = Response::result(json!(42), bar.id);
+        let (source, response) = multiplexer.route(response).unwrap();
+        assert_eq!(source, "bar");
+        assert_eq!(
+            response,
+            Response::result(json!(42), Id::String("1".into()))
+        );
+
+        let response = Response::result(json!(42), baz.id);
+        assert_eq!(
+            multiplexer.route(response.clone()).unwrap(),
+            ("bar".to_owned(), Response::result(json!(42), Id::Number(1)))
+        );
+        assert_eq!(multiplexer.route(response), None);
+        assert_eq!(multiplexer.pending(), 1);
+    }
+
+    #[test]
+    fn forward_cancellation() {
+        let multiplexer = IdMultiplexer::new();
+        let request =
+            multiplexer.forward("foo", Request::new("a".into(), json!(null), Id::Number(7)));
+
+        let cancel = Notification::new("$/cancelRequest".into(), json!({ "id": 7 }));
+        let forwarded = multiplexer.forward_cancellation("foo", cancel.clone());
+        assert_eq!(forwarded.params, Some(json!({ "id": request.id })));
+
+        let unknown = multiplexer.forward_cancellation("bar", cancel.clone());
+        assert_eq!(unknown, cancel);
+    }
+}
@@ -0,0 +1,177 @@
+use futures::task::{Context, Poll, Waker};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    future::Future,
+    pin::Pin,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Condvar, Mutex, Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A future that completes once the given duration has elapsed.
+///
+/// The delay does not depend on the timer of a specific runtime.
+/// All delays share a single background thread that sleeps until the next deadline
+/// and a delay that is dropped before its deadline is removed from the timer.
+#[derive(Debug)]
+pub struct Delay {
+    deadline: Instant,
+    // The entry of the timer once the delay has been polled.
+    id: Option<u64>,
+}
+
+impl Delay {
+    /// Creates a delay that completes once the given duration has elapsed.
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + duration,
+            id: None,
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                timer().cancel(id);
+            }
+            return Poll::Ready(());
+        }
+
+        let id = timer().register(self.id, self.deadline, cx.waker().clone());
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            timer().cancel(id);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Timer {
+    state: Mutex<TimerState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct TimerState {
+    next_id: u64,
+    // Entries of cancelled delays stay in the heap until their deadline,
+    // but they no longer have a waker.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    wakers: HashMap<u64, Waker>,
+}
+
+impl Timer {
+    // Stores the waker of a delay and returns the id of its entry.
+    fn register(&self, id: Option<u64>, deadline: Instant, waker: Waker) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = match id {
+            Some(id) if state.wakers.contains_key(&id) => id,
+            _ => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.deadlines.push(Reverse((deadline, id)));
+                self.changed.notify_one();
+                id
+            }
+        };
+        state.wakers.insert(id, waker);
+        id
+    }
+
+    fn cancel(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.wakers.remove(&id);
+        if state.wakers.is_empty() {
+            state.deadlines.clear();
+        }
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut due = Vec::new();
+            while let Some(Reverse((deadline, id))) = state.deadlines.peek().cloned() {
+                if deadline > now {
+                    break;
+                }
+
+                state.deadlines.pop();
+                due.extend(state.wakers.remove(&id));
+            }
+
+            // The wakers may poll the delays right away, which locks the state again.
+            if !due.is_empty() {
+                drop(state);
+                due.into_iter().for_each(Waker::wake);
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state = match state.deadlines.peek() {
+                Some(Reverse((deadline, _))) => {
+                    let timeout = *deadline - now;
+                    self.changed.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.changed.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+// Returns the timer of the process and starts its thread on first use.
+fn timer() -> &'static Timer {
+    static START: Once = Once::new();
+    static TIMER: AtomicPtr<Timer> = AtomicPtr::new(ptr::null_mut());
+
+    START.call_once(|| {
+        let timer: &'static Timer = Box::leak(Box::new(Timer::default()));
+        thread::Builder::new()
+            .name("language-server-timer".into())
+            .spawn(move || timer.run())
+            .expect("failed to start the timer thread");
+        TIMER.store(timer as *const Timer as *mut Timer, Ordering::Release);
+    });
+
+    // The pointer has been stored by `call_once` and refers to a leaked timer that is never freed.
+    unsafe { &*TIMER.load(Ordering::Acquire) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, FutureExt};
+
+    #[test]
+    fn delay_elapsed() {
+        let start = Instant::now();
+        block_on(Delay::new(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn delay_dropped() {
+        let mut delay = Delay::new(Duration::from_secs(60));
+        assert_eq!((&mut delay).now_or_never(), None);
+        let id = delay.id.unwrap();
+        assert!(timer().state.lock().unwrap().wakers.contains_key(&id));
+
+        drop(delay);
+        assert!(!timer().state.lock().unwrap().wakers.contains_key(&id));
+    }
+}
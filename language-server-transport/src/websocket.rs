@@ -0,0 +1,182 @@
+use bytes::{BufMut, BytesMut};
+use futures_codec::{Decoder, Encoder};
+use std::io::{Error, ErrorKind};
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+
+/// Encodes and decodes messages that are sent as text frames of the
+/// [WebSocket protocol](https://tools.ietf.org/html/rfc6455), e.g. by a web-based editor.
+///
+/// The codec expects a connection that has already been upgraded by the HTTP server,
+/// so the handshake is not part of the codec.
+/// Messages may be split into several fragments by the client.
+///
+/// The codec does not answer control frames: ping and pong frames are skipped
+/// and the bytes after a close frame are discarded, so the input stream ends without an error
+/// once the connection has been closed. The HTTP server that upgraded the connection
+/// needs to reply to ping frames with pong frames and to close frames with a close frame
+/// as required by [RFC 6455](https://tools.ietf.org/html/rfc6455#section-5.5).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WebSocketCodec;
+
+// A single frame whose payload has already been unmasked.
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// Parses the frame at the start of the buffer and returns it with its length.
+fn parse_frame(src: &[u8]) -> Option<(Frame, usize)> {
+    if src.len() < 2 {
+        return None;
+    }
+
+    let fin = src[0] & 0x80 != 0;
+    let opcode = src[0] & 0x0F;
+    let masked = src[1] & 0x80 != 0;
+    let (length, mut offset) = match src[1] & 0x7F {
+        126 if src.len() >= 4 => (u64::from(u16::from_be_bytes([src[2], src[3]])), 4),
+        127 if src.len() >= 10 => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&src[2..10]);
+            (u64::from_be_bytes(bytes), 10)
+        }
+        126 | 127 => return None,
+        length => (u64::from(length), 2),
+    };
+
+    let mut mask = [0; 4];
+    if masked {
+        if src.len() < offset + 4 {
+            return None;
+        }
+        mask.copy_from_slice(&src[offset..offset + 4]);
+        offset += 4;
+    }
+
+    let end = offset.checked_add(length as usize)?;
+    if src.len() < end {
+        return None;
+    }
+
+    let payload = src[offset..end]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+
+    let frame = Frame {
+        fin,
+        opcode,
+        payload,
+    };
+    Some((frame, end))
+}
+
+impl Decoder for WebSocketCodec {
+    type Item = String;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // The frames are only consumed once the last fragment of the message has arrived.
+        let mut offset = 0;
+        let mut content = Vec::new();
+        while let Some((frame, length)) = parse_frame(&src[offset..]) {
+            offset += length;
+            match frame.opcode {
+                OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {
+                    content.extend(frame.payload);
+                    if frame.fin {
+                        let _ = src.split_to(offset);
+                        return String::from_utf8(content)
+                            .map(Some)
+                            .map_err(|_| ErrorKind::InvalidData.into());
+                    }
+                }
+                OPCODE_CLOSE => {
+                    // The other side does not send any data after a close frame.
+                    src.clear();
+                    return Ok(None);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Encoder for WebSocketCodec {
+    type Item = String;
+    type Error = Error;
+
+    fn encode(&mut self, item: String, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let length = item.len();
+        dst.reserve(length + 10);
+        dst.put_u8(0x80 | OPCODE_TEXT);
+        if length < 126 {
+            dst.put_u8(length as u8);
+        } else if length <= 0xFFFF {
+            dst.put_u8(126);
+            dst.put_u16(length as u16);
+        } else {
+            dst.put_u8(127);
+            dst.put_u64(length as u64);
+        }
+        dst.put(item.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        frame
+    }
+
+    #[test]
+    fn decode_fragmented_message() {
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&masked_frame(OPCODE_TEXT, b"{\"id\":"));
+        assert_eq!(WebSocketCodec.decode(&mut src).unwrap(), None);
+
+        src.extend_from_slice(&masked_frame(0x80 | 0x9, b"ping"));
+        src.extend_from_slice(&masked_frame(0x80 | OPCODE_CONTINUATION, b"1}"));
+        src.extend_from_slice(&masked_frame(0x80 | OPCODE_CLOSE, b""));
+        let message = WebSocketCodec.decode(&mut src).unwrap();
+        assert_eq!(message, Some("{\"id\":1}".to_owned()));
+        assert_eq!(WebSocketCodec.decode(&mut src).unwrap(), None);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn encode_message() {
+        let mut dst = BytesMut::new();
+        WebSocketCodec.encode("foo".into(), &mut dst).unwrap();
+        assert_eq!(&dst[..], b"\x81\x03foo");
+
+        let mut dst = BytesMut::new();
+        let message = "x".repeat(300);
+        WebSocketCodec.encode(message.clone(), &mut dst).unwrap();
+        assert_eq!(&dst[..4], &[0x81, 126, 0x01, 0x2C]);
+
+        let mut src = dst;
+        let decoded = WebSocketCodec.decode(&mut src).unwrap();
+        assert_eq!(decoded, Some(message));
+    }
+}
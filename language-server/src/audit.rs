@@ -0,0 +1,290 @@
+use crate::{jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// A mutation of a message by a middleware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// The name of the middleware that has changed the message.
+    pub middleware: &'static str,
+
+    /// The hook of the middleware, e.g. `on_outgoing_request`.
+    pub hook: &'static str,
+
+    /// The method of the message, if it has one.
+    pub method: Option<String>,
+
+    /// The JSON pointers of the fields that have been added, removed or changed.
+    pub fields: Vec<String>,
+}
+
+/// Records the most recent mutations of an [`AuditMiddleware`](struct.AuditMiddleware.html).
+///
+/// Cloned trails share their entries.
+#[derive(Debug, Clone)]
+pub struct AuditTrail {
+    entries: Arc<Mutex<VecDeque<AuditEntry>>>,
+    capacity: usize,
+}
+
+impl AuditTrail {
+    /// Creates a trail that keeps the given number of entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Arc::default(),
+            capacity,
+        }
+    }
+
+    /// Returns the recorded entries in the order in which the mutations have been applied.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn record(
+        &self,
+        middleware: &dyn Middleware,
+        hook: &'static str,
+        method: Option<&str>,
+        before: &Value,
+        after: &Value,
+    ) {
+        let mut fields = Vec::new();
+        diff(before, after, &mut String::new(), &mut fields);
+        if fields.is_empty() || self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+
+        entries.push_back(AuditEntry {
+            middleware: middleware.name(),
+            hook,
+            method: method.map(ToOwned::to_owned),
+            fields,
+        });
+    }
+}
+
+/// Middleware that runs the given middlewares in the order of their priority
+/// and records which of them changed which fields of a message.
+///
+/// Useful to debug surprising interactions in a complex middleware stack.
+/// Every message is serialized before and after each middleware, so the overhead is significant.
+pub struct AuditMiddleware {
+    middlewares: Vec<Arc<dyn Middleware>>,
+    trail: AuditTrail,
+}
+
+impl AuditMiddleware {
+    /// Wraps the middlewares and records their mutations in the given trail.
+    pub fn new(mut middlewares: Vec<Arc<dyn Middleware>>, trail: AuditTrail) -> Self {
+        crate::middleware::sort_by_priority(&mut middlewares);
+        Self { middlewares, trail }
+    }
+}
+
+#[async_trait]
+impl Middleware for AuditMiddleware {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        metadata: &MessageMetadata,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        for middleware in &self.middlewares {
+            let before = json!(message);
+            let flow = middleware
+                .on_incoming_message(message, metadata, context, Arc::clone(&client))
+                .await;
+
+            let method = match &*message {
+                Message::Request(request) => Some(request.method.as_str()),
+                Message::Notification(notification) => Some(notification.method.as_str()),
+                Message::Response(_) => None,
+            };
+            let after = json!(message);
+            self.trail.record(
+                middleware.as_ref(),
+                "on_incoming_message",
+                method,
+                &before,
+                &after,
+            );
+
+            if flow != MessageFlow::Continue {
+                return flow;
+            }
+        }
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        request: &Request,
+        metadata: &MessageMetadata,
+        response: &mut Response,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        for middleware in &self.middlewares {
+            let before = json!(response);
+            middleware
+                .on_outgoing_response(request, metadata, response, context, Arc::clone(&client))
+                .await;
+
+            self.trail.record(
+                middleware.as_ref(),
+                "on_outgoing_response",
+                Some(&request.method),
+                &before,
+                &json!(response),
+            );
+        }
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        for middleware in &self.middlewares {
+            let before = json!(request);
+            middleware
+                .on_outgoing_request(request, context, Arc::clone(&client))
+                .await;
+
+            self.trail.record(
+                middleware.as_ref(),
+                "on_outgoing_request",
+                Some(&request.method),
+                &before,
+                &json!(request),
+            );
+        }
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        notification: &mut Notification,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        for middleware in &self.middlewares {
+            let before = json!(notification);
+            middleware
+                .on_outgoing_notification(notification, context, Arc::clone(&client))
+                .await;
+
+            self.trail.record(
+                middleware.as_ref(),
+                "on_outgoing_notification",
+                Some(&notification.method),
+                &before,
+                &json!(notification),
+            );
+        }
+    }
+
+    async fn on_request_cancelled(
+        &self,
+        id: &Id,
+        method: &str,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        for middleware in &self.middlewares {
+            middleware
+                .on_request_cancelled(id, method, context, Arc::clone(&client))
+                .await;
+        }
+    }
+
+    fn on_message_received(&self, message: &Message, metadata: &MessageMetadata) {
+        for middleware in &self.middlewares {
+            middleware.on_message_received(message, metadata);
+        }
+    }
+
+    fn on_message_sent(&self, message: &Message) {
+        for middleware in &self.middlewares {
+            middleware.on_message_sent(message);
+        }
+    }
+}
+
+// Collects the JSON pointers of the fields that differ between both values.
+fn diff(before: &Value, after: &Value, path: &mut String, fields: &mut Vec<String>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let mut keys: Vec<_> = before.keys().chain(after.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let length = path.len();
+                path.push('/');
+                path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                match (before.get(key), after.get(key)) {
+                    (Some(before), Some(after)) => diff(before, after, path, fields),
+                    _ => fields.push(path.clone()),
+                }
+                path.truncate(length);
+            }
+        }
+        (Value::Array(before), Value::Array(after)) if before.len() == after.len() => {
+            for (index, (before, after)) in before.iter().zip(after).enumerate() {
+                let length = path.len();
+                path.push('/');
+                path.push_str(&index.to_string());
+                diff(before, after, path, fields);
+                path.truncate(length);
+            }
+        }
+        (before, after) if before != after => fields.push(path.clone()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_fields() {
+        let before = json!({ "a/b": 1, "c": [1, 2], "d": { "e": true }, "f": null });
+        let after = json!({ "a/b": 2, "c": [1, 2, 3], "d": { "e": true }, "g": null });
+        let mut fields = Vec::new();
+        diff(&before, &after, &mut String::new(), &mut fields);
+        assert_eq!(fields, vec!["/a~1b", "/c", "/f", "/g"]);
+    }
+
+    #[test]
+    fn trail_capacity() {
+        let trail = AuditTrail::new(1);
+        let middleware = crate::LoggingMiddleware;
+        trail.record(&middleware, "foo", None, &json!(1), &json!(2));
+        trail.record(&middleware, "bar", Some("baz"), &json!(1), &json!(2));
+        trail.record(&middleware, "qux", None, &json!(1), &json!(1));
+
+        let entries = trail.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].hook, "bar");
+        assert_eq!(entries[0].method, Some("baz".to_owned()));
+        assert_eq!(entries[0].fields, vec![""]);
+        assert!(entries[0].middleware.ends_with("LoggingMiddleware"));
+    }
+}
@@ -0,0 +1,158 @@
+use futures::task::{Context, Poll, Waker};
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+thread_local! {
+    // The token of the request whose handler is being polled on this thread.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static CURRENT: RefCell<Option<CancellationToken>> = RefCell::new(None);
+}
+
+/// A token that signals the cancellation of a long-running operation.
+///
+/// Cloned tokens share their state, so cancelling one of them cancels all of them.
+///
+/// The handler of a request can obtain the token of the request with
+/// [`current`](#method.current), which is cancelled once the client sends `$/cancelRequest`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    /// Creates a new token that has not been cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token and wakes up all tasks waiting for the cancellation.
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            let wakers: Vec<_> = self.inner.wakers.lock().unwrap().drain(..).collect();
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns `true` if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that completes once the token has been cancelled.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+
+    /// Returns the token of the request that is being handled by the current task.
+    ///
+    /// The token is only available while the handler is polled,
+    /// so it needs to be cloned before spawning another task.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    // Makes the token available through `current` while the given future is polled.
+    pub(crate) fn scope<F: Future + Unpin>(self, future: F) -> Scoped<F> {
+        Scoped {
+            token: self,
+            future,
+        }
+    }
+}
+
+// A future that exposes a token through `CancellationToken::current`.
+pub(crate) struct Scoped<F> {
+    token: CancellationToken,
+    future: F,
+}
+
+impl<F: Future + Unpin> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let token = self.token.clone();
+        let previous = CURRENT.with(|current| current.replace(Some(token)));
+        let result = Pin::new(&mut self.future).poll(cx);
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`](struct.CancellationToken.html#method.cancelled).
+#[derive(Debug)]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        {
+            let mut wakers = self.token.inner.wakers.lock().unwrap();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, future::join};
+
+    #[test]
+    fn cancel_shared() {
+        let token = CancellationToken::new();
+        let other = token.clone();
+        assert!(!other.is_cancelled());
+        token.cancel();
+        assert!(other.is_cancelled());
+    }
+
+    #[test]
+    fn current_token_in_scope() {
+        let token = CancellationToken::new();
+        let current = block_on(
+            token
+                .clone()
+                .scope(Box::pin(async { CancellationToken::current() })),
+        );
+        current.unwrap().cancel();
+        assert!(token.is_cancelled());
+        assert!(CancellationToken::current().is_none());
+    }
+
+    #[test]
+    fn cancelled_wakes_waiting_task() {
+        let token = CancellationToken::new();
+        block_on(join(token.cancelled(), async { token.cancel() }));
+        assert!(token.is_cancelled());
+    }
+}
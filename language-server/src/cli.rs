@@ -0,0 +1,219 @@
+//! Parses the conventional command line arguments that editors use to launch a language server.
+//!
+//! The following arguments are supported:
+//!
+//! - `--stdio`: Communicate over the standard streams (default).
+//! - `--socket=PORT` or `--port=PORT`: Connect to the TCP socket of the client at the given port.
+//! - `--pipe=PATH`: Connect to the Unix domain socket of the client (not supported on Windows yet).
+//! - `--node-ipc`: Rejected because the IPC channel of Node.js is not available outside of Node.js.
+//!
+//! All other arguments are ignored, so servers can define their own flags.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use language_server::cli::Transport;
+//!
+//! let (input, output) = Transport::from_env()
+//!     .expect("invalid arguments")
+//!     .connect()
+//!     .expect("failed to connect");
+//! ```
+use crate::stdio::{ThreadedReader, ThreadedWriter};
+use std::{error, fmt, io, path::PathBuf};
+
+/// The transport that is requested by the command line arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Communicate over the standard input and output streams.
+    Stdio,
+
+    /// Connect to the TCP socket of the client on the local host.
+    Socket(u16),
+
+    /// Connect to the Unix domain socket of the client.
+    Pipe(PathBuf),
+}
+
+impl Transport {
+    /// Parses the arguments of the current process.
+    pub fn from_env() -> Result<Self, CliError> {
+        Self::from_args(std::env::args().skip(1))
+    }
+
+    /// Parses the given arguments which must not include the name of the program.
+    ///
+    /// Uses the standard streams if no transport has been specified.
+    pub fn from_args<I, T>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut args = args.into_iter().peekable();
+        let mut transport = None;
+        while let Some(arg) = args.next() {
+            let arg = arg.as_ref();
+            let (flag, value) = match arg.find('=') {
+                Some(index) => (&arg[..index], Some(arg[index + 1..].to_owned())),
+                None => (arg, None),
+            };
+
+            let next = match flag {
+                "--stdio" => Transport::Stdio,
+                "--socket" | "--port" => {
+                    let value = match value {
+                        Some(value) => value,
+                        None => next_value(&mut args, flag)?,
+                    };
+                    let port = value.parse().map_err(|_| CliError::InvalidPort(value))?;
+                    Transport::Socket(port)
+                }
+                "--pipe" => {
+                    let value = match value {
+                        Some(value) => value,
+                        None => next_value(&mut args, flag)?,
+                    };
+                    Transport::Pipe(value.into())
+                }
+                "--node-ipc" => return Err(CliError::NodeIpc),
+                _ => continue,
+            };
+
+            match transport {
+                Some(ref previous) if *previous != next => return Err(CliError::Conflict),
+                _ => transport = Some(next),
+            }
+        }
+
+        Ok(transport.unwrap_or(Transport::Stdio))
+    }
+
+    /// Connects to the client and returns the input and output streams
+    /// that can be passed to a [`LanguageService`](../struct.LanguageService.html).
+    ///
+    /// The underlying I/O is performed on background threads,
+    /// so the streams can be used with any executor.
+    pub fn connect(&self) -> io::Result<(ThreadedReader, ThreadedWriter)> {
+        match self {
+            Transport::Stdio => Ok(crate::stdio::stdio()),
+            Transport::Socket(port) => {
+                let stream = std::net::TcpStream::connect(("127.0.0.1", *port))?;
+                Ok((
+                    ThreadedReader::new(stream.try_clone()?),
+                    ThreadedWriter::new(stream),
+                ))
+            }
+            Transport::Pipe(path) => crate::stdio::connect_pipe(path),
+        }
+    }
+}
+
+fn next_value<I, T>(args: &mut std::iter::Peekable<I>, flag: &str) -> Result<String, CliError>
+where
+    I: Iterator<Item = T>,
+    T: AsRef<str>,
+{
+    match args.peek() {
+        Some(value) if !value.as_ref().starts_with("--") => {
+            Ok(args.next().unwrap().as_ref().to_owned())
+        }
+        _ => Err(CliError::MissingValue(flag.to_owned())),
+    }
+}
+
+/// The error type for invalid command line arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    /// The `--node-ipc` transport has been requested.
+    NodeIpc,
+
+    /// The port of the `--socket` argument is not a valid port number.
+    InvalidPort(String),
+
+    /// The argument requires a value but none has been specified.
+    MissingValue(String),
+
+    /// Multiple different transports have been specified.
+    Conflict,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::NodeIpc => write!(f, "The node-ipc transport is not supported"),
+            CliError::InvalidPort(port) => write!(f, "Invalid port: {}", port),
+            CliError::MissingValue(flag) => write!(f, "Missing value for argument: {}", flag),
+            CliError::Conflict => write!(f, "Multiple transports have been specified"),
+        }
+    }
+}
+
+impl error::Error for CliError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn parse_transport() {
+        assert_eq!(
+            Transport::from_args(Vec::<String>::new()),
+            Ok(Transport::Stdio)
+        );
+        assert_eq!(Transport::from_args(vec!["--stdio"]), Ok(Transport::Stdio));
+        assert_eq!(
+            Transport::from_args(vec!["--socket=5007"]),
+            Ok(Transport::Socket(5007))
+        );
+        assert_eq!(
+            Transport::from_args(vec!["--clientProcessId=42", "--port", "5007"]),
+            Ok(Transport::Socket(5007))
+        );
+        assert_eq!(
+            Transport::from_args(vec!["--pipe=/tmp/lsp.sock"]),
+            Ok(Transport::Pipe("/tmp/lsp.sock".into()))
+        );
+    }
+
+    #[test]
+    fn parse_transport_error() {
+        assert_eq!(
+            Transport::from_args(vec!["--node-ipc"]),
+            Err(CliError::NodeIpc)
+        );
+        assert_eq!(
+            Transport::from_args(vec!["--socket=foo"]),
+            Err(CliError::InvalidPort("foo".into()))
+        );
+        assert_eq!(
+            Transport::from_args(vec!["--pipe", "--stdio"]),
+            Err(CliError::MissingValue("--pipe".into()))
+        );
+        assert_eq!(
+            Transport::from_args(vec!["--stdio", "--socket=5007"]),
+            Err(CliError::Conflict)
+        );
+    }
+
+    #[test]
+    fn connect_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (mut input, mut output) = Transport::Socket(port).connect().unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        stream.write_all(b"foo").unwrap();
+        let mut buf = [0; 3];
+        block_on(input.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"foo");
+
+        block_on(output.write_all(b"bar")).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"bar");
+    }
+}
@@ -1,23 +1,51 @@
-use crate::jsonrpc::*;
+#[cfg(feature = "draft")]
+use crate::draft::*;
+use crate::{jsonrpc::*, trace::LogTraceParams, Clock, SystemClock};
 use async_trait::async_trait;
-use futures::{
-    channel::{mpsc, oneshot},
-    lock::Mutex,
-    prelude::*,
-};
+use futures::future::{select, Either};
 use language_server_macros::*;
 use lsp_types::*;
-use serde::Serialize;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::{
-    collections::HashMap,
     sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
 
+/// Sends untyped messages to the client.
+///
+/// Use the typed methods of [`LanguageClientExt`](trait.LanguageClientExt.html) instead.
+#[async_trait]
+pub trait RawClient: Send + Sync {
+    /// Sends a request with the given method and parameters and waits for the result.
+    async fn send_raw_request(&self, method: String, params: Value) -> Result<Value>;
+
+    /// Sends a request with the given method and parameters and waits for the result
+    /// for at most the given duration.
+    ///
+    /// The client of a service delays the timeout with the clock of the service.
+    /// Other clients use the [`SystemClock`](struct.SystemClock.html) by default.
+    async fn send_raw_request_with_timeout(
+        &self,
+        method: String,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let error = Error::request_timeout(&method);
+        let request = self.send_raw_request(method, params);
+        match select(request, SystemClock.sleep(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(error),
+        }
+    }
+
+    /// Sends a notification with the given method and parameters.
+    async fn send_raw_notification(&self, method: String, params: Value);
+}
+
 /// Defines the client-side implementation of the [Language Server Protocol](https://microsoft.github.io/language-server-protocol/specification).
 #[jsonrpc_client(ident = "LanguageClientImpl")]
 #[async_trait]
-pub trait LanguageClient: Send + Sync + 'static {
+pub trait LanguageClient: RawClient + Send + Sync + 'static {
     /// The base protocol offers also support to report progress in a generic fashion.
     /// [This mechanism](https://microsoft.github.io/language-server-protocol/specification#progress)
     /// can be used to report any kind of progress including work done progress
@@ -44,6 +72,13 @@ pub trait LanguageClient: Send + Sync + 'static {
     #[jsonrpc_method(name = "window/logMessage", kind = "notification")]
     async fn log_message(&self, params: LogMessageParams);
 
+    /// A [notification](https://microsoft.github.io/language-server-protocol/specification#logTrace)
+    /// to log the trace of the server's execution.
+    ///
+    /// Use a [`Tracer`](struct.Tracer.html) to respect the trace setting of the client.
+    #[jsonrpc_method(name = "$/logTrace", kind = "notification")]
+    async fn log_trace(&self, params: LogTraceParams);
+
     /// The [`window/workDoneProgress/create`](https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create)
     /// request is sent from the server to the client to ask the client to create a work done progress.
     #[jsonrpc_method(name = "window/workDoneProgress/create", kind = "request")]
@@ -59,6 +94,26 @@ pub trait LanguageClient: Send + Sync + 'static {
     #[jsonrpc_method(name = "client/registerCapability", kind = "request")]
     async fn register_capability(&self, params: RegistrationParams) -> Result<()>;
 
+    /// The `workspace/codeLens/refresh` request is sent from the server to the client
+    /// to ask the client to refresh all code lenses.
+    #[jsonrpc_method(name = "workspace/codeLens/refresh", kind = "request")]
+    async fn code_lens_refresh(&self, params: ()) -> Result<()>;
+
+    /// The `workspace/semanticTokens/refresh` request is sent from the server to the client
+    /// to ask the client to refresh all semantic tokens.
+    #[jsonrpc_method(name = "workspace/semanticTokens/refresh", kind = "request")]
+    async fn semantic_tokens_refresh(&self, params: ()) -> Result<()>;
+
+    /// The `workspace/inlayHint/refresh` request is sent from the server to the client
+    /// to ask the client to refresh all inlay hints.
+    #[jsonrpc_method(name = "workspace/inlayHint/refresh", kind = "request")]
+    async fn inlay_hint_refresh(&self, params: ()) -> Result<()>;
+
+    /// The `workspace/diagnostic/refresh` request is sent from the server to the client
+    /// to ask the client to pull the diagnostics of all documents again.
+    #[jsonrpc_method(name = "workspace/diagnostic/refresh", kind = "request")]
+    async fn diagnostic_refresh(&self, params: ()) -> Result<()>;
+
     /// The [`client/unregisterCapability`](https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability)
     /// request is sent from the server to the client to unregister a previously registered capability.
     #[jsonrpc_method(name = "client/unregisterCapability", kind = "request")]
@@ -93,159 +148,301 @@ pub trait LanguageClient: Send + Sync + 'static {
     #[cfg(feature = "proposed")]
     #[jsonrpc_method(name = "textDocument/semanticHighlighting", kind = "notification")]
     async fn semantic_highlighting(&self, params: SemanticHighlightingParams);
-}
 
-#[async_trait]
-pub trait ResponseHandler {
-    async fn handle(&self, response: Response);
-}
+    /// Dynamically registers the `workspace/didChangeWatchedFiles` notification
+    /// for all files matching the given glob patterns.
+    ///
+    /// Returns the id of the registration which can be passed to `unwatch_files`.
+    async fn watch_files(&self, globs: Vec<String>) -> Result<String> {
+        let watchers = globs
+            .into_iter()
+            .map(|glob_pattern| FileSystemWatcher {
+                glob_pattern,
+                kind: None,
+            })
+            .collect();
 
-#[derive(Debug)]
-pub struct Client {
-    output: mpsc::Sender<Message>,
-    request_id: AtomicU64,
-    senders_by_id: Mutex<HashMap<Id, oneshot::Sender<Result<serde_json::Value>>>>,
-}
+        let id = next_registration_id();
+        let registration = Registration {
+            id: id.clone(),
+            method: "workspace/didChangeWatchedFiles".to_owned(),
+            register_options: Some(json!(DidChangeWatchedFilesRegistrationOptions { watchers })),
+        };
+
+        self.register_capability(RegistrationParams {
+            registrations: vec![registration],
+        })
+        .await?;
+        Ok(id)
+    }
+
+    /// Removes a registration that has been created by `watch_files`.
+    async fn unwatch_files(&self, id: String) -> Result<()> {
+        let unregistration = Unregistration {
+            id,
+            method: "workspace/didChangeWatchedFiles".to_owned(),
+        };
+
+        self.unregister_capability(UnregistrationParams {
+            unregisterations: vec![unregistration],
+        })
+        .await
+    }
+
+    /// Asks the client to refresh all results of the given kinds, e.g. after the project has been reloaded.
+    ///
+    /// Servers should only pass the kinds that the client has announced support for.
+    /// Refresh requests that the client rejects with a
+    /// [`MethodNotFound`](jsonrpc/enum.ErrorCode.html#variant.MethodNotFound) error are skipped.
+    async fn refresh_all(&self, kinds: &[RefreshKind]) -> Result<()> {
+        for kind in kinds {
+            let result = match kind {
+                RefreshKind::CodeLens => self.code_lens_refresh(()).await,
+                RefreshKind::SemanticTokens => self.semantic_tokens_refresh(()).await,
+                RefreshKind::InlayHints => self.inlay_hint_refresh(()).await,
+                RefreshKind::Diagnostics => self.diagnostic_refresh(()).await,
+            };
 
-impl Client {
-    pub fn new(output: mpsc::Sender<Message>) -> Self {
-        Self {
-            output,
-            request_id: AtomicU64::new(0),
-            senders_by_id: Mutex::new(HashMap::new()),
+            match result {
+                Err(ref why) if why.code == ErrorCode::MethodNotFound => (),
+                result => result?,
+            }
         }
+        Ok(())
     }
 
-    pub async fn send_request<T: Serialize>(
+    /// Dynamically registers the `textDocument/inlineCompletion` request.
+    ///
+    /// Returns the id of the registration which can be passed to `unregister_capability`.
+    #[cfg_attr(docsrs, doc(cfg(feature = "draft")))]
+    #[cfg(feature = "draft")]
+    async fn register_inline_completion(
         &self,
-        method: String,
-        params: T,
-    ) -> Result<serde_json::Value> {
-        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
-        let request = Request::new(method, json!(params), Id::Number(id));
-
-        let (result_tx, result_rx) = oneshot::channel();
-        {
-            let mut senders_by_id = self.senders_by_id.lock().await;
-            senders_by_id.insert(request.id.clone(), result_tx);
-        }
+        options: InlineCompletionRegistrationOptions,
+    ) -> Result<String> {
+        let id = next_registration_id();
+        let registration = Registration {
+            id: id.clone(),
+            method: "textDocument/inlineCompletion".to_owned(),
+            register_options: Some(json!(options)),
+        };
 
-        let mut output = self.output.clone();
-        output.send(Message::Request(request)).await.unwrap();
+        self.register_capability(RegistrationParams {
+            registrations: vec![registration],
+        })
+        .await?;
+        Ok(id)
+    }
+}
 
-        result_rx.await.unwrap()
+/// Sends requests and notifications that are defined with the traits of `lsp_types`,
+/// e.g. vendor-specific extensions that are not part of [`LanguageClient`](trait.LanguageClient.html).
+#[async_trait]
+pub trait LanguageClientExt: RawClient {
+    /// Sends a request of the given type and waits for the result.
+    async fn request<R>(&self, params: R::Params) -> Result<R::Result>
+    where
+        R: request::Request,
+        R::Params: Send + 'static,
+    {
+        let result = self
+            .send_raw_request(R::METHOD.to_owned(), json!(params))
+            .await?;
+        serde_json::from_value(result).map_err(|_| Error::result_deserialize_error())
     }
 
-    pub async fn send_notification<T: Serialize>(&self, method: String, params: T) {
-        let notification = Notification::new(method, json!(params));
-        let mut output = self.output.clone();
-        output
-            .send(Message::Notification(notification))
+    /// Sends a request of the given type and waits for the result for at most the given duration.
+    ///
+    /// Requests without a response in time are cancelled and fail with a `RequestTimeout` error.
+    /// The client of a service measures the timeout with the clock of the service.
+    async fn request_with_timeout<R>(
+        &self,
+        params: R::Params,
+        timeout: Duration,
+    ) -> Result<R::Result>
+    where
+        R: request::Request,
+        R::Params: Send + 'static,
+    {
+        let result = self
+            .send_raw_request_with_timeout(R::METHOD.to_owned(), json!(params), timeout)
+            .await?;
+        serde_json::from_value(result).map_err(|_| Error::result_deserialize_error())
+    }
+
+    /// Sends a notification of the given type.
+    async fn notify<N>(&self, params: N::Params)
+    where
+        N: notification::Notification,
+        N::Params: Send + 'static,
+    {
+        self.send_raw_notification(N::METHOD.to_owned(), json!(params))
             .await
-            .unwrap();
     }
 }
 
-#[async_trait]
-impl ResponseHandler for Client {
-    async fn handle(&self, response: Response) {
-        let id = response.id.expect("Expected response with id");
-        let result = match response.error {
-            Some(why) => Err(why),
-            None => Ok(response.result.unwrap_or(serde_json::Value::Null)),
-        };
+impl<C: RawClient + ?Sized> LanguageClientExt for C {}
 
-        let result_tx = {
-            let mut senders_by_id = self.senders_by_id.lock().await;
-            senders_by_id
-                .remove(&id)
-                .expect("Unexpected response received")
-        };
+/// The kinds of results that can be refreshed using [`LanguageClient::refresh_all`](trait.LanguageClient.html#method.refresh_all).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RefreshKind {
+    /// Sends a `workspace/codeLens/refresh` request.
+    CodeLens,
 
-        result_tx.send(result).unwrap();
-    }
+    /// Sends a `workspace/semanticTokens/refresh` request.
+    SemanticTokens,
+
+    /// Sends a `workspace/inlayHint/refresh` request.
+    InlayHints,
+
+    /// Sends a `workspace/diagnostic/refresh` request.
+    Diagnostics,
+}
+
+pub(crate) fn next_registration_id() -> String {
+    static REGISTRATION_ID: AtomicU64 = AtomicU64::new(0);
+    let id = REGISTRATION_ID.fetch_add(1, Ordering::SeqCst);
+    format!("language-server/{}", id)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::future::{join, join3};
+    use futures::{
+        channel::mpsc,
+        executor::LocalPool,
+        future::{self, join3},
+        prelude::*,
+        task::LocalSpawnExt,
+    };
+    use language_server_transport::ResponseHandler;
+    use std::sync::Arc;
 
     #[tokio::test]
-    async fn notification() {
+    async fn refresh_all_skips_unsupported() {
         let (tx, mut rx) = mpsc::channel(0);
-        let client = Client::new(tx);
-        let ((), output) = join(client.send_notification("foo".into(), 42u64), rx.next()).await;
+        let client = LanguageClientImpl::new(tx);
+        let kinds = [RefreshKind::CodeLens, RefreshKind::InlayHints];
+        let responses = async {
+            let request = rx.next().await.unwrap();
+            client
+                .handle(Response::error(
+                    Error::method_not_found_error(),
+                    Some(Id::Number(0)),
+                ))
+                .await;
+
+            let next = rx.next().await.unwrap();
+            client
+                .handle(Response::result(serde_json::Value::Null, Id::Number(1)))
+                .await;
+            (request, next)
+        };
 
+        let (result, (first, second)) = future::join(client.refresh_all(&kinds), responses).await;
+        result.unwrap();
+        let methods: Vec<_> = vec![first, second]
+            .into_iter()
+            .map(|message| match message {
+                Message::Request(request) => request.method,
+                _ => unreachable!(),
+            })
+            .collect();
         assert_eq!(
-            output.unwrap(),
-            Message::Notification(Notification::new("foo".to_owned(), json!(42)))
+            methods,
+            vec!["workspace/codeLens/refresh", "workspace/inlayHint/refresh"]
         );
     }
 
     #[tokio::test]
-    async fn request_success() {
+    async fn watch_files() {
         let (tx, mut rx) = mpsc::channel(0);
-        let client = Client::new(tx);
-        let (response, output, ()) = join3(
-            client.send_request("foo".into(), 42u64),
+        let client = LanguageClientImpl::new(tx);
+        let (id, output, ()) = join3(
+            client.watch_files(vec!["**/*.tex".into()]),
             rx.next(),
-            client.handle(Response::result(
-                serde_json::to_value(1337u64).unwrap(),
-                Id::Number(0),
-            )),
+            client.handle(Response::result(serde_json::Value::Null, Id::Number(0))),
         )
         .await;
+
+        let id = id.unwrap();
+        let registration = Registration {
+            id,
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: Some(json!({ "watchers": [{ "globPattern": "**/*.tex" }] })),
+        };
         assert_eq!(
             output.unwrap(),
-            Message::Request(Request::new("foo".to_owned(), json!(42), Id::Number(0)))
-        );
-        assert_eq!(
-            serde_json::from_value::<u64>(response.unwrap()).unwrap(),
-            1337
+            Message::Request(Request::new(
+                "client/registerCapability".to_owned(),
+                json!(RegistrationParams {
+                    registrations: vec![registration]
+                }),
+                Id::Number(0)
+            ))
         );
     }
 
     #[tokio::test]
-    async fn request_failure() {
+    async fn typed_extension_messages() {
         let (tx, mut rx) = mpsc::channel(0);
-        let client = Client::new(tx);
-        let (response, output, ()) = join3(
-            client.send_request("foo".into(), 42u64),
+        let client_impl = Arc::new(LanguageClientImpl::new(tx));
+        let client: Arc<dyn LanguageClient> = client_impl.clone();
+        let params = LogMessageParams {
+            typ: MessageType::Log,
+            message: "foo".into(),
+        };
+        let (_, output) = future::join(
+            client.notify::<notification::LogMessage>(params.clone()),
             rx.next(),
-            client.handle(Response::error(
-                Error::internal_error("bar".into()),
-                Some(Id::Number(0)),
-            )),
         )
         .await;
         assert_eq!(
             output.unwrap(),
-            Message::Request(Request::new("foo".to_owned(), json!(42), Id::Number(0)))
+            Message::Notification(Notification::new("window/logMessage".into(), json!(params)))
         );
-        assert_eq!(response.unwrap_err(), Error::internal_error("bar".into()));
-    }
 
-    #[tokio::test]
-    #[should_panic(expected = "Unexpected response received")]
-    async fn request_unexpected_response() {
-        let (tx, _) = mpsc::channel(0);
-        let client = Client::new(tx);
-        client
-            .handle(Response::error(
-                Error::internal_error("bar".into()),
-                Some(Id::Number(42)),
+        let (result, output, ()) = join3(
+            client.request::<request::WorkspaceFoldersRequest>(()),
+            rx.next(),
+            client_impl.handle(Response::result(Value::Null, Id::Number(0))),
+        )
+        .await;
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(
+            output.unwrap(),
+            Message::Request(Request::new(
+                "workspace/workspaceFolders".into(),
+                json!(()),
+                Id::Number(0)
             ))
-            .await;
+        );
     }
 
-    #[tokio::test]
-    #[should_panic(expected = "Expected response with id")]
-    async fn request_response_without_id() {
-        let (tx, _) = mpsc::channel(0);
-        let client = Client::new(tx);
-        client
-            .handle(Response::error(Error::internal_error("bar".into()), None))
-            .await;
+    #[test]
+    fn request_timeout_follows_clock() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let clock = crate::ManualClock::new();
+        let timer_clock = clock.clone();
+        let client = LanguageClientImpl::new(tx)
+            .with_timer(Arc::new(move |duration| timer_clock.sleep(duration)));
+
+        let mut pool = LocalPool::new();
+        let request = async move {
+            client
+                .request_with_timeout::<request::WorkspaceFoldersRequest>(
+                    (),
+                    Duration::from_secs(5),
+                )
+                .await
+        };
+        let result = pool.spawner().spawn_local_with_handle(request).unwrap();
+        pool.run_until_stalled();
+        assert!(pool.run_until(rx.next()).is_some());
+        assert_eq!(clock.sleepers(), 1);
+
+        clock.advance(Duration::from_secs(5));
+        let error = pool.run_until(result).unwrap_err();
+        assert_eq!(error.code, ErrorCode::RequestTimeout);
     }
 }
@@ -0,0 +1,154 @@
+use futures::{channel::oneshot, future::BoxFuture, FutureExt};
+use language_server_transport::Delay;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A source of time for the features that depend on it, e.g. rate limits and backoffs.
+///
+/// The [`SystemClock`](struct.SystemClock.html) is used by default.
+/// Tests can replace it with a [`ManualClock`](struct.ManualClock.html) to control the passage of time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current point in time.
+    fn now(&self) -> Instant;
+
+    /// Returns a future that completes once the given duration has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// A clock that follows the time of the operating system.
+///
+/// Sleeping does not depend on the timer of a specific runtime.
+/// All sleeps share one background thread and a sleep that is dropped early is removed from it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Delay::new(duration).boxed()
+    }
+}
+
+/// A clock that only advances when told to, which makes time-based behavior deterministic in tests.
+///
+/// Cloned clocks share their time.
+///
+/// # Example
+///
+/// ```
+/// # use language_server::{Clock, ManualClock};
+/// # use std::time::Duration;
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - start, Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    inner: Arc<Mutex<ManualState>>,
+}
+
+#[derive(Debug)]
+struct ManualState {
+    now: Instant,
+    sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        let state = ManualState {
+            now: Instant::now(),
+            sleepers: Vec::new(),
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock that starts at the current time.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward and wakes up the sleepers whose deadline has been reached.
+    pub fn advance(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.now += duration;
+        let now = inner.now;
+        let (due, sleepers) = inner
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= now);
+        inner.sleepers = sleepers;
+        drop(inner);
+
+        for (_, sender) in due {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Returns the number of sleepers that are waiting for the clock to advance.
+    pub fn sleepers(&self) -> usize {
+        self.inner.lock().unwrap().sleepers.len()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        if duration == Duration::from_secs(0) {
+            let _ = sender.send(());
+        } else {
+            let mut inner = self.inner.lock().unwrap();
+            let deadline = inner.now + duration;
+            inner.sleepers.push((deadline, sender));
+        }
+        receiver.map(drop).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::LocalPool, task::LocalSpawnExt};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn manual_sleep() {
+        let clock = ManualClock::new();
+        let woken = Arc::new(AtomicBool::new(false));
+        let mut pool = LocalPool::new();
+        let sleep = clock.sleep(Duration::from_secs(2));
+        let flag = Arc::clone(&woken);
+        pool.spawner()
+            .spawn_local(async move {
+                sleep.await;
+                flag.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        pool.run_until_stalled();
+        clock.advance(Duration::from_secs(1));
+        pool.run_until_stalled();
+        assert!(!woken.load(Ordering::SeqCst));
+        assert_eq!(clock.sleepers(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        pool.run_until_stalled();
+        assert!(woken.load(Ordering::SeqCst));
+        assert_eq!(clock.sleepers(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::fuzzy;
+use futures::Future;
+use lsp_types::*;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Produces incomplete completion lists that are narrowed down while the user keeps typing.
+///
+/// Only the best `limit` items are sent to the client and the list is marked as incomplete
+/// if there are more candidates, so the client asks again once the user types another character.
+/// The candidates are cached per word, so subsequent queries that extend the previous prefix
+/// are answered from the cache and can include items that did not make the cut before.
+#[derive(Debug)]
+pub struct CompletionCache {
+    limit: usize,
+    entries: Mutex<HashMap<(Url, u64, u64), Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    prefix: String,
+    items: Vec<CompletionItem>,
+}
+
+impl CompletionCache {
+    /// Creates a cache that sends at most `limit` items per response.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Answers a completion request for the word that begins at `start` and has been typed up to `prefix`.
+    ///
+    /// The candidates are computed using `compute` unless the cached candidates of a previous query
+    /// can be reused. The result is filtered and ranked by the prefix and the `sortText` of the items
+    /// is set accordingly.
+    pub async fn complete<F, T>(
+        &self,
+        uri: &Url,
+        start: Position,
+        prefix: &str,
+        compute: F,
+    ) -> CompletionResponse
+    where
+        F: FnOnce(String) -> T,
+        T: Future<Output = Vec<CompletionItem>>,
+    {
+        let key = (uri.clone(), start.line, start.character);
+        let cached = {
+            let entries = self.entries.lock().unwrap();
+            entries
+                .get(&key)
+                .filter(|entry| prefix.starts_with(entry.prefix.as_str()))
+                .map(|entry| entry.items.clone())
+        };
+
+        let candidates = match cached {
+            Some(items) => items,
+            None => {
+                let items = compute(prefix.to_owned()).await;
+                let entry = Entry {
+                    prefix: prefix.to_owned(),
+                    items: items.clone(),
+                };
+                self.entries.lock().unwrap().insert(key, entry);
+                items
+            }
+        };
+
+        let ranked = fuzzy::rank(prefix, candidates, |item| {
+            item.filter_text.as_ref().unwrap_or(&item.label)
+        });
+        let is_incomplete = ranked.len() > self.limit;
+        let items = ranked
+            .into_iter()
+            .take(self.limit)
+            .enumerate()
+            .map(|(position, (mut item, _))| {
+                item.sort_text = Some(fuzzy::sort_text(position));
+                item
+            })
+            .collect();
+
+        CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        })
+    }
+
+    /// Removes the cached candidates of the given document, e.g. when it has been closed.
+    pub fn remove(&self, uri: &Url) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(entry_uri, _, _), _| entry_uri != uri);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::Cell;
+
+    fn labels(response: CompletionResponse) -> (bool, Vec<String>) {
+        match response {
+            CompletionResponse::List(list) => (
+                list.is_incomplete,
+                list.items.into_iter().map(|item| item.label).collect(),
+            ),
+            CompletionResponse::Array(_) => unreachable!(),
+        }
+    }
+
+    fn candidates() -> Vec<CompletionItem> {
+        vec!["section", "subsection", "subsubsection", "setlength"]
+            .into_iter()
+            .map(|label| CompletionItem::new_simple(label.into(), String::new()))
+            .collect()
+    }
+
+    #[test]
+    fn complete_narrows_cached_items() {
+        let cache = CompletionCache::new(2);
+        let uri = Url::parse("file:///foo.tex").unwrap();
+        let start = Position::new(0, 1);
+        let calls = Cell::new(0);
+        let compute = |_| {
+            calls.set(calls.get() + 1);
+            async { candidates() }
+        };
+
+        let response = block_on(cache.complete(&uri, start, "s", compute));
+        assert_eq!(
+            labels(response),
+            (true, vec!["section".into(), "setlength".into()])
+        );
+
+        let response = block_on(cache.complete(&uri, start, "subs", compute));
+        assert_eq!(
+            labels(response),
+            (false, vec!["subsection".into(), "subsubsection".into()])
+        );
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn complete_recomputes_shorter_prefix() {
+        let cache = CompletionCache::new(10);
+        let uri = Url::parse("file:///foo.tex").unwrap();
+        let start = Position::new(0, 1);
+        let calls = Cell::new(0);
+        let compute = |_| {
+            calls.set(calls.get() + 1);
+            async { candidates() }
+        };
+
+        block_on(cache.complete(&uri, start, "sub", compute));
+        block_on(cache.complete(&uri, start, "s", compute));
+        cache.remove(&uri);
+        block_on(cache.complete(&uri, start, "se", compute));
+        assert_eq!(calls.get(), 3);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{
+    i18n::Catalog,
+    jsonrpc::{Message, Notification, Request, Response},
+    trace::SetTraceParams,
+};
+use lsp_types::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// The lifecycle state of the server as observed by the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerState {
+    /// The `initialize` request has not been received yet or it has failed and may be retried.
+    Uninitialized,
+
+    /// The `initialize` request has been passed to the server and waits for its response.
+    Initializing,
+
+    /// The `initialize` request has failed and the client has not been asked to retry it.
+    Failed,
+
+    /// The server has been initialized and processes requests.
+    Initialized,
+
+    /// The `shutdown` request has been received.
+    ShuttingDown,
+}
+
+/// Information about the session that is tracked by the service.
+///
+/// The context is passed to every middleware hook, so cross-cutting layers can make
+/// capability-aware decisions without caching the state themselves.
+/// Cloned instances share their state.
+#[derive(Debug, Clone)]
+pub struct ServerContext {
+    inner: Arc<RwLock<ContextState>>,
+}
+
+#[derive(Debug)]
+struct ContextState {
+    state: ServerState,
+    client_capabilities: Option<ClientCapabilities>,
+    client_info: Option<ClientInfo>,
+    locale: Option<String>,
+    catalog: Arc<Catalog>,
+    telemetry_enabled: bool,
+    workspace_folders: Vec<WorkspaceFolder>,
+    initialization_options: Option<Value>,
+    language_ids: HashMap<Url, String>,
+    trace: TraceOption,
+}
+
+impl Default for ServerContext {
+    fn default() -> Self {
+        let state = ContextState {
+            state: ServerState::Uninitialized,
+            client_capabilities: None,
+            client_info: None,
+            locale: None,
+            catalog: Arc::default(),
+            telemetry_enabled: true,
+            workspace_folders: Vec::new(),
+            initialization_options: None,
+            language_ids: HashMap::new(),
+            trace: TraceOption::Off,
+        };
+
+        Self {
+            inner: Arc::new(RwLock::new(state)),
+        }
+    }
+}
+
+impl ServerContext {
+    /// Returns the current lifecycle state of the server.
+    pub fn state(&self) -> ServerState {
+        self.inner.read().unwrap().state
+    }
+
+    /// Returns the capabilities that the client has sent with the `initialize` request.
+    pub fn client_capabilities(&self) -> Option<ClientCapabilities> {
+        self.inner.read().unwrap().client_capabilities.clone()
+    }
+
+    /// Returns the name and version of the client, if it has sent them with the `initialize` request.
+    pub fn client_info(&self) -> Option<ClientInfo> {
+        self.inner.read().unwrap().client_info.clone()
+    }
+
+    /// Returns the locale of the client that has been sent with the `initialize` request, e.g. `en-us`.
+    pub fn locale(&self) -> Option<String> {
+        self.inner.read().unwrap().locale.clone()
+    }
+
+    /// Replaces the catalog that translates the messages shown to the user.
+    pub fn set_catalog(&self, catalog: Catalog) {
+        self.inner.write().unwrap().catalog = Arc::new(catalog);
+    }
+
+    /// Formats a message of the catalog in the locale of the client.
+    ///
+    /// See the [`i18n`](i18n/index.html) module for details.
+    pub fn localize(&self, key: &str, args: &[&str]) -> String {
+        let inner = self.inner.read().unwrap();
+        match &inner.locale {
+            Some(locale) => inner.catalog.format(Some(locale), key, args),
+            None => inner.catalog.format(None, key, args),
+        }
+    }
+
+    /// Returns `false` if the user has opted out of telemetry.
+    ///
+    /// The opt-out is read from the `telemetry` field of the initialization options,
+    /// which is either a boolean or an object with an `enabled` field.
+    /// Telemetry is considered enabled if the client does not send the field.
+    pub fn telemetry_enabled(&self) -> bool {
+        self.inner.read().unwrap().telemetry_enabled
+    }
+
+    /// Returns the workspace folders that are currently open in the client.
+    pub fn workspace_folders(&self) -> Vec<WorkspaceFolder> {
+        self.inner.read().unwrap().workspace_folders.clone()
+    }
+
+    /// Parses the initialization options that the client has sent with the `initialize` request.
+    ///
+    /// Missing options are parsed as an empty object.
+    /// See [`InitializationOptions`](struct.InitializationOptions.html) to validate them upfront.
+    pub fn initialization_options<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        let inner = self.inner.read().unwrap();
+        let options = inner
+            .initialization_options
+            .as_ref()
+            .unwrap_or(&Value::Null);
+        T::deserialize(options)
+    }
+
+    /// Returns the language id of an open text document, e.g. `latex`.
+    ///
+    /// The document is known from its `textDocument/didOpen` notification
+    /// until its `textDocument/didClose` notification has been processed.
+    pub fn language_id(&self, uri: &Url) -> Option<String> {
+        self.inner.read().unwrap().language_ids.get(uri).cloned()
+    }
+
+    /// Returns the trace setting of the client.
+    ///
+    /// The setting is sent with the `initialize` request and changed with `$/setTrace` notifications.
+    pub fn trace(&self) -> TraceOption {
+        self.inner.read().unwrap().trace
+    }
+
+    pub(crate) fn on_incoming_message(&self, message: &Message) {
+        match message {
+            Message::Request(request) if request.method == "initialize" => {
+                if let Ok(params) = serde_json::from_value::<InitializeParams>(
+                    request.params.clone().unwrap_or_default(),
+                ) {
+                    let mut inner = self.inner.write().unwrap();
+                    inner.client_capabilities = Some(params.capabilities);
+                    inner.client_info = params.client_info;
+                    inner.locale = request
+                        .params
+                        .as_ref()
+                        .and_then(|params| params.get("locale"))
+                        .and_then(|locale| locale.as_str())
+                        .map(ToOwned::to_owned);
+                    inner.telemetry_enabled =
+                        telemetry_enabled(params.initialization_options.as_ref());
+                    inner.workspace_folders = params.workspace_folders.unwrap_or_default();
+                    inner.initialization_options = params.initialization_options;
+                    inner.trace = params.trace.unwrap_or_default();
+                }
+            }
+            Message::Notification(notification)
+                if notification.method == "workspace/didChangeWorkspaceFolders" =>
+            {
+                if let Ok(params) = serde_json::from_value::<DidChangeWorkspaceFoldersParams>(
+                    notification.params.clone().unwrap_or_default(),
+                ) {
+                    let mut inner = self.inner.write().unwrap();
+                    let removed = params.event.removed;
+                    inner
+                        .workspace_folders
+                        .retain(|folder| !removed.iter().any(|other| other.uri == folder.uri));
+                    inner.workspace_folders.extend(params.event.added);
+                }
+            }
+            Message::Notification(notification)
+                if notification.method == "textDocument/didOpen" =>
+            {
+                if let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(
+                    notification.params.clone().unwrap_or_default(),
+                ) {
+                    let document = params.text_document;
+                    let mut inner = self.inner.write().unwrap();
+                    inner
+                        .language_ids
+                        .insert(document.uri, document.language_id);
+                }
+            }
+            Message::Notification(notification) if notification.method == "$/setTrace" => {
+                if let Ok(params) = serde_json::from_value::<SetTraceParams>(
+                    notification.params.clone().unwrap_or_default(),
+                ) {
+                    self.inner.write().unwrap().trace = params.value;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Forgets the language of a closed document once the server has processed the notification,
+    // so the middlewares and the handler can still look it up.
+    pub(crate) fn on_processed_notification(&self, notification: &Notification) {
+        if notification.method == "textDocument/didClose" {
+            if let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(
+                notification.params.clone().unwrap_or_default(),
+            ) {
+                let uri = params.text_document.uri;
+                self.inner.write().unwrap().language_ids.remove(&uri);
+            }
+        }
+    }
+
+    // Only requests that passed the checks and the middlewares change the state,
+    // so a rejected `shutdown` request does not shut the server down.
+    pub(crate) fn on_accepted_request(&self, request: &Request) {
+        let mut inner = self.inner.write().unwrap();
+        match request.method.as_str() {
+            "initialize" if inner.state == ServerState::Uninitialized => {
+                inner.state = ServerState::Initializing;
+            }
+            "shutdown" => inner.state = ServerState::ShuttingDown,
+            _ => (),
+        }
+    }
+
+    pub(crate) fn on_outgoing_response(&self, request: &Request, response: &Response) {
+        if request.method == "initialize" {
+            let mut inner = self.inner.write().unwrap();
+            inner.state = match (inner.state, &response.error) {
+                (ServerState::Uninitialized, None) | (ServerState::Initializing, None) => {
+                    ServerState::Initialized
+                }
+                // The client only sends the `initialize` request again if the error allows it.
+                (ServerState::Uninitialized, Some(error))
+                | (ServerState::Initializing, Some(error)) => {
+                    let retry = error.data.as_ref().and_then(|data| data.get("retry"));
+                    if retry == Some(&Value::Bool(true)) {
+                        ServerState::Uninitialized
+                    } else {
+                        ServerState::Failed
+                    }
+                }
+                (state, _) => state,
+            };
+        }
+    }
+}
+
+// Checks the initialization options for a telemetry opt-out.
+fn telemetry_enabled(options: Option<&Value>) -> bool {
+    match options.and_then(|options| options.get("telemetry")) {
+        Some(Value::Bool(enabled)) => *enabled,
+        Some(telemetry) => match telemetry.get("enabled") {
+            Some(Value::Bool(enabled)) => *enabled,
+            _ => true,
+        },
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{Error, Id};
+    use serde_json::json;
+
+    fn folder(name: &str) -> WorkspaceFolder {
+        WorkspaceFolder {
+            uri: Url::parse(&format!("file:///{}", name)).unwrap(),
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn initialize_updates_context() {
+        let context = ServerContext::default();
+        let params = json!({
+            "capabilities": { "workspace": { "configuration": true } },
+            "workspaceFolders": [folder("foo")],
+            "clientInfo": { "name": "foo", "version": "1.0" },
+            "locale": "de",
+            "initializationOptions": { "telemetry": { "enabled": false } },
+        });
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        context.on_incoming_message(&Message::Request(request.clone()));
+        context.on_accepted_request(&request);
+        assert_eq!(context.state(), ServerState::Initializing);
+
+        let response = Response::error(Error::initialize_error("foo".into(), true), None);
+        context.on_outgoing_response(&request, &response);
+        assert_eq!(context.state(), ServerState::Uninitialized);
+
+        context.on_accepted_request(&request);
+        let response = Response::error(Error::initialize_error("foo".into(), false), None);
+        context.on_outgoing_response(&request, &response);
+        assert_eq!(context.state(), ServerState::Failed);
+
+        let context = ServerContext::default();
+        context.on_incoming_message(&Message::Request(request.clone()));
+        context.on_accepted_request(&request);
+        let response = Response::result(json!({ "capabilities": {} }), Id::Number(0));
+        context.on_outgoing_response(&request, &response);
+        assert_eq!(context.state(), ServerState::Initialized);
+        assert_eq!(
+            context
+                .client_capabilities()
+                .unwrap()
+                .workspace
+                .unwrap()
+                .configuration,
+            Some(true)
+        );
+        assert_eq!(context.workspace_folders(), vec![folder("foo")]);
+        assert_eq!(context.client_info().unwrap().version, Some("1.0".into()));
+        assert_eq!(context.locale(), Some("de".into()));
+        assert!(!context.telemetry_enabled());
+        let options: Value = context.initialization_options().unwrap();
+        assert_eq!(options["telemetry"]["enabled"], json!(false));
+    }
+
+    #[test]
+    fn workspace_folders_change() {
+        let context = ServerContext::default();
+        context.inner.write().unwrap().workspace_folders = vec![folder("foo"), folder("bar")];
+
+        let params = json!({ "event": { "added": [folder("baz")], "removed": [folder("foo")] } });
+        let notification = Notification::new("workspace/didChangeWorkspaceFolders".into(), params);
+        context.on_incoming_message(&Message::Notification(notification));
+        assert_eq!(
+            context.workspace_folders(),
+            vec![folder("bar"), folder("baz")]
+        );
+    }
+
+    #[test]
+    fn language_ids_of_open_documents() {
+        let context = ServerContext::default();
+        let uri = Url::parse("file:///foo.tex").unwrap();
+        let params = json!({
+            "textDocument": { "uri": uri, "languageId": "latex", "version": 0, "text": "" }
+        });
+        let notification = Notification::new("textDocument/didOpen".into(), params);
+        context.on_incoming_message(&Message::Notification(notification));
+        assert_eq!(context.language_id(&uri), Some("latex".into()));
+
+        let params = json!({ "textDocument": { "uri": uri } });
+        let notification = Notification::new("textDocument/didClose".into(), params);
+        context.on_incoming_message(&Message::Notification(notification.clone()));
+        assert_eq!(context.language_id(&uri), Some("latex".into()));
+        context.on_processed_notification(&notification);
+        assert_eq!(context.language_id(&uri), None);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{Clock, ExecutorMetrics, LanguageClient, SystemClock};
+use futures::{future::poll_fn, task::Poll};
+use lsp_types::{PublishDiagnosticsParams, Url};
+use std::{
+    cmp,
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    task::Waker,
+    time::Duration,
+};
+
+const WINDOW: Duration = Duration::from_millis(100);
+
+// Diagnostics are held back for at most one second, so they are not starved by a long-running request.
+const MAX_DEFERRED_WINDOWS: u32 = 10;
+
+/// The counters of a [`DiagnosticsBatcher`](struct.DiagnosticsBatcher.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiagnosticsCounters {
+    /// The number of notifications that have been sent.
+    pub sent: u64,
+
+    /// The number of batches that have been held back because the service was saturated.
+    pub deferred: u64,
+}
+
+/// Paces the `textDocument/publishDiagnostics` notifications of the server,
+/// so that a large rebuild does not flood the client with thousands of messages at once.
+///
+/// Diagnostics are sent immediately as long as the rate limit is not reached.
+/// Afterwards, they are queued and sent in small batches, where the diagnostics of visible
+/// documents are sent first. Queued diagnostics of a document are replaced by newer ones,
+/// so the client never receives outdated diagnostics. Cloned batchers share their queue.
+///
+/// With [`with_backpressure`](#method.with_backpressure), batches are additionally held back
+/// while the service is saturated, so responses to interactive requests are not stuck behind them.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsBatcher {
+    inner: Arc<Mutex<BatcherState>>,
+    messages_per_second: u32,
+    clock: Arc<dyn Clock>,
+    metrics: Option<ExecutorMetrics>,
+}
+
+#[derive(Debug, Default)]
+struct BatcherState {
+    pending: Vec<PublishDiagnosticsParams>,
+    visible: HashSet<Url>,
+    closed: bool,
+    waker: Option<Waker>,
+    counters: DiagnosticsCounters,
+}
+
+impl DiagnosticsBatcher {
+    /// Creates a batcher that sends at most the given number of notifications per second.
+    pub fn new(messages_per_second: u32) -> Self {
+        Self {
+            inner: Arc::default(),
+            messages_per_second,
+            clock: Arc::new(SystemClock),
+            metrics: None,
+        }
+    }
+
+    /// Sets the clock that paces the notifications.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Holds back the diagnostics while the given metrics of the service report pending requests
+    /// or a backed up output, e.g. on a slow remote connection.
+    pub fn with_backpressure(self, metrics: ExecutorMetrics) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// Returns the number of sent and deferred notifications.
+    pub fn counters(&self) -> DiagnosticsCounters {
+        self.inner.lock().unwrap().counters
+    }
+
+    /// Queues the diagnostics of a document.
+    pub fn publish(&self, params: PublishDiagnosticsParams) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner
+            .pending
+            .iter_mut()
+            .find(|other| other.uri == params.uri)
+        {
+            Some(other) => *other = params,
+            None => inner.pending.push(params),
+        }
+
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Sets the documents that are currently visible in the client, e.g. the open documents.
+    ///
+    /// Their diagnostics take precedence over the diagnostics of other documents.
+    pub fn set_visible<I: IntoIterator<Item = Url>>(&self, uris: I) {
+        self.inner.lock().unwrap().visible = uris.into_iter().collect();
+    }
+
+    /// Returns the number of documents whose diagnostics have not been sent yet.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+
+    /// Stops the batcher once all queued diagnostics have been sent.
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Sends the queued diagnostics to the client until the batcher is closed.
+    pub async fn run(&self, client: Arc<dyn LanguageClient>) {
+        let per_window = cmp::max(
+            1,
+            self.messages_per_second as usize * WINDOW.as_millis() as usize / 1000,
+        );
+
+        let mut window_start = self.clock.now();
+        let mut sent = 0;
+        loop {
+            if sent >= per_window {
+                let elapsed = self.clock.now() - window_start;
+                if elapsed < WINDOW {
+                    self.clock.sleep(WINDOW - elapsed).await;
+                }
+            }
+
+            let finished = poll_fn(|cx| {
+                let mut inner = self.inner.lock().unwrap();
+                if !inner.pending.is_empty() || inner.closed {
+                    Poll::Ready(inner.pending.is_empty())
+                } else {
+                    inner.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+            .await;
+
+            if finished {
+                return;
+            }
+
+            self.wait_for_capacity().await;
+            let now = self.clock.now();
+            if now - window_start >= WINDOW {
+                window_start = now;
+                sent = 0;
+            }
+
+            let batch = self.take(per_window - sent);
+            sent += batch.len();
+            self.inner.lock().unwrap().counters.sent += batch.len() as u64;
+            for params in batch {
+                client.publish_diagnostics(params).await;
+            }
+        }
+    }
+
+    // Waits until the service is no longer saturated or the diagnostics have been held back for too long.
+    async fn wait_for_capacity(&self) {
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics,
+            None => return,
+        };
+
+        let mut windows = 0;
+        while windows < MAX_DEFERRED_WINDOWS && metrics.is_saturated() {
+            self.clock.sleep(WINDOW).await;
+            windows += 1;
+        }
+
+        if windows > 0 {
+            self.inner.lock().unwrap().counters.deferred += 1;
+        }
+    }
+
+    // Removes up to `count` queued diagnostics, starting with the visible documents.
+    fn take(&self, count: usize) -> Vec<PublishDiagnosticsParams> {
+        let mut inner = self.inner.lock().unwrap();
+        let pending = inner.pending.split_off(0);
+        let (mut queue, hidden): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|params| inner.visible.contains(&params.uri));
+        queue.extend(hidden);
+
+        let rest = queue.split_off(cmp::min(count, queue.len()));
+        inner.pending = rest;
+        queue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::LanguageClientImpl, ManualClock, TaskOrigin};
+    use futures::{channel::mpsc, executor::LocalPool, task::LocalSpawnExt, StreamExt};
+
+    fn params(name: &str, version: i64) -> PublishDiagnosticsParams {
+        let uri = Url::parse(&format!("file:///{}", name)).unwrap();
+        PublishDiagnosticsParams::new(uri, Vec::new(), Some(version))
+    }
+
+    #[test]
+    fn visible_documents_first() {
+        let batcher = DiagnosticsBatcher::new(10);
+        batcher.publish(params("foo", 0));
+        batcher.publish(params("bar", 0));
+        batcher.publish(params("baz", 0));
+        batcher.publish(params("foo", 1));
+        batcher.set_visible(vec![params("baz", 0).uri]);
+        assert_eq!(batcher.pending(), 3);
+
+        assert_eq!(batcher.take(2), vec![params("baz", 0), params("foo", 1)]);
+        assert_eq!(batcher.take(2), vec![params("bar", 0)]);
+        assert_eq!(batcher.pending(), 0);
+    }
+
+    #[test]
+    fn defer_while_saturated() {
+        let metrics = ExecutorMetrics::default();
+        let clock = ManualClock::new();
+        let batcher = DiagnosticsBatcher::new(10)
+            .with_clock(Arc::new(clock.clone()))
+            .with_backpressure(metrics.clone());
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let client = Arc::new(LanguageClientImpl::new(tx));
+        let mut pool = LocalPool::new();
+        let runner = batcher.clone();
+        pool.spawner()
+            .spawn_local(async move { runner.run(client).await })
+            .unwrap();
+
+        let request = metrics.track(TaskOrigin::Request, async {});
+        batcher.publish(params("foo", 0));
+        pool.run_until_stalled();
+        assert_eq!(batcher.counters().sent, 0);
+
+        drop(request);
+        clock.advance(WINDOW);
+        pool.run_until_stalled();
+        assert!(pool.run_until(rx.next()).is_some());
+        let counters = batcher.counters();
+        assert_eq!(
+            counters,
+            DiagnosticsCounters {
+                sent: 1,
+                deferred: 1
+            }
+        );
+    }
+}
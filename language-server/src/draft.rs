@@ -0,0 +1,134 @@
+//! Types of the upcoming version of the Language Server Protocol that are not part of `lsp-types` yet.
+//!
+//! These types follow the draft of the specification and may change without further notice.
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use serde_repr::*;
+
+/// Parameters of the `textDocument/inlineCompletion` request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionParams {
+    #[serde(flatten)]
+    pub text_document_position: TextDocumentPositionParams,
+
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+
+    /// Additional information about the context in which inline completions were requested.
+    pub context: InlineCompletionContext,
+}
+
+/// Describes how an inline completion request was triggered.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize_repr, Serialize_repr)]
+#[repr(u8)]
+pub enum InlineCompletionTriggerKind {
+    /// Completion was triggered explicitly by a user gesture.
+    Invoked = 1,
+
+    /// Completion was triggered automatically while editing.
+    Automatic = 2,
+}
+
+/// Provides information about the context in which an inline completion was requested.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionContext {
+    /// Describes how the inline completion was triggered.
+    pub trigger_kind: InlineCompletionTriggerKind,
+
+    /// Provides information about the currently selected item in the autocomplete widget if it is visible.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_completion_info: Option<SelectedCompletionInfo>,
+}
+
+/// Describes the currently selected completion item.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct SelectedCompletionInfo {
+    /// The range that will be replaced if this completion item is accepted.
+    pub range: Range,
+
+    /// The text the range will be replaced with if this completion is accepted.
+    pub text: String,
+}
+
+/// A string value used as a snippet is a template which allows to insert text
+/// and to control the editor cursor when insertion happens.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct StringValue {
+    /// The kind of string value, which is always `snippet`.
+    pub kind: String,
+
+    /// The snippet string.
+    pub value: String,
+}
+
+/// The text that is inserted by an inline completion item.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum InlineCompletionText {
+    String(String),
+    Snippet(StringValue),
+}
+
+/// An inline completion item represents a text snippet that is proposed inline to complete text that is being typed.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionItem {
+    /// The text to replace the range with.
+    pub insert_text: InlineCompletionText,
+
+    /// A text that is used to decide if this inline completion should be shown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_text: Option<String>,
+
+    /// The range to replace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+
+    /// An optional command that is executed after inserting this completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+}
+
+/// Represents a collection of inline completion items to be presented in the editor.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineCompletionList {
+    /// The inline completion items.
+    pub items: Vec<InlineCompletionItem>,
+}
+
+/// The result of the `textDocument/inlineCompletion` request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum InlineCompletionResponse {
+    Array(Vec<InlineCompletionItem>),
+    List(InlineCompletionList),
+}
+
+/// Options to dynamically register the `textDocument/inlineCompletion` request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionRegistrationOptions {
+    #[serde(flatten)]
+    pub text_document_registration_options: TextDocumentRegistrationOptions,
+
+    #[serde(flatten)]
+    pub work_done_progress_options: WorkDoneProgressOptions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_inline_completion_params() {
+        let json = r#"{"textDocument":{"uri":"file:///foo.tex"},"position":{"line":1,"character":2},"context":{"triggerKind":2}}"#;
+        let params: InlineCompletionParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            params.context.trigger_kind,
+            InlineCompletionTriggerKind::Automatic
+        );
+        assert_eq!(params.text_document_position.position, Position::new(1, 2));
+    }
+}
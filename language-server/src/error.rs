@@ -0,0 +1,216 @@
+use crate::jsonrpc::{Error, ErrorCode, Request, Response};
+use serde_json::{json, Value};
+use std::{any::Any, fmt};
+
+/// The reasons why a handler can fail.
+///
+/// A `HandlerError` converts into the matching [`jsonrpc::Error`](jsonrpc/struct.Error.html),
+/// so handlers can use the `?` operator on functions that return a `HandlerError`.
+/// The conversion logs the failure, internal errors with all of their sources.
+#[derive(Debug)]
+pub enum HandlerError {
+    /// The request has been cancelled by the client or the server.
+    Cancelled,
+
+    /// The result would be outdated because the content has changed,
+    /// which tells the client to retry the request.
+    ContentModified,
+
+    /// An unexpected failure of the server.
+    Internal {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// The server does not support the request, e.g. for the given kind of document.
+    Unsupported,
+
+    /// A failure with a custom error code and additional data.
+    Custom {
+        code: ErrorCode,
+        message: String,
+        data: Option<Value>,
+    },
+}
+
+impl HandlerError {
+    /// Wraps an unexpected failure of the server.
+    pub fn internal<E>(source: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::Internal {
+            source: source.into(),
+        }
+    }
+
+    /// Returns the JSON-RPC error code that is sent to the client.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Cancelled => ErrorCode::RequestCancelled,
+            Self::ContentModified => ErrorCode::ContentModified,
+            Self::Internal { .. } => ErrorCode::InternalError,
+            Self::Unsupported => ErrorCode::MethodNotFound,
+            Self::Custom { code, .. } => *code,
+        }
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "the request has been cancelled"),
+            Self::ContentModified => write!(f, "the content has been modified"),
+            Self::Internal { source } => write!(f, "internal error: {}", source),
+            Self::Unsupported => write!(f, "the request is not supported"),
+            Self::Custom { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for HandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Internal { source } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for HandlerError {
+    fn from(error: Error) -> Self {
+        Self::Custom {
+            code: error.code,
+            message: error.message,
+            data: error.data,
+        }
+    }
+}
+
+impl From<HandlerError> for Error {
+    fn from(error: HandlerError) -> Self {
+        match &error {
+            HandlerError::Internal { .. } => {
+                let mut message = error.to_string();
+                let mut source = std::error::Error::source(&error).and_then(|s| s.source());
+                while let Some(cause) = source {
+                    message = format!("{}: {}", message, cause);
+                    source = cause.source();
+                }
+                log::error!("{}", message);
+            }
+            _ => log::debug!("{}", error),
+        };
+
+        let code = error.code();
+        match error {
+            HandlerError::Custom {
+                code,
+                message,
+                data,
+            } => Self {
+                code,
+                message,
+                data,
+            },
+            HandlerError::Unsupported => Self::method_not_found_error(),
+            error => Self {
+                code,
+                message: error.to_string(),
+                data: None,
+            },
+        }
+    }
+}
+
+// The response to a request whose handler has panicked, which contains the panic message.
+pub(crate) fn panicked(request: &Request, payload: Box<dyn Any + Send>) -> Response {
+    let reason = match payload.downcast::<String>() {
+        Ok(reason) => *reason,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(reason) => (*reason).to_owned(),
+            None => "unknown reason".to_owned(),
+        },
+    };
+
+    let message = format!("The handler of {} has panicked: {}", request.method, reason);
+    log::error!("{}", message);
+    Response::error(Error::internal_error(message), Some(request.id.clone()))
+}
+
+/// Converts the errors of arbitrary results into internal errors,
+/// so handlers can use the `?` operator on them.
+///
+/// Any error that converts into `Box<dyn Error + Send + Sync>` is supported,
+/// including `anyhow::Error`. The messages of the error and its sources are sent
+/// in the `chain` field of the error data.
+pub trait HandlerResultExt<T> {
+    /// Maps the error of the result into an `InternalError`.
+    fn map_internal_err(self) -> crate::Result<T>;
+}
+
+impl<T, E> HandlerResultExt<T> for Result<T, E>
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn map_internal_err(self) -> crate::Result<T> {
+        self.map_err(|error| {
+            let error = error.into();
+            let mut chain = vec![error.to_string()];
+            let mut source = error.source();
+            while let Some(cause) = source {
+                chain.push(cause.to_string());
+                source = cause.source();
+            }
+
+            log::error!("{}", chain.join(": "));
+            let mut result = Error::internal_error(error.to_string());
+            result.data = Some(json!({ "chain": chain }));
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(error: HandlerError) -> crate::Result<()> {
+        Err(error)?;
+        Ok(())
+    }
+
+    #[test]
+    fn convert_to_jsonrpc_error() {
+        let error = handle(HandlerError::ContentModified).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ContentModified);
+
+        let error = handle(HandlerError::internal("foo")).unwrap_err();
+        assert_eq!(
+            error,
+            Error::internal_error("internal error: foo".to_owned())
+        );
+
+        let error = handle(HandlerError::Unsupported).unwrap_err();
+        assert_eq!(error, Error::method_not_found_error());
+    }
+
+    #[test]
+    fn map_internal_error_chain() {
+        let error = HandlerError::internal(std::io::Error::from(std::io::ErrorKind::NotFound));
+        let result: Result<(), _> = Err(error);
+        let error = result.map_internal_err().unwrap_err();
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert_eq!(error.message, "internal error: entity not found");
+        assert_eq!(
+            error.data,
+            Some(json!({ "chain": ["internal error: entity not found", "entity not found"] }))
+        );
+    }
+
+    #[test]
+    fn custom_error_roundtrip() {
+        let original = Error::invalid_params("foo".to_owned());
+        let error: Error = HandlerError::from(original.clone()).into();
+        assert_eq!(error, original);
+    }
+}
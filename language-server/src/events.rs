@@ -0,0 +1,129 @@
+use crate::jsonrpc::Notification;
+use futures::{channel::mpsc, Stream};
+use lsp_types::*;
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, Mutex};
+
+/// Provides streams of selected notifications that are sent from the client to the server.
+///
+/// The streams receive the notifications before they are passed to the
+/// [`LanguageServer`](trait.LanguageServer.html), so servers can consume them reactively
+/// instead of overriding the corresponding trait methods.
+/// Cloned instances share their subscriptions.
+#[derive(Debug, Clone, Default)]
+pub struct ClientEvents {
+    configuration: Subscribers<DidChangeConfigurationParams>,
+    watched_files: Subscribers<DidChangeWatchedFilesParams>,
+    workspace_folders: Subscribers<DidChangeWorkspaceFoldersParams>,
+}
+
+impl ClientEvents {
+    /// Returns a stream of `workspace/didChangeConfiguration` notifications.
+    pub fn configuration_changes(&self) -> impl Stream<Item = DidChangeConfigurationParams> {
+        self.configuration.subscribe()
+    }
+
+    /// Returns a stream of `workspace/didChangeWatchedFiles` notifications.
+    pub fn watched_files_changes(&self) -> impl Stream<Item = DidChangeWatchedFilesParams> {
+        self.watched_files.subscribe()
+    }
+
+    /// Returns a stream of `workspace/didChangeWorkspaceFolders` notifications.
+    pub fn workspace_folders_changes(&self) -> impl Stream<Item = DidChangeWorkspaceFoldersParams> {
+        self.workspace_folders.subscribe()
+    }
+
+    pub(crate) fn publish(&self, notification: &Notification) {
+        match notification.method.as_str() {
+            "workspace/didChangeConfiguration" => self.configuration.publish(notification),
+            "workspace/didChangeWatchedFiles" => self.watched_files.publish(notification),
+            "workspace/didChangeWorkspaceFolders" => self.workspace_folders.publish(notification),
+            _ => (),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Subscribers<T> {
+    senders: Arc<Mutex<Vec<mpsc::UnboundedSender<T>>>>,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Self {
+            senders: Arc::default(),
+        }
+    }
+}
+
+impl<T> Clone for Subscribers<T> {
+    fn clone(&self) -> Self {
+        Self {
+            senders: Arc::clone(&self.senders),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Clone> Subscribers<T> {
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<T> {
+        let (tx, rx) = mpsc::unbounded();
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn publish(&self, notification: &Notification) {
+        let mut senders = self.senders.lock().unwrap();
+        if senders.is_empty() {
+            return;
+        }
+
+        match serde_json::from_value::<T>(notification.params.clone().unwrap_or_default()) {
+            Ok(params) => senders.retain(|tx| tx.unbounded_send(params.clone()).is_ok()),
+            Err(why) => log::warn!("{}: {}", notification.method, why),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, StreamExt};
+    use serde_json::json;
+
+    fn notification(method: &str, params: serde_json::Value) -> Notification {
+        Notification::new(method.to_owned(), params)
+    }
+
+    #[test]
+    fn publish_to_subscribers() {
+        let events = ClientEvents::default();
+        let first = events.configuration_changes();
+        let second = events.clone().configuration_changes();
+        let files = events.watched_files_changes();
+
+        events.publish(&notification(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": 42 }),
+        ));
+        drop(events);
+
+        let expected = vec![DidChangeConfigurationParams {
+            settings: json!(42),
+        }];
+        assert_eq!(block_on(first.collect::<Vec<_>>()), expected);
+        assert_eq!(block_on(second.collect::<Vec<_>>()), expected);
+        assert_eq!(block_on(files.collect::<Vec<_>>()), Vec::new());
+    }
+
+    #[test]
+    fn publish_removes_closed_subscribers() {
+        let events = ClientEvents::default();
+        drop(events.configuration_changes());
+
+        events.publish(&notification(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": null }),
+        ));
+        assert!(events.configuration.senders.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    jsonrpc::*,
+    validate::{notification_params_error, params_error},
+    LanguageClient,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use lsp_types::{notification, request};
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+type RequestFn =
+    dyn Fn(Request, Arc<dyn LanguageClient>) -> BoxFuture<'static, Response> + Send + Sync;
+
+type NotificationFn =
+    dyn Fn(Notification, Arc<dyn LanguageClient>) -> BoxFuture<'static, ()> + Send + Sync;
+
+/// A table of additional methods that are dispatched by the service
+/// before the methods of the [`LanguageServer`](trait.LanguageServer.html).
+///
+/// The methods are described by the `Request` and `Notification` traits of `lsp-types`,
+/// so add-on crates can support new versions of the protocol or custom extensions
+/// without changing the `LanguageServer` trait. Every add-on crate provides its own table,
+/// which is registered with [`LanguageServiceBuilder::extensions`](struct.LanguageServiceBuilder.html).
+/// If several tables handle the same method, the table that has been registered first wins.
+#[derive(Clone, Default)]
+pub struct MethodTable {
+    requests: HashMap<String, Arc<RequestFn>>,
+    notifications: HashMap<String, Arc<NotificationFn>>,
+}
+
+impl MethodTable {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles the requests of the given type with the given function.
+    pub fn with_request<R, F, T>(mut self, handler: F) -> Self
+    where
+        R: request::Request,
+        F: Fn(R::Params, Arc<dyn LanguageClient>) -> T + Send + Sync + 'static,
+        T: Future<Output = Result<R::Result>> + Send + 'static,
+    {
+        let handler = move |request: Request, client| {
+            let id = request.id;
+            match serde_json::from_value(request.params.unwrap_or_default()) {
+                Ok(params) => handler(params, client)
+                    .map(move |result| match result {
+                        Ok(result) => Response::result(serde_json::json!(result), id),
+                        Err(error) => Response::error(error, Some(id)),
+                    })
+                    .boxed(),
+                Err(why) => {
+                    let response = Response::error(params_error(R::METHOD, why), Some(id));
+                    future::ready(response).boxed()
+                }
+            }
+        };
+
+        self.requests
+            .insert(R::METHOD.to_owned(), Arc::new(handler));
+        self
+    }
+
+    /// Handles the notifications of the given type with the given function.
+    pub fn with_notification<N, F, T>(mut self, handler: F) -> Self
+    where
+        N: notification::Notification,
+        F: Fn(N::Params, Arc<dyn LanguageClient>) -> T + Send + Sync + 'static,
+        T: Future<Output = ()> + Send + 'static,
+    {
+        let handler = move |notification: Notification, client| match serde_json::from_value(
+            notification.params.unwrap_or_default(),
+        ) {
+            Ok(params) => handler(params, client).boxed(),
+            Err(why) => {
+                notification_params_error(N::METHOD, why);
+                future::ready(()).boxed()
+            }
+        };
+
+        self.notifications
+            .insert(N::METHOD.to_owned(), Arc::new(handler));
+        self
+    }
+
+    /// Returns the methods of the table in alphabetical order.
+    pub fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<_> = self
+            .requests
+            .keys()
+            .chain(self.notifications.keys())
+            .map(String::as_str)
+            .collect();
+        methods.sort();
+        methods
+    }
+
+    // Combines the tables, where the methods of earlier tables take precedence.
+    pub(crate) fn merge(tables: Vec<MethodTable>) -> Self {
+        let mut merged = Self::new();
+        for table in tables.into_iter().rev() {
+            merged.requests.extend(table.requests);
+            merged.notifications.extend(table.notifications);
+        }
+        merged
+    }
+
+    // Handles the request if the table contains its method.
+    pub(crate) fn handle_request(
+        &self,
+        request: Request,
+        client: Arc<dyn LanguageClient>,
+    ) -> std::result::Result<BoxFuture<'static, Response>, Request> {
+        match self.requests.get(&request.method) {
+            Some(handler) => Ok(handler(request, client)),
+            None => Err(request),
+        }
+    }
+
+    // Handles the notification if the table contains its method.
+    pub(crate) fn handle_notification(
+        &self,
+        notification: Notification,
+        client: Arc<dyn LanguageClient>,
+    ) -> std::result::Result<BoxFuture<'static, ()>, Notification> {
+        match self.notifications.get(&notification.method) {
+            Some(handler) => Ok(handler(notification, client)),
+            None => Err(notification),
+        }
+    }
+}
+
+impl std::fmt::Debug for MethodTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MethodTable")
+            .field("methods", &self.methods())
+            .finish()
+    }
+}
@@ -0,0 +1,473 @@
+use crate::{jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext};
+use async_trait::async_trait;
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A pattern kind describing if a glob pattern matches a file, a folder, or both.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileOperationPatternKind {
+    /// The pattern matches a file only.
+    File,
+
+    /// The pattern matches a folder only.
+    Folder,
+}
+
+/// Matching options for the file operation pattern.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationPatternOptions {
+    /// The pattern should be matched ignoring casing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_case: Option<bool>,
+}
+
+/// A pattern to describe in which file operation requests or notifications the server is interested in.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationPattern {
+    /// The glob pattern to match, e.g. `**/*.tex`.
+    pub glob: String,
+
+    /// Whether to match files or folders with this pattern. Matches both if undefined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<FileOperationPatternKind>,
+
+    /// Additional options used during matching.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<FileOperationPatternOptions>,
+}
+
+/// A filter to describe in which file operation requests or notifications the server is interested in.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationFilter {
+    /// A URI scheme like `file` or `untitled`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+
+    /// The actual file operation pattern.
+    pub pattern: FileOperationPattern,
+}
+
+/// The options to register for file operations.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationRegistrationOptions {
+    /// The actual filters.
+    pub filters: Vec<FileOperationFilter>,
+}
+
+/// Represents information on a file or folder rename.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRename {
+    /// A URI for the original location of the file or folder being renamed.
+    pub old_uri: String,
+
+    /// A URI for the new location of the file or folder being renamed.
+    pub new_uri: String,
+}
+
+/// The parameters sent in notifications or requests for user-initiated renames of files.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameFilesParams {
+    /// An array of all files or folders renamed in this operation.
+    /// When a folder is renamed, only the folder will be included, and not its children.
+    pub files: Vec<FileRename>,
+}
+
+/// A compiled glob pattern as used by the file operation filters.
+///
+/// The following syntax is supported:
+///
+/// - `*` matches zero or more characters in a path segment.
+/// - `?` matches one character in a path segment.
+/// - `**` matches any number of path segments, including none.
+/// - `{}` groups alternatives, e.g. `**/*.{tex,bib}`.
+/// - `[]` matches a range of characters, e.g. `[0-9]`, and `[!...]` negates the range.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    alternatives: Vec<Vec<Token>>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    GlobStar,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    /// Compiles the given pattern.
+    pub fn new(pattern: &str, ignore_case: bool) -> Self {
+        let pattern = if ignore_case {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_owned()
+        };
+
+        let alternatives = expand_braces(&pattern)
+            .iter()
+            .map(|pattern| tokenize(pattern))
+            .collect();
+
+        Self {
+            alternatives,
+            ignore_case,
+        }
+    }
+
+    /// Returns `true` if the path matches the pattern.
+    pub fn is_match(&self, path: &str) -> bool {
+        let path: Vec<char> = if self.ignore_case {
+            path.to_lowercase().chars().collect()
+        } else {
+            path.chars().collect()
+        };
+
+        self.alternatives
+            .iter()
+            .any(|tokens| match_tokens(tokens, &path))
+    }
+}
+
+// Expands the groups of a pattern into separate patterns, e.g. `*.{a,b}` into `*.a` and `*.b`.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let start = match pattern.find('{') {
+        Some(start) => start,
+        None => return vec![pattern.to_owned()],
+    };
+
+    let mut depth = 0;
+    let mut options = Vec::new();
+    let mut option_start = start + 1;
+    for (index, c) in pattern[start..].char_indices().map(|(i, c)| (start + i, c)) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    options.push(&pattern[option_start..index]);
+                    let rest = &pattern[index + 1..];
+                    return options
+                        .into_iter()
+                        .flat_map(|option| {
+                            expand_braces(&format!("{}{}{}", &pattern[..start], option, rest))
+                        })
+                        .collect();
+                }
+            }
+            ',' if depth == 1 => {
+                options.push(&pattern[option_start..index]);
+                option_start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    vec![pattern.to_owned()]
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                tokens.push(Token::GlobStar);
+                i += 2;
+                // `**/` also matches zero segments.
+                if chars.get(i) == Some(&'/') {
+                    i += 1;
+                }
+            }
+            '*' => {
+                tokens.push(Token::Star);
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '[' => match chars[i + 1..].iter().position(|c| *c == ']') {
+                Some(length) => {
+                    let class = &chars[i + 1..i + 1 + length];
+                    let negated = class.first() == Some(&'!');
+                    let class = if negated { &class[1..] } else { class };
+                    let mut ranges = Vec::new();
+                    let mut j = 0;
+                    while j < class.len() {
+                        if j + 2 < class.len() && class[j + 1] == '-' {
+                            ranges.push((class[j], class[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((class[j], class[j]));
+                            j += 1;
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    i += length + 2;
+                }
+                None => {
+                    tokens.push(Token::Char('['));
+                    i += 1;
+                }
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+fn match_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return path.is_empty(),
+    };
+
+    match token {
+        Token::Char(c) => path.first() == Some(c) && match_tokens(rest, &path[1..]),
+        Token::Any => match path.first() {
+            Some(c) if *c != '/' => match_tokens(rest, &path[1..]),
+            _ => false,
+        },
+        Token::Class { negated, ranges } => match path.first() {
+            Some(c) if *c != '/' => {
+                let contained = ranges.iter().any(|(low, high)| low <= c && c <= high);
+                contained != *negated && match_tokens(rest, &path[1..])
+            }
+            _ => false,
+        },
+        Token::Star => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != '/')
+            .any(|i| match_tokens(rest, &path[i..])),
+        Token::GlobStar => (0..=path.len())
+            .filter(|i| *i == 0 || *i == path.len() || path[i - 1] == '/')
+            .any(|i| match_tokens(rest, &path[i..])),
+    }
+}
+
+/// Matches URIs against the file operation filters that have been registered by the server.
+#[derive(Debug, Clone)]
+pub struct FileOperationMatcher {
+    filters: Vec<(Option<String>, Glob, Option<FileOperationPatternKind>)>,
+}
+
+impl FileOperationMatcher {
+    /// Compiles the patterns of the given filters.
+    pub fn new(filters: &[FileOperationFilter]) -> Self {
+        let filters = filters
+            .iter()
+            .map(|filter| {
+                let ignore_case = filter
+                    .pattern
+                    .options
+                    .as_ref()
+                    .and_then(|options| options.ignore_case)
+                    .unwrap_or(false);
+                let glob = Glob::new(&filter.pattern.glob, ignore_case);
+                (filter.scheme.clone(), glob, filter.pattern.matches)
+            })
+            .collect();
+
+        Self { filters }
+    }
+
+    /// Returns `true` if the URI matches one of the filters.
+    ///
+    /// The kind is only checked if it is known, because the file may not exist anymore.
+    pub fn is_match(&self, uri: &Url, kind: Option<FileOperationPatternKind>) -> bool {
+        let path = match uri.to_file_path() {
+            Ok(path) => path.to_string_lossy().replace('\\', "/"),
+            Err(()) => uri.path().to_owned(),
+        };
+
+        self.filters.iter().any(|(scheme, glob, matches)| {
+            let scheme_matches = match scheme {
+                Some(scheme) => scheme == uri.scheme(),
+                None => true,
+            };
+
+            scheme_matches
+                && (matches.is_none() || kind.is_none() || *matches == kind)
+                && glob.is_match(&path)
+        })
+    }
+
+    /// Removes the renames from the parameters whose old URI does not match any filter.
+    pub fn filter_renames(&self, params: &mut RenameFilesParams) {
+        params.files.retain(|file| {
+            let uri = match Url::parse(&file.old_uri) {
+                Ok(uri) => uri,
+                Err(_) => return false,
+            };
+
+            let kind = [&file.old_uri, &file.new_uri]
+                .iter()
+                .filter_map(|uri| Url::parse(uri).ok()?.to_file_path().ok())
+                .find(|path| path.exists())
+                .map(|path| {
+                    if path.is_dir() {
+                        FileOperationPatternKind::Folder
+                    } else {
+                        FileOperationPatternKind::File
+                    }
+                });
+
+            self.is_match(&uri, kind)
+        });
+    }
+}
+
+/// Middleware that removes the files from `workspace/willRenameFiles` and `workspace/didRenameFiles`
+/// that do not match the filters that the server has registered for these messages.
+///
+/// Messages whose files have all been removed reach the handlers with an empty list.
+pub struct FileOperationMiddleware {
+    matcher: FileOperationMatcher,
+}
+
+impl FileOperationMiddleware {
+    /// Creates a middleware that applies the given filters.
+    pub fn new(filters: &[FileOperationFilter]) -> Self {
+        Self {
+            matcher: FileOperationMatcher::new(filters),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for FileOperationMiddleware {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        let (method, params) = match message {
+            Message::Request(request) => (request.method.as_str(), &mut request.params),
+            Message::Notification(notification) => {
+                (notification.method.as_str(), &mut notification.params)
+            }
+            Message::Response(_) => return MessageFlow::Continue,
+        };
+
+        if method != "workspace/willRenameFiles" && method != "workspace/didRenameFiles" {
+            return MessageFlow::Continue;
+        }
+
+        if let Some(params) = params {
+            if let Ok(mut rename_params) = serde_json::from_value(params.clone()) {
+                self.matcher.filter_renames(&mut rename_params);
+                *params = serde_json::to_value(rename_params).unwrap();
+            }
+        }
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        _request: &Request,
+        _metadata: &MessageMetadata,
+        _response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_syntax() {
+        let glob = Glob::new("**/*.{tex,bib}", false);
+        assert!(glob.is_match("/foo/bar.tex"));
+        assert!(glob.is_match("bar.bib"));
+        assert!(!glob.is_match("/foo/bar.sty"));
+
+        let glob = Glob::new("/foo/**/chapter[0-9].tex", false);
+        assert!(glob.is_match("/foo/chapter1.tex"));
+        assert!(glob.is_match("/foo/bar/baz/chapter2.tex"));
+        assert!(!glob.is_match("/foo/chapterA.tex"));
+
+        let glob = Glob::new("/foo/*.t?x", true);
+        assert!(glob.is_match("/FOO/bar.TEX"));
+        assert!(!glob.is_match("/foo/bar/baz.tex"));
+
+        let glob = Glob::new("/foo/[!a]*", false);
+        assert!(glob.is_match("/foo/bar"));
+        assert!(!glob.is_match("/foo/abc"));
+    }
+
+    #[test]
+    fn filter_renames() {
+        let filter = |glob: &str, scheme: Option<&str>| FileOperationFilter {
+            scheme: scheme.map(ToOwned::to_owned),
+            pattern: FileOperationPattern {
+                glob: glob.to_owned(),
+                matches: Some(FileOperationPatternKind::File),
+                options: Some(FileOperationPatternOptions {
+                    ignore_case: Some(true),
+                }),
+            },
+        };
+        let matcher = FileOperationMatcher::new(&[
+            filter("**/*.tex", Some("file")),
+            filter("**/*.bib", Some("untitled")),
+        ]);
+
+        let rename = |uri: &str| FileRename {
+            old_uri: uri.to_owned(),
+            new_uri: format!("{}.new", uri),
+        };
+        let mut params = RenameFilesParams {
+            files: vec![
+                rename("file:///foo/BAR.TEX"),
+                rename("file:///foo/bar.bib"),
+                rename("untitled:/foo/bar.bib"),
+            ],
+        };
+
+        matcher.filter_renames(&mut params);
+        assert_eq!(
+            params.files,
+            vec![
+                rename("file:///foo/BAR.TEX"),
+                rename("untitled:/foo/bar.bib")
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Fuzzy matching of queries against symbol names, as done by editors when filtering a list.
+//!
+//! The matcher can be used to filter and rank the results of `workspace/symbol` and
+//! `textDocument/completion` requests. Characters of the query need to appear in the same order
+//! in the candidate, but not necessarily next to each other. Matches at the start of a word
+//! or a camel hump (`fooBar`, `foo_bar`, `\foo`) are preferred.
+//!
+//! # Example
+//!
+//! ```
+//! use language_server::fuzzy;
+//!
+//! let symbols = vec!["newcommand", "renewcommand", "NewDocumentCommand"];
+//! let ranked = fuzzy::rank("ndc", symbols, |symbol| symbol);
+//! assert_eq!(ranked[0].0, "NewDocumentCommand");
+//! ```
+
+const MATCH_SCORE: i64 = 1;
+const CASE_SCORE: i64 = 1;
+const BOUNDARY_SCORE: i64 = 8;
+const CONSECUTIVE_SCORE: i64 = 5;
+const GAP_PENALTY: i64 = 3;
+const MAX_LEADING_PENALTY: i64 = 5;
+
+/// The result of a successful fuzzy match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// The score of the match. Higher scores are better matches.
+    pub score: i64,
+
+    /// The indices of the matched characters within the candidate (counted in chars).
+    pub indices: Vec<usize>,
+}
+
+/// Matches the query against the candidate ignoring case.
+///
+/// Returns `None` if the characters of the query do not appear in the candidate in the same order.
+/// An empty query matches every candidate with a score of zero.
+pub fn score(query: &str, candidate: &str) -> Option<Match> {
+    let query: Vec<char> = query.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    if query.is_empty() {
+        return Some(Match {
+            score: 0,
+            indices: Vec::new(),
+        });
+    }
+
+    if query.len() > candidate.len() {
+        return None;
+    }
+
+    // scores[i][j] contains the best score for matching query[..=i]
+    // where query[i] is matched with candidate[j].
+    let width = candidate.len();
+    let mut scores: Vec<Option<i64>> = vec![None; query.len() * width];
+    let mut parents: Vec<usize> = vec![0; query.len() * width];
+    for (i, &q) in query.iter().enumerate() {
+        // The best score of the previous row that ends at least two characters before `j`.
+        let mut best_gap: Option<(i64, usize)> = None;
+        for (j, &c) in candidate.iter().enumerate().skip(i) {
+            if i > 0 && j >= 2 {
+                if let Some(previous) = scores[(i - 1) * width + j - 2] {
+                    match best_gap {
+                        Some((best, _)) if best >= previous => (),
+                        _ => best_gap = Some((previous, j - 2)),
+                    }
+                }
+            }
+
+            if !eq_ignore_case(q, c) {
+                continue;
+            }
+
+            let mut char_score = MATCH_SCORE;
+            if q == c {
+                char_score += CASE_SCORE;
+            }
+            if is_boundary(&candidate, j) {
+                char_score += BOUNDARY_SCORE;
+            }
+
+            let index = i * width + j;
+            if i == 0 {
+                scores[index] = Some(char_score - (j as i64).min(MAX_LEADING_PENALTY));
+                continue;
+            }
+
+            let consecutive = scores[(i - 1) * width + j - 1]
+                .map(|previous| (previous + CONSECUTIVE_SCORE, j - 1));
+            let gap = best_gap.map(|(previous, k)| (previous - GAP_PENALTY, k));
+            let best = match (consecutive, gap) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+
+            if let Some((previous, parent)) = best {
+                scores[index] = Some(previous + char_score);
+                parents[index] = parent;
+            }
+        }
+    }
+
+    let last = query.len() - 1;
+    let (score, mut j) = (0..width)
+        .filter_map(|j| scores[last * width + j].map(|score| (score, j)))
+        .fold(None, |best: Option<(i64, usize)>, (score, j)| match best {
+            Some((best_score, _)) if best_score >= score => best,
+            _ => Some((score, j)),
+        })?;
+
+    let mut indices = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        indices[i] = j;
+        j = parents[i * width + j];
+    }
+
+    Some(Match { score, indices })
+}
+
+/// Filters the items by the query and sorts them by their score.
+///
+/// Items with the same score are ordered by the length of their key and then alphabetically.
+pub fn rank<T, I, F>(query: &str, items: I, key: F) -> Vec<(T, Match)>
+where
+    I: IntoIterator<Item = T>,
+    F: Fn(&T) -> &str,
+{
+    let mut ranked: Vec<_> = items
+        .into_iter()
+        .filter_map(|item| score(query, key(&item)).map(|result| (item, result)))
+        .collect();
+
+    ranked.sort_by(|(a, a_match), (b, b_match)| {
+        b_match
+            .score
+            .cmp(&a_match.score)
+            .then_with(|| key(a).len().cmp(&key(b).len()))
+            .then_with(|| key(a).cmp(key(b)))
+    });
+    ranked
+}
+
+/// Returns a `sortText` for the item at the given position of a ranked list.
+///
+/// The texts are padded with zeros, so they preserve the order of the ranking when compared as strings.
+pub fn sort_text(position: usize) -> String {
+    format!("{:08}", position)
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+fn is_boundary(text: &[char], index: usize) -> bool {
+    if index == 0 {
+        return true;
+    }
+
+    let previous = text[index - 1];
+    let current = text[index];
+    !previous.is_alphanumeric() && current.is_alphanumeric()
+        || previous.is_lowercase() && current.is_uppercase()
+        || !previous.is_numeric() && current.is_numeric()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_no_match() {
+        assert_eq!(score("bar", "foo"), None);
+        assert_eq!(score("oof", "foo"), None);
+        assert_eq!(score("foobar", "foo"), None);
+    }
+
+    #[test]
+    fn score_empty_query() {
+        assert_eq!(
+            score("", "foo"),
+            Some(Match {
+                score: 0,
+                indices: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn score_prefers_word_boundaries() {
+        assert_eq!(score("fb", "fooBar").unwrap().indices, vec![0, 3]);
+        assert_eq!(score("fb", "foo_bar").unwrap().indices, vec![0, 4]);
+        assert_eq!(score("ba", "abba_bar").unwrap().indices, vec![5, 6]);
+        assert!(score("fb", "fooBar").unwrap().score > score("fb", "fabric").unwrap().score);
+    }
+
+    #[test]
+    fn score_prefers_consecutive_matches() {
+        assert!(score("foo", "foobar").unwrap().score > score("foo", "frodo").unwrap().score);
+    }
+
+    #[test]
+    fn rank_sorts_by_score() {
+        let items = vec![
+            "renewcommand",
+            "NewDocumentCommand",
+            "newcommand",
+            "section",
+        ];
+        let ranked: Vec<_> = rank("newc", items, |item| item)
+            .into_iter()
+            .map(|(item, _)| item)
+            .collect();
+        assert_eq!(
+            ranked,
+            vec!["newcommand", "NewDocumentCommand", "renewcommand"]
+        );
+    }
+
+    #[test]
+    fn sort_text_preserves_order() {
+        assert!(sort_text(9) < sort_text(10));
+        assert_eq!(sort_text(42), "00000042");
+    }
+}
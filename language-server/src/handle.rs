@@ -0,0 +1,158 @@
+use crate::cancellation::CancellationToken;
+use futures::task::{Context, Poll};
+use std::{fmt, future::Future, io, pin::Pin};
+
+/// The reason why a [`LanguageService`](struct.LanguageService.html) stopped processing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The input stream has been closed by the client.
+    Disconnected,
+
+    /// The service has been stopped gracefully using [`ServiceController::stop`](struct.ServiceController.html#method.stop).
+    Stopped,
+
+    /// The service has been aborted using [`ServiceController::abort`](struct.ServiceController.html#method.abort).
+    Aborted,
+
+    /// The parent process of the server has exited while the service was watching it.
+    ParentExited,
+
+    /// The client has sent the `exit` notification.
+    Exited {
+        /// Whether the client has sent the `shutdown` request before.
+        after_shutdown: bool,
+    },
+}
+
+impl ExitReason {
+    /// Returns the exit code of the server process.
+    ///
+    /// As required by the protocol, the code is `0` if the client has sent the `exit` notification
+    /// after the `shutdown` request. A service that has been stopped gracefully also exits with `0`,
+    /// whereas all other reasons exit with `1`.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Exited {
+                after_shutdown: true,
+            }
+            | Self::Stopped => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// An error that has stopped a [`LanguageService`](struct.LanguageService.html)
+/// or a [`JsonRpcService`](struct.JsonRpcService.html).
+#[derive(Debug)]
+pub enum ServiceError {
+    /// Reading from the input stream has failed, e.g. because a header is malformed.
+    Input(io::Error),
+
+    /// Writing to the output stream has failed, e.g. because the client has closed it.
+    Output(io::Error),
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input(why) => write!(f, "failed to read from the input stream: {}", why),
+            Self::Output(why) => write!(f, "failed to write to the output stream: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Input(why) | Self::Output(why) => Some(why),
+        }
+    }
+}
+
+/// Allows to stop a running [`LanguageService`](struct.LanguageService.html) from the outside.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceController {
+    pub(crate) stop_token: CancellationToken,
+    pub(crate) abort_token: CancellationToken,
+}
+
+impl ServiceController {
+    /// Stops reading new messages from the input stream.
+    ///
+    /// The service finishes once all pending requests have been answered
+    /// and all queued messages have been written to the output.
+    pub fn stop(&self) {
+        self.stop_token.cancel();
+    }
+
+    /// Stops the service immediately without waiting for pending requests.
+    pub fn abort(&self) {
+        self.abort_token.cancel();
+    }
+}
+
+/// A running [`LanguageService`](struct.LanguageService.html).
+///
+/// The handle is a future that needs to be polled in order to process messages.
+/// It completes with the reason why the service has stopped
+/// or with the error that has stopped it.
+pub struct ServiceHandle<F> {
+    future: Pin<Box<F>>,
+    controller: ServiceController,
+}
+
+impl<F> ServiceHandle<F>
+where
+    F: Future<Output = Result<ExitReason, ServiceError>>,
+{
+    pub(crate) fn new(future: F, controller: ServiceController) -> Self {
+        Self {
+            future: Box::pin(future),
+            controller,
+        }
+    }
+
+    /// Returns a controller that can be used to stop the service while the handle is being awaited.
+    pub fn controller(&self) -> ServiceController {
+        self.controller.clone()
+    }
+
+    /// Stops the service gracefully.
+    ///
+    /// See [`ServiceController::stop`](struct.ServiceController.html#method.stop).
+    pub fn stop(&self) {
+        self.controller.stop();
+    }
+
+    /// Stops the service immediately.
+    ///
+    /// See [`ServiceController::abort`](struct.ServiceController.html#method.abort).
+    pub fn abort(&self) {
+        self.controller.abort();
+    }
+
+    /// Stops the service gracefully and waits until it has finished.
+    ///
+    /// The service stops reading messages, answers the pending requests,
+    /// writes the queued messages and waits for the tasks that it has spawned.
+    pub async fn shutdown(self) -> Result<ExitReason, ServiceError> {
+        self.stop();
+        self.await
+    }
+
+    /// Waits until the service has stopped processing messages.
+    pub async fn join(self) -> Result<ExitReason, ServiceError> {
+        self.await
+    }
+}
+
+impl<F> Future for ServiceHandle<F>
+where
+    F: Future<Output = Result<ExitReason, ServiceError>>,
+{
+    type Output = Result<ExitReason, ServiceError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,191 @@
+//! Translations of the messages that this crate shows to the user.
+//!
+//! The messages are looked up by the locale that the client has sent with the `initialize` request,
+//! falling back to the language without region (`de-ch` → `de`) and finally to English.
+//! Placeholders of the form `{0}`, `{1}`, ... are replaced by the arguments of the message.
+//! A catalog is attached to a session with [`ServerContext::set_catalog`](../struct.ServerContext.html#method.set_catalog).
+//!
+//! # Example
+//!
+//! ```
+//! use language_server::i18n::{self, Catalog};
+//!
+//! let catalog = Catalog::new().with_translation(
+//!     "de",
+//!     i18n::TRUST_DISABLED,
+//!     "{0} ist deaktiviert, weil dem Arbeitsbereich nicht vertraut wird",
+//! );
+//!
+//! let message = catalog.format(Some("de-ch"), i18n::TRUST_DISABLED, &["textDocument/hover"]);
+//! assert_eq!(
+//!     message,
+//!     "textDocument/hover ist deaktiviert, weil dem Arbeitsbereich nicht vertraut wird"
+//! );
+//! ```
+
+use std::collections::HashMap;
+
+const DEFAULT_LOCALE: &str = "en";
+
+/// A request has been rejected by the `TrustMiddleware`. Arguments: method.
+pub const TRUST_DISABLED: &str = "trust.disabled";
+
+/// A request has been rejected during the warm-up. Arguments: method.
+pub const WARM_UP_REJECTED: &str = "warmup.rejected";
+
+/// A request has been shed because the server is low on memory. Arguments: method.
+pub const MEMORY_SHED: &str = "memory.shed";
+
+/// A request has been received before the `initialize` request. Arguments: method.
+pub const LIFECYCLE_NOT_INITIALIZED: &str = "lifecycle.not_initialized";
+
+/// The `initialize` request has been received while the first one is being processed.
+pub const LIFECYCLE_INITIALIZING: &str = "lifecycle.initializing";
+
+/// The `initialize` request has been received after a failed one that may not be retried.
+pub const LIFECYCLE_INITIALIZE_FAILED: &str = "lifecycle.initialize_failed";
+
+/// The `initialize` request has been received again.
+pub const LIFECYCLE_ALREADY_INITIALIZED: &str = "lifecycle.already_initialized";
+
+/// A request has been received after the `shutdown` request. Arguments: method.
+pub const LIFECYCLE_SHUTTING_DOWN: &str = "lifecycle.shutting_down";
+
+/// A supervised process has exited. Arguments: name, exit code.
+pub const SUPERVISOR_EXITED: &str = "supervisor.exited";
+
+/// A supervised process has crashed. Arguments: name.
+pub const SUPERVISOR_CRASHED: &str = "supervisor.crashed";
+
+/// A supervisor has stopped restarting a process. Arguments: name.
+pub const SUPERVISOR_GIVEN_UP: &str = "supervisor.given_up";
+
+/// The marker of the symbols that have been removed by the `ResponseSizeMiddleware`.
+/// Arguments: number of symbols.
+pub const OMITTED_SYMBOLS: &str = "size.omitted_symbols";
+
+const DEFAULT_MESSAGES: &[(&str, &str)] = &[
+    (
+        TRUST_DISABLED,
+        "{0} is disabled because the workspace is not trusted",
+    ),
+    (
+        WARM_UP_REJECTED,
+        "{0} is not available until the server has finished warming up",
+    ),
+    (
+        MEMORY_SHED,
+        "{0} has been skipped because the server is low on memory",
+    ),
+    (
+        LIFECYCLE_NOT_INITIALIZED,
+        "{0} has been received before the server has been initialized",
+    ),
+    (
+        LIFECYCLE_INITIALIZING,
+        "The server is already being initialized",
+    ),
+    (
+        LIFECYCLE_INITIALIZE_FAILED,
+        "The server could not be initialized",
+    ),
+    (
+        LIFECYCLE_ALREADY_INITIALIZED,
+        "The server has already been initialized",
+    ),
+    (
+        LIFECYCLE_SHUTTING_DOWN,
+        "{0} has been received after the server has been shut down",
+    ),
+    (SUPERVISOR_EXITED, "{0} has exited with code {1}"),
+    (SUPERVISOR_CRASHED, "{0} has crashed"),
+    (
+        SUPERVISOR_GIVEN_UP,
+        "{0} has crashed too often and will not be restarted",
+    ),
+    (OMITTED_SYMBOLS, "... {0} more symbols"),
+];
+
+/// A set of message templates per locale.
+///
+/// The catalog contains the English messages of this crate by default
+/// and can be extended with translations and custom messages of the server.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        let messages = DEFAULT_MESSAGES
+            .iter()
+            .map(|(key, template)| ((*key).to_owned(), (*template).to_owned()))
+            .collect();
+
+        let mut locales = HashMap::new();
+        locales.insert(DEFAULT_LOCALE.to_owned(), messages);
+        Self { messages: locales }
+    }
+}
+
+impl Catalog {
+    /// Creates a catalog with the English messages of this crate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the template of a message for the given locale.
+    pub fn with_translation(mut self, locale: &str, key: &str, template: &str) -> Self {
+        self.messages
+            .entry(locale.to_lowercase())
+            .or_default()
+            .insert(key.to_owned(), template.to_owned());
+        self
+    }
+
+    /// Returns the template of a message for the given locale.
+    pub fn template(&self, locale: Option<&str>, key: &str) -> Option<&str> {
+        let locale = locale.unwrap_or(DEFAULT_LOCALE).to_lowercase();
+        let language = locale.split(&['-', '_'][..]).next().unwrap();
+        [locale.as_str(), language, DEFAULT_LOCALE]
+            .iter()
+            .filter_map(|locale| self.messages.get(*locale)?.get(key))
+            .map(String::as_str)
+            .next()
+    }
+
+    /// Formats a message for the given locale.
+    ///
+    /// Unknown keys are returned unchanged, so a missing message never hides information.
+    pub fn format(&self, locale: Option<&str>, key: &str, args: &[&str]) -> String {
+        let template = self.template(locale, key).unwrap_or(key);
+        args.iter()
+            .enumerate()
+            .fold(template.to_owned(), |message, (index, arg)| {
+                message.replace(&format!("{{{}}}", index), arg)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_fallback() {
+        let catalog = Catalog::new()
+            .with_translation("de", SUPERVISOR_CRASHED, "{0} ist abgestürzt")
+            .with_translation("de-AT", SUPERVISOR_CRASHED, "{0} is hin");
+
+        let format = |locale| catalog.format(locale, SUPERVISOR_CRASHED, &["foo"]);
+        assert_eq!(format(Some("de-at")), "foo is hin");
+        assert_eq!(format(Some("de_CH")), "foo ist abgestürzt");
+        assert_eq!(format(Some("fr")), "foo has crashed");
+        assert_eq!(format(None), "foo has crashed");
+        assert_eq!(catalog.format(None, "bar", &[]), "bar");
+        assert_eq!(
+            catalog.format(None, SUPERVISOR_EXITED, &["foo", "1"]),
+            "foo has exited with code 1"
+        );
+    }
+}
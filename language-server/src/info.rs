@@ -0,0 +1,61 @@
+use crate::Framing;
+use serde::Serialize;
+
+/// The version of the Language Server Protocol that is supported by this crate.
+pub const PROTOCOL_VERSION: &str = "3.15";
+
+/// Describes the build of this crate and the transport of the service,
+/// so bug reports from editor users contain the details that are needed to reproduce them.
+///
+/// It is logged on start and returned by the custom `$/serverInfo` request
+/// if the service has been built with `build_info`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// The version of this crate.
+    pub version: &'static str,
+
+    /// The features of this crate that have been enabled at compile time.
+    pub features: Vec<&'static str>,
+
+    /// The supported version of the Language Server Protocol.
+    pub protocol_version: &'static str,
+
+    /// How the messages are delimited on the streams, e.g. `contentLength`.
+    pub transport: &'static str,
+}
+
+impl BuildInfo {
+    /// Returns the information about the current build and the given framing.
+    pub fn new(framing: Framing) -> Self {
+        let features = [
+            ("async-std", cfg!(feature = "async-std")),
+            ("audit", cfg!(feature = "audit")),
+            ("cli", cfg!(feature = "cli")),
+            ("draft", cfg!(feature = "draft")),
+            ("file-operations", cfg!(feature = "file-operations")),
+            ("proposed", cfg!(feature = "proposed")),
+            ("testing", cfg!(feature = "testing")),
+            ("tokio", cfg!(feature = "tokio")),
+            ("validate", cfg!(feature = "validate")),
+            ("websocket", cfg!(feature = "websocket")),
+        ];
+
+        let transport = match framing {
+            Framing::ContentLength => "contentLength",
+            #[cfg(feature = "websocket")]
+            Framing::WebSocket => "webSocket",
+        };
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features: features
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+            protocol_version: PROTOCOL_VERSION,
+            transport,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    jsonrpc::{Error, Message, Request, Response},
+    CancellationToken,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{fmt, process::Command, sync::Arc, thread, time::Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// A function that receives the locale of the client as soon as the `initialize` request arrives,
+/// e.g. to select the language of user-visible messages.
+pub type LocaleHook = dyn Fn(&str) + Send + Sync;
+
+/// Validates the `initializationOptions` of the `initialize` request against a serde type.
+///
+/// Options that do not match the type are rejected with an error response
+/// whose data contains the reason, so the client can show it to the user.
+/// Missing options are validated as an empty object, so the type should provide defaults for them.
+/// The server can parse the options later with
+/// [`ServerContext::initialization_options`](struct.ServerContext.html#method.initialization_options).
+#[derive(Clone, Copy)]
+pub struct InitializationOptions {
+    validate: fn(&Value) -> serde_json::Result<()>,
+}
+
+impl InitializationOptions {
+    /// Validates the options against the type `T`.
+    pub fn of<T: DeserializeOwned>() -> Self {
+        Self {
+            validate: validate::<T>,
+        }
+    }
+
+    // Returns the error response to an `initialize` request with invalid options.
+    pub(crate) fn check(&self, request: &Request) -> Option<Response> {
+        if request.method != "initialize" {
+            return None;
+        }
+
+        let options = match request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("initializationOptions"))
+        {
+            Some(Value::Null) | None => json!({}),
+            Some(options) => options.clone(),
+        };
+        let why = (self.validate)(&options).err()?;
+        let mut error =
+            Error::initialize_error(format!("Invalid initialization options: {}", why), false);
+        error.data = Some(json!({ "retry": false, "reason": why.to_string() }));
+        Some(Response::error(error, Some(request.id.clone())))
+    }
+}
+
+impl fmt::Debug for InitializationOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InitializationOptions").finish()
+    }
+}
+
+fn validate<T: DeserializeOwned>(options: &Value) -> serde_json::Result<()> {
+    T::deserialize(options).map(drop)
+}
+
+// Inspects the `initialize` request before it reaches the middlewares and the server.
+pub(crate) struct EarlyInitialize {
+    pub(crate) watch_parent: bool,
+    pub(crate) locale_hook: Option<Arc<LocaleHook>>,
+    pub(crate) parent_exited: CancellationToken,
+}
+
+impl EarlyInitialize {
+    pub(crate) fn on_incoming_message(&self, message: &Message) {
+        let request = match message {
+            Message::Request(request) if request.method == "initialize" => request,
+            _ => return,
+        };
+
+        if let (Some(locale_hook), Some(locale)) = (
+            &self.locale_hook,
+            request
+                .params
+                .as_ref()
+                .and_then(|params| params.get("locale"))
+                .and_then(|locale| locale.as_str()),
+        ) {
+            locale_hook(locale);
+        }
+
+        let process_id = request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("processId"))
+            .and_then(|process_id| process_id.as_u64());
+
+        if let (true, Some(process_id)) = (self.watch_parent, process_id) {
+            let token = self.parent_exited.clone();
+            thread::spawn(move || watch_parent(process_id, &token));
+        }
+    }
+}
+
+// Cancels the token once the process has exited.
+// The watcher stops as well if the token is cancelled by the service.
+fn watch_parent(process_id: u64, token: &CancellationToken) {
+    while !token.is_cancelled() {
+        if !is_alive(process_id) {
+            log::warn!("The parent process {} has exited", process_id);
+            token.cancel();
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+fn is_alive(process_id: u64) -> bool {
+    match Command::new("kill")
+        .arg("-0")
+        .arg(process_id.to_string())
+        .output()
+    {
+        Ok(output) => output.status.success(),
+        Err(_) => true,
+    }
+}
+
+#[cfg(windows)]
+fn is_alive(process_id: u64) -> bool {
+    match Command::new("tasklist")
+        .arg("/FI")
+        .arg(format!("PID eq {}", process_id))
+        .arg("/NH")
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&process_id.to_string()),
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_alive(_process_id: u64) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{ErrorCode, Id};
+    use serde::Deserialize;
+
+    #[test]
+    fn invalid_initialization_options() {
+        #[derive(Deserialize)]
+        struct Options {
+            #[serde(default)]
+            _verbose: bool,
+        }
+
+        let options = InitializationOptions::of::<Options>();
+        let params = json!({ "capabilities": {} });
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        assert_eq!(options.check(&request), None);
+
+        let params = json!({ "capabilities": {}, "initializationOptions": { "_verbose": 42 } });
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        let error = options.check(&request).unwrap().error.unwrap();
+        assert_eq!(error.code, ErrorCode::RequestFailed);
+        assert_eq!(error.data.unwrap()["retry"], json!(false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exited_process() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let process_id = u64::from(child.id());
+        assert!(is_alive(u64::from(std::process::id())));
+        child.wait().unwrap();
+        assert!(!is_alive(process_id));
+    }
+}
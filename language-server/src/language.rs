@@ -0,0 +1,150 @@
+use crate::{jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext};
+use async_trait::async_trait;
+use lsp_types::Url;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Middleware that applies another middleware only to the messages of documents with the given language ids,
+/// e.g. a formatting middleware that handles `latex` documents while `bibtex` documents bypass it.
+///
+/// The document of a message is taken from the `textDocument.uri` or `uri` field of its parameters
+/// and its language is looked up with [`ServerContext::language_id`](struct.ServerContext.html#method.language_id),
+/// unless the message carries the language itself like `textDocument/didOpen`.
+/// Messages that do not refer to a document are always passed to the middleware,
+/// whereas messages of documents that are not open are not.
+pub struct LanguageScope {
+    languages: Vec<String>,
+    middleware: Arc<dyn Middleware>,
+}
+
+impl LanguageScope {
+    /// Restricts the given middleware to the documents of the given languages.
+    pub fn new(languages: Vec<String>, middleware: Arc<dyn Middleware>) -> Self {
+        Self {
+            languages,
+            middleware,
+        }
+    }
+
+    fn applies(&self, params: Option<&Value>, context: &ServerContext) -> bool {
+        let uri = params.and_then(|params| {
+            params
+                .pointer("/textDocument/uri")
+                .or_else(|| params.get("uri"))
+        });
+
+        // The context records the language of an opened document once the middlewares have run.
+        let opened = params
+            .and_then(|params| params.pointer("/textDocument/languageId"))
+            .and_then(|language| language.as_str());
+
+        match uri {
+            Some(uri) => {
+                let language = match opened {
+                    Some(language) => Some(language.to_owned()),
+                    None => serde_json::from_value::<Url>(uri.clone())
+                        .ok()
+                        .and_then(|uri| context.language_id(&uri)),
+                };
+                self.languages
+                    .iter()
+                    .any(|candidate| language.as_ref() == Some(candidate))
+            }
+            None => true,
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for LanguageScope {
+    fn name(&self) -> &'static str {
+        self.middleware.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.middleware.priority()
+    }
+
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        metadata: &MessageMetadata,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        let params = match message {
+            Message::Request(request) => request.params.as_ref(),
+            Message::Notification(notification) => notification.params.as_ref(),
+            Message::Response(_) => None,
+        };
+
+        if self.applies(params, context) {
+            self.middleware
+                .on_incoming_message(message, metadata, context, client)
+                .await
+        } else {
+            MessageFlow::Continue
+        }
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        request: &Request,
+        metadata: &MessageMetadata,
+        response: &mut Response,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        if self.applies(request.params.as_ref(), context) {
+            self.middleware
+                .on_outgoing_response(request, metadata, response, context, client)
+                .await;
+        }
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        if self.applies(request.params.as_ref(), context) {
+            self.middleware
+                .on_outgoing_request(request, context, client)
+                .await;
+        }
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        notification: &mut Notification,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        if self.applies(notification.params.as_ref(), context) {
+            self.middleware
+                .on_outgoing_notification(notification, context, client)
+                .await;
+        }
+    }
+
+    async fn on_request_cancelled(
+        &self,
+        id: &Id,
+        method: &str,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        self.middleware
+            .on_request_cancelled(id, method, context, client)
+            .await;
+    }
+
+    fn on_message_received(&self, message: &Message, metadata: &MessageMetadata) {
+        self.middleware.on_message_received(message, metadata);
+    }
+
+    fn on_message_sent(&self, message: &Message) {
+        self.middleware.on_message_sent(message);
+    }
+}
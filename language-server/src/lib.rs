@@ -42,7 +42,7 @@
 //!     let executor = TokioTp::try_from(&mut tokio::runtime::Builder::new())
 //!         .expect("failed to create thread pool");
 //!
-//!     executor.block_on(
+//!     let result = executor.block_on(
 //!         LanguageService::builder()
 //!             .server(Arc::new(Server))
 //!             .input(tokio::io::stdin().compat())
@@ -51,40 +51,196 @@
 //!             .build()
 //!             .listen(),
 //!     );
+//!
+//!     std::process::exit(match result {
+//!         Ok(reason) => reason.exit_code(),
+//!         Err(why) => {
+//!             eprintln!("{}", why);
+//!             1
+//!         }
+//!     });
 //! }
 //! ```
+// Allows the code generated by the procedural macros to refer to this crate by name.
+extern crate self as language_server;
+
+#[cfg(feature = "audit")]
+mod audit;
+mod cancellation;
+#[cfg_attr(docsrs, doc(cfg(feature = "cli")))]
+#[cfg(feature = "cli")]
+pub mod cli;
 mod client;
-mod codec;
-pub mod jsonrpc;
+mod clock;
+mod completion;
+mod context;
+mod diagnostics;
+#[cfg_attr(docsrs, doc(cfg(feature = "draft")))]
+#[cfg(feature = "draft")]
+pub mod draft;
+mod error;
+mod events;
+mod extension;
+#[cfg(feature = "file-operations")]
+mod fileops;
+pub mod fuzzy;
+mod handle;
+pub mod i18n;
+mod info;
+mod initialize;
+mod language;
+mod lifecycle;
+mod link;
+mod memory;
+mod metrics;
 mod middleware;
+mod options;
+mod ordering;
+mod partition;
+mod progress;
+mod quirks;
+mod recording;
+mod registration;
+mod rename;
+mod retry;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+mod runtime;
+mod scope;
+#[cfg(feature = "proposed")]
+mod semantic;
+mod serve;
 mod server;
+pub mod servers;
+mod service;
+mod size;
+mod standby;
+mod status;
+mod stdio;
+mod supervisor;
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
+mod trust;
+mod uri;
+mod validate;
+mod warmup;
+mod watchdog;
 
-pub use client::LanguageClient;
+#[cfg_attr(docsrs, doc(cfg(feature = "audit")))]
+#[cfg(feature = "audit")]
+pub use audit::{AuditEntry, AuditMiddleware, AuditTrail};
+pub use cancellation::{CancellationToken, Cancelled};
+pub use client::{LanguageClient, LanguageClientExt, RawClient, RefreshKind};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use completion::CompletionCache;
+pub use context::{ServerContext, ServerState};
+pub use diagnostics::{DiagnosticsBatcher, DiagnosticsCounters};
+pub use error::{HandlerError, HandlerResultExt};
+pub use events::ClientEvents;
+pub use extension::MethodTable;
+#[cfg_attr(docsrs, doc(cfg(feature = "file-operations")))]
+#[cfg(feature = "file-operations")]
+pub use fileops::{
+    FileOperationFilter, FileOperationMatcher, FileOperationMiddleware, FileOperationPattern,
+    FileOperationPatternKind, FileOperationPatternOptions, FileOperationRegistrationOptions,
+    FileRename, Glob, RenameFilesParams,
+};
+pub use handle::{ExitReason, ServiceController, ServiceError, ServiceHandle};
+pub use info::{BuildInfo, PROTOCOL_VERSION};
+pub use initialize::{InitializationOptions, LocaleHook};
 pub use jsonrpc::Result;
-pub use middleware::{LoggingMiddleware, Middleware};
-pub use server::LanguageServer;
+pub use language::LanguageScope;
+pub use link::DocumentLinkProvider;
+pub use memory::{MemoryLimit, MemoryProbe, ShrinkHook};
+pub use metrics::{
+    ExecutorMetrics, Histogram, InstrumentedExecutor, MethodMetrics, MetricsMiddleware,
+    MetricsSnapshot, TaskGauges, TaskOrigin,
+};
+pub use middleware::{LoggingMiddleware, MessageFlow, MessageMetadata, Middleware};
+pub use options::{CodeActionOptionsBuilder, CompletionOptionsBuilder};
+#[cfg(feature = "proposed")]
+pub use options::{LegendError, SemanticTokensLegendExt, SemanticTokensOptionsBuilder};
+pub use ordering::ResponseOrder;
+pub use partition::ServerFactory;
+pub use progress::{Progress, ProgressRegistry};
+pub use quirks::ClientQuirks;
+pub use recording::RecordingMiddleware;
+pub use registration::CapabilityRegistry;
+pub use rename::{RenameProvider, RenameTarget};
+pub use retry::RetryPolicy;
+#[cfg_attr(docsrs, doc(cfg(feature = "proposed")))]
+#[cfg(feature = "proposed")]
+pub use semantic::SemanticTokensCache;
+pub use serve::serve_tcp;
+pub use server::{LanguageServer, MethodKind, RawHandler, RequestHandler, SupportedMethod};
+pub use service::JsonRpcService;
+pub use size::ResponseSizeMiddleware;
+pub use standby::Standby;
+pub use status::{Problem, ProblemKind, ServerStatus, StatusReport};
+pub use stdio::{
+    accept, accept_pipe, accept_tcp, connect_pipe, stdio, ThreadedReader, ThreadedWriter,
+};
+pub use supervisor::{ProcessHealth, Supervisor, SupervisorHandle};
+pub use trace::{LogTraceParams, SetTraceParams, Tracer};
+pub use trust::{TrustMiddleware, WorkspaceTrust};
+pub use uri::{PrefixUriMapper, UriMapper, UriMappingMiddleware};
+pub use warmup::{WarmUp, WarmUpPolicy};
+pub use watchdog::{StuckHandler, StuckHandlerHook, Watchdog};
 
 pub use async_trait;
+pub use language_server_macros::{jsonrpc_client, jsonrpc_method, jsonrpc_server};
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+#[cfg(feature = "websocket")]
+pub use language_server_transport::WebSocketCodec;
+pub use language_server_transport::{
+    jsonrpc, DeadlockPolicy, Framing, IdMultiplexer, OutputFormat,
+};
 pub use lsp_types as types;
 
+// The items that are used by the code generated by the procedural macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{
+        server::empty_result,
+        validate::{notification_params_error, params_error},
+    };
+    pub use futures::channel::mpsc;
+    pub use language_server_transport::{Client, ResponseHandler, Timer};
+    pub use log;
+    pub use serde_json::{self, json};
+}
+
 use crate::{
-    client::{LanguageClientImpl, ResponseHandler},
-    codec::LspCodec,
-    jsonrpc::*,
-    middleware::AggregateMiddleware,
-    server::RequestHandler,
+    client::LanguageClientImpl, initialize::EarlyInitialize, jsonrpc::*,
+    middleware::AggregateMiddleware, ordering::ResponseSequencer, partition::Partitions,
+    scope::TaskScope,
 };
 use futures::{
     channel::mpsc,
+    future::{join, select, Either, FutureExt, RemoteHandle},
     sink::SinkExt,
     stream::StreamExt,
-    task::{Spawn, SpawnExt},
-    AsyncRead, AsyncWrite,
+    task::{Spawn, SpawnError, SpawnExt},
+    AsyncRead, AsyncWrite, Future,
 };
 use futures_codec::{FramedRead, FramedWrite};
-use std::sync::Arc;
+use language_server_transport::{BlockingSection, ResponseHandler};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 use typed_builder::TypedBuilder;
 
+// The maximum number of queued messages that are written at once.
+const MAX_BATCH_SIZE: usize = 64;
+
 /// Represents a service that processes messages according to the
 /// [Language Server Protocol](https://microsoft.github.io/language-server-protocol/specification).
 #[builder(builder_type_doc = "A builder to construct a `LanguageService`.")]
@@ -100,109 +256,942 @@ pub struct LanguageService<I, O, S, E> {
     #[builder(setter(doc = "Sets the language server for the service."))]
     server: Arc<S>,
 
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Creates a separate language server for every workspace folder and routes the document messages to them. The server of the service handles the remaining messages."
+    ))]
+    server_factory: Option<Arc<dyn ServerFactory<S>>>,
+
     #[builder(setter(doc = "Sets the executor on which futures are spawned."))]
     executor: E,
 
     #[builder(default)]
-    #[builder(setter(doc = "Attaches multiple middlewares to the service."))]
+    #[builder(setter(
+        doc = "Attaches multiple middlewares to the service, which run in the order of their priority."
+    ))]
     middlewares: Vec<Arc<dyn Middleware>>,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Sets the registry that receives the cancellation requests for work done progress."
+    ))]
+    progress: ProgressRegistry,
+
+    #[builder(default)]
+    #[builder(setter(doc = "Sets the event streams that receive selected client notifications."))]
+    events: ClientEvents,
+
+    #[builder(default)]
+    #[builder(setter(doc = "Sets the context that tracks the state of the session."))]
+    context: ServerContext,
+
+    #[builder(default = ResponseOrder::Completion)]
+    #[builder(setter(
+        doc = "Sets the order in which the responses of concurrent requests are written."
+    ))]
+    response_order: ResponseOrder,
+
+    #[builder(default)]
+    #[builder(setter(doc = "Sets the metrics that count the tasks of the service."))]
+    executor_metrics: ExecutorMetrics,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Answers requests that are not implemented with an empty result instead of a `MethodNotFound` error."
+    ))]
+    lenient_defaults: bool,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Rejects incoming messages whose `jsonrpc` field is not `2.0` instead of only logging them."
+    ))]
+    strict_version: bool,
+
+    #[builder(default = DeadlockPolicy::Report)]
+    #[builder(setter(
+        doc = "Sets how requests to the client are handled that are sent while a notification is being processed."
+    ))]
+    deadlock_policy: DeadlockPolicy,
+
+    #[builder(default = OutputFormat::Compact)]
+    #[builder(setter(doc = "Sets the format in which outgoing messages are serialized."))]
+    output_format: OutputFormat,
+
+    #[builder(default = Framing::ContentLength)]
+    #[builder(setter(
+        doc = "Sets how messages are delimited on the input and output streams, e.g. as WebSocket frames for web-based editors."
+    ))]
+    framing: Framing,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Runs the `warm_up` hook of the server in the background after initialization and holds back the requests that arrive in the meantime."
+    ))]
+    warm_up: Option<WarmUp>,
+
+    #[builder(default = Arc::new(SystemClock))]
+    #[builder(setter(
+        doc = "Sets the clock that timestamps the incoming messages and delays retries and the timeouts of client requests."
+    ))]
+    clock: Arc<dyn Clock>,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Stops the service once the process whose id has been sent with the `initialize` request has exited."
+    ))]
+    watch_parent_process: bool,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Calls the given function with the locale of the client before the `initialize` request is processed."
+    ))]
+    locale_hook: Option<Arc<LocaleHook>>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Rejects the `initialize` request if its initialization options do not match the given type."
+    ))]
+    initialization_options: Option<InitializationOptions>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Runs the warmers of the standby mode while waiting for the first message and answers its ping requests."
+    ))]
+    standby: Option<Standby>,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Attaches the methods that are known to the service to the data of `MethodNotFound` errors."
+    ))]
+    debug_method_not_found: bool,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Retries selected requests whose handler has failed with an internal error."
+    ))]
+    retry: Option<RetryPolicy>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Logs a warning for every request handler that exceeds the budget of the watchdog."
+    ))]
+    watchdog: Option<Watchdog>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Sets the default timeout of the requests that the server sends to the client."
+    ))]
+    client_timeout: Option<Duration>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Records the recent errors of the service and answers the `$/serverStatus` request with them."
+    ))]
+    status: Option<ServerStatus>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Shrinks the memory usage of the server and sheds low-priority requests once it exceeds the soft limit."
+    ))]
+    memory_limit: Option<MemoryLimit>,
+
+    #[builder(default, setter(strip_option))]
+    #[builder(setter(
+        doc = "Forwards the requests that the server answers with `MethodNotFound` and the notifications that it does not know to the given handler."
+    ))]
+    fallback_handler: Option<Arc<dyn RawHandler>>,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Logs the version, the features and the transport of this crate on start and answers the `$/serverInfo` request with them."
+    ))]
+    build_info: bool,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Registers tables of additional methods, which are dispatched before the methods of the server."
+    ))]
+    extensions: Vec<MethodTable>,
+
+    #[builder(default)]
+    #[builder(setter(
+        doc = "Answers requests that the lifecycle of the protocol does not permit with an error, e.g. requests before `initialize` or after `shutdown`."
+    ))]
+    strict_lifecycle: bool,
+}
+
+impl<I, O, S, E> LanguageService<I, O, S, E>
+where
+    E: Spawn,
+{
+    /// Checks the configuration for errors that would otherwise surface once the service is running,
+    /// e.g. options that cannot work at all or that contradict each other.
+    pub fn validate(&self) -> std::result::Result<(), BuildError> {
+        self.executor.status().map_err(BuildError::Executor)?;
+
+        if let ResponseOrder::ArrivalFor(methods) = &self.response_order {
+            if methods.is_empty() {
+                return Err(BuildError::ResponseOrder(
+                    "no methods are ordered by arrival".into(),
+                ));
+            }
+
+            let misplaced = methods
+                .iter()
+                .find(|pattern| pattern.is_empty() || pattern[..pattern.len() - 1].contains('*'));
+            if let Some(pattern) = misplaced {
+                return Err(BuildError::ResponseOrder(format!(
+                    "`{}` is not a method or a prefix ending with `*`",
+                    pattern
+                )));
+            }
+        }
+
+        if self.client_timeout == Some(Duration::from_secs(0)) {
+            return Err(BuildError::ClientTimeout);
+        }
+
+        if let Some(method) = self.standby.as_ref().and_then(Standby::ping_method) {
+            let conflicts = method == "initialize"
+                || (method == "$/serverStatus" && self.status.is_some())
+                || (method == "$/serverInfo" && self.build_info)
+                || self
+                    .extensions
+                    .iter()
+                    .any(|table| table.methods().contains(&method));
+            if conflicts {
+                return Err(BuildError::ConflictingMethod(method.to_owned()));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<I, O, S, E> LanguageService<I, O, S, E>
 where
     I: AsyncRead + Unpin,
-    O: AsyncWrite + Send + Unpin + 'static,
+    O: AsyncWrite + Unpin,
     S: LanguageServer + Send + Sync + 'static,
     E: Spawn + Clone,
 {
+    /// Returns every method that the service dispatches to the server,
+    /// including the methods that have been disabled by a feature flag.
+    pub fn supported_methods(&self) -> Vec<SupportedMethod> {
+        server::language_server_supported_methods()
+    }
+
     /// Starts the service and processes messages.
     /// It is guaranteed that all notifications are processed in order.
-    pub async fn listen(self) {
-        let (output_tx, mut output_rx) = mpsc::channel(0);
-        let client = Arc::new(LanguageClientImpl::new(output_tx.clone()));
-        let output = self.output;
-        let middleware = AggregateMiddleware {
-            middlewares: self.middlewares,
+    ///
+    /// The returned handle needs to be awaited in order to drive the service.
+    /// It completes once the client has sent the `exit` notification, the input stream has ended
+    /// or reading or writing a message has failed.
+    ///
+    /// Request handlers that panic are answered with an `InternalError` that contains the panic message,
+    /// unless the crate is compiled with `panic = "abort"`.
+    pub fn listen(
+        self,
+    ) -> ServiceHandle<impl Future<Output = std::result::Result<ExitReason, ServiceError>>> {
+        let controller = ServiceController::default();
+        ServiceHandle::new(self.run(controller.clone()), controller)
+    }
+
+    /// Spawns the service on its executor, e.g. to embed it in a larger application.
+    ///
+    /// The returned handle does not need to be polled. It stops the service with
+    /// [`ServiceHandle::shutdown`](struct.ServiceHandle.html#method.shutdown)
+    /// and aborts it when it is dropped.
+    pub fn spawn(
+        self,
+    ) -> std::result::Result<
+        ServiceHandle<RemoteHandle<std::result::Result<ExitReason, ServiceError>>>,
+        SpawnError,
+    >
+    where
+        I: Send + 'static,
+        O: Send + 'static,
+        E: Send + 'static,
+    {
+        let controller = ServiceController::default();
+        let executor = self.executor.clone();
+        let service = executor.spawn_with_handle(self.run(controller.clone()))?;
+        Ok(ServiceHandle::new(service, controller))
+    }
+
+    async fn run(
+        self,
+        controller: ServiceController,
+    ) -> std::result::Result<ExitReason, ServiceError> {
+        let build_info = if self.build_info {
+            let info = BuildInfo::new(self.framing);
+            log::info!("{}", serde_json::json!(info));
+            Some(info)
+        } else {
+            None
         };
-        {
-            let middleware = middleware.clone();
-            let client = Arc::clone(&client);
-            self.executor
-                .spawn(async move {
-                    let mut output = FramedWrite::new(output, LspCodec);
-                    while let Some(mut message) = output_rx.next().await {
-                        match &mut message {
-                            Message::Request(ref mut request) => {
-                                middleware
-                                    .on_outgoing_request(request, client.clone())
-                                    .await;
-                            }
-                            Message::Notification(ref mut notification) => {
-                                middleware
-                                    .on_outgoing_notification(notification, client.clone())
-                                    .await;
-                            }
-                            Message::Response(_) => {}
-                        };
 
-                        let json =
-                            serde_json::to_string(&message).expect("failed to serialize message");
-                        output.send(json).await.expect("failed to send message");
-                    }
-                })
-                .expect("failed to spawn future");
+        let (output_tx, output_rx) = mpsc::channel(0);
+        let clock = Arc::clone(&self.clock);
+        let client = LanguageClientImpl::new(output_tx.clone())
+            .with_timer(Arc::new(move |duration| clock.sleep(duration)));
+        let client = Arc::new(match self.client_timeout {
+            Some(timeout) => client.with_timeout(timeout),
+            None => client,
+        });
+        let client_guard = ClientGuard(Arc::clone(&client));
+        let mut middlewares = self.middlewares;
+        middleware::sort_by_priority(&mut middlewares);
+        let middleware = AggregateMiddleware { middlewares };
+
+        let write_loop = self.executor_metrics.track(
+            TaskOrigin::Output,
+            Self::write_messages(
+                self.output,
+                self.output_format,
+                self.framing,
+                output_rx,
+                middleware.clone(),
+                self.context.clone(),
+                Arc::clone(&client),
+                self.executor_metrics.clone(),
+                controller.stop_token.clone(),
+            ),
+        );
+
+        let scope = TaskScope::default();
+        let server = Arc::new(Partitions::new(self.server, self.server_factory));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let dispatcher = Dispatcher {
+            server: Arc::clone(&server),
+            client,
+            output: output_tx.clone(),
+            executor: InstrumentedExecutor::new(self.executor, self.executor_metrics)
+                .with_origin(TaskOrigin::Request),
+            middleware,
+            context: self.context,
+            progress: self.progress,
+            events: self.events,
+            sequencer: ResponseSequencer::new(self.response_order),
+            shutdown: Arc::clone(&shutdown),
+            scope: scope.clone(),
+            in_flight: Arc::default(),
+            lenient_defaults: self.lenient_defaults,
+            strict_version: self.strict_version,
+            debug_method_not_found: self.debug_method_not_found,
+            deadlock_policy: self.deadlock_policy,
+            warm_up: self.warm_up,
+            retry: self.retry,
+            clock: self.clock,
+            status: self.status,
+            initialization_options: self.initialization_options,
+            watchdog: self.watchdog,
+            memory_limit: self.memory_limit,
+            fallback_handler: self.fallback_handler,
+            build_info,
+            extensions: Arc::new(MethodTable::merge(self.extensions)),
+            strict_lifecycle: self.strict_lifecycle,
+        };
+
+        let mut standby = self.standby;
+        if let Some(standby) = &mut standby {
+            let executor = dispatcher
+                .executor
+                .clone()
+                .with_origin(TaskOrigin::Background);
+            for warmer in standby.take_warmers() {
+                executor.spawn(warmer).expect("failed to spawn future");
+            }
+        }
+
+        let input = self.input;
+        let framing = self.framing;
+        let early = EarlyInitialize {
+            watch_parent: self.watch_parent_process,
+            locale_hook: self.locale_hook,
+            parent_exited: CancellationToken::new(),
+        };
+        let parent_exited = early.parent_exited.clone();
+        let stop_token = controller.stop_token.clone();
+        let read_loop = async {
+            let reason =
+                Self::read_messages(input, framing, dispatcher, early, standby, &stop_token).await;
+            // No response can arrive once the input has ended,
+            // so handlers awaiting the client must not keep the service alive.
+            client_guard.0.close();
+            scope.join().await;
+            output_tx.clone().close_channel();
+            reason
+        };
+
+        // A failed write stops the service, so the error takes precedence over the exit reason.
+        let service = join(read_loop, write_loop).map(|(reason, written)| {
+            written.map_err(ServiceError::Output)?;
+            reason
+        });
+        let abort = controller.abort_token.cancelled();
+        let reason = match select(Box::pin(service), abort).await {
+            Either::Left((reason, _)) => reason,
+            Either::Right(((), _)) => {
+                scope.cancel();
+                scope.join().await;
+                Ok(ExitReason::Aborted)
+            }
+        };
+
+        // Stops watching the parent process.
+        parent_exited.cancel();
+
+        if !shutdown.swap(true, Ordering::SeqCst) {
+            server.on_shutdown().await;
         }
+        server.on_exit().await;
+        reason
+    }
+
+    async fn read_messages(
+        input: I,
+        framing: Framing,
+        dispatcher: Dispatcher<S, InstrumentedExecutor<E>>,
+        early: EarlyInitialize,
+        standby: Option<Standby>,
+        stop_token: &CancellationToken,
+    ) -> std::result::Result<ExitReason, ServiceError> {
+        let mut input = FramedRead::new(input, framing);
+        let mut sequence = 0;
+        loop {
+            let stopped = select(stop_token.cancelled(), early.parent_exited.cancelled());
+            let json = match select(input.next(), stopped).await {
+                Either::Left((Some(Ok(json)), _)) => json,
+                Either::Left((Some(Err(why)), _)) => return Err(ServiceError::Input(why)),
+                Either::Left((None, _)) => return Ok(ExitReason::Disconnected),
+                Either::Right((Either::Left(_), _)) => return Ok(ExitReason::Stopped),
+                Either::Right((Either::Right(_), _)) => return Ok(ExitReason::ParentExited),
+            };
 
-        let mut input = FramedRead::new(self.input, LspCodec);
-        while let Some(Ok(json)) = input.next().await {
-            let server = Arc::clone(&self.server);
-            let client = Arc::clone(&client);
-            let mut output = output_tx.clone();
-            let executor = self.executor.clone();
-            let middleware = middleware.clone();
+            let metadata = MessageMetadata {
+                sequence,
+                received_at: dispatcher.clock.now(),
+            };
+            sequence += 1;
 
             match serde_json::from_str(&json) {
                 Ok(message) => {
-                    Self::handle_message(server, client, output, executor, middleware, message)
-                        .await
+                    dispatcher
+                        .middleware
+                        .on_message_received(&message, &metadata);
+                    if let Some(response) = standby
+                        .as_ref()
+                        .and_then(|standby| standby.answer(&message))
+                    {
+                        let mut output = dispatcher.output.clone();
+                        output.send(Message::Response(response)).await.unwrap();
+                        continue;
+                    }
+
+                    early.on_incoming_message(&message);
+                    let exit = match &message {
+                        Message::Notification(notification) => notification.method == "exit",
+                        _ => false,
+                    };
+
+                    let after_shutdown = dispatcher.context.state() == ServerState::ShuttingDown;
+                    dispatcher.clone().handle_message(message, metadata).await;
+                    if exit {
+                        return Ok(ExitReason::Exited { after_shutdown });
+                    }
                 }
-                Err(_) => {
+                Err(why) => {
+                    if let Some(status) = &dispatcher.status {
+                        status.record(ProblemKind::ParseError, None, why.to_string());
+                    }
+
                     let response = Response::error(Error::parse_error(), None);
+                    let mut output = dispatcher.output.clone();
                     output.send(Message::Response(response)).await.unwrap();
                 }
             };
         }
     }
 
-    async fn handle_message(
-        server: Arc<S>,
-        client: Arc<LanguageClientImpl>,
-        mut output: mpsc::Sender<Message>,
-        executor: E,
+    #[allow(clippy::too_many_arguments)]
+    async fn write_messages(
+        output: O,
+        format: OutputFormat,
+        framing: Framing,
+        mut output_rx: mpsc::Receiver<Message>,
         middleware: AggregateMiddleware,
-        mut message: Message,
-    ) {
-        middleware
-            .on_incoming_message(&mut message, client.clone())
+        context: ServerContext,
+        client: Arc<LanguageClientImpl>,
+        metrics: ExecutorMetrics,
+        stop_token: CancellationToken,
+    ) -> io::Result<()> {
+        let mut output = FramedWrite::new(output, framing);
+        let mut result = Ok(());
+        while let Some(message) = output_rx.next().await {
+            // The messages that have been queued in the meantime are combined into a single write.
+            let failed = result.is_err();
+            let mut next = Some(message);
+            let mut batch_size = 0;
+            while let Some(mut message) = next.take() {
+                match &mut message {
+                    Message::Request(ref mut request) => {
+                        middleware
+                            .on_outgoing_request(request, &context, client.clone())
+                            .await;
+                    }
+                    Message::Notification(ref mut notification) => {
+                        middleware
+                            .on_outgoing_notification(notification, &context, client.clone())
+                            .await;
+                    }
+                    Message::Response(_) => {}
+                };
+
+                middleware.on_message_sent(&message);
+                let json = format
+                    .serialize(&message)
+                    .expect("failed to serialize message");
+                if result.is_ok() {
+                    result = output.feed(json).await;
+                }
+
+                batch_size += 1;
+                if batch_size < MAX_BATCH_SIZE {
+                    next = output_rx.next().now_or_never().unwrap_or(None);
+                }
+            }
+
+            metrics.record_output_backlog(batch_size);
+            if result.is_ok() {
+                result = output.flush().await;
+            }
+            metrics.record_output_backlog(0);
+
+            // The remaining messages are discarded, so the senders do not fail while the service stops.
+            if let (false, Err(why)) = (failed, &result) {
+                log::error!("Failed to write to the output stream: {}", why);
+                stop_token.cancel();
+            }
+        }
+        result
+    }
+}
+
+/// An error that indicates an invalid configuration of a `LanguageService`.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The executor is not able to spawn futures, for example because it has been shut down.
+    Executor(SpawnError),
+
+    /// The response order cannot be applied, e.g. because it orders no methods at all.
+    ResponseOrder(String),
+
+    /// The default timeout of client requests is zero, so every request would time out immediately.
+    ClientTimeout,
+
+    /// The ping method of the standby mode shadows a method that another option handles.
+    ConflictingMethod(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Executor(why) => write!(f, "the executor cannot spawn futures: {}", why),
+            Self::ResponseOrder(why) => write!(f, "invalid response order: {}", why),
+            Self::ClientTimeout => write!(f, "the timeout of client requests is zero"),
+            Self::ConflictingMethod(method) => write!(
+                f,
+                "the ping method `{}` conflicts with another handler of that method",
+                method
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Executor(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+struct Dispatcher<S, E> {
+    server: Arc<Partitions<S>>,
+    client: Arc<LanguageClientImpl>,
+    output: mpsc::Sender<Message>,
+    executor: E,
+    middleware: AggregateMiddleware,
+    context: ServerContext,
+    progress: ProgressRegistry,
+    events: ClientEvents,
+    sequencer: ResponseSequencer,
+    shutdown: Arc<AtomicBool>,
+    scope: TaskScope,
+    // The methods of the requests that have not been answered yet.
+    in_flight: Arc<Mutex<HashMap<Id, (String, CancellationToken)>>>,
+    lenient_defaults: bool,
+    strict_version: bool,
+    debug_method_not_found: bool,
+    deadlock_policy: DeadlockPolicy,
+    warm_up: Option<WarmUp>,
+    retry: Option<RetryPolicy>,
+    clock: Arc<dyn Clock>,
+    status: Option<ServerStatus>,
+    initialization_options: Option<InitializationOptions>,
+    watchdog: Option<Watchdog>,
+    memory_limit: Option<MemoryLimit>,
+    fallback_handler: Option<Arc<dyn RawHandler>>,
+    build_info: Option<BuildInfo>,
+    extensions: Arc<MethodTable>,
+    strict_lifecycle: bool,
+}
+
+impl<S, E: Clone> Clone for Dispatcher<S, E> {
+    fn clone(&self) -> Self {
+        Self {
+            server: Arc::clone(&self.server),
+            client: Arc::clone(&self.client),
+            output: self.output.clone(),
+            executor: self.executor.clone(),
+            middleware: self.middleware.clone(),
+            context: self.context.clone(),
+            progress: self.progress.clone(),
+            events: self.events.clone(),
+            sequencer: self.sequencer.clone(),
+            shutdown: Arc::clone(&self.shutdown),
+            scope: self.scope.clone(),
+            in_flight: Arc::clone(&self.in_flight),
+            lenient_defaults: self.lenient_defaults,
+            strict_version: self.strict_version,
+            debug_method_not_found: self.debug_method_not_found,
+            deadlock_policy: self.deadlock_policy,
+            warm_up: self.warm_up.clone(),
+            retry: self.retry.clone(),
+            clock: Arc::clone(&self.clock),
+            status: self.status.clone(),
+            initialization_options: self.initialization_options,
+            watchdog: self.watchdog.clone(),
+            memory_limit: self.memory_limit.clone(),
+            fallback_handler: self.fallback_handler.clone(),
+            build_info: self.build_info.clone(),
+            extensions: Arc::clone(&self.extensions),
+            strict_lifecycle: self.strict_lifecycle,
+        }
+    }
+}
+
+impl<S, E> Dispatcher<S, InstrumentedExecutor<E>>
+where
+    S: LanguageServer + Send + Sync + 'static,
+    E: Spawn + Clone,
+{
+    async fn handle_message(self, mut message: Message, metadata: MessageMetadata) {
+        let Self {
+            server,
+            client,
+            mut output,
+            executor,
+            middleware,
+            context,
+            progress,
+            events,
+            sequencer,
+            shutdown,
+            scope,
+            in_flight,
+            lenient_defaults,
+            strict_version,
+            debug_method_not_found,
+            deadlock_policy,
+            warm_up,
+            retry,
+            clock,
+            status,
+            initialization_options,
+            watchdog,
+            memory_limit,
+            fallback_handler,
+            build_info,
+            extensions,
+            strict_lifecycle,
+        } = self;
+
+        if !message.has_valid_version() {
+            log::warn!(
+                "Received a message with the unsupported version {:?}",
+                message.version()
+            );
+
+            if strict_version {
+                if let Some(status) = &status {
+                    let method = match &message {
+                        Message::Request(request) => Some(request.method.as_str()),
+                        Message::Notification(notification) => Some(notification.method.as_str()),
+                        Message::Response(_) => None,
+                    };
+                    let reason = format!("Unsupported JSON-RPC version: {}", message.version());
+                    status.record(ProblemKind::DroppedMessage, method, reason);
+                }
+
+                if let Message::Request(request) = message {
+                    let message = format!("Unsupported JSON-RPC version: {}", request.jsonrpc);
+                    let response =
+                        Response::error(Error::invalid_request(message), Some(request.id));
+                    output.send(Message::Response(response)).await.unwrap();
+                }
+                return;
+            }
+        }
+
+        if let (true, Message::Request(request)) = (strict_lifecycle, &message) {
+            if let Some(response) = lifecycle::check(request, &context) {
+                output.send(Message::Response(response)).await.unwrap();
+                return;
+            }
+        }
+
+        if let (Some(options), Message::Request(request)) = (&initialization_options, &message) {
+            if let Some(response) = options.check(request) {
+                context.on_outgoing_response(request, &response);
+                output.send(Message::Response(response)).await.unwrap();
+                return;
+            }
+        }
+
+        if let (Some(limit), Message::Request(request)) = (&memory_limit, &message) {
+            if !limit.admit(&request.method) {
+                let response = memory::shed(request, &context);
+                output.send(Message::Response(response)).await.unwrap();
+                return;
+            }
+        }
+
+        let flow = middleware
+            .on_incoming_message(&mut message, &metadata, &context, client.clone())
             .await;
 
+        // The context observes the message in the form that the server receives,
+        // e.g. with the URIs that a middleware has mapped.
+        let message = match (flow, message) {
+            (MessageFlow::Continue, message) => {
+                context.on_incoming_message(&message);
+                message
+            }
+            (MessageFlow::Respond(mut response), Message::Request(request)) => {
+                response.id = Some(request.id.clone());
+                middleware
+                    .on_outgoing_response(&request, &metadata, &mut response, &context, client)
+                    .await;
+                output.send(Message::Response(response)).await.unwrap();
+                return;
+            }
+            (MessageFlow::Respond(_), _) | (MessageFlow::Drop, _) => return,
+        };
+
         match message {
+            Message::Request(request) if request.method == "$/serverStatus" && status.is_some() => {
+                let report = status.unwrap().report();
+                let response = Response::result(serde_json::json!(report), request.id);
+                output.send(Message::Response(response)).await.unwrap();
+            }
+            Message::Request(request)
+                if request.method == "$/serverInfo" && build_info.is_some() =>
+            {
+                let info = build_info.unwrap();
+                let response = Response::result(serde_json::json!(info), request.id);
+                output.send(Message::Response(response)).await.unwrap();
+            }
             Message::Request(request) => {
+                context.on_accepted_request(&request);
                 let client = client.clone();
-                executor
-                    .spawn(async move {
-                        let mut response =
-                            server.handle_request(request.clone(), client.clone()).await;
+                let ticket = sequencer.ticket(&request.method);
+                let is_shutdown = request.method == "shutdown";
+                let token = CancellationToken::new();
+                in_flight
+                    .lock()
+                    .unwrap()
+                    .insert(request.id.clone(), (request.method.clone(), token.clone()));
+                let previous_tasks = if is_shutdown {
+                    // The warm-up and the requests that are waiting for it would delay the shutdown.
+                    if let Some(warm_up) = &warm_up {
+                        warm_up.stop();
+                    }
+                    Some(scope.join_current())
+                } else {
+                    None
+                };
+
+                scope
+                    .spawn(&executor, async move {
+                        if let Some(previous_tasks) = previous_tasks {
+                            previous_tasks.await;
+                        }
+
+                        let handler = async {
+                            let admitted = match &warm_up {
+                                Some(warm_up) => warm_up.admit(&request.method).await,
+                                None => true,
+                            };
+
+                            let mut response = if admitted {
+                                let request = request.clone();
+                                dispatch_request(&server, &extensions, request, client.clone())
+                                    .await
+                            } else {
+                                warmup::rejected(&request, &context)
+                            };
+                            if let Some(fallback) = &fallback_handler {
+                                if admitted && server::is_method_not_found(&response) {
+                                    response = fallback
+                                        .handle_raw_request(request.clone(), client.clone())
+                                        .await;
+                                }
+                            }
+                            if let Some(retry) = &retry {
+                                let mut retries = 0;
+                                while retry.should_retry(&request.method, &response, retries) {
+                                    clock.sleep(retry.delay(retries)).await;
+                                    retries += 1;
+                                    let request = request.clone();
+                                    let client = client.clone();
+                                    response =
+                                        dispatch_request(&server, &extensions, request, client)
+                                            .await;
+                                }
+                            }
+                            response
+                        };
+
+                        // A panic of the handler is answered with an internal error,
+                        // so the service keeps running.
+                        let handler = async {
+                            match AssertUnwindSafe(handler).catch_unwind().await {
+                                Ok(response) => response,
+                                Err(payload) => error::panicked(&request, payload),
+                            }
+                        };
+
+                        let handler = async {
+                            match &watchdog {
+                                Some(watchdog) => {
+                                    let clock = clock.as_ref();
+                                    watchdog
+                                        .watch(&request.id, &request.method, clock, handler)
+                                        .await
+                                }
+                                None => handler.await,
+                            }
+                        };
+
+                        // The handler is dropped once the client has cancelled the request.
+                        let handler = token.clone().scope(Box::pin(handler));
+                        let mut response = match select(handler, token.cancelled()).await {
+                            Either::Left((response, _)) => response,
+                            Either::Right(((), _)) => Response::error(
+                                Error::request_cancelled(),
+                                Some(request.id.clone()),
+                            ),
+                        };
+                        response = server::default_response(&request, response, lenient_defaults);
+                        if debug_method_not_found {
+                            response = server::debug_response(response);
+                        }
+                        if is_shutdown && !shutdown.swap(true, Ordering::SeqCst) {
+                            server.on_shutdown().await;
+                        }
+
+                        in_flight.lock().unwrap().remove(&request.id);
+                        if let (Some(status), Some(error)) = (&status, &response.error) {
+                            let method = Some(request.method.as_str());
+                            status.record(ProblemKind::HandlerError, method, error.message.clone());
+                        }
+
+                        context.on_outgoing_response(&request, &response);
                         middleware
-                            .on_outgoing_response(&request, &mut response, client)
+                            .on_outgoing_response(
+                                &request,
+                                &metadata,
+                                &mut response,
+                                &context,
+                                client,
+                            )
                             .await;
 
-                        output.send(Message::Response(response)).await.unwrap();
+                        match ticket {
+                            Some(ticket) => ticket.send(response, &mut output).await,
+                            None => output.send(Message::Response(response)).await.unwrap(),
+                        }
                     })
                     .expect("failed to spawn future");
             }
             Message::Notification(notification) => {
-                server.handle_notification(notification, client).await;
+                if notification.method == "window/workDoneProgress/cancel" {
+                    if let Ok(params) = serde_json::from_value::<types::WorkDoneProgressCancelParams>(
+                        notification.params.clone().unwrap_or_default(),
+                    ) {
+                        progress.cancel(&params.token);
+                    }
+                }
+
+                if notification.method == "$/cancelRequest" {
+                    if let Ok(params) = serde_json::from_value::<types::CancelParams>(
+                        notification.params.clone().unwrap_or_default(),
+                    ) {
+                        let id = match params.id {
+                            types::NumberOrString::Number(id) => Id::Number(id),
+                            types::NumberOrString::String(id) => Id::String(id),
+                        };
+
+                        let entry = in_flight.lock().unwrap().get(&id).cloned();
+                        if let Some((method, token)) = entry {
+                            middleware
+                                .on_request_cancelled(&id, &method, &context, client.clone())
+                                .await;
+                            token.cancel();
+                        }
+                    }
+                }
+
+                events.publish(&notification);
+
+                // Notifications are processed inline, so the handler cannot receive
+                // the responses to the requests that it sends to the client.
+                let label = notification.method.clone();
+                let closed = if label == "textDocument/didClose" {
+                    Some(notification.clone())
+                } else {
+                    None
+                };
+                let handler = match extensions.handle_notification(notification, client.clone()) {
+                    Ok(handler) => handler,
+                    Err(notification) => match fallback_handler {
+                        Some(fallback) if !server::is_dispatched(&label) => {
+                            let client = client.clone();
+                            async move { fallback.handle_raw_notification(notification, client).await }
+                                .boxed()
+                        }
+                        _ => server
+                            .handle_notification(notification, client.clone())
+                            .boxed(),
+                    },
+                };
+                BlockingSection::new(label.clone(), deadlock_policy, handler).await;
+                if let Some(closed) = closed {
+                    context.on_processed_notification(&closed);
+                }
+
+                if label == "initialized" {
+                    if let Some(warm_up) = warm_up {
+                        let executor = executor.with_origin(TaskOrigin::Background);
+                        let task = run_warm_up(server, client, context, progress, warm_up);
+                        scope
+                            .spawn(&executor, task)
+                            .expect("failed to spawn future");
+                    }
+                }
             }
             Message::Response(response) => {
                 client.handle(response).await;
@@ -210,3 +1199,61 @@ where
         };
     }
 }
+
+// Dispatches a request to the extensions or to the server.
+async fn dispatch_request<S>(
+    server: &Partitions<S>,
+    extensions: &MethodTable,
+    request: Request,
+    client: Arc<LanguageClientImpl>,
+) -> Response
+where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    match extensions.handle_request(request, client.clone()) {
+        Ok(response) => response.await,
+        Err(request) => server.handle_request(request, client).await,
+    }
+}
+
+// Runs the warm-up of the server and releases the requests that have been held back.
+async fn run_warm_up<S>(
+    server: Arc<Partitions<S>>,
+    client: Arc<LanguageClientImpl>,
+    context: ServerContext,
+    registry: ProgressRegistry,
+    warm_up: WarmUp,
+) where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    let supports_progress = context
+        .client_capabilities()
+        .and_then(|capabilities| capabilities.window)
+        .and_then(|window| window.work_done_progress)
+        .unwrap_or(false);
+
+    let progress = if supports_progress {
+        let title = warm_up.title().to_owned();
+        registry.begin(client.clone(), title, false).await.ok()
+    } else {
+        None
+    };
+
+    // The hook is dropped once the shutdown request has stopped the warm-up.
+    let hook = Box::pin(server.warm_up(progress.as_ref(), client));
+    select(hook, warm_up.stop_token().cancelled()).await;
+    if let Some(progress) = progress {
+        progress.end(None).await;
+    }
+    warm_up.finish();
+}
+
+// Closes the client once the service has exited or has been dropped,
+// so requests of tasks that outlive the service do not wait for a response forever.
+struct ClientGuard(Arc<LanguageClientImpl>);
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
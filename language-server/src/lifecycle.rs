@@ -0,0 +1,113 @@
+use crate::{i18n, jsonrpc::*, ServerContext, ServerState};
+
+// Answers the request with an error if the lifecycle of the protocol does not permit it:
+// Requests other than `initialize` need to wait for the server to be initialized,
+// `initialize` is only accepted again if it has failed with `retry` set
+// and no requests are accepted after `shutdown`.
+pub(crate) fn check(request: &Request, context: &ServerContext) -> Option<Response> {
+    let method = request.method.as_str();
+    let error = match (context.state(), method) {
+        (ServerState::Uninitialized, "initialize") => return None,
+        (ServerState::Initializing, "initialize") => {
+            let message = context.localize(i18n::LIFECYCLE_INITIALIZING, &[]);
+            Error::invalid_request(message)
+        }
+        (ServerState::Failed, "initialize") => {
+            let message = context.localize(i18n::LIFECYCLE_INITIALIZE_FAILED, &[]);
+            Error::invalid_request(message)
+        }
+        (ServerState::Uninitialized, _)
+        | (ServerState::Initializing, _)
+        | (ServerState::Failed, _) => {
+            let message = context.localize(i18n::LIFECYCLE_NOT_INITIALIZED, &[method]);
+            Error::server_not_initialized_error(message)
+        }
+        (ServerState::Initialized, "initialize") => {
+            let message = context.localize(i18n::LIFECYCLE_ALREADY_INITIALIZED, &[]);
+            Error::invalid_request(message)
+        }
+        (ServerState::Initialized, _) => return None,
+        (ServerState::ShuttingDown, _) => {
+            let message = context.localize(i18n::LIFECYCLE_SHUTTING_DOWN, &[method]);
+            Error::invalid_request(message)
+        }
+    };
+
+    Some(Response::error(error, Some(request.id.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check_code(method: &str, context: &ServerContext) -> Option<ErrorCode> {
+        let request = Request::new(method.into(), json!({}), Id::Number(0));
+        check(&request, context).map(|response| response.error.unwrap().code)
+    }
+
+    #[test]
+    fn follow_lifecycle() {
+        let context = ServerContext::default();
+        assert_eq!(check_code("initialize", &context), None);
+        assert_eq!(
+            check_code("textDocument/hover", &context),
+            Some(ErrorCode::ServerNotInitialized)
+        );
+
+        let initialize = Request::new(
+            "initialize".into(),
+            json!({ "capabilities": {} }),
+            Id::Number(0),
+        );
+        context.on_accepted_request(&initialize);
+        assert_eq!(
+            check_code("initialize", &context),
+            Some(ErrorCode::InvalidRequest)
+        );
+        assert_eq!(
+            check_code("textDocument/hover", &context),
+            Some(ErrorCode::ServerNotInitialized)
+        );
+
+        let error = Error::initialize_error("foo".into(), true);
+        context.on_outgoing_response(&initialize, &Response::error(error, Some(Id::Number(0))));
+        assert_eq!(check_code("initialize", &context), None);
+
+        context.on_accepted_request(&initialize);
+        context.on_outgoing_response(&initialize, &Response::result(json!({}), Id::Number(0)));
+        assert_eq!(check_code("textDocument/hover", &context), None);
+        assert_eq!(
+            check_code("initialize", &context),
+            Some(ErrorCode::InvalidRequest)
+        );
+
+        let shutdown = Request::new("shutdown".into(), json!(null), Id::Number(1));
+        context.on_accepted_request(&shutdown);
+        assert_eq!(
+            check_code("textDocument/hover", &context),
+            Some(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn initialize_without_retry() {
+        let context = ServerContext::default();
+        let initialize = Request::new(
+            "initialize".into(),
+            json!({ "capabilities": {} }),
+            Id::Number(0),
+        );
+        context.on_accepted_request(&initialize);
+        let error = Error::initialize_error("foo".into(), false);
+        context.on_outgoing_response(&initialize, &Response::error(error, Some(Id::Number(0))));
+        assert_eq!(
+            check_code("initialize", &context),
+            Some(ErrorCode::InvalidRequest)
+        );
+        assert_eq!(
+            check_code("textDocument/hover", &context),
+            Some(ErrorCode::ServerNotInitialized)
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::jsonrpc::{Error, Result};
+use async_trait::async_trait;
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Wraps the data of a link, so the document is known when resolving the link.
+#[derive(Debug, Serialize, Deserialize)]
+struct LinkData {
+    uri: Url,
+
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+/// Combines the `textDocument/documentLink` and `documentLink/resolve` requests
+/// so that links can be resolved even if the server does not recognize them anymore,
+/// for example after a restart.
+///
+/// A `LanguageServer` can forward both requests to `handle_document_link` and `handle_document_link_resolve`.
+#[async_trait]
+pub trait DocumentLinkProvider: Send + Sync {
+    /// Computes the links of the given document.
+    async fn find_links(&self, params: &DocumentLinkParams) -> Result<Vec<DocumentLink>>;
+
+    /// Resolves the target of the given link.
+    ///
+    /// Returns `None` if the link is not recognized by the server.
+    async fn resolve_link(&self, link: DocumentLink) -> Result<Option<DocumentLink>>;
+
+    /// Determines whether unrecognized links are resolved by recomputing the links of the document
+    /// and matching them by range. Disabled by default.
+    fn recompute_unknown_links(&self) -> bool {
+        false
+    }
+
+    /// Answers a `textDocument/documentLink` request.
+    async fn handle_document_link(&self, params: DocumentLinkParams) -> Result<Vec<DocumentLink>> {
+        let uri = &params.text_document.uri;
+        let links = self
+            .find_links(&params)
+            .await?
+            .into_iter()
+            .map(|link| wrap_data(uri.clone(), link))
+            .collect();
+        Ok(links)
+    }
+
+    /// Answers a `documentLink/resolve` request.
+    async fn handle_document_link_resolve(&self, item: DocumentLink) -> Result<DocumentLink> {
+        let (uri, link) = unwrap_data(item);
+        let range = link.range;
+        if let Some(link) = self.resolve_link(link).await? {
+            return Ok(rewrap_data(uri, link));
+        }
+
+        if let Some(uri) = uri.filter(|_| self.recompute_unknown_links()) {
+            let params = DocumentLinkParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            };
+
+            let candidate = self
+                .find_links(&params)
+                .await?
+                .into_iter()
+                .find(|link| link.range == range);
+
+            if let Some(candidate) = candidate {
+                let link = match self.resolve_link(candidate.clone()).await? {
+                    Some(link) => link,
+                    None => candidate,
+                };
+                return Ok(wrap_data(uri, link));
+            }
+        }
+
+        Err(Error::invalid_params("unknown document link".to_owned()))
+    }
+}
+
+fn wrap_data(uri: Url, mut link: DocumentLink) -> DocumentLink {
+    let data = LinkData {
+        uri,
+        data: link.data.take(),
+    };
+    link.data = serde_json::to_value(data).ok();
+    link
+}
+
+fn rewrap_data(uri: Option<Url>, link: DocumentLink) -> DocumentLink {
+    match uri {
+        Some(uri) => wrap_data(uri, link),
+        None => link,
+    }
+}
+
+// Links that have not been created by `handle_document_link` are passed through unchanged.
+fn unwrap_data(mut link: DocumentLink) -> (Option<Url>, DocumentLink) {
+    let data = link
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<LinkData>(data).ok());
+
+    match data {
+        Some(data) => {
+            link.data = data.data;
+            (Some(data.uri), link)
+        }
+        None => (None, link),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    struct Provider {
+        recompute: bool,
+    }
+
+    fn link(line: u64, data: Value) -> DocumentLink {
+        DocumentLink {
+            range: Range::new(Position::new(line, 0), Position::new(line, 4)),
+            target: None,
+            tooltip: None,
+            data: Some(data),
+        }
+    }
+
+    #[async_trait]
+    impl DocumentLinkProvider for Provider {
+        async fn find_links(&self, _params: &DocumentLinkParams) -> Result<Vec<DocumentLink>> {
+            Ok(vec![link(0, json!("foo")), link(1, json!("bar"))])
+        }
+
+        async fn resolve_link(&self, mut link: DocumentLink) -> Result<Option<DocumentLink>> {
+            if link.data == Some(json!("stale")) {
+                return Ok(None);
+            }
+
+            let path = format!(
+                "file:///{}.tex",
+                link.data.as_ref().unwrap().as_str().unwrap()
+            );
+            link.target = Some(Url::parse(&path).unwrap());
+            Ok(Some(link))
+        }
+
+        fn recompute_unknown_links(&self) -> bool {
+            self.recompute
+        }
+    }
+
+    fn stale_link(line: u64) -> DocumentLink {
+        wrap_data(
+            Url::parse("file:///main.tex").unwrap(),
+            link(line, json!("stale")),
+        )
+    }
+
+    #[test]
+    fn resolve_known_link() {
+        let provider = Provider { recompute: false };
+        let params = DocumentLinkParams {
+            text_document: TextDocumentIdentifier::new(Url::parse("file:///main.tex").unwrap()),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let links = block_on(provider.handle_document_link(params)).unwrap();
+        let link = block_on(provider.handle_document_link_resolve(links[0].clone())).unwrap();
+        assert_eq!(link.target, Some(Url::parse("file:///foo.tex").unwrap()));
+    }
+
+    #[test]
+    fn resolve_unknown_link() {
+        let provider = Provider { recompute: false };
+        assert!(block_on(provider.handle_document_link_resolve(stale_link(1))).is_err());
+
+        let provider = Provider { recompute: true };
+        let link = block_on(provider.handle_document_link_resolve(stale_link(1))).unwrap();
+        assert_eq!(link.target, Some(Url::parse("file:///bar.tex").unwrap()));
+        assert!(block_on(provider.handle_document_link_resolve(stale_link(2))).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{i18n, jsonrpc::*, ServerContext};
+use std::{collections::HashSet, fmt, sync::Arc};
+
+/// A function that returns the number of bytes that the server currently uses.
+pub type MemoryProbe = dyn Fn() -> u64 + Send + Sync;
+
+/// A function that releases memory, e.g. by dropping caches or closing cold documents.
+/// It receives the usage that has exceeded the limit.
+pub type ShrinkHook = dyn Fn(u64) + Send + Sync;
+
+/// Keeps the memory usage of the server below a soft limit,
+/// so the server stays alive instead of being killed by the operating system in the middle of a session.
+///
+/// The probe is queried before every request. If the usage exceeds the limit,
+/// the shrink hooks are called and requests of low-priority methods are answered
+/// with a `ServerCancelled` error until the usage has dropped below the limit again.
+#[derive(Clone)]
+pub struct MemoryLimit {
+    soft_limit: u64,
+    probe: Arc<MemoryProbe>,
+    shrink_hooks: Vec<Arc<ShrinkHook>>,
+    low_priority: HashSet<String>,
+}
+
+impl MemoryLimit {
+    /// Creates a limit of the given number of bytes for the usage that is reported by the probe.
+    pub fn new(soft_limit: u64, probe: Arc<MemoryProbe>) -> Self {
+        Self {
+            soft_limit,
+            probe,
+            shrink_hooks: Vec::new(),
+            low_priority: HashSet::new(),
+        }
+    }
+
+    /// Calls the given function every time the usage exceeds the limit.
+    pub fn with_shrink_hook(mut self, hook: Arc<ShrinkHook>) -> Self {
+        self.shrink_hooks.push(hook);
+        self
+    }
+
+    /// Sheds the requests of the given method while the usage exceeds the limit,
+    /// e.g. `textDocument/documentHighlight`.
+    pub fn with_low_priority(mut self, method: String) -> Self {
+        self.low_priority.insert(method);
+        self
+    }
+
+    /// Returns `true` if the usage currently exceeds the limit.
+    pub fn is_exceeded(&self) -> bool {
+        (self.probe)() > self.soft_limit
+    }
+
+    // Tries to shrink the usage if it exceeds the limit and returns `false` if the request has to be shed.
+    pub(crate) fn admit(&self, method: &str) -> bool {
+        let usage = (self.probe)();
+        if usage <= self.soft_limit {
+            return true;
+        }
+
+        log::warn!(
+            "The server uses {} bytes, which exceeds the soft limit of {} bytes",
+            usage,
+            self.soft_limit
+        );
+
+        for hook in &self.shrink_hooks {
+            hook(usage);
+        }
+
+        !self.low_priority.contains(method) || !self.is_exceeded()
+    }
+}
+
+impl fmt::Debug for MemoryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryLimit")
+            .field("soft_limit", &self.soft_limit)
+            .field("low_priority", &self.low_priority)
+            .finish()
+    }
+}
+
+// The response to a request that has been shed because of the memory limit.
+pub(crate) fn shed(request: &Request, context: &ServerContext) -> Response {
+    let message = context.localize(i18n::MEMORY_SHED, &[&request.method]);
+    Response::error(
+        Error::server_cancelled_error(message),
+        Some(request.id.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn limit(usage: &Arc<AtomicU64>) -> MemoryLimit {
+        let probe = Arc::clone(usage);
+        MemoryLimit::new(100, Arc::new(move || probe.load(Ordering::SeqCst)))
+            .with_low_priority("textDocument/documentHighlight".into())
+    }
+
+    #[test]
+    fn shed_low_priority_requests() {
+        let usage = Arc::new(AtomicU64::new(50));
+        let limit = limit(&usage);
+        assert!(limit.admit("textDocument/documentHighlight"));
+
+        usage.store(150, Ordering::SeqCst);
+        assert!(!limit.admit("textDocument/documentHighlight"));
+        assert!(limit.admit("textDocument/completion"));
+    }
+
+    #[test]
+    fn shrink_before_shedding() {
+        let usage = Arc::new(AtomicU64::new(150));
+        let shrunk = Arc::clone(&usage);
+        let limit = limit(&usage).with_shrink_hook(Arc::new(move |_| {
+            shrunk.store(80, Ordering::SeqCst);
+        }));
+
+        assert!(limit.admit("textDocument/documentHighlight"));
+        assert_eq!(usage.load(Ordering::SeqCst), 80);
+    }
+}
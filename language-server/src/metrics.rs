@@ -0,0 +1,710 @@
+use crate::{
+    jsonrpc::*, Clock, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext,
+    SystemClock,
+};
+use async_trait::async_trait;
+use futures::{
+    future::FutureObj,
+    task::{Spawn, SpawnError},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+const DEFAULT_BACKLOG_THRESHOLD: usize = 64;
+
+const DEFAULT_SIZE_BUCKETS: &[f64] = &[
+    256.0,
+    1024.0,
+    4096.0,
+    16384.0,
+    65536.0,
+    262_144.0,
+    1_048_576.0,
+];
+
+const DEFAULT_LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// The origin of a task that is spawned on the executor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskOrigin {
+    /// The task handles a request of the client.
+    Request,
+
+    /// The task writes the outgoing messages.
+    Output,
+
+    /// The task has been spawned by the server, e.g. to run a background analysis.
+    Background,
+}
+
+impl TaskOrigin {
+    fn index(self) -> usize {
+        match self {
+            Self::Request => 0,
+            Self::Output => 1,
+            Self::Background => 2,
+        }
+    }
+}
+
+/// A snapshot of the tasks of one origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TaskGauges {
+    /// The number of tasks that have been spawned but not polled yet.
+    pub queued: usize,
+
+    /// The number of tasks that have been polled at least once and have not finished yet.
+    pub in_flight: usize,
+}
+
+/// Counts the queued and in-flight tasks of the service per origin.
+///
+/// A warning is logged whenever the number of pending request handlers reaches the backlog threshold,
+/// which usually means that the client perceives the server as slow.
+/// Cloned instances share their counters.
+#[derive(Debug, Clone)]
+pub struct ExecutorMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+#[derive(Debug)]
+struct MetricsInner {
+    counters: [Counters; 3],
+    backlog_threshold: usize,
+    output_backlog: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+impl Default for ExecutorMetrics {
+    fn default() -> Self {
+        Self::with_backlog_threshold(DEFAULT_BACKLOG_THRESHOLD)
+    }
+}
+
+impl ExecutorMetrics {
+    /// Creates metrics that warn once the given number of request handlers is pending.
+    pub fn with_backlog_threshold(backlog_threshold: usize) -> Self {
+        Self {
+            inner: Arc::new(MetricsInner {
+                counters: Default::default(),
+                backlog_threshold,
+                output_backlog: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the current gauges of the given origin.
+    pub fn gauges(&self, origin: TaskOrigin) -> TaskGauges {
+        let counters = &self.inner.counters[origin.index()];
+        TaskGauges {
+            queued: counters.queued.load(Ordering::SeqCst),
+            in_flight: counters.in_flight.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Returns the number of messages that the service is currently flushing to the transport.
+    ///
+    /// A value above one means that messages have piled up because the transport cannot keep up,
+    /// e.g. a remote connection.
+    pub fn output_backlog(&self) -> usize {
+        self.inner.output_backlog.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if request handlers are pending or the output is backed up,
+    /// so low-priority messages should be held back.
+    pub fn is_saturated(&self) -> bool {
+        let requests = self.gauges(TaskOrigin::Request);
+        requests.queued + requests.in_flight > 0 || self.output_backlog() > 1
+    }
+
+    pub(crate) fn record_output_backlog(&self, backlog: usize) {
+        self.inner.output_backlog.store(backlog, Ordering::SeqCst);
+    }
+
+    /// Wraps the future, so it is counted as a task of the given origin.
+    pub fn track<F>(&self, origin: TaskOrigin, future: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+    {
+        let mut guard = TaskGuard::new(Arc::clone(&self.inner), origin);
+        async move {
+            guard.start();
+            future.await
+        }
+    }
+}
+
+// Keeps the counters up to date, even if the executor drops the task without polling it.
+struct TaskGuard {
+    inner: Arc<MetricsInner>,
+    origin: TaskOrigin,
+    started: bool,
+}
+
+impl TaskGuard {
+    fn new(inner: Arc<MetricsInner>, origin: TaskOrigin) -> Self {
+        let counters = &inner.counters[origin.index()];
+        let backlog = counters.queued.fetch_add(1, Ordering::SeqCst)
+            + counters.in_flight.load(Ordering::SeqCst)
+            + 1;
+
+        if origin == TaskOrigin::Request && backlog == inner.backlog_threshold {
+            log::warn!(
+                "{} requests are pending, the server cannot keep up with the client",
+                backlog
+            );
+        }
+
+        Self {
+            inner,
+            origin,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) {
+        let counters = &self.inner.counters[self.origin.index()];
+        counters.in_flight.fetch_add(1, Ordering::SeqCst);
+        counters.queued.fetch_sub(1, Ordering::SeqCst);
+        self.started = true;
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let counters = &self.inner.counters[self.origin.index()];
+        if self.started {
+            counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            counters.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Wraps an executor and counts the tasks that are spawned on it.
+///
+/// The service instruments the request handlers on its own.
+/// Servers can use this wrapper to include their background tasks in the metrics.
+#[derive(Debug, Clone)]
+pub struct InstrumentedExecutor<E> {
+    executor: E,
+    metrics: ExecutorMetrics,
+    origin: TaskOrigin,
+}
+
+impl<E> InstrumentedExecutor<E> {
+    /// Wraps the executor, so spawned tasks are counted as background tasks.
+    pub fn new(executor: E, metrics: ExecutorMetrics) -> Self {
+        Self {
+            executor,
+            metrics,
+            origin: TaskOrigin::Background,
+        }
+    }
+
+    /// Returns a wrapper that counts spawned tasks for the given origin instead.
+    pub fn with_origin(self, origin: TaskOrigin) -> Self {
+        Self { origin, ..self }
+    }
+}
+
+impl<E: Spawn> Spawn for InstrumentedExecutor<E> {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        let future = self.metrics.track(self.origin, future);
+        self.executor.spawn_obj(FutureObj::new(Box::new(future)))
+    }
+
+    fn status(&self) -> Result<(), SpawnError> {
+        self.executor.status()
+    }
+}
+
+/// A histogram with fixed buckets.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram with the given upper bounds of the buckets in ascending order.
+    ///
+    /// Values that exceed the last bound are counted in an additional overflow bucket.
+    pub fn new(bounds: Vec<f64>) -> Self {
+        let counts = vec![0; bounds.len() + 1];
+        Self {
+            bounds,
+            counts,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Adds a value to the histogram.
+    pub fn record(&mut self, value: f64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Returns the upper bounds of the buckets.
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// Returns the number of values per bucket, including the overflow bucket.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the sum of all values.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the number of values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Estimates the value below which the given fraction of the values falls, e.g. `0.95`,
+    /// by interpolating linearly within the bucket that contains it.
+    ///
+    /// Values in the overflow bucket are estimated with the last bound.
+    /// Returns `None` if the histogram is empty.
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = quantile * self.count as f64;
+        let mut cumulative = 0;
+        for (index, bound) in self.bounds.iter().enumerate() {
+            let count = self.counts[index];
+            if count > 0 && (cumulative + count) as f64 >= rank {
+                let lower = if index == 0 {
+                    0.0
+                } else {
+                    self.bounds[index - 1]
+                };
+                let fraction = (rank - cumulative as f64) / count as f64;
+                return Some(lower + (bound - lower) * fraction);
+            }
+            cumulative += count;
+        }
+
+        self.bounds.last().copied().or(Some(0.0))
+    }
+
+    fn write_prometheus(&self, output: &mut String, name: &str, method: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                output,
+                "{}_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                name, method, bound, cumulative
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "{}_bucket{{method=\"{}\",le=\"+Inf\"}} {}",
+            name, method, self.count
+        );
+        let _ = writeln!(output, "{}_sum{{method=\"{}\"}} {}", name, method, self.sum);
+        let _ = writeln!(
+            output,
+            "{}_count{{method=\"{}\"}} {}",
+            name, method, self.count
+        );
+    }
+}
+
+/// The histograms of a single method.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodMetrics {
+    /// The sizes of the parameters of the incoming messages in bytes.
+    pub request_size: Histogram,
+
+    /// The sizes of the results or errors of the outgoing responses in bytes.
+    pub response_size: Histogram,
+
+    /// The time between receiving a request and sending its response in seconds.
+    pub latency: Histogram,
+
+    /// The number of requests that have been cancelled by the client.
+    pub cancelled: u64,
+
+    /// The number of requests that have been answered.
+    pub requests: u64,
+
+    /// The number of requests that have been answered with an error.
+    pub errors: u64,
+}
+
+impl MethodMetrics {
+    /// Returns the estimated median, 95th and 99th percentile of the latency in seconds.
+    pub fn latency_percentiles(&self) -> Option<(f64, f64, f64)> {
+        let median = self.latency.quantile(0.5)?;
+        let p95 = self.latency.quantile(0.95)?;
+        let p99 = self.latency.quantile(0.99)?;
+        Some((median, p95, p99))
+    }
+}
+
+/// The metrics that have been recorded by a [`MetricsMiddleware`](struct.MetricsMiddleware.html).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    /// The metrics per method.
+    pub methods: BTreeMap<String, MethodMetrics>,
+}
+
+impl MetricsSnapshot {
+    /// Serializes the snapshot to JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+
+    /// Serializes the snapshot in the text format of Prometheus.
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+        self.write_prometheus(&mut output, "lsp_request_size_bytes", |metrics| {
+            &metrics.request_size
+        });
+        self.write_prometheus(&mut output, "lsp_response_size_bytes", |metrics| {
+            &metrics.response_size
+        });
+        self.write_prometheus(&mut output, "lsp_latency_seconds", |metrics| {
+            &metrics.latency
+        });
+
+        self.write_counter(&mut output, "lsp_cancelled_total", |metrics| {
+            metrics.cancelled
+        });
+        self.write_counter(&mut output, "lsp_requests_total", |metrics| {
+            metrics.requests
+        });
+        self.write_counter(&mut output, "lsp_errors_total", |metrics| metrics.errors);
+        output
+    }
+
+    fn write_counter<F>(&self, output: &mut String, name: &str, counter: F)
+    where
+        F: Fn(&MethodMetrics) -> u64,
+    {
+        let _ = writeln!(output, "# TYPE {} counter", name);
+        for (method, metrics) in &self.methods {
+            let _ = writeln!(
+                output,
+                "{}{{method=\"{}\"}} {}",
+                name,
+                method,
+                counter(metrics)
+            );
+        }
+    }
+
+    fn write_prometheus<F>(&self, output: &mut String, name: &str, histogram: F)
+    where
+        F: Fn(&MethodMetrics) -> &Histogram,
+    {
+        let _ = writeln!(output, "# TYPE {} histogram", name);
+        for (method, metrics) in &self.methods {
+            histogram(metrics).write_prometheus(output, name, method);
+        }
+    }
+}
+
+/// Middleware that records the payload sizes, latencies, errors and cancellations of the messages per method.
+///
+/// Cloned middlewares share their metrics.
+#[derive(Debug, Clone)]
+pub struct MetricsMiddleware {
+    methods: Arc<Mutex<BTreeMap<String, MethodMetrics>>>,
+    size_buckets: Vec<f64>,
+    latency_buckets: Vec<f64>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MetricsMiddleware {
+    fn default() -> Self {
+        Self {
+            methods: Arc::default(),
+            size_buckets: DEFAULT_SIZE_BUCKETS.to_vec(),
+            latency_buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl MetricsMiddleware {
+    /// Creates a middleware with buckets from 256 bytes to 1 MiB and from 5 milliseconds to 2.5 seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the upper bounds of the buckets of the payload sizes in bytes.
+    pub fn with_size_buckets(self, size_buckets: Vec<f64>) -> Self {
+        Self {
+            size_buckets,
+            ..self
+        }
+    }
+
+    /// Sets the upper bounds of the buckets of the latencies in seconds.
+    pub fn with_latency_buckets(self, latency_buckets: Vec<f64>) -> Self {
+        Self {
+            latency_buckets,
+            ..self
+        }
+    }
+
+    /// Sets the clock that measures the latencies. It needs to match the clock of the service.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Returns a copy of the recorded metrics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            methods: self.methods.lock().unwrap().clone(),
+        }
+    }
+
+    fn update<F: FnOnce(&mut MethodMetrics)>(&self, method: &str, update: F) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods
+            .entry(method.to_owned())
+            .or_insert_with(|| MethodMetrics {
+                request_size: Histogram::new(self.size_buckets.clone()),
+                response_size: Histogram::new(self.size_buckets.clone()),
+                latency: Histogram::new(self.latency_buckets.clone()),
+                cancelled: 0,
+                requests: 0,
+                errors: 0,
+            });
+        update(metrics);
+    }
+}
+
+// Returns the length of the serialized value.
+fn payload_size<T: Serialize>(value: &T) -> f64 {
+    serde_json::to_vec(value)
+        .map(|json| json.len())
+        .unwrap_or(0) as f64
+}
+
+#[async_trait]
+impl Middleware for MetricsMiddleware {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        let (method, params) = match message {
+            Message::Request(request) => (&request.method, &request.params),
+            Message::Notification(notification) => (&notification.method, &notification.params),
+            Message::Response(_) => return MessageFlow::Continue,
+        };
+
+        let size = payload_size(params);
+        self.update(method, |metrics| metrics.request_size.record(size));
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        request: &Request,
+        metadata: &MessageMetadata,
+        response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        let size = match (&response.result, &response.error) {
+            (Some(result), _) => payload_size(result),
+            (None, Some(error)) => payload_size(error),
+            (None, None) => 0.0,
+        };
+
+        let latency = self
+            .clock
+            .now()
+            .saturating_duration_since(metadata.received_at)
+            .as_secs_f64();
+
+        self.update(&request.method, |metrics| {
+            metrics.response_size.record(size);
+            metrics.latency.record(latency);
+            metrics.requests += 1;
+            if response.error.is_some() {
+                metrics.errors += 1;
+            }
+        });
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_request_cancelled(
+        &self,
+        _id: &Id,
+        method: &str,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        self.update(method, |metrics| metrics.cancelled += 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{
+        channel::oneshot,
+        executor::LocalPool,
+        future::pending,
+        task::{LocalSpawnExt, SpawnExt},
+    };
+
+    #[test]
+    fn gauges_follow_task_lifecycle() {
+        let metrics = ExecutorMetrics::default();
+        let (tx, rx) = oneshot::channel::<()>();
+        let task = metrics.track(TaskOrigin::Request, async move {
+            let _ = rx.await;
+        });
+
+        let expected = TaskGauges {
+            queued: 1,
+            in_flight: 0,
+        };
+        assert_eq!(metrics.gauges(TaskOrigin::Request), expected);
+
+        let mut pool = LocalPool::new();
+        pool.spawner().spawn_local(task).unwrap();
+        pool.run_until_stalled();
+        let expected = TaskGauges {
+            queued: 0,
+            in_flight: 1,
+        };
+        assert_eq!(metrics.gauges(TaskOrigin::Request), expected);
+
+        tx.send(()).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(metrics.gauges(TaskOrigin::Request), TaskGauges::default());
+    }
+
+    #[test]
+    fn instrumented_executor() {
+        let metrics = ExecutorMetrics::default();
+        let mut pool = LocalPool::new();
+        let executor = InstrumentedExecutor::new(pool.spawner(), metrics.clone());
+        executor.spawn(pending::<()>()).unwrap();
+        executor
+            .clone()
+            .with_origin(TaskOrigin::Output)
+            .spawn(async {})
+            .unwrap();
+
+        pool.run_until_stalled();
+        let expected = TaskGauges {
+            queued: 0,
+            in_flight: 1,
+        };
+        assert_eq!(metrics.gauges(TaskOrigin::Background), expected);
+        assert_eq!(metrics.gauges(TaskOrigin::Output), TaskGauges::default());
+
+        drop(pool);
+        assert_eq!(
+            metrics.gauges(TaskOrigin::Background),
+            TaskGauges::default()
+        );
+    }
+
+    #[test]
+    fn histogram_prometheus() {
+        let mut histogram = Histogram::new(vec![1.0, 10.0]);
+        histogram.record(0.5);
+        histogram.record(5.0);
+        histogram.record(50.0);
+        assert_eq!(histogram.counts(), &[1, 1, 1]);
+        assert_eq!(histogram.sum(), 55.5);
+
+        let metrics = MethodMetrics {
+            request_size: histogram.clone(),
+            response_size: histogram.clone(),
+            latency: histogram,
+            cancelled: 2,
+            requests: 3,
+            errors: 1,
+        };
+        let mut snapshot = MetricsSnapshot::default();
+        snapshot.methods.insert("foo".into(), metrics);
+
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("# TYPE lsp_latency_seconds histogram\n"));
+        assert!(text.contains("lsp_request_size_bytes_bucket{method=\"foo\",le=\"10\"} 2\n"));
+        assert!(text.contains("lsp_response_size_bytes_bucket{method=\"foo\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("lsp_latency_seconds_sum{method=\"foo\"} 55.5\n"));
+        assert!(text.contains("lsp_cancelled_total{method=\"foo\"} 2\n"));
+        assert!(text.contains("lsp_requests_total{method=\"foo\"} 3\n"));
+        assert!(text.contains("lsp_errors_total{method=\"foo\"} 1\n"));
+        assert_eq!(
+            snapshot.to_json()["methods"]["foo"]["latency"]["counts"],
+            serde_json::json!([1, 1, 1])
+        );
+    }
+
+    #[test]
+    fn histogram_quantile() {
+        let mut histogram = Histogram::new(vec![1.0, 2.0, 4.0]);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for value in &[0.5, 1.5, 1.5, 3.0] {
+            histogram.record(*value);
+        }
+        assert_eq!(histogram.quantile(0.25), Some(1.0));
+        assert_eq!(histogram.quantile(0.5), Some(1.5));
+        assert_eq!(histogram.quantile(1.0), Some(4.0));
+
+        histogram.record(10.0);
+        assert_eq!(histogram.quantile(1.0), Some(4.0));
+    }
+}
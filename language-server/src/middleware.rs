@@ -1,30 +1,122 @@
-use crate::{jsonrpc::*, LanguageClient};
+use crate::{jsonrpc::*, LanguageClient, ServerContext};
 use async_trait::async_trait;
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
+
+/// Information about an incoming message that is recorded when the message is decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageMetadata {
+    /// The position of the message in the input stream, starting with zero.
+    pub sequence: u64,
+
+    /// The point in time at which the message has been received.
+    pub received_at: Instant,
+}
+
+/// Determines how an incoming message is processed after it has passed a middleware.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageFlow {
+    /// Passes the message on to the next middleware and finally to the server.
+    Continue,
+
+    /// Answers a request with the given response without passing it on.
+    /// The response passes `on_outgoing_response` of all middlewares as usual.
+    ///
+    /// Notifications and responses are dropped instead.
+    Respond(Response),
+
+    /// Drops the message without passing it on.
+    ///
+    /// A request that is dropped is never answered, so the client should be informed otherwise.
+    Drop,
+}
 
 /// Allows to do additional work before and/or after processing the message.
+///
+/// Every hook receives the [`ServerContext`](struct.ServerContext.html) of the session.
+/// The middlewares of a service run in the order of their [`priority`](#method.priority)
+/// and in the order in which they have been attached if their priorities are equal.
 #[async_trait]
 pub trait Middleware: Send + Sync {
+    /// Returns the name that identifies the middleware in diagnostics.
+    ///
+    /// Defaults to the name of the type.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Returns the priority of the middleware. Middlewares with a lower priority run first.
+    ///
+    /// Defaults to zero, which is also the priority of the `LoggingMiddleware`,
+    /// so a negative priority runs before logging and a positive priority runs after it.
+    fn priority(&self) -> i32 {
+        0
+    }
+
     /// Method invoked before an incoming message is being processed.
-    async fn on_incoming_message(&self, message: &mut Message, client: Arc<dyn LanguageClient>);
+    ///
+    /// The returned flow decides whether the message is passed on to the next middleware and the server.
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        metadata: &MessageMetadata,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow;
 
     /// Method invoked before an outgoing response is being sent.
+    ///
+    /// The metadata belongs to the request that is being answered.
     async fn on_outgoing_response(
         &self,
         request: &Request,
+        metadata: &MessageMetadata,
         response: &mut Response,
+        context: &ServerContext,
         client: Arc<dyn LanguageClient>,
     );
 
     /// Method invoked before an outgoing request is being sent.
-    async fn on_outgoing_request(&self, request: &mut Request, client: Arc<dyn LanguageClient>);
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    );
 
     /// Method invoked before an outgoing notification is being sent.
     async fn on_outgoing_notification(
         &self,
         notification: &mut Notification,
+        context: &ServerContext,
         client: Arc<dyn LanguageClient>,
     );
+
+    /// Method invoked when a message has been decoded from the input stream,
+    /// before the service or any middleware has processed it.
+    fn on_message_received(&self, _message: &Message, _metadata: &MessageMetadata) {}
+
+    /// Method invoked when a message is written to the output stream, after all other hooks have run.
+    ///
+    /// Unlike `on_outgoing_response`, it also observes the responses of the service itself,
+    /// e.g. to messages that cannot be parsed or that the lifecycle of the protocol does not permit.
+    fn on_message_sent(&self, _message: &Message) {}
+
+    /// Method invoked when the client cancels a request that is still being processed.
+    ///
+    /// The response to the request passes `on_outgoing_response` afterwards as usual.
+    async fn on_request_cancelled(
+        &self,
+        _id: &Id,
+        _method: &str,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+}
+
+// Orders the middlewares by their priority and keeps the order of middlewares with equal priority.
+pub(crate) fn sort_by_priority(middlewares: &mut Vec<Arc<dyn Middleware>>) {
+    middlewares.sort_by_key(|middleware| middleware.priority());
 }
 
 #[derive(Clone)]
@@ -34,31 +126,48 @@ pub struct AggregateMiddleware {
 
 #[async_trait]
 impl Middleware for AggregateMiddleware {
-    async fn on_incoming_message(&self, message: &mut Message, client: Arc<dyn LanguageClient>) {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        metadata: &MessageMetadata,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
         for middleware in &self.middlewares {
-            middleware
-                .on_incoming_message(message, Arc::clone(&client))
+            let flow = middleware
+                .on_incoming_message(message, metadata, context, Arc::clone(&client))
                 .await;
+            if flow != MessageFlow::Continue {
+                return flow;
+            }
         }
+        MessageFlow::Continue
     }
 
     async fn on_outgoing_response(
         &self,
         request: &Request,
+        metadata: &MessageMetadata,
         response: &mut Response,
+        context: &ServerContext,
         client: Arc<dyn LanguageClient>,
     ) {
         for middleware in &self.middlewares {
             middleware
-                .on_outgoing_response(request, response, Arc::clone(&client))
+                .on_outgoing_response(request, metadata, response, context, Arc::clone(&client))
                 .await;
         }
     }
 
-    async fn on_outgoing_request(&self, request: &mut Request, client: Arc<dyn LanguageClient>) {
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
         for middleware in &self.middlewares {
             middleware
-                .on_outgoing_request(request, Arc::clone(&client))
+                .on_outgoing_request(request, context, Arc::clone(&client))
                 .await;
         }
     }
@@ -66,14 +175,41 @@ impl Middleware for AggregateMiddleware {
     async fn on_outgoing_notification(
         &self,
         notification: &mut Notification,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        for middleware in &self.middlewares {
+            middleware
+                .on_outgoing_notification(notification, context, Arc::clone(&client))
+                .await;
+        }
+    }
+
+    async fn on_request_cancelled(
+        &self,
+        id: &Id,
+        method: &str,
+        context: &ServerContext,
         client: Arc<dyn LanguageClient>,
     ) {
         for middleware in &self.middlewares {
             middleware
-                .on_outgoing_notification(notification, Arc::clone(&client))
+                .on_request_cancelled(id, method, context, Arc::clone(&client))
                 .await;
         }
     }
+
+    fn on_message_received(&self, message: &Message, metadata: &MessageMetadata) {
+        for middleware in &self.middlewares {
+            middleware.on_message_received(message, metadata);
+        }
+    }
+
+    fn on_message_sent(&self, message: &Message) {
+        for middleware in &self.middlewares {
+            middleware.on_message_sent(message);
+        }
+    }
 }
 
 /// Middleware that logs every incoming and outgoing message.
@@ -93,7 +229,13 @@ impl LoggingMiddleware {
 
 #[async_trait]
 impl Middleware for LoggingMiddleware {
-    async fn on_incoming_message(&self, message: &mut Message, _client: Arc<dyn LanguageClient>) {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
         let kind = match message {
             Message::Request(_) => "request",
             Message::Notification(_) => "notification",
@@ -101,26 +243,122 @@ impl Middleware for LoggingMiddleware {
         };
 
         Self::log_message(message, &format!("Received {} (->)", kind));
+        MessageFlow::Continue
     }
 
     async fn on_outgoing_response(
         &self,
         _request: &Request,
+        _metadata: &MessageMetadata,
         response: &mut Response,
+        _context: &ServerContext,
         _client: Arc<dyn LanguageClient>,
     ) {
         Self::log_message(response, "Sent response (<-)");
     }
 
-    async fn on_outgoing_request(&self, request: &mut Request, _client: Arc<dyn LanguageClient>) {
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
         Self::log_message(request, "Sent request (<-)");
     }
 
     async fn on_outgoing_notification(
         &self,
         notification: &mut Notification,
+        _context: &ServerContext,
         _client: Arc<dyn LanguageClient>,
     ) {
         Self::log_message(notification, "Sent notification (<-)");
     }
+
+    async fn on_request_cancelled(
+        &self,
+        id: &Id,
+        method: &str,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        log::trace!("Cancelled request {:?} ({})", id, method);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Prioritized(i32);
+
+    #[async_trait]
+    impl Middleware for Prioritized {
+        fn priority(&self) -> i32 {
+            self.0
+        }
+
+        async fn on_incoming_message(
+            &self,
+            _message: &mut Message,
+            _metadata: &MessageMetadata,
+            _context: &ServerContext,
+            _client: Arc<dyn LanguageClient>,
+        ) -> MessageFlow {
+            MessageFlow::Continue
+        }
+
+        async fn on_outgoing_response(
+            &self,
+            _request: &Request,
+            _metadata: &MessageMetadata,
+            _response: &mut Response,
+            _context: &ServerContext,
+            _client: Arc<dyn LanguageClient>,
+        ) {
+        }
+
+        async fn on_outgoing_request(
+            &self,
+            _request: &mut Request,
+            _context: &ServerContext,
+            _client: Arc<dyn LanguageClient>,
+        ) {
+        }
+
+        async fn on_outgoing_notification(
+            &self,
+            _notification: &mut Notification,
+            _context: &ServerContext,
+            _client: Arc<dyn LanguageClient>,
+        ) {
+        }
+    }
+
+    #[test]
+    fn sort_middlewares_by_priority() {
+        let mut middlewares: Vec<Arc<dyn Middleware>> = vec![
+            Arc::new(Prioritized(1)),
+            Arc::new(LoggingMiddleware),
+            Arc::new(Prioritized(-1)),
+            Arc::new(Prioritized(0)),
+        ];
+        sort_by_priority(&mut middlewares);
+
+        let names: Vec<_> = middlewares
+            .iter()
+            .map(|middleware| (middleware.priority(), middleware.name()))
+            .collect();
+        let prioritized = std::any::type_name::<Prioritized>();
+        let logging = std::any::type_name::<LoggingMiddleware>();
+        assert_eq!(
+            names,
+            vec![
+                (-1, prioritized),
+                (0, logging),
+                (0, prioritized),
+                (1, prioritized)
+            ]
+        );
+    }
 }
@@ -0,0 +1,312 @@
+use lsp_types::*;
+#[cfg(feature = "proposed")]
+use std::fmt;
+
+/// Builds the `CompletionOptions` of the server capabilities.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionOptionsBuilder {
+    options: CompletionOptions,
+}
+
+impl CompletionOptionsBuilder {
+    /// Creates a builder without trigger characters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a character that triggers completion automatically. Duplicates are ignored.
+    pub fn trigger_character(mut self, character: char) -> Self {
+        let characters = self.options.trigger_characters.get_or_insert_with(Vec::new);
+        let character = character.to_string();
+        if !characters.contains(&character) {
+            characters.push(character);
+        }
+        self
+    }
+
+    /// Adds multiple characters that trigger completion automatically.
+    pub fn trigger_characters<I: IntoIterator<Item = char>>(self, characters: I) -> Self {
+        characters.into_iter().fold(self, |builder, character| {
+            builder.trigger_character(character)
+        })
+    }
+
+    /// Announces support for `completionItem/resolve`.
+    pub fn resolve_provider(mut self, resolve_provider: bool) -> Self {
+        self.options.resolve_provider = Some(resolve_provider);
+        self
+    }
+
+    /// Announces support for work done progress.
+    pub fn work_done_progress(mut self, work_done_progress: bool) -> Self {
+        self.options.work_done_progress_options.work_done_progress = Some(work_done_progress);
+        self
+    }
+
+    /// Returns the options.
+    pub fn build(self) -> CompletionOptions {
+        self.options
+    }
+}
+
+/// Builds the `CodeActionOptions` of the server capabilities.
+#[derive(Debug, Clone)]
+pub struct CodeActionOptionsBuilder {
+    options: CodeActionOptions,
+}
+
+impl Default for CodeActionOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: CodeActionOptions {
+                code_action_kinds: None,
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            },
+        }
+    }
+}
+
+impl CodeActionOptionsBuilder {
+    /// Creates a builder without code action kinds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a kind of code actions that the server may return. Duplicates are ignored.
+    pub fn kind(mut self, kind: CodeActionKind) -> Self {
+        let kinds = self.options.code_action_kinds.get_or_insert_with(Vec::new);
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+        self
+    }
+
+    /// Adds multiple kinds of code actions that the server may return.
+    pub fn kinds<I: IntoIterator<Item = CodeActionKind>>(self, kinds: I) -> Self {
+        kinds
+            .into_iter()
+            .fold(self, |builder, kind| builder.kind(kind))
+    }
+
+    /// Announces support for work done progress.
+    pub fn work_done_progress(mut self, work_done_progress: bool) -> Self {
+        self.options.work_done_progress_options.work_done_progress = Some(work_done_progress);
+        self
+    }
+
+    /// Returns the options.
+    pub fn build(self) -> CodeActionOptions {
+        self.options
+    }
+}
+
+/// Builds the `SemanticTokensOptions` of the server capabilities.
+///
+/// The token types and modifiers are added to the legend in the given order.
+/// [`SemanticTokensLegendExt`](trait.SemanticTokensLegendExt.html) maps them to the indices
+/// that are used to encode the tokens.
+#[cfg_attr(docsrs, doc(cfg(feature = "proposed")))]
+#[cfg(feature = "proposed")]
+#[derive(Debug, Clone, Default)]
+pub struct SemanticTokensOptionsBuilder {
+    options: SemanticTokensOptions,
+}
+
+#[cfg(feature = "proposed")]
+impl SemanticTokensOptionsBuilder {
+    /// Creates a builder with an empty legend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a token type to the legend.
+    pub fn token_type(mut self, token_type: SemanticTokenType) -> Self {
+        self.options.legend.token_types.push(token_type);
+        self
+    }
+
+    /// Adds a token modifier to the legend.
+    pub fn token_modifier(mut self, token_modifier: SemanticTokenModifier) -> Self {
+        self.options.legend.token_modifiers.push(token_modifier);
+        self
+    }
+
+    /// Announces support for `textDocument/semanticTokens/range`.
+    pub fn range(mut self, range: bool) -> Self {
+        self.options.range_provider = Some(range);
+        self
+    }
+
+    /// Announces support for semantic tokens of full documents, optionally as deltas.
+    pub fn full(mut self, delta: bool) -> Self {
+        self.options.document_provider = Some(if delta {
+            SemanticTokensDocumentProvider::Edits { edits: Some(true) }
+        } else {
+            SemanticTokensDocumentProvider::Bool(true)
+        });
+        self
+    }
+
+    /// Announces support for work done progress.
+    pub fn work_done_progress(mut self, work_done_progress: bool) -> Self {
+        self.options.work_done_progress_options.work_done_progress = Some(work_done_progress);
+        self
+    }
+
+    /// Validates the legend and returns the options.
+    pub fn build(self) -> Result<SemanticTokensOptions, LegendError> {
+        let legend = &self.options.legend;
+        for (i, token_type) in legend.token_types.iter().enumerate() {
+            if legend.token_types[..i].contains(token_type) {
+                return Err(LegendError::DuplicateTokenType(
+                    token_type.as_str().to_owned(),
+                ));
+            }
+        }
+
+        for (i, token_modifier) in legend.token_modifiers.iter().enumerate() {
+            if legend.token_modifiers[..i].contains(token_modifier) {
+                return Err(LegendError::DuplicateTokenModifier(
+                    token_modifier.as_str().to_owned(),
+                ));
+            }
+        }
+
+        if legend.token_modifiers.len() > 32 {
+            return Err(LegendError::TooManyTokenModifiers(
+                legend.token_modifiers.len(),
+            ));
+        }
+
+        Ok(self.options)
+    }
+}
+
+/// Maps the token types and modifiers of a `SemanticTokensLegend` to their encoded values.
+#[cfg_attr(docsrs, doc(cfg(feature = "proposed")))]
+#[cfg(feature = "proposed")]
+pub trait SemanticTokensLegendExt {
+    /// Returns the index of the token type or `None` if it is not part of the legend.
+    fn token_type_index(&self, token_type: &SemanticTokenType) -> Option<u32>;
+
+    /// Returns the bit set of the token modifiers or `None` if one of them is not part of the legend.
+    fn token_modifier_set(&self, token_modifiers: &[SemanticTokenModifier]) -> Option<u32>;
+}
+
+#[cfg(feature = "proposed")]
+impl SemanticTokensLegendExt for SemanticTokensLegend {
+    fn token_type_index(&self, token_type: &SemanticTokenType) -> Option<u32> {
+        self.token_types
+            .iter()
+            .position(|other| other == token_type)
+            .map(|index| index as u32)
+    }
+
+    fn token_modifier_set(&self, token_modifiers: &[SemanticTokenModifier]) -> Option<u32> {
+        let mut set = 0;
+        for token_modifier in token_modifiers {
+            let index = self
+                .token_modifiers
+                .iter()
+                .position(|other| other == token_modifier)?;
+            set |= 1 << index;
+        }
+        Some(set)
+    }
+}
+
+/// An error that indicates an invalid `SemanticTokensLegend`.
+#[cfg_attr(docsrs, doc(cfg(feature = "proposed")))]
+#[cfg(feature = "proposed")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegendError {
+    /// The token type has been added more than once.
+    DuplicateTokenType(String),
+
+    /// The token modifier has been added more than once.
+    DuplicateTokenModifier(String),
+
+    /// The modifiers do not fit into the bit set of a token.
+    TooManyTokenModifiers(usize),
+}
+
+#[cfg(feature = "proposed")]
+impl fmt::Display for LegendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateTokenType(name) => write!(f, "duplicate token type: {}", name),
+            Self::DuplicateTokenModifier(name) => write!(f, "duplicate token modifier: {}", name),
+            Self::TooManyTokenModifiers(count) => {
+                write!(f, "{} token modifiers exceed the limit of 32", count)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "proposed")]
+impl std::error::Error for LegendError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completion_options_without_duplicates() {
+        let options = CompletionOptionsBuilder::new()
+            .trigger_characters("\\{\\".chars())
+            .resolve_provider(true)
+            .build();
+
+        assert_eq!(
+            options.trigger_characters,
+            Some(vec!["\\".to_owned(), "{".to_owned()])
+        );
+        assert_eq!(options.resolve_provider, Some(true));
+    }
+
+    #[test]
+    fn code_action_options_kinds() {
+        let options = CodeActionOptionsBuilder::new()
+            .kinds(vec![CodeActionKind::QUICKFIX, CodeActionKind::QUICKFIX])
+            .build();
+
+        assert_eq!(
+            options.code_action_kinds,
+            Some(vec![CodeActionKind::QUICKFIX])
+        );
+    }
+
+    #[cfg(feature = "proposed")]
+    #[test]
+    fn semantic_tokens_legend() {
+        let options = SemanticTokensOptionsBuilder::new()
+            .token_type(SemanticTokenType::KEYWORD)
+            .token_type(SemanticTokenType::COMMENT)
+            .token_modifier(SemanticTokenModifier::DEPRECATED)
+            .token_modifier(SemanticTokenModifier::STATIC)
+            .full(true)
+            .build()
+            .unwrap();
+
+        let legend = &options.legend;
+        assert_eq!(
+            legend.token_type_index(&SemanticTokenType::COMMENT),
+            Some(1)
+        );
+        assert_eq!(legend.token_type_index(&SemanticTokenType::STRING), None);
+        assert_eq!(
+            legend.token_modifier_set(&[
+                SemanticTokenModifier::STATIC,
+                SemanticTokenModifier::DEPRECATED
+            ]),
+            Some(0b11)
+        );
+
+        let error = SemanticTokensOptionsBuilder::new()
+            .token_type(SemanticTokenType::KEYWORD)
+            .token_type(SemanticTokenType::KEYWORD)
+            .build()
+            .unwrap_err();
+        assert_eq!(error, LegendError::DuplicateTokenType("keyword".to_owned()));
+    }
+}
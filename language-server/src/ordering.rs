@@ -0,0 +1,182 @@
+use crate::jsonrpc::{Message, Response};
+use futures::{channel::mpsc, lock::Mutex as AsyncMutex, sink::SinkExt};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Determines the order in which the responses of concurrently running requests are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseOrder {
+    /// Responses are written as soon as they are available.
+    Completion,
+
+    /// Responses are written in the order in which the requests have been received.
+    Arrival,
+
+    /// Responses of the given methods are written in the order in which their requests have been received.
+    ///
+    /// A method ending with `*` matches all methods that start with the preceding text,
+    /// e.g. `textDocument/*`. Responses of other methods are written as soon as they are available.
+    ArrivalFor(Vec<String>),
+}
+
+impl ResponseOrder {
+    fn applies_to(&self, method: &str) -> bool {
+        match self {
+            ResponseOrder::Completion => false,
+            ResponseOrder::Arrival => true,
+            ResponseOrder::ArrivalFor(methods) => methods.iter().any(|pattern| {
+                if pattern.ends_with('*') {
+                    method.starts_with(&pattern[..pattern.len() - 1])
+                } else {
+                    pattern == method
+                }
+            }),
+        }
+    }
+}
+
+// Buffers the responses of ordered requests until all previous responses have been written.
+#[derive(Debug, Clone)]
+pub struct ResponseSequencer {
+    order: ResponseOrder,
+    state: Arc<Mutex<SequencerState>>,
+    write_lock: Arc<AsyncMutex<()>>,
+}
+
+#[derive(Debug, Default)]
+struct SequencerState {
+    next_ticket: u64,
+    next_write: u64,
+    slots: BTreeMap<u64, Slot>,
+}
+
+#[derive(Debug)]
+enum Slot {
+    Pending,
+    Ready(Response),
+    Dropped,
+}
+
+impl ResponseSequencer {
+    pub fn new(order: ResponseOrder) -> Self {
+        Self {
+            order,
+            state: Arc::default(),
+            write_lock: Arc::default(),
+        }
+    }
+
+    // Reserves the position of a request. Needs to be called in the order in which the requests arrive.
+    pub fn ticket(&self, method: &str) -> Option<Ticket> {
+        if !self.order.applies_to(method) {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_ticket;
+        state.next_ticket += 1;
+        state.slots.insert(id, Slot::Pending);
+        Some(Ticket {
+            id,
+            sequencer: self.clone(),
+            completed: false,
+        })
+    }
+
+    fn complete(&self, id: u64, slot: Slot) {
+        self.state.lock().unwrap().slots.insert(id, slot);
+    }
+
+    // Removes the next slot if it is not pending anymore.
+    fn pop_ready(&self) -> Option<Slot> {
+        let mut state = self.state.lock().unwrap();
+        let next = state.next_write;
+        match state.slots.get(&next) {
+            None | Some(Slot::Pending) => None,
+            Some(_) => {
+                state.next_write += 1;
+                state.slots.remove(&next)
+            }
+        }
+    }
+}
+
+// The reserved position of a request whose response is written in order.
+#[derive(Debug)]
+pub struct Ticket {
+    id: u64,
+    sequencer: ResponseSequencer,
+    completed: bool,
+}
+
+impl Ticket {
+    // Writes the response and all following responses that are ready
+    // once the responses of all previous requests have been written.
+    pub async fn send(mut self, response: Response, output: &mut mpsc::Sender<Message>) {
+        self.completed = true;
+        self.sequencer.complete(self.id, Slot::Ready(response));
+
+        let _guard = self.sequencer.write_lock.lock().await;
+        while let Some(slot) = self.sequencer.pop_ready() {
+            if let Slot::Ready(response) = slot {
+                output.send(Message::Response(response)).await.unwrap();
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.sequencer.complete(self.id, Slot::Dropped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::Id;
+    use futures::{executor::block_on, StreamExt};
+
+    fn response(id: u64) -> Response {
+        Response::result(serde_json::Value::Null, Id::Number(id))
+    }
+
+    #[test]
+    fn order_applies_to_method() {
+        let order = ResponseOrder::ArrivalFor(vec!["textDocument/*".into(), "shutdown".into()]);
+        assert!(order.applies_to("textDocument/hover"));
+        assert!(order.applies_to("shutdown"));
+        assert!(!order.applies_to("workspace/symbol"));
+        assert!(ResponseOrder::Arrival.applies_to("workspace/symbol"));
+        assert!(!ResponseOrder::Completion.applies_to("shutdown"));
+    }
+
+    #[test]
+    fn send_in_arrival_order() {
+        let sequencer = ResponseSequencer::new(ResponseOrder::Arrival);
+        let first = sequencer.ticket("foo").unwrap();
+        let second = sequencer.ticket("bar").unwrap();
+        let third = sequencer.ticket("baz").unwrap();
+        let (mut tx, rx) = mpsc::channel(3);
+
+        block_on(async {
+            third.send(response(2), &mut tx).await;
+            drop(second);
+            first.send(response(0), &mut tx).await;
+        });
+        drop(tx);
+
+        let ids: Vec<_> = block_on(rx.collect::<Vec<_>>())
+            .into_iter()
+            .map(|message| match message {
+                Message::Response(response) => response.id.unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(ids, vec![Id::Number(0), Id::Number(2)]);
+    }
+}
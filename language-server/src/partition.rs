@@ -0,0 +1,294 @@
+use crate::{jsonrpc::*, server::RequestHandler, LanguageClient, LanguageServer, Progress};
+use futures::future::{join, join_all};
+use lsp_types::{DidChangeWorkspaceFoldersParams, Url, WorkspaceFolder};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Creates a separate `LanguageServer` for every workspace folder.
+///
+/// See [`LanguageServiceBuilder::server_factory`](struct.LanguageServiceBuilder.html).
+pub trait ServerFactory<S>: Send + Sync {
+    /// Creates the server that is responsible for the given folder.
+    fn create(&self, folder: &WorkspaceFolder) -> Arc<S>;
+}
+
+impl<S, F> ServerFactory<S> for F
+where
+    F: Fn(&WorkspaceFolder) -> Arc<S> + Send + Sync,
+{
+    fn create(&self, folder: &WorkspaceFolder) -> Arc<S> {
+        self(folder)
+    }
+}
+
+/// Routes the messages of the service to the server instances.
+///
+/// Without a factory, all messages are handled by the default server.
+/// Otherwise, every workspace folder gets its own instance, which receives an `initialize` request
+/// whose root is the folder. Messages that refer to a text document are routed to the instance
+/// of the innermost folder that contains the document.
+/// Other notifications are sent to all instances, while other requests are handled by the default server,
+/// except for `shutdown` and `workspace/symbol`, whose results are combined.
+pub(crate) struct Partitions<S> {
+    default: Arc<S>,
+    factory: Option<Arc<dyn ServerFactory<S>>>,
+    state: Mutex<PartitionState<S>>,
+}
+
+struct PartitionState<S> {
+    instances: Vec<(Url, Arc<S>)>,
+    initialize_params: Value,
+}
+
+impl<S> Partitions<S>
+where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    pub fn new(default: Arc<S>, factory: Option<Arc<dyn ServerFactory<S>>>) -> Self {
+        let state = PartitionState {
+            instances: Vec::new(),
+            initialize_params: Value::Null,
+        };
+
+        Self {
+            default,
+            factory,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub async fn handle_request<C: LanguageClient>(
+        &self,
+        request: Request,
+        client: Arc<C>,
+    ) -> Response {
+        if self.factory.is_none() {
+            return self.default.handle_request(request, client).await;
+        }
+
+        if let Some(instance) = self.find_instance(request.params.as_ref()) {
+            return instance.handle_request(request, client).await;
+        }
+
+        match request.method.as_str() {
+            "initialize" => {
+                let folders: Vec<WorkspaceFolder> = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("workspaceFolders"))
+                    .and_then(|folders| serde_json::from_value(folders.clone()).ok())
+                    .unwrap_or_default();
+
+                {
+                    let mut state = self.state.lock().unwrap();
+                    state.instances.clear();
+                    state.initialize_params = request.params.clone().unwrap_or_default();
+                }
+
+                for folder in folders {
+                    self.add_folder(folder, Arc::clone(&client)).await;
+                }
+                self.default.handle_request(request, client).await
+            }
+            "shutdown" => {
+                let instances = self.instances();
+                let response = self.default.handle_request(request.clone(), client.clone());
+                join(response, broadcast(instances, &request, &client))
+                    .await
+                    .0
+            }
+            "workspace/symbol" => {
+                let mut symbols = Vec::new();
+                for response in broadcast(self.all(), &request, &client).await {
+                    if let Some(Value::Array(results)) = response.result {
+                        symbols.extend(results);
+                    }
+                }
+                Response::result(Value::Array(symbols), request.id)
+            }
+            _ => self.default.handle_request(request, client).await,
+        }
+    }
+
+    pub async fn handle_notification<C: LanguageClient>(
+        &self,
+        notification: Notification,
+        client: Arc<C>,
+    ) {
+        if self.factory.is_none() {
+            return self.default.handle_notification(notification, client).await;
+        }
+
+        if let Some(instance) = self.find_instance(notification.params.as_ref()) {
+            return instance.handle_notification(notification, client).await;
+        }
+
+        if notification.method == "workspace/didChangeWorkspaceFolders" {
+            if let Ok(params) = serde_json::from_value::<DidChangeWorkspaceFoldersParams>(
+                notification.params.clone().unwrap_or_default(),
+            ) {
+                for folder in params.event.removed {
+                    self.remove_folder(&folder).await;
+                }
+
+                for folder in params.event.added {
+                    let instance = self.add_folder(folder, Arc::clone(&client)).await;
+                    let initialized = Notification::new("initialized".into(), json!({}));
+                    instance
+                        .handle_notification(initialized, Arc::clone(&client))
+                        .await;
+                }
+            }
+
+            return self.default.handle_notification(notification, client).await;
+        }
+
+        join_all(self.all().into_iter().map(|instance| {
+            let notification = notification.clone();
+            let client = Arc::clone(&client);
+            async move { instance.handle_notification(notification, client).await }
+        }))
+        .await;
+    }
+
+    pub async fn on_shutdown(&self) {
+        join_all(self.all().into_iter().map(|instance| async move {
+            instance.on_shutdown().await;
+        }))
+        .await;
+    }
+
+    pub async fn on_exit(&self) {
+        join_all(self.all().into_iter().map(|instance| async move {
+            instance.on_exit().await;
+        }))
+        .await;
+    }
+
+    pub async fn warm_up(&self, progress: Option<&Progress>, client: Arc<dyn LanguageClient>) {
+        join_all(self.all().into_iter().map(|instance| {
+            let client = Arc::clone(&client);
+            async move { instance.warm_up(progress, client).await }
+        }))
+        .await;
+    }
+
+    async fn add_folder<C: LanguageClient>(
+        &self,
+        folder: WorkspaceFolder,
+        client: Arc<C>,
+    ) -> Arc<S> {
+        let instance = self.factory.as_ref().unwrap().create(&folder);
+        let mut params = self.state.lock().unwrap().initialize_params.clone();
+        if let Value::Object(params) = &mut params {
+            params.insert("rootUri".into(), json!(folder.uri));
+            params.insert("rootPath".into(), Value::Null);
+            params.insert("workspaceFolders".into(), json!(vec![&folder]));
+        }
+
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        let response = instance.handle_request(request, client).await;
+        if let Some(error) = response.error {
+            log::warn!("{}: {}", folder.uri, error.message);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.instances.push((folder.uri, Arc::clone(&instance)));
+        instance
+    }
+
+    async fn remove_folder(&self, folder: &WorkspaceFolder) {
+        let instance = {
+            let mut state = self.state.lock().unwrap();
+            let index = state
+                .instances
+                .iter()
+                .position(|(uri, _)| *uri == folder.uri);
+            index.map(|index| state.instances.remove(index).1)
+        };
+
+        if let Some(instance) = instance {
+            instance.on_shutdown().await;
+            instance.on_exit().await;
+        }
+    }
+
+    fn find_instance(&self, params: Option<&Value>) -> Option<Arc<S>> {
+        let uri = params?
+            .pointer("/textDocument/uri")
+            .and_then(|uri| uri.as_str())
+            .and_then(|uri| Url::parse(uri).ok())?;
+
+        let state = self.state.lock().unwrap();
+        state
+            .instances
+            .iter()
+            .filter(|(folder, _)| contains(folder, &uri))
+            .max_by_key(|(folder, _)| folder.as_str().len())
+            .map(|(_, instance)| Arc::clone(instance))
+    }
+
+    fn instances(&self) -> Vec<Arc<S>> {
+        let state = self.state.lock().unwrap();
+        state
+            .instances
+            .iter()
+            .map(|(_, instance)| Arc::clone(instance))
+            .collect()
+    }
+
+    fn all(&self) -> Vec<Arc<S>> {
+        let mut instances = self.instances();
+        instances.push(Arc::clone(&self.default));
+        instances
+    }
+}
+
+async fn broadcast<S, C>(
+    instances: Vec<Arc<S>>,
+    request: &Request,
+    client: &Arc<C>,
+) -> Vec<Response>
+where
+    S: LanguageServer + Send + Sync + 'static,
+    C: LanguageClient,
+{
+    join_all(instances.into_iter().map(|instance| {
+        let request = request.clone();
+        let client = Arc::clone(client);
+        async move { instance.handle_request(request, client).await }
+    }))
+    .await
+}
+
+// Checks whether the document belongs to the folder, e.g. `file:///foo/bar.tex` to `file:///foo`
+// but not `file:///foobar.tex`.
+fn contains(folder: &Url, uri: &Url) -> bool {
+    let folder = folder.as_str().trim_end_matches('/');
+    let uri = uri.as_str();
+    uri.get(..folder.len()) == Some(folder) && uri[folder.len()..].starts_with('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folder_contains_document() {
+        let folder = Url::parse("file:///foo").unwrap();
+        assert!(contains(
+            &folder,
+            &Url::parse("file:///foo/bar.tex").unwrap()
+        ));
+        assert!(!contains(
+            &folder,
+            &Url::parse("file:///foobar.tex").unwrap()
+        ));
+
+        let folder = Url::parse("file:///foo/").unwrap();
+        assert!(contains(
+            &folder,
+            &Url::parse("file:///foo/bar.tex").unwrap()
+        ));
+    }
+}
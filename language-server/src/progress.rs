@@ -0,0 +1,131 @@
+use crate::{cancellation::CancellationToken, jsonrpc::Result, LanguageClient};
+use lsp_types::*;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Keeps track of the work done progress created by the server.
+///
+/// When attached to a [`LanguageService`](struct.LanguageService.html), every
+/// `window/workDoneProgress/cancel` notification sent by the client fires the cancellation token
+/// of the corresponding [`Progress`](struct.Progress.html) handle.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressRegistry {
+    tokens: Arc<Mutex<HashMap<ProgressToken, CancellationToken>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ProgressRegistry {
+    /// Creates a new work done progress on the client and reports its beginning.
+    pub async fn begin(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        title: String,
+        cancellable: bool,
+    ) -> Result<Progress> {
+        let token = ProgressToken::Number(self.next_id.fetch_add(1, Ordering::SeqCst));
+        client
+            .work_done_progress_create(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await?;
+
+        let cancellation_token = CancellationToken::new();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token.clone(), cancellation_token.clone());
+
+        let progress = Progress {
+            token,
+            client,
+            cancellation_token,
+            registry: self.clone(),
+        };
+
+        progress
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title,
+                cancellable: Some(cancellable),
+                message: None,
+                percentage: None,
+            }))
+            .await;
+
+        Ok(progress)
+    }
+
+    /// Cancels the progress with the given token.
+    ///
+    /// Returns `false` if the token does not belong to a running progress.
+    pub fn cancel(&self, token: &ProgressToken) -> bool {
+        match self.tokens.lock().unwrap().get(token) {
+            Some(cancellation_token) => {
+                cancellation_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A handle to a work done progress that has been created by the server.
+///
+/// The progress is removed from its registry once the handle is dropped.
+pub struct Progress {
+    token: ProgressToken,
+    client: Arc<dyn LanguageClient>,
+    cancellation_token: CancellationToken,
+    registry: ProgressRegistry,
+}
+
+impl Progress {
+    /// Returns the token that identifies the progress.
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /// Returns the token that is cancelled when the user cancels the progress in the editor.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Returns `true` if the user has cancelled the progress.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_token.is_cancelled()
+    }
+
+    /// Reports an intermediate state of the progress.
+    pub async fn report(&self, message: Option<String>, percentage: Option<f64>) {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message,
+            percentage,
+        }))
+        .await;
+    }
+
+    /// Signals the end of the progress.
+    pub async fn end(self, message: Option<String>) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+            .await;
+    }
+
+    async fn notify(&self, value: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        };
+        self.client.progress(params).await;
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.registry.tokens.lock().unwrap().remove(&self.token);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::ServerContext;
+use lsp_types::ClientInfo;
+use std::{cmp::Ordering, collections::HashMap};
+
+/// A registry of workarounds for bugs in specific clients.
+///
+/// Each workaround is registered for one or more clients by name, optionally together with the
+/// first version of the client that has fixed the bug. The workaround is active for all versions
+/// before the fixed one and for clients that do not report a version.
+///
+/// # Example
+///
+/// ```
+/// # use language_server::ClientQuirks;
+/// let quirks = ClientQuirks::new()
+///     .with_quirk("stale-diagnostics", "Visual Studio Code", Some("1.52"))
+///     .with_quirk("stale-diagnostics", "vim-lsp", None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientQuirks {
+    quirks: HashMap<String, Vec<Quirk>>,
+}
+
+#[derive(Debug, Clone)]
+struct Quirk {
+    client: String,
+    fixed_in: Option<Vec<u64>>,
+}
+
+impl ClientQuirks {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the workaround with the given id for the client with the given name.
+    ///
+    /// The client name is compared case-insensitively with the name in the `clientInfo`.
+    pub fn with_quirk(mut self, id: &str, client: &str, fixed_in: Option<&str>) -> Self {
+        let quirk = Quirk {
+            client: client.to_lowercase(),
+            fixed_in: fixed_in.map(parse_version),
+        };
+        self.quirks.entry(id.to_owned()).or_default().push(quirk);
+        self
+    }
+
+    /// Returns `true` if the workaround with the given id applies to the client of the session.
+    ///
+    /// Workarounds never apply before the client has sent its `clientInfo`.
+    pub fn is_active(&self, id: &str, context: &ServerContext) -> bool {
+        match context.client_info() {
+            Some(client_info) => self.applies_to(id, &client_info),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the workaround with the given id applies to the given client.
+    pub fn applies_to(&self, id: &str, client_info: &ClientInfo) -> bool {
+        let quirks = match self.quirks.get(id) {
+            Some(quirks) => quirks,
+            None => return false,
+        };
+
+        let name = client_info.name.to_lowercase();
+        let version = client_info
+            .version
+            .as_ref()
+            .map(|version| parse_version(version));
+        quirks
+            .iter()
+            .filter(|quirk| quirk.client == name)
+            .any(|quirk| match (&quirk.fixed_in, &version) {
+                (Some(fixed_in), Some(version)) => {
+                    compare_versions(version, fixed_in) == Ordering::Less
+                }
+                _ => true,
+            })
+    }
+}
+
+// Parses the leading numbers of the dot-separated components, e.g. `1.52.0-insider` into `[1, 52, 0]`.
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|component| {
+            let digits: String = component
+                .trim()
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse().unwrap_or(0)
+        })
+        .collect()
+}
+
+// Compares two versions, treating missing components as zero.
+fn compare_versions(left: &[u64], right: &[u64]) -> Ordering {
+    let length = left.len().max(right.len());
+    (0..length)
+        .map(|i| {
+            let left = left.get(i).copied().unwrap_or(0);
+            let right = right.get(i).copied().unwrap_or(0);
+            left.cmp(&right)
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(name: &str, version: Option<&str>) -> ClientInfo {
+        ClientInfo {
+            name: name.into(),
+            version: version.map(ToOwned::to_owned),
+        }
+    }
+
+    #[test]
+    fn version_gates() {
+        let quirks = ClientQuirks::new()
+            .with_quirk("foo", "Visual Studio Code", Some("1.52"))
+            .with_quirk("foo", "bar", None);
+
+        let vscode = |version| client("visual studio code", version);
+        assert!(quirks.applies_to("foo", &vscode(Some("1.51.1"))));
+        assert!(quirks.applies_to("foo", &vscode(None)));
+        assert!(!quirks.applies_to("foo", &vscode(Some("1.52.0-insider"))));
+        assert!(!quirks.applies_to("foo", &vscode(Some("1.60"))));
+        assert!(quirks.applies_to("foo", &client("bar", Some("99"))));
+        assert!(!quirks.applies_to("foo", &client("baz", None)));
+        assert!(!quirks.applies_to("qux", &vscode(None)));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    jsonrpc::*, Clock, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext,
+    SystemClock,
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const INCOMING: &str = "-->";
+const OUTGOING: &str = "<--";
+
+/// Middleware that records every message of a session with a timestamp,
+/// so users can attach a reproducible trace to a bug report.
+///
+/// A trace contains one message per line in the order in which they have been exchanged:
+///
+/// ```text
+/// 0.000 --> {"jsonrpc":"2.0","method":"initialize","params":{...},"id":0}
+/// 0.012 <-- {"jsonrpc":"2.0","result":{...},"id":0}
+/// ```
+///
+/// Every line starts with the seconds that have elapsed since the recording has started,
+/// followed by `-->` for a message that the client has sent to the server
+/// or `<--` for a message that the server has sent to the client
+/// and the message itself as compact JSON.
+/// Without the timestamps, the lines have the same format as the golden files of the `testing` module,
+/// which replays traces with `GoldenSession::load_trace`.
+///
+/// The messages are recorded as they have been read from the input stream and written to the output stream,
+/// i.e. before other middlewares change the incoming messages and after they have changed the outgoing messages.
+/// This includes the messages that the service answers itself, e.g. lifecycle errors or parse errors.
+/// Every line is flushed immediately, so the trace is complete even if the server crashes.
+pub struct RecordingMiddleware {
+    writer: Mutex<Box<dyn Write + Send>>,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+}
+
+impl RecordingMiddleware {
+    /// Records the session to the given writer.
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+            clock: Arc::new(SystemClock),
+            started: SystemClock.now(),
+        }
+    }
+
+    /// Records the session to a new file at the given path, replacing an existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file))))
+    }
+
+    /// Sets the clock that provides the timestamps.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        let started = clock.now();
+        Self {
+            clock,
+            started,
+            ..self
+        }
+    }
+
+    fn record<T: Serialize>(&self, direction: &str, timestamp: Instant, message: &T) {
+        let elapsed = timestamp
+            .checked_duration_since(self.started)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        let message = serde_json::to_string(message).unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        let result = writeln!(
+            writer,
+            "{}.{:03} {} {}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            direction,
+            message
+        )
+        .and_then(|()| writer.flush());
+
+        if let Err(why) = result {
+            log::warn!("Failed to record a message: {}", why);
+        }
+    }
+}
+
+impl fmt::Debug for RecordingMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingMiddleware")
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Middleware for RecordingMiddleware {
+    async fn on_incoming_message(
+        &self,
+        _message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        _request: &Request,
+        _metadata: &MessageMetadata,
+        _response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    fn on_message_received(&self, message: &Message, metadata: &MessageMetadata) {
+        self.record(INCOMING, metadata.received_at, message);
+    }
+
+    fn on_message_sent(&self, message: &Message) {
+        self.record(OUTGOING, self.clock.now(), message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use serde_json::json;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn record_session() {
+        let buffer = SharedBuffer::default();
+        let clock = ManualClock::new();
+        let recorder =
+            RecordingMiddleware::new(Box::new(buffer.clone())).with_clock(Arc::new(clock.clone()));
+
+        let metadata = MessageMetadata {
+            sequence: 0,
+            received_at: clock.now(),
+        };
+
+        let request = Request::new("foo".into(), json!({}), Id::Number(0));
+        recorder.on_message_received(&Message::Request(request), &metadata);
+        clock.advance(Duration::from_millis(1234));
+        let response = Response::result(json!(null), Id::Number(0));
+        recorder.on_message_sent(&Message::Response(response));
+
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            trace,
+            concat!(
+                "0.000 --> {\"jsonrpc\":\"2.0\",\"method\":\"foo\",\"params\":{},\"id\":0}\n",
+                "1.234 <-- {\"jsonrpc\":\"2.0\",\"result\":null,\"id\":0}\n"
+            )
+        );
+    }
+}
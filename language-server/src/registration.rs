@@ -0,0 +1,232 @@
+use crate::{client::next_registration_id, jsonrpc::Result, LanguageClient};
+use lsp_types::*;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Registers capabilities dynamically and remembers the ids of the registrations,
+/// so they can be removed by method later on.
+///
+/// This complements the static capabilities of the `initialize` response
+/// for clients that prefer dynamic registration.
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityRegistry {
+    ids: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+impl CapabilityRegistry {
+    /// Registers the given method with the given registration options.
+    ///
+    /// Returns the id of the registration.
+    pub async fn register<T: Serialize>(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        method: &str,
+        options: T,
+    ) -> Result<String> {
+        let id = next_registration_id();
+        let registration = Registration {
+            id: id.clone(),
+            method: method.to_owned(),
+            register_options: Some(json!(options)),
+        };
+
+        client
+            .register_capability(RegistrationParams {
+                registrations: vec![registration],
+            })
+            .await?;
+
+        self.ids
+            .lock()
+            .unwrap()
+            .entry(method.to_owned())
+            .or_default()
+            .push(id.clone());
+        Ok(id)
+    }
+
+    /// Removes all registrations of the given method.
+    pub async fn unregister(&self, client: Arc<dyn LanguageClient>, method: &str) -> Result<()> {
+        let ids = self.ids.lock().unwrap().remove(method).unwrap_or_default();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let unregisterations = ids
+            .into_iter()
+            .map(|id| Unregistration {
+                id,
+                method: method.to_owned(),
+            })
+            .collect();
+
+        client
+            .unregister_capability(UnregistrationParams { unregisterations })
+            .await
+    }
+
+    /// Returns the ids of all registrations of the given method.
+    pub fn ids(&self, method: &str) -> Vec<String> {
+        self.ids
+            .lock()
+            .unwrap()
+            .get(method)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Registers the `textDocument/foldingRange` request for the given documents.
+    pub async fn register_folding_range(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/foldingRange", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/selectionRange` request for the given documents.
+    pub async fn register_selection_range(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/selectionRange", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/documentSymbol` request for the given documents.
+    pub async fn register_document_symbol(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/documentSymbol", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/documentHighlight` request for the given documents.
+    pub async fn register_document_highlight(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/documentHighlight", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/hover` request for the given documents.
+    pub async fn register_hover(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/hover", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/definition` request for the given documents.
+    pub async fn register_definition(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/definition", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/references` request for the given documents.
+    pub async fn register_references(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/references", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/formatting` request for the given documents.
+    pub async fn register_formatting(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/formatting", selector)
+            .await
+    }
+
+    /// Registers the `textDocument/rangeFormatting` request for the given documents.
+    pub async fn register_range_formatting(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        self.register_for_documents(client, "textDocument/rangeFormatting", selector)
+            .await
+    }
+
+    async fn register_for_documents(
+        &self,
+        client: Arc<dyn LanguageClient>,
+        method: &str,
+        selector: DocumentSelector,
+    ) -> Result<String> {
+        let options = TextDocumentRegistrationOptions {
+            document_selector: Some(selector),
+        };
+        self.register(client, method, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::LanguageClientImpl,
+        jsonrpc::{Id, Message, Response},
+    };
+    use futures::{channel::mpsc, future::join3, prelude::*};
+    use language_server_transport::ResponseHandler;
+
+    #[tokio::test]
+    async fn register_and_unregister() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let client = Arc::new(LanguageClientImpl::new(tx));
+        let registry = CapabilityRegistry::default();
+        let selector = vec![DocumentFilter {
+            language: Some("latex".into()),
+            scheme: None,
+            pattern: None,
+        }];
+
+        let (id, _, ()) = join3(
+            registry.register_folding_range(client.clone(), selector),
+            rx.next(),
+            client.handle(Response::result(serde_json::Value::Null, Id::Number(0))),
+        )
+        .await;
+        let id = id.unwrap();
+        assert_eq!(registry.ids("textDocument/foldingRange"), vec![id.clone()]);
+
+        let (result, output, ()) = join3(
+            registry.unregister(client.clone(), "textDocument/foldingRange"),
+            rx.next(),
+            client.handle(Response::result(serde_json::Value::Null, Id::Number(1))),
+        )
+        .await;
+        result.unwrap();
+        let params = match output.unwrap() {
+            Message::Request(request) => request.params.unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            params,
+            json!({ "unregisterations": [{ "id": id, "method": "textDocument/foldingRange" }] })
+        );
+        assert!(registry.ids("textDocument/foldingRange").is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::jsonrpc::Result;
+use async_trait::async_trait;
+use lsp_types::*;
+
+/// A symbol that has been found at the position of a rename request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameTarget<T> {
+    /// The range of the token that is going to be renamed.
+    pub range: Range,
+
+    /// The text that is shown to the user when entering the new name.
+    pub placeholder: Option<String>,
+
+    /// Additional information about the symbol that is needed to compute the edit.
+    pub symbol: T,
+}
+
+impl<T> RenameTarget<T> {
+    /// Creates a target for the token at the given range of the text
+    /// and uses the text of the token as placeholder.
+    ///
+    /// Returns `None` if the range is not part of the text.
+    pub fn from_token(text: &str, range: Range, symbol: T) -> Option<Self> {
+        let start = offset(text, range.start)?;
+        let end = offset(text, range.end)?;
+        let placeholder = text.get(start..end)?.to_owned();
+        Some(Self {
+            range,
+            placeholder: Some(placeholder),
+            symbol,
+        })
+    }
+}
+
+/// Combines the `textDocument/prepareRename` and `textDocument/rename` requests
+/// so that both of them use the same symbol resolution.
+///
+/// A `LanguageServer` can forward both requests to `handle_prepare_rename` and `handle_rename`.
+#[async_trait]
+pub trait RenameProvider: Send + Sync {
+    /// The information about a symbol that is needed to compute the edit.
+    type Symbol: Send;
+
+    /// Finds the symbol that can be renamed at the given position.
+    async fn find_target(
+        &self,
+        params: &TextDocumentPositionParams,
+    ) -> Result<Option<RenameTarget<Self::Symbol>>>;
+
+    /// Computes the edit that renames the given symbol.
+    async fn rename_symbol(
+        &self,
+        symbol: Self::Symbol,
+        new_name: String,
+    ) -> Result<Option<WorkspaceEdit>>;
+
+    /// Answers a `textDocument/prepareRename` request.
+    async fn handle_prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let response = self.find_target(&params).await?.map(|target| {
+            let range = target.range;
+            match target.placeholder {
+                Some(placeholder) => {
+                    PrepareRenameResponse::RangeWithPlaceholder { range, placeholder }
+                }
+                None => PrepareRenameResponse::Range(range),
+            }
+        });
+        Ok(response)
+    }
+
+    /// Answers a `textDocument/rename` request.
+    async fn handle_rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        match self.find_target(&params.text_document_position).await? {
+            Some(target) => self.rename_symbol(target.symbol, params.new_name).await,
+            None => Ok(None),
+        }
+    }
+}
+
+// Converts a position with UTF-16 based character offsets to a byte offset.
+// Positions that point into the middle of a character are rejected.
+fn offset(text: &str, position: Position) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+
+    let line = text[line_start..].split('\n').next().unwrap();
+    let mut character = 0;
+    for (index, c) in line.char_indices() {
+        if character == position.character {
+            return Some(line_start + index);
+        } else if character > position.character {
+            return None;
+        }
+        character += c.len_utf16() as u64;
+    }
+
+    if character == position.character {
+        Some(line_start + line.len())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+
+    const TEXT: &str = "\\newcommand{\\foo}{bar}\n\\foo \\foo";
+
+    struct Provider;
+
+    #[async_trait]
+    impl RenameProvider for Provider {
+        type Symbol = Url;
+
+        async fn find_target(
+            &self,
+            params: &TextDocumentPositionParams,
+        ) -> Result<Option<RenameTarget<Url>>> {
+            let range = Range::new(Position::new(1, 0), Position::new(1, 4));
+            if params.position.line == 1 {
+                let uri = params.text_document.uri.clone();
+                Ok(RenameTarget::from_token(TEXT, range, uri))
+            } else {
+                Ok(None)
+            }
+        }
+
+        async fn rename_symbol(&self, uri: Url, new_name: String) -> Result<Option<WorkspaceEdit>> {
+            let range = Range::new(Position::new(1, 0), Position::new(1, 4));
+            let mut changes = HashMap::new();
+            changes.insert(uri, vec![TextEdit::new(range, new_name)]);
+            Ok(Some(WorkspaceEdit::new(changes)))
+        }
+    }
+
+    fn position_params(line: u64) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(Url::parse("file:///foo.tex").unwrap()),
+            Position::new(line, 2),
+        )
+    }
+
+    #[test]
+    fn prepare_rename_placeholder() {
+        let response = block_on(Provider.handle_prepare_rename(position_params(1))).unwrap();
+        assert_eq!(
+            response,
+            Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range: Range::new(Position::new(1, 0), Position::new(1, 4)),
+                placeholder: "\\foo".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn prepare_rename_no_symbol() {
+        let response = block_on(Provider.handle_prepare_rename(position_params(0))).unwrap();
+        assert_eq!(response, None);
+    }
+
+    #[test]
+    fn rename_uses_target() {
+        let params = RenameParams {
+            text_document_position: position_params(1),
+            new_name: "\\baz".into(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        let edit = block_on(Provider.handle_rename(params)).unwrap().unwrap();
+        let edits = &edit.changes.unwrap()[&Url::parse("file:///foo.tex").unwrap()];
+        assert_eq!(edits[0].new_text, "\\baz");
+    }
+
+    #[test]
+    fn offset_utf16() {
+        let text = "a\u{1F600}b\nc";
+        assert_eq!(offset(text, Position::new(0, 2)), None);
+        assert_eq!(offset(text, Position::new(0, 3)), Some(5));
+        assert_eq!(offset(text, Position::new(1, 1)), Some(8));
+        assert_eq!(offset(text, Position::new(1, 2)), None);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::jsonrpc::{ErrorCode, Response};
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Retries selected requests whose handler has failed with an internal error,
+/// e.g. because an index has been locked briefly during a burst of edits.
+///
+/// The delay before the n-th retry is `delay * 2^n` with a random jitter of ±50%,
+/// so concurrent retries do not run into the same race again.
+/// Other errors, like `ContentModified`, are sent to the client immediately.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    methods: HashSet<String>,
+    max_retries: u32,
+    delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that retries a request at most `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            methods: HashSet::new(),
+            max_retries,
+            delay: Duration::from_millis(20),
+        }
+    }
+
+    /// Marks the given request method as retriable.
+    pub fn with_method(mut self, method: String) -> Self {
+        self.methods.insert(method);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    // Determines whether the request should be handled again after the given number of retries.
+    pub(crate) fn should_retry(&self, method: &str, response: &Response, retries: u32) -> bool {
+        let failed = match &response.error {
+            Some(error) => error.code == ErrorCode::InternalError,
+            None => false,
+        };
+
+        failed && retries < self.max_retries && self.methods.contains(method)
+    }
+
+    // Returns the delay before the next retry.
+    pub(crate) fn delay(&self, retries: u32) -> Duration {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(retries);
+        let jitter = 0.5 + (hasher.finish() % 1000) as f64 / 1000.0;
+        let delay = self.delay * 2u32.saturating_pow(retries);
+        Duration::from_secs_f64(delay.as_secs_f64() * jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{Error, Id};
+    use serde_json::json;
+
+    #[test]
+    fn retry_internal_errors() {
+        let policy = RetryPolicy::new(2).with_method("foo".into());
+        let internal = Response::error(Error::internal_error("bar".into()), Some(Id::Number(0)));
+        assert!(policy.should_retry("foo", &internal, 0));
+        assert!(policy.should_retry("foo", &internal, 1));
+        assert!(!policy.should_retry("foo", &internal, 2));
+        assert!(!policy.should_retry("baz", &internal, 0));
+
+        let success = Response::result(json!(null), Id::Number(0));
+        assert!(!policy.should_retry("foo", &success, 0));
+
+        let policy = policy.with_delay(Duration::from_millis(100));
+        let delay = policy.delay(1);
+        assert!(delay >= Duration::from_millis(100) && delay < Duration::from_millis(300));
+    }
+}
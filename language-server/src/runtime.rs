@@ -0,0 +1,45 @@
+use crate::{LanguageServer, LanguageService};
+use std::sync::Arc;
+
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[cfg(feature = "tokio")]
+impl<S> LanguageService<crate::ThreadedReader, crate::ThreadedWriter, S, async_executors::TokioTp>
+where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    /// Creates a service that communicates over the standard streams
+    /// and spawns its tasks on a new multi-threaded Tokio runtime.
+    ///
+    /// The standard streams are bridged on dedicated threads, so the returned handle
+    /// can be driven outside of the runtime, e.g. with `futures::executor::block_on`.
+    pub fn tokio_stdio(server: Arc<S>) -> std::io::Result<Self> {
+        use std::convert::TryFrom;
+
+        let executor = async_executors::TokioTp::try_from(&mut tokio::runtime::Builder::new())?;
+        let (input, output) = crate::stdio();
+        Ok(Self::builder()
+            .server(server)
+            .input(input)
+            .output(output)
+            .executor(executor)
+            .build())
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "async-std")))]
+#[cfg(feature = "async-std")]
+impl<S> LanguageService<async_std::io::Stdin, async_std::io::Stdout, S, async_executors::AsyncStd>
+where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    /// Creates a service that communicates over the standard streams of `async-std`
+    /// and spawns its tasks on the global `async-std` executor.
+    pub fn async_std_stdio(server: Arc<S>) -> Self {
+        Self::builder()
+            .server(server)
+            .input(async_std::io::stdin())
+            .output(async_std::io::stdout())
+            .executor(async_executors::AsyncStd)
+            .build()
+    }
+}
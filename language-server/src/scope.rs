@@ -0,0 +1,191 @@
+use futures::{
+    future::{AbortHandle, Abortable},
+    task::{Context, Poll, Spawn, SpawnError, SpawnExt, Waker},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// Owns the tasks that are spawned by the service.
+///
+/// Tasks are cancelled once the scope is dropped, so none of them outlives the service.
+#[derive(Debug, Clone, Default)]
+pub struct TaskScope {
+    inner: Arc<Mutex<ScopeInner>>,
+}
+
+#[derive(Debug, Default)]
+struct ScopeInner {
+    next_id: u64,
+    handles: HashMap<u64, AbortHandle>,
+    wakers: Vec<Waker>,
+}
+
+impl TaskScope {
+    /// Spawns a task on the executor that belongs to this scope.
+    pub fn spawn<E, F>(&self, executor: &E, future: F) -> Result<(), SpawnError>
+    where
+        E: Spawn,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let id = {
+            let mut inner = self.inner.lock().unwrap();
+            let id = inner.next_id;
+            inner.next_id += 1;
+            inner.handles.insert(id, abort_handle);
+            id
+        };
+
+        // The guard lives inside the spawned future, so the task is removed from the scope
+        // even if the executor drops the future without polling it.
+        let guard = TaskGuard {
+            id,
+            scope: Arc::downgrade(&self.inner),
+        };
+
+        let future = Abortable::new(future, abort_registration);
+        executor.spawn(async move {
+            let _guard = guard;
+            let _ = future.await;
+        })
+    }
+
+    /// Cancels all tasks of the scope.
+    pub fn cancel(&self) {
+        let inner = self.inner.lock().unwrap();
+        for handle in inner.handles.values() {
+            handle.abort();
+        }
+    }
+
+    /// Waits until all tasks of the scope have finished.
+    pub fn join(&self) -> Join {
+        Join {
+            scope: self.clone(),
+            tasks: None,
+        }
+    }
+
+    /// Waits until the tasks that are currently running have finished.
+    /// Tasks that are spawned afterwards are ignored.
+    pub fn join_current(&self) -> Join {
+        let tasks = self.inner.lock().unwrap().handles.keys().copied().collect();
+        Join {
+            scope: self.clone(),
+            tasks: Some(tasks),
+        }
+    }
+}
+
+impl Drop for ScopeInner {
+    fn drop(&mut self) {
+        for handle in self.handles.values() {
+            handle.abort();
+        }
+    }
+}
+
+struct TaskGuard {
+    id: u64,
+    scope: Weak<Mutex<ScopeInner>>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if let Some(scope) = self.scope.upgrade() {
+            let mut inner = scope.lock().unwrap();
+            inner.handles.remove(&self.id);
+            for waker in inner.wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Future returned by `TaskScope::join` and `TaskScope::join_current`.
+pub struct Join {
+    scope: TaskScope,
+    tasks: Option<Vec<u64>>,
+}
+
+impl Future for Join {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.scope.inner.lock().unwrap();
+        let finished = match &self.tasks {
+            Some(tasks) => tasks.iter().all(|id| !inner.handles.contains_key(id)),
+            None => inner.handles.is_empty(),
+        };
+
+        if finished {
+            Poll::Ready(())
+        } else {
+            if !inner.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                inner.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::LocalPool, future::pending};
+
+    #[test]
+    fn join_waits_for_tasks() {
+        let mut executor = LocalPool::new();
+        let scope = TaskScope::default();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        scope
+            .spawn(&executor.spawner(), async move { tx.send(()).unwrap() })
+            .unwrap();
+
+        executor.run_until(scope.join());
+        assert!(executor.run_until(rx).is_ok());
+    }
+
+    #[test]
+    fn join_current_ignores_new_tasks() {
+        let mut executor = LocalPool::new();
+        let scope = TaskScope::default();
+        scope.spawn(&executor.spawner(), async {}).unwrap();
+        let join = scope.join_current();
+        scope.spawn(&executor.spawner(), pending::<()>()).unwrap();
+
+        executor.run_until(join);
+    }
+
+    #[test]
+    fn cancel_aborts_tasks() {
+        let mut executor = LocalPool::new();
+        let scope = TaskScope::default();
+        scope.spawn(&executor.spawner(), pending::<()>()).unwrap();
+
+        scope.cancel();
+        executor.run_until(scope.join());
+    }
+
+    #[test]
+    fn drop_aborts_tasks() {
+        let mut executor = LocalPool::new();
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        {
+            let scope = TaskScope::default();
+            scope
+                .spawn(&executor.spawner(), async move {
+                    pending::<()>().await;
+                    drop(tx);
+                })
+                .unwrap();
+        }
+
+        assert!(executor.run_until(rx).is_err());
+    }
+}
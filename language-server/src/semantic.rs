@@ -0,0 +1,146 @@
+use lsp_types::*;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+// The number of integers that encode a single token.
+const TOKEN_SIZE: u32 = 5;
+
+/// Remembers the semantic tokens that have been sent to the client,
+/// so `textDocument/semanticTokens/edits` requests can be answered with a delta.
+///
+/// The server only needs to compute the full tokens of a document:
+///
+/// - `textDocument/semanticTokens` is answered with [`full`](#method.full).
+/// - `textDocument/semanticTokens/edits` is answered with [`delta`](#method.delta),
+///   which falls back to the full tokens if the previous result is not known anymore.
+#[derive(Debug, Default)]
+pub struct SemanticTokensCache {
+    entries: Mutex<HashMap<Url, (String, Vec<SemanticToken>)>>,
+    next_id: AtomicU64,
+}
+
+impl SemanticTokensCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the tokens of a document and returns them with a new result id.
+    pub fn full(&self, uri: &Url, data: Vec<SemanticToken>) -> SemanticTokens {
+        let result_id = self.next_id.fetch_add(1, Ordering::SeqCst).to_string();
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), (result_id.clone(), data.clone()));
+
+        SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        }
+    }
+
+    /// Stores the tokens of a document and returns the edits that turn the previous result into them.
+    pub fn delta(
+        &self,
+        uri: &Url,
+        previous_result_id: &str,
+        data: Vec<SemanticToken>,
+    ) -> SemanticTokensEditResult {
+        let previous = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(uri)
+            .filter(|(result_id, _)| result_id == previous_result_id)
+            .map(|(_, previous)| previous.clone());
+
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return self.full(uri, data).into(),
+        };
+
+        let edits = diff(&previous, &data);
+        let result_id = self.full(uri, data).result_id;
+        SemanticTokensEdits { result_id, edits }.into()
+    }
+
+    /// Forgets the tokens of a document, e.g. after it has been closed.
+    pub fn remove(&self, uri: &Url) {
+        self.entries.lock().unwrap().remove(uri);
+    }
+}
+
+// Computes a single edit that replaces the tokens between the common prefix and suffix.
+fn diff(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+
+    vec![SemanticTokensEdit {
+        start: prefix as u32 * TOKEN_SIZE,
+        delete_count: deleted as u32 * TOKEN_SIZE,
+        data: if inserted.is_empty() {
+            None
+        } else {
+            Some(inserted.to_vec())
+        },
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(length: u32) -> SemanticToken {
+        SemanticToken {
+            length,
+            ..SemanticToken::default()
+        }
+    }
+
+    #[test]
+    fn delta_edits() {
+        let cache = SemanticTokensCache::new();
+        let uri = Url::parse("file:///foo.tex").unwrap();
+        let full = cache.full(&uri, vec![token(1), token(2), token(3)]);
+
+        let result = cache.delta(
+            &uri,
+            full.result_id.as_ref().unwrap(),
+            vec![token(1), token(4), token(5), token(3)],
+        );
+        let edits = match result {
+            SemanticTokensEditResult::TokensEdits(edits) => edits,
+            _ => panic!("expected edits"),
+        };
+        assert_eq!(
+            edits.edits,
+            vec![SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![token(4), token(5)]),
+            }]
+        );
+
+        let result = cache.delta(&uri, "unknown", vec![token(1)]);
+        assert!(match result {
+            SemanticTokensEditResult::Tokens(tokens) => tokens.data == vec![token(1)],
+            _ => false,
+        });
+    }
+}
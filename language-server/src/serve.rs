@@ -0,0 +1,68 @@
+use crate::{LanguageServer, LanguageService, ThreadedReader, ThreadedWriter};
+use futures::{
+    channel::mpsc,
+    stream::StreamExt,
+    task::{Spawn, SpawnExt},
+};
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+};
+
+/// Accepts the connections of the given listener and runs an independent
+/// [`LanguageService`](struct.LanguageService.html) for each of them,
+/// so a single process can serve many editors at once.
+///
+/// The factory creates the server of every connection.
+/// Each service has its own client and its own pending requests,
+/// so a connection that is closed does not affect the others.
+/// The returned future completes once the listener fails.
+pub async fn serve_tcp<S, E, F>(
+    listener: TcpListener,
+    executor: E,
+    mut factory: F,
+) -> io::Result<()>
+where
+    S: LanguageServer + Send + Sync + 'static,
+    E: Spawn + Clone + Send + 'static,
+    F: FnMut() -> Arc<S>,
+{
+    let mut connections = accept_loop(listener);
+    while let Some(stream) = connections.next().await {
+        let stream = stream?;
+        let peer = stream.peer_addr().ok();
+        let service = LanguageService::builder()
+            .input(ThreadedReader::new(stream.try_clone()?))
+            .output(ThreadedWriter::new(stream))
+            .server(factory())
+            .executor(executor.clone())
+            .build();
+
+        let handle = service.listen();
+        executor
+            .spawn(async move {
+                let reason = handle.await;
+                log::info!("The connection of {:?} has ended: {:?}", peer, reason);
+            })
+            .map_err(|why| {
+                log::error!("Failed to spawn the service of {:?}: {}", peer, why);
+                io::Error::from(io::ErrorKind::Other)
+            })?;
+    }
+    Ok(())
+}
+
+// Accepts the connections on a dedicated thread because the listener is blocking.
+fn accept_loop(listener: TcpListener) -> mpsc::UnboundedReceiver<io::Result<TcpStream>> {
+    let (tx, rx) = mpsc::unbounded();
+    thread::spawn(move || loop {
+        let stream = listener.accept().map(|(stream, _)| stream);
+        let failed = stream.is_err();
+        if tx.unbounded_send(stream).is_err() || failed {
+            break;
+        }
+    });
+    rx
+}
@@ -1,19 +1,55 @@
-use crate::{client::LanguageClient, jsonrpc::*};
+#[cfg(feature = "draft")]
+use crate::draft::*;
+#[cfg(feature = "file-operations")]
+use crate::fileops::RenameFilesParams;
+use crate::{client::LanguageClient, jsonrpc::*, progress::Progress, trace::SetTraceParams};
 use async_trait::async_trait;
 use language_server_macros::*;
 use lsp_types::*;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::json;
 use std::sync::Arc;
 
+/// The kind of a method that is dispatched to the `LanguageServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MethodKind {
+    /// The method is a request and is answered with a response.
+    Request,
+
+    /// The method is a notification.
+    Notification,
+}
+
+/// A method that is known to the dispatch of the `LanguageServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedMethod {
+    /// The name of the method, e.g. `textDocument/hover`.
+    pub name: &'static str,
+
+    /// The kind of the method.
+    pub kind: MethodKind,
+
+    /// `false` if the method is known but has been disabled by a feature flag of this crate.
+    pub enabled: bool,
+}
+
 /// Defines the server-side implementation of the [Language Server Protocol](https://microsoft.github.io/language-server-protocol/specification).
 ///
-/// Empty default implementations are provided for convenience.
+/// Default implementations are provided for convenience.
+/// Requests that are not implemented are answered with a `MethodNotFound` error,
+/// unless the service has been built with `lenient_defaults`.
 #[allow(unused_variables)]
 #[jsonrpc_server]
 #[async_trait]
 pub trait LanguageServer {
     /// The [`initialize`](https://microsoft.github.io/language-server-protocol/specifications/specification-current/#initialize)
     /// request is sent as the first request from the client to the server.
+    ///
+    /// If the server cannot be initialized, it can respond with
+    /// [`Error::initialize_error`](jsonrpc/struct.Error.html#method.initialize_error).
+    /// With `strict_lifecycle`, the service only accepts another `initialize` request if the error has been created with `retry` set.
     #[jsonrpc_method(name = "initialize", kind = "request")]
     async fn initialize(
         &self,
@@ -40,6 +76,33 @@ pub trait LanguageServer {
     #[jsonrpc_method(name = "exit", kind = "notification")]
     async fn exit(&self, params: (), client: Arc<dyn LanguageClient>) {}
 
+    /// A [notification](https://microsoft.github.io/language-server-protocol/specification#setTrace)
+    /// that should be used by the client to modify the trace setting of the server.
+    ///
+    /// The service keeps track of the setting, see [`Tracer`](struct.Tracer.html).
+    #[jsonrpc_method(name = "$/setTrace", kind = "notification")]
+    async fn set_trace(&self, params: SetTraceParams, client: Arc<dyn LanguageClient>) {}
+
+    /// Called exactly once by the service when the server is shutting down.
+    ///
+    /// The hook runs after all requests that have been received before the `shutdown` request have finished
+    /// and before the response to the `shutdown` request is sent.
+    /// If the service stops without receiving a `shutdown` request, the hook is called before `on_exit`.
+    async fn on_shutdown(&self) {}
+
+    /// Called exactly once by the service after it has stopped processing messages
+    /// and all pending requests have finished.
+    ///
+    /// This is the last chance to release resources. The client cannot be reached anymore.
+    async fn on_exit(&self) {}
+
+    /// Runs in the background after the `initialized` notification
+    /// if the service has been built with a [`WarmUp`](struct.WarmUp.html).
+    ///
+    /// The progress is only available if the client supports work done progress.
+    /// Requests that arrive while the hook is running are handled according to the `WarmUp`.
+    async fn warm_up(&self, progress: Option<&Progress>, client: Arc<dyn LanguageClient>) {}
+
     /// The [`window/workDoneProgress/cancel`](https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_cancel)
     /// notification is sent from the client to the server to cancel a progress initiated on the server side using the
     /// [`window/workDoneProgress/create`](https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create).
@@ -89,7 +152,7 @@ pub trait LanguageServer {
         params: WorkspaceSymbolParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<SymbolInformation>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [`workspace/executeCommand`](https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand)
@@ -100,7 +163,7 @@ pub trait LanguageServer {
         params: ExecuteCommandParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<serde_json::Value>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The [document open notification](https://microsoft.github.io/language-server-protocol/specification#textDocument_didOpen)
@@ -132,7 +195,7 @@ pub trait LanguageServer {
         params: WillSaveTextDocumentParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<TextEdit>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document save notification](https://microsoft.github.io/language-server-protocol/specification#textDocument_didSave)
@@ -154,7 +217,7 @@ pub trait LanguageServer {
         params: CompletionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<CompletionResponse> {
-        Ok(CompletionResponse::Array(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [request](https://microsoft.github.io/language-server-protocol/specification#completionItem_resolve)
@@ -165,7 +228,7 @@ pub trait LanguageServer {
         item: CompletionItem,
         client: Arc<dyn LanguageClient>,
     ) -> Result<CompletionItem> {
-        Ok(item)
+        Err(not_implemented())
     }
 
     /// The [hover request](https://microsoft.github.io/language-server-protocol/specification#textDocument_hover)
@@ -176,7 +239,7 @@ pub trait LanguageServer {
         params: HoverParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<Hover>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The [signature help request](https://microsoft.github.io/language-server-protocol/specification#textDocument_signatureHelp)
@@ -187,7 +250,7 @@ pub trait LanguageServer {
         params: SignatureHelpParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<SignatureHelp>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The [go to declaration](https://microsoft.github.io/language-server-protocol/specification#textDocument_declaration)
@@ -198,7 +261,7 @@ pub trait LanguageServer {
         params: GotoDefinitionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<GotoDefinitionResponse> {
-        Ok(GotoDefinitionResponse::Array(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [go to definition request](https://microsoft.github.io/language-server-protocol/specification#textDocument_definition)
@@ -209,7 +272,7 @@ pub trait LanguageServer {
         params: GotoDefinitionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<GotoDefinitionResponse> {
-        Ok(GotoDefinitionResponse::Array(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [go to type definition request](https://microsoft.github.io/language-server-protocol/specification#textDocument_typeDefinition)
@@ -220,7 +283,7 @@ pub trait LanguageServer {
         params: GotoDefinitionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<GotoDefinitionResponse> {
-        Ok(GotoDefinitionResponse::Array(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [go to implementation request](https://microsoft.github.io/language-server-protocol/specification#textDocument_implementation)
@@ -231,7 +294,7 @@ pub trait LanguageServer {
         params: GotoDefinitionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<GotoDefinitionResponse> {
-        Ok(GotoDefinitionResponse::Array(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [references request](https://microsoft.github.io/language-server-protocol/specification#textDocument_references)
@@ -242,7 +305,7 @@ pub trait LanguageServer {
         params: ReferenceParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<Location>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document highlight request](https://microsoft.github.io/language-server-protocol/specification#textDocument_documentHighlight)
@@ -253,7 +316,7 @@ pub trait LanguageServer {
         params: DocumentHighlightParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<DocumentHighlight>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document symbol request](https://microsoft.github.io/language-server-protocol/specification#textDocument_documentSymbol)
@@ -264,7 +327,7 @@ pub trait LanguageServer {
         params: DocumentSymbolParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<DocumentSymbolResponse> {
-        Ok(DocumentSymbolResponse::Flat(Vec::new()))
+        Err(not_implemented())
     }
 
     /// The [code action request](https://microsoft.github.io/language-server-protocol/specification#textDocument_codeAction)
@@ -275,7 +338,7 @@ pub trait LanguageServer {
         params: CodeActionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<CodeActionResponse> {
-        Ok(CodeActionResponse::new())
+        Err(not_implemented())
     }
 
     /// The [code lens request](https://microsoft.github.io/language-server-protocol/specification#textDocument_codeLens)
@@ -286,7 +349,7 @@ pub trait LanguageServer {
         params: CodeLensParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<CodeLens>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [code lens resolve request](https://microsoft.github.io/language-server-protocol/specification#codeLens_resolve)
@@ -297,7 +360,7 @@ pub trait LanguageServer {
         item: CodeLens,
         client: Arc<dyn LanguageClient>,
     ) -> Result<CodeLens> {
-        Ok(item)
+        Err(not_implemented())
     }
 
     /// The [document links request](https://microsoft.github.io/language-server-protocol/specification#textDocument_documentLink)
@@ -308,7 +371,7 @@ pub trait LanguageServer {
         params: DocumentLinkParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<DocumentLink>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document link resolve request](https://microsoft.github.io/language-server-protocol/specification#documentLink_resolve)
@@ -319,7 +382,7 @@ pub trait LanguageServer {
         item: DocumentLink,
         client: Arc<dyn LanguageClient>,
     ) -> Result<DocumentLink> {
-        Ok(item)
+        Err(not_implemented())
     }
 
     /// The [document color request](https://microsoft.github.io/language-server-protocol/specification#textDocument_documentColor)
@@ -330,7 +393,7 @@ pub trait LanguageServer {
         params: DocumentColorParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<ColorInformation>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [color presentation request](https://microsoft.github.io/language-server-protocol/specification#textDocument_colorPresentation)
@@ -341,7 +404,7 @@ pub trait LanguageServer {
         params: ColorPresentationParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<ColorPresentation>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document formatting request](https://microsoft.github.io/language-server-protocol/specification#textDocument_formatting)
@@ -352,7 +415,7 @@ pub trait LanguageServer {
         params: DocumentFormattingParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<TextEdit>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document range formatting request](https://microsoft.github.io/language-server-protocol/specification#textDocument_rangeFormatting)
@@ -363,7 +426,7 @@ pub trait LanguageServer {
         params: DocumentRangeFormattingParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<TextEdit>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [document on type formatting request](https://microsoft.github.io/language-server-protocol/specification#textDocument_onTypeFormatting)
@@ -374,7 +437,7 @@ pub trait LanguageServer {
         params: DocumentOnTypeFormattingParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<TextEdit>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [rename request](https://microsoft.github.io/language-server-protocol/specification#textDocument_rename)
@@ -386,7 +449,7 @@ pub trait LanguageServer {
         params: RenameParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<WorkspaceEdit>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The [prepare rename request](https://microsoft.github.io/language-server-protocol/specification#textDocument_prepareRename)
@@ -397,7 +460,7 @@ pub trait LanguageServer {
         params: TextDocumentPositionParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<PrepareRenameResponse>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The [folding range request](https://microsoft.github.io/language-server-protocol/specification#textDocument_foldingRange)
@@ -408,7 +471,7 @@ pub trait LanguageServer {
         params: FoldingRangeParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<FoldingRange>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [selection range request](https://microsoft.github.io/language-server-protocol/specification#textDocument_selectionRange)
@@ -419,7 +482,7 @@ pub trait LanguageServer {
         params: SelectionRangeParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<SelectionRange>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [call hierarchy request](https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_prepareCallHierarchy)
@@ -432,7 +495,7 @@ pub trait LanguageServer {
         params: CallHierarchyPrepareParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<CallHierarchyItem>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [request](https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#callHierarchy_incomingCalls)
@@ -445,7 +508,7 @@ pub trait LanguageServer {
         params: CallHierarchyIncomingCallsParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<CallHierarchyIncomingCall>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The [request](https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#callHierarchy_outgoingCalls)
@@ -458,7 +521,7 @@ pub trait LanguageServer {
         params: CallHierarchyOutgoingCallsParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Vec<CallHierarchyOutgoingCall>> {
-        Ok(Vec::new())
+        Err(not_implemented())
     }
 
     /// The `textDocument/semanticTokens` request is sent from the client to the server
@@ -471,7 +534,7 @@ pub trait LanguageServer {
         params: SemanticTokensParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<SemanticTokensResult>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The `textDocument/semanticTokens/edits` request is sent from the client to the server
@@ -484,7 +547,7 @@ pub trait LanguageServer {
         params: SemanticTokensEditsParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<SemanticTokensEditResult>> {
-        Ok(None)
+        Err(not_implemented())
     }
 
     /// The `textDocument/semanticTokens/range` request is sent from the client to the server
@@ -497,16 +560,153 @@ pub trait LanguageServer {
         params: SemanticTokensRangeParams,
         client: Arc<dyn LanguageClient>,
     ) -> Result<Option<SemanticTokensRangeResult>> {
-        Ok(None)
+        Err(not_implemented())
+    }
+
+    /// The `textDocument/inlineCompletion` request is sent from the client to the server
+    /// to compute inline completions for a given text document either explicitly by a user gesture
+    /// or implicitly when typing.
+    #[cfg_attr(docsrs, doc(cfg(feature = "draft")))]
+    #[cfg(feature = "draft")]
+    #[jsonrpc_method(name = "textDocument/inlineCompletion", kind = "request")]
+    async fn inline_completion(
+        &self,
+        params: InlineCompletionParams,
+        client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<InlineCompletionResponse>> {
+        Err(not_implemented())
+    }
+
+    /// The [`workspace/willRenameFiles`](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_willRenameFiles)
+    /// request is sent from the client to the server before files are actually renamed.
+    /// The server can return a workspace edit, which is applied before the files are renamed.
+    #[cfg_attr(docsrs, doc(cfg(feature = "file-operations")))]
+    #[cfg(feature = "file-operations")]
+    #[jsonrpc_method(name = "workspace/willRenameFiles", kind = "request")]
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+        client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<WorkspaceEdit>> {
+        Err(not_implemented())
     }
+
+    /// The [`workspace/didRenameFiles`](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_didRenameFiles)
+    /// notification is sent from the client to the server when files were renamed from within the client.
+    #[cfg_attr(docsrs, doc(cfg(feature = "file-operations")))]
+    #[cfg(feature = "file-operations")]
+    #[jsonrpc_method(name = "workspace/didRenameFiles", kind = "notification")]
+    async fn did_rename_files(&self, params: RenameFilesParams, client: Arc<dyn LanguageClient>) {}
 }
 
+/// Dispatches incoming requests and notifications to the methods of a server.
+///
+/// The trait is implemented by [`jsonrpc_server`](attr.jsonrpc_server.html)
+/// for every type that implements the annotated trait.
 #[async_trait]
-pub trait RequestHandler<C>
-where
-    C: LanguageClient,
-{
+pub trait RequestHandler<C> {
+    /// Handles the given request and returns its response.
     async fn handle_request(&self, request: Request, client: Arc<C>) -> Response;
 
+    /// Handles the given notification.
     async fn handle_notification(&self, notification: Notification, client: Arc<C>);
 }
+
+/// Handles the messages that are not known to the `LanguageServer`, e.g. to wrap a legacy dispatch
+/// during a migration.
+///
+/// See [`LanguageServiceBuilder::fallback_handler`](struct.LanguageServiceBuilder.html).
+#[async_trait]
+pub trait RawHandler: Send + Sync {
+    /// Handles a request that has been answered with a `MethodNotFound` error by the server.
+    async fn handle_raw_request(
+        &self,
+        request: Request,
+        client: Arc<dyn LanguageClient>,
+    ) -> Response;
+
+    /// Handles a notification that is not dispatched to the server.
+    async fn handle_raw_notification(
+        &self,
+        notification: Notification,
+        client: Arc<dyn LanguageClient>,
+    );
+}
+
+// Returns `true` if the response tells the client that the method is not implemented.
+pub(crate) fn is_method_not_found(response: &Response) -> bool {
+    response
+        .error
+        .as_ref()
+        .map(|error| error.code == ErrorCode::MethodNotFound)
+        .unwrap_or(false)
+}
+
+// Returns `true` if notifications of the given method are dispatched to the server.
+pub(crate) fn is_dispatched(method: &str) -> bool {
+    language_server_supported_methods()
+        .iter()
+        .any(|supported| supported.enabled && supported.name == method)
+}
+
+// Marks the `MethodNotFound` errors of the default implementations,
+// so they can be told apart from the errors that a handler returns on purpose.
+const NOT_IMPLEMENTED: &str = "$/notImplemented";
+
+// Returns the error of a request that the server does not implement.
+fn not_implemented() -> Error {
+    Error {
+        data: Some(json!(NOT_IMPLEMENTED)),
+        ..Error::method_not_found_error()
+    }
+}
+
+// Returns the first candidate that has the type of the result:
+// `null` for optional results, an empty array for lists and the parameters for requests that resolve an item.
+pub fn empty_result<T: DeserializeOwned>(params: &serde_json::Value) -> Option<serde_json::Value> {
+    vec![serde_json::Value::Null, json!([]), params.clone()]
+        .into_iter()
+        .find(|candidate| serde_json::from_value::<T>(candidate.clone()).is_ok())
+}
+
+// Removes the marker of a request that is not implemented by the server from its response
+// and replaces the error with an empty result if `lenient_defaults` is enabled.
+pub(crate) fn default_response(
+    request: &Request,
+    mut response: Response,
+    lenient: bool,
+) -> Response {
+    let not_implemented = match &mut response.error {
+        Some(error) if error.data == Some(json!(NOT_IMPLEMENTED)) => {
+            error.data = None;
+            true
+        }
+        _ => false,
+    };
+
+    if !not_implemented || !lenient {
+        return response;
+    }
+
+    let params = request.params.clone().unwrap_or_default();
+    match language_server_empty_result(&request.method, &params) {
+        Some(result) => Response::result(result, request.id.clone()),
+        None => response,
+    }
+}
+
+// Attaches the known methods to a `MethodNotFound` error, so mismatches of the capabilities can be diagnosed.
+pub(crate) fn debug_response(mut response: Response) -> Response {
+    if let Some(error) = &mut response.error {
+        if error.code == ErrorCode::MethodNotFound {
+            let methods = language_server_supported_methods();
+            let enabled: Vec<_> = methods.iter().filter(|method| method.enabled).collect();
+            let disabled: Vec<_> = methods.iter().filter(|method| !method.enabled).collect();
+            error.data = Some(json!({
+                "supportedMethods": enabled,
+                "disabledMethods": disabled,
+            }));
+        }
+    }
+    response
+}
@@ -0,0 +1,112 @@
+//! Reference servers to smoke-test the transport, the codecs and middlewares
+//! without writing a dummy `LanguageServer`.
+//!
+//! - [`NullServer`](struct.NullServer.html) uses the default implementation of every method,
+//!   so it answers `initialize` with empty capabilities and every other request with `MethodNotFound`.
+//! - [`EchoServer`](struct.EchoServer.html) sends the payloads of the client back,
+//!   so arbitrary JSON can be round-tripped through a service.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use async_executors::TokioTp;
+//! use language_server::{servers::EchoServer, *};
+//! use std::{convert::TryFrom, sync::Arc};
+//! use tokio_util::compat::*;
+//!
+//! let executor = TokioTp::try_from(&mut tokio::runtime::Builder::new())
+//!     .expect("failed to create thread pool");
+//!
+//! let result = executor.block_on(
+//!     LanguageService::builder()
+//!         .server(Arc::new(EchoServer))
+//!         .input(tokio::io::stdin().compat())
+//!         .output(tokio::io::stdout().compat_write())
+//!         .executor(executor.clone())
+//!         .build()
+//!         .listen(),
+//! );
+//!
+//! std::process::exit(match result {
+//!     Ok(reason) => reason.exit_code(),
+//!     Err(why) => {
+//!         eprintln!("{}", why);
+//!         1
+//!     }
+//! });
+//! ```
+use crate::{jsonrpc::*, LanguageClient, LanguageServer};
+use async_trait::async_trait;
+use lsp_types::*;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// The command of the [`EchoServer`](struct.EchoServer.html).
+pub const ECHO_COMMAND: &str = "echo";
+
+/// A server that does nothing but answering the `initialize` request with empty capabilities.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullServer;
+
+#[async_trait]
+impl LanguageServer for NullServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+}
+
+/// A server that sends the payloads of the client back:
+///
+/// - The `echo` command of `workspace/executeCommand` returns its arguments.
+/// - The settings of `workspace/didChangeConfiguration` are sent back as a `telemetry/event` notification.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EchoServer;
+
+#[async_trait]
+impl LanguageServer for EchoServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        let capabilities = ServerCapabilities {
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands: vec![ECHO_COMMAND.to_owned()],
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            }),
+            ..ServerCapabilities::default()
+        };
+
+        Ok(InitializeResult {
+            capabilities,
+            server_info: None,
+        })
+    }
+
+    async fn did_change_configuration(
+        &self,
+        params: DidChangeConfigurationParams,
+        client: Arc<dyn LanguageClient>,
+    ) {
+        client.telemetry_event(params.settings).await;
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<Value>> {
+        if params.command == ECHO_COMMAND {
+            Ok(Some(Value::Array(params.arguments)))
+        } else {
+            Err(Error::invalid_params(format!(
+                "unknown command: {}",
+                params.command
+            )))
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    jsonrpc::*, scope::TaskScope, CancellationToken, ExitReason, RequestHandler, ServiceError,
+};
+use futures::{
+    channel::mpsc,
+    future::{join, select, Either},
+    sink::SinkExt,
+    stream::StreamExt,
+    task::Spawn,
+    AsyncRead, AsyncWrite,
+};
+use futures_codec::{FramedRead, FramedWrite};
+use language_server_transport::{Framing, ResponseHandler};
+use std::{io, sync::Arc};
+use typed_builder::TypedBuilder;
+
+/// Represents a service that processes the messages of an arbitrary JSON-RPC protocol.
+///
+/// The server implements a trait that has been annotated with
+/// [`jsonrpc_server`](attr.jsonrpc_server.html) and the client is generated by
+/// [`jsonrpc_client`](attr.jsonrpc_client.html), so the framing of the messages,
+/// the dispatch and the tracking of pending requests are shared with `LanguageService`.
+/// Unlike `LanguageService`, the service knows nothing about the lifecycle of the protocol
+/// and stops with [`ExitReason::Disconnected`](enum.ExitReason.html#variant.Disconnected)
+/// once the input stream has ended.
+///
+/// The handler of a custom protocol is implemented for the trait object of the server,
+/// e.g. `Arc<dyn MyServer + Send + Sync>`.
+#[builder(builder_type_doc = "A builder to construct a `JsonRpcService`.")]
+#[builder(builder_method_doc = "Returns a builder for constructing a new `JsonRpcService`.")]
+#[derive(TypedBuilder)]
+pub struct JsonRpcService<I, O, S: ?Sized, C, E> {
+    #[builder(setter(doc = "Sets the input stream for the service."))]
+    input: I,
+    #[builder(setter(doc = "Sets the output stream for the service."))]
+    output: O,
+    #[builder(setter(doc = "Sets the server that handles the incoming messages."))]
+    server: Arc<S>,
+    #[builder(setter(doc = "Sets the constructor of the client, e.g. `MyClientImpl::new`."))]
+    client: fn(mpsc::Sender<Message>) -> C,
+    #[builder(setter(doc = "Sets the executor that is used to handle requests concurrently."))]
+    executor: E,
+    #[builder(
+        default = Framing::ContentLength,
+        setter(doc = "Sets how the messages are delimited in the input and output streams.")
+    )]
+    framing: Framing,
+}
+
+impl<I, O, S, C, E> JsonRpcService<I, O, S, C, E>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+    S: RequestHandler<C> + Send + Sync + ?Sized + 'static,
+    C: ResponseHandler + Send + Sync + 'static,
+    E: Spawn,
+{
+    /// Starts the service and processes messages until the input stream has ended.
+    /// Notifications are processed in order, whereas requests are handled concurrently.
+    ///
+    /// Once the input has ended, the pending requests of the client fail
+    /// and the service waits until the running handlers have sent their responses.
+    pub async fn listen(self) -> std::result::Result<ExitReason, ServiceError> {
+        let (output_tx, output_rx) = mpsc::channel(0);
+        let client = Arc::new((self.client)(output_tx.clone()));
+        let failed = CancellationToken::new();
+        let write_loop = Self::write_messages(self.output, self.framing, output_rx, &failed);
+
+        let scope = TaskScope::default();
+        let input = self.input;
+        let framing = self.framing;
+        let server = self.server;
+        let executor = self.executor;
+        let read_loop = async {
+            let reason = Self::read_messages(
+                input,
+                framing,
+                server,
+                Arc::clone(&client),
+                &executor,
+                &scope,
+                output_tx.clone(),
+                &failed,
+            )
+            .await;
+            // No response can arrive once the input has ended,
+            // so handlers awaiting the client must not keep the service alive.
+            client.close();
+            scope.join().await;
+            output_tx.clone().close_channel();
+            reason
+        };
+
+        // A failed write stops the service, so the error takes precedence over the exit reason.
+        let (reason, written) = join(read_loop, write_loop).await;
+        written.map_err(ServiceError::Output)?;
+        reason
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn read_messages(
+        input: I,
+        framing: Framing,
+        server: Arc<S>,
+        client: Arc<C>,
+        executor: &E,
+        scope: &TaskScope,
+        mut output: mpsc::Sender<Message>,
+        failed: &CancellationToken,
+    ) -> std::result::Result<ExitReason, ServiceError> {
+        let mut input = FramedRead::new(input, framing);
+        loop {
+            let json = match select(input.next(), failed.cancelled()).await {
+                Either::Left((Some(Ok(json)), _)) => json,
+                Either::Left((Some(Err(why)), _)) => return Err(ServiceError::Input(why)),
+                Either::Left((None, _)) | Either::Right(_) => return Ok(ExitReason::Disconnected),
+            };
+
+            match serde_json::from_str(&json) {
+                Ok(Message::Request(request)) => {
+                    let server = Arc::clone(&server);
+                    let client = Arc::clone(&client);
+                    let mut output = output.clone();
+                    let handler = async move {
+                        let response = server.handle_request(request, client).await;
+                        output.send(Message::Response(response)).await.unwrap();
+                    };
+                    scope
+                        .spawn(executor, handler)
+                        .expect("failed to spawn future");
+                }
+                Ok(Message::Notification(notification)) => {
+                    server
+                        .handle_notification(notification, Arc::clone(&client))
+                        .await;
+                }
+                Ok(Message::Response(response)) => client.handle(response).await,
+                Err(_) => {
+                    let response = Response::error(Error::parse_error(), None);
+                    output.send(Message::Response(response)).await.unwrap();
+                }
+            }
+        }
+    }
+
+    async fn write_messages(
+        output: O,
+        framing: Framing,
+        mut output_rx: mpsc::Receiver<Message>,
+        failed: &CancellationToken,
+    ) -> io::Result<()> {
+        let mut output = FramedWrite::new(output, framing);
+        let mut result = Ok(());
+        while let Some(message) = output_rx.next().await {
+            // The remaining messages are discarded, so the handlers do not block while the service stops.
+            if result.is_ok() {
+                let json = serde_json::to_string(&message).expect("failed to serialize message");
+                result = output.send(json).await;
+                if let Err(why) = &result {
+                    log::error!("Failed to write to the output stream: {}", why);
+                    failed.cancel();
+                }
+            }
+        }
+        result
+    }
+}
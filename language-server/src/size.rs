@@ -0,0 +1,190 @@
+use crate::{
+    i18n, jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Middleware that measures the size of the serialized responses
+/// and logs a warning if a response exceeds the configured limit.
+///
+/// Oversized completion and document symbol results can optionally be truncated,
+/// so the editor does not have to process hundreds of megabytes.
+/// Truncated completion lists are marked as incomplete,
+/// truncated document symbols end with a symbol that tells how many symbols have been omitted.
+#[derive(Debug, Clone)]
+pub struct ResponseSizeMiddleware {
+    limit: usize,
+    max_completion_items: Option<usize>,
+    max_document_symbols: Option<usize>,
+}
+
+impl ResponseSizeMiddleware {
+    /// Creates a middleware that warns about responses that are larger than the given number of bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            max_completion_items: None,
+            max_document_symbols: None,
+        }
+    }
+
+    /// Truncates oversized `textDocument/completion` results to the given number of items.
+    pub fn with_max_completion_items(self, max_completion_items: usize) -> Self {
+        Self {
+            max_completion_items: Some(max_completion_items),
+            ..self
+        }
+    }
+
+    /// Truncates oversized `textDocument/documentSymbol` results to the given number of symbols.
+    pub fn with_max_document_symbols(self, max_document_symbols: usize) -> Self {
+        Self {
+            max_document_symbols: Some(max_document_symbols.max(1)),
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for ResponseSizeMiddleware {
+    async fn on_incoming_message(
+        &self,
+        _message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        request: &Request,
+        _metadata: &MessageMetadata,
+        response: &mut Response,
+        context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        let result = match &mut response.result {
+            Some(result) => result,
+            None => return,
+        };
+
+        let size = serde_json::to_vec(result)
+            .map(|json| json.len())
+            .unwrap_or(0);
+        if size <= self.limit {
+            return;
+        }
+
+        let truncated = match (
+            request.method.as_str(),
+            self.max_completion_items,
+            self.max_document_symbols,
+        ) {
+            ("textDocument/completion", Some(max), _) => truncate_completion(result, max),
+            ("textDocument/documentSymbol", _, Some(max)) => {
+                truncate_document_symbols(result, max, context)
+            }
+            _ => false,
+        };
+
+        log::warn!(
+            "{}: the response has a size of {} bytes{}",
+            request.method,
+            size,
+            if truncated {
+                " and has been truncated"
+            } else {
+                ""
+            }
+        );
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+}
+
+// Truncates a `CompletionResponse` and turns it into an incomplete list.
+fn truncate_completion(result: &mut Value, max: usize) -> bool {
+    let items = match result {
+        Value::Array(items) => items,
+        Value::Object(list) => match list.get_mut("items") {
+            Some(Value::Array(items)) => items,
+            _ => return false,
+        },
+        _ => return false,
+    };
+
+    if items.len() <= max {
+        return false;
+    }
+
+    items.truncate(max);
+    let items = items.split_off(0);
+    *result = json!({ "isIncomplete": true, "items": items });
+    true
+}
+
+// Truncates a `DocumentSymbolResponse` and replaces the last symbol with a marker.
+fn truncate_document_symbols(result: &mut Value, max: usize, context: &ServerContext) -> bool {
+    let symbols = match result {
+        Value::Array(symbols) if symbols.len() > max => symbols,
+        _ => return false,
+    };
+
+    let omitted = symbols.len() - max + 1;
+    symbols.truncate(max);
+    if let Some(Value::Object(marker)) = symbols.last_mut() {
+        marker.remove("children");
+        marker.remove("detail");
+        marker.insert(
+            "name".to_owned(),
+            Value::String(context.localize(i18n::OMITTED_SYMBOLS, &[&omitted.to_string()])),
+        );
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_completion_array() {
+        let mut result = json!([{ "label": "foo" }, { "label": "bar" }, { "label": "baz" }]);
+        assert!(truncate_completion(&mut result, 2));
+        assert_eq!(
+            result,
+            json!({ "isIncomplete": true, "items": [{ "label": "foo" }, { "label": "bar" }] })
+        );
+        assert!(!truncate_completion(&mut result, 2));
+    }
+
+    #[test]
+    fn truncate_document_symbols_marker() {
+        let symbol = |name: &str| json!({ "name": name, "kind": 12, "children": [] });
+        let mut result = json!([symbol("foo"), symbol("bar"), symbol("baz")]);
+        let context = ServerContext::default();
+        assert!(truncate_document_symbols(&mut result, 2, &context));
+        assert_eq!(
+            result,
+            json!([symbol("foo"), { "name": "... 2 more symbols", "kind": 12 }])
+        );
+        assert!(!truncate_document_symbols(&mut result, 2, &context));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::jsonrpc::*;
+use futures::future::{BoxFuture, FutureExt};
+use std::{fmt, future::Future};
+
+/// Prepares the service while the editor is still starting up.
+///
+/// The warmers are spawned as soon as the service starts listening, so work that does not depend
+/// on the `initialize` parameters, e.g. loading a package database, is done before the first request.
+/// Optionally, the service answers a ping request directly by echoing its parameters,
+/// which allows launchers to verify the transport before sending `initialize`.
+/// Pings bypass the middlewares and the server.
+#[derive(Default)]
+pub struct Standby {
+    warmers: Vec<BoxFuture<'static, ()>>,
+    ping_method: Option<String>,
+}
+
+impl fmt::Debug for Standby {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Standby")
+            .field("warmers", &self.warmers.len())
+            .field("ping_method", &self.ping_method)
+            .finish()
+    }
+}
+
+impl Standby {
+    /// Creates a standby mode without warmers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the given future in the background once the service starts listening.
+    pub fn with_warmer<F>(mut self, warmer: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.warmers.push(warmer.boxed());
+        self
+    }
+
+    /// Answers requests with the given method, e.g. `$/ping`, with their parameters.
+    pub fn with_ping(self, method: String) -> Self {
+        Self {
+            ping_method: Some(method),
+            ..self
+        }
+    }
+
+    pub(crate) fn ping_method(&self) -> Option<&str> {
+        match &self.ping_method {
+            Some(method) => Some(method),
+            None => None,
+        }
+    }
+
+    pub(crate) fn take_warmers(&mut self) -> Vec<BoxFuture<'static, ()>> {
+        self.warmers.split_off(0)
+    }
+
+    // Returns the response to a ping request.
+    pub(crate) fn answer(&self, message: &Message) -> Option<Response> {
+        match (message, &self.ping_method) {
+            (Message::Request(request), Some(method)) if request.method == *method => {
+                Some(Response::result(
+                    request.params.clone().unwrap_or_default(),
+                    request.id.clone(),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn answer_ping() {
+        let standby = Standby::new().with_ping("$/ping".into());
+        let ping = Request::new("$/ping".into(), json!({ "foo": 1 }), Id::Number(0));
+        assert_eq!(
+            standby.answer(&Message::Request(ping)),
+            Some(Response::result(json!({ "foo": 1 }), Id::Number(0)))
+        );
+
+        let other = Request::new("initialize".into(), json!({}), Id::Number(1));
+        assert_eq!(standby.answer(&Message::Request(other)), None);
+        assert_eq!(Standby::new().take_warmers().len(), 0);
+    }
+}
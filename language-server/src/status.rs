@@ -0,0 +1,142 @@
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// The kind of a problem that has been recorded by the [`ServerStatus`](struct.ServerStatus.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProblemKind {
+    /// A request has been answered with an error.
+    HandlerError,
+
+    /// An incoming message is not valid JSON-RPC.
+    ParseError,
+
+    /// An incoming message has been discarded without being processed.
+    DroppedMessage,
+}
+
+/// A problem that has been recorded by the [`ServerStatus`](struct.ServerStatus.html).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Problem {
+    /// The number of problems that have been recorded before this one.
+    pub sequence: u64,
+
+    /// The kind of the problem.
+    pub kind: ProblemKind,
+
+    /// The method of the affected message, if it is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// The result of the `$/serverStatus` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    /// The number of requests that have been answered with an error.
+    pub handler_errors: u64,
+
+    /// The number of incoming messages that are not valid JSON-RPC.
+    pub parse_errors: u64,
+
+    /// The number of incoming messages that have been discarded.
+    pub dropped_messages: u64,
+
+    /// The most recent problems, starting with the oldest one.
+    pub recent: Vec<Problem>,
+}
+
+/// Keeps a bounded history of the recent problems of the service
+/// and answers the custom `$/serverStatus` request with it,
+/// so editor extensions can show the health of the server without scraping the log.
+///
+/// The counters cover the whole session, whereas only the last `capacity` problems are kept.
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    capacity: usize,
+    history: Arc<Mutex<History>>,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    handler_errors: u64,
+    parse_errors: u64,
+    dropped_messages: u64,
+    recent: VecDeque<Problem>,
+}
+
+impl ServerStatus {
+    /// Creates a history that keeps the given number of recent problems.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            history: Arc::default(),
+        }
+    }
+
+    /// Returns the counters and the recent problems.
+    pub fn report(&self) -> StatusReport {
+        let history = self.history.lock().unwrap();
+        StatusReport {
+            handler_errors: history.handler_errors,
+            parse_errors: history.parse_errors,
+            dropped_messages: history.dropped_messages,
+            recent: history.recent.iter().cloned().collect(),
+        }
+    }
+
+    // Records a problem and evicts the oldest one if the history is full.
+    pub(crate) fn record(&self, kind: ProblemKind, method: Option<&str>, message: String) {
+        let mut history = self.history.lock().unwrap();
+        let sequence = history.handler_errors + history.parse_errors + history.dropped_messages;
+        match kind {
+            ProblemKind::HandlerError => history.handler_errors += 1,
+            ProblemKind::ParseError => history.parse_errors += 1,
+            ProblemKind::DroppedMessage => history.dropped_messages += 1,
+        };
+
+        history.recent.push_back(Problem {
+            sequence,
+            kind,
+            method: method.map(ToOwned::to_owned),
+            message,
+        });
+        while history.recent.len() > self.capacity {
+            history.recent.pop_front();
+        }
+    }
+}
+
+impl Default for ServerStatus {
+    fn default() -> Self {
+        Self::new(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_history() {
+        let status = ServerStatus::new(2);
+        status.record(ProblemKind::ParseError, None, "foo".into());
+        status.record(ProblemKind::HandlerError, Some("bar"), "baz".into());
+        status.record(ProblemKind::HandlerError, Some("qux"), "quux".into());
+
+        let report = status.report();
+        assert_eq!(report.handler_errors, 2);
+        assert_eq!(report.parse_errors, 1);
+        assert_eq!(report.dropped_messages, 0);
+        assert_eq!(report.recent.len(), 2);
+        assert_eq!(report.recent[0].sequence, 1);
+        assert_eq!(report.recent[1].method.as_ref().unwrap(), "qux");
+    }
+}
@@ -0,0 +1,393 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    future::FutureExt,
+    io::{AsyncRead, AsyncWrite},
+    ready,
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Returns the standard input and output streams of the process.
+///
+/// The blocking standard streams are bridged on dedicated threads, so they can be used
+/// with any executor, including single-threaded ones like `futures::executor::LocalPool`.
+pub fn stdio() -> (ThreadedReader, ThreadedWriter) {
+    (
+        ThreadedReader::new(io::stdin()),
+        ThreadedWriter::new(io::stdout()),
+    )
+}
+
+/// Listens on the given address and returns the streams of the first TCP connection,
+/// for editors that connect to a server which has been started separately.
+pub fn accept_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<(ThreadedReader, ThreadedWriter)> {
+    accept(&TcpListener::bind(addr)?)
+}
+
+/// Waits for the next connection of the given listener and returns its streams.
+pub fn accept(listener: &TcpListener) -> io::Result<(ThreadedReader, ThreadedWriter)> {
+    let (stream, _) = listener.accept()?;
+    Ok((
+        ThreadedReader::new(stream.try_clone()?),
+        ThreadedWriter::new(stream),
+    ))
+}
+
+/// Creates a Unix domain socket at the given path and returns the streams of the first connection.
+///
+/// Named pipes are not supported on Windows yet: the threads of the streams would share
+/// a synchronous handle, whose reads block the writes. The function fails on other platforms.
+pub fn accept_pipe<P: AsRef<Path>>(path: P) -> io::Result<(ThreadedReader, ThreadedWriter)> {
+    #[cfg(unix)]
+    {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+        Ok((
+            ThreadedReader::new(stream.try_clone()?),
+            ThreadedWriter::new(stream),
+        ))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Err(pipes_unsupported())
+    }
+}
+
+/// Connects to the Unix domain socket at the given path and returns its streams,
+/// e.g. to the socket that the client has passed with `--pipe`.
+///
+/// Like [`accept_pipe`](fn.accept_pipe.html), the function fails on platforms other than Unix.
+pub fn connect_pipe<P: AsRef<Path>>(path: P) -> io::Result<(ThreadedReader, ThreadedWriter)> {
+    #[cfg(unix)]
+    {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        Ok((
+            ThreadedReader::new(stream.try_clone()?),
+            ThreadedWriter::new(stream),
+        ))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Err(pipes_unsupported())
+    }
+}
+
+#[cfg(not(unix))]
+fn pipes_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "pipes are only supported on Unix domain sockets",
+    )
+}
+
+/// An input stream that reads from a blocking reader on a dedicated thread.
+#[derive(Debug)]
+pub struct ThreadedReader {
+    receiver: mpsc::UnboundedReceiver<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ThreadedReader {
+    /// Spawns a thread that reads from the given reader until it reaches the end of the stream.
+    pub fn new<R: Read + Send + 'static>(mut reader: R) -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        thread::spawn(move || {
+            let mut buf = vec![0; 8192];
+            loop {
+                let chunk = match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(count) => Ok(buf[..count].to_vec()),
+                    Err(ref why) if why.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(why) => Err(why),
+                };
+
+                let failed = chunk.is_err();
+                if tx.unbounded_send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            receiver: rx,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl AsyncRead for ThreadedReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.position == self.buffer.len() {
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.buffer = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(Some(Err(why))) => return Poll::Ready(Err(why)),
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let count = buf.len().min(self.buffer.len() - self.position);
+        let start = self.position;
+        buf[..count].copy_from_slice(&self.buffer[start..start + count]);
+        self.position += count;
+        Poll::Ready(Ok(count))
+    }
+}
+
+/// An output stream that writes to a blocking writer on a dedicated thread.
+///
+/// Flushing the stream waits until the thread has flushed the writer and closing it waits
+/// until the thread has exited, so no output is lost when the process exits afterwards.
+/// An error of the writer is returned by the next operation on the stream.
+#[derive(Debug)]
+pub struct ThreadedWriter {
+    sender: mpsc::UnboundedSender<WriteCommand>,
+    error: Arc<Mutex<Option<io::Error>>>,
+    flushed: Option<oneshot::Receiver<io::Result<()>>>,
+    exited: oneshot::Receiver<()>,
+}
+
+#[derive(Debug)]
+enum WriteCommand {
+    Write(Vec<u8>),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+impl ThreadedWriter {
+    /// Spawns a thread that writes to the given writer until the stream is closed.
+    pub fn new<W: Write + Send + 'static>(mut writer: W) -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        let (exited_tx, exited_rx) = oneshot::channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+        thread::spawn(move || {
+            // The sender is dropped once the thread exits, which completes the close of the stream.
+            let _exited = exited_tx;
+            for command in futures::executor::block_on_stream(rx) {
+                let result = match command {
+                    WriteCommand::Write(chunk) => writer.write_all(&chunk),
+                    WriteCommand::Flush(ack) => {
+                        let _ = ack.send(writer.flush());
+                        Ok(())
+                    }
+                };
+
+                // The error is stored before the commands are dropped, which fails pending flushes.
+                if let Err(why) = result {
+                    *thread_error.lock().unwrap() = Some(why);
+                    return;
+                }
+            }
+
+            if let Err(why) = writer.flush() {
+                *thread_error.lock().unwrap() = Some(why);
+            }
+        });
+
+        Self {
+            sender: tx,
+            error,
+            flushed: None,
+            exited: exited_rx,
+        }
+    }
+
+    // Returns the error that has stopped the thread.
+    fn take_error(&self) -> io::Error {
+        self.error
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+impl AsyncWrite for ThreadedWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Some(why) = self.error.lock().unwrap().take() {
+            return Poll::Ready(Err(why));
+        }
+
+        let result = match self
+            .sender
+            .unbounded_send(WriteCommand::Write(buf.to_vec()))
+        {
+            Ok(()) => Ok(buf.len()),
+            Err(_) => Err(self.take_error()),
+        };
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.flushed.is_none() {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            if self
+                .sender
+                .unbounded_send(WriteCommand::Flush(ack_tx))
+                .is_err()
+            {
+                return Poll::Ready(Err(self.take_error()));
+            }
+            self.flushed = Some(ack_rx);
+        }
+
+        let result = ready!(self.flushed.as_mut().unwrap().poll_unpin(cx));
+        self.flushed = None;
+        match result {
+            Ok(result) => Poll::Ready(result),
+            Err(_) => Poll::Ready(Err(self.take_error())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.sender.close_channel();
+        let _ = ready!(self.exited.poll_unpin(cx));
+        match self.error.lock().unwrap().take() {
+            Some(why) => Poll::Ready(Err(why)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn read_to_end() {
+        let mut reader = ThreadedReader::new(io::Cursor::new(b"foo bar".to_vec()));
+        let mut buf = String::new();
+        block_on(reader.read_to_string(&mut buf)).unwrap();
+        assert_eq!(buf, "foo bar");
+    }
+
+    #[test]
+    fn accept_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"foo").unwrap();
+            let mut buf = [0; 3];
+            stream.read_exact(&mut buf).unwrap();
+            buf
+        });
+
+        let (mut reader, mut writer) = accept(&listener).unwrap();
+        let mut buf = [0; 3];
+        block_on(reader.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"foo");
+        block_on(writer.write_all(b"bar")).unwrap();
+        assert_eq!(&client.join().unwrap(), b"bar");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accept_pipe_connection() {
+        let path =
+            std::env::temp_dir().join(format!("language-server-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server_path = path.clone();
+        let server = thread::spawn(move || {
+            let (mut reader, mut writer) = accept_pipe(server_path).unwrap();
+            let mut buf = [0; 3];
+            block_on(reader.read_exact(&mut buf)).unwrap();
+            block_on(writer.write_all(b"bar")).unwrap();
+            buf
+        });
+
+        let (mut reader, mut writer) = loop {
+            match connect_pipe(&path) {
+                Ok(streams) => break streams,
+                Err(_) => thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        };
+        block_on(writer.write_all(b"foo")).unwrap();
+        let mut buf = [0; 3];
+        block_on(reader.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"bar");
+        assert_eq!(&server.join().unwrap(), b"foo");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_and_close() {
+        let mut writer = ThreadedWriter::new(io::sink());
+        block_on(writer.write_all(b"foo")).unwrap();
+        block_on(writer.close()).unwrap();
+        assert!(block_on(writer.write_all(b"bar")).is_err());
+    }
+
+    // Appends to a shared buffer after a delay and fails once the buffer is full.
+    #[derive(Clone, Default)]
+    struct SlowWriter {
+        buffer: Arc<Mutex<Vec<u8>>>,
+        flushed: Arc<Mutex<usize>>,
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            thread::sleep(std::time::Duration::from_millis(20));
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.len() >= 6 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            *self.flushed.lock().unwrap() = self.buffer.lock().unwrap().len();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_waits_for_thread() {
+        let inner = SlowWriter::default();
+        let mut writer = ThreadedWriter::new(inner.clone());
+        block_on(writer.write_all(b"foo")).unwrap();
+        block_on(writer.flush()).unwrap();
+        assert_eq!(*inner.flushed.lock().unwrap(), 3);
+
+        block_on(writer.write_all(b"bar")).unwrap();
+        block_on(writer.close()).unwrap();
+        assert_eq!(*inner.flushed.lock().unwrap(), 6);
+    }
+
+    #[test]
+    fn flush_reports_write_error() {
+        let inner = SlowWriter::default();
+        let mut writer = ThreadedWriter::new(inner);
+        block_on(writer.write_all(b"foobar")).unwrap();
+        block_on(writer.write_all(b"baz")).unwrap();
+        let error = block_on(writer.flush()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
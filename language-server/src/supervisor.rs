@@ -0,0 +1,307 @@
+use crate::{i18n, Clock, LanguageClient, ServerContext, SystemClock};
+use lsp_types::{MessageType, ShowMessageParams};
+use std::{
+    cmp,
+    process::{Child, Command},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type SpawnHook = dyn Fn(&mut Child) + Send + Sync;
+
+/// The health of a supervised process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessHealth {
+    /// `true` if the process is currently running.
+    pub running: bool,
+
+    /// The number of times the process has been restarted.
+    pub restarts: u64,
+
+    /// The exit code of the last run, if the process has exited normally.
+    pub last_exit_code: Option<i32>,
+
+    /// `true` if the process has crashed too often and will not be restarted anymore.
+    pub given_up: bool,
+}
+
+/// Keeps a helper process alive, e.g. a parser or linter daemon that the server delegates to.
+///
+/// The process is watched on a dedicated thread and restarted with an exponential backoff
+/// whenever it exits. The backoff is reset once a process has been running
+/// for longer than the maximum backoff.
+pub struct Supervisor {
+    name: String,
+    command: Arc<dyn Fn() -> Command + Send + Sync>,
+    spawn_hook: Option<Arc<SpawnHook>>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_restarts: Option<u64>,
+    client: Option<Arc<dyn LanguageClient>>,
+    clock: Arc<dyn Clock>,
+    context: ServerContext,
+}
+
+impl Supervisor {
+    /// Creates a supervisor for the process with the given name,
+    /// which is started by the command that is returned by the given function.
+    pub fn new<F>(name: String, command: F) -> Self
+    where
+        F: Fn() -> Command + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            command: Arc::new(command),
+            spawn_hook: None,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: None,
+            client: None,
+            clock: Arc::new(SystemClock),
+            context: ServerContext::default(),
+        }
+    }
+
+    /// Sets the delay before the first restart and the upper limit of the exponential backoff.
+    pub fn with_backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff: cmp::max(initial_backoff, max_backoff),
+            ..self
+        }
+    }
+
+    /// Gives up after the given number of restarts.
+    pub fn with_max_restarts(self, max_restarts: u64) -> Self {
+        Self {
+            max_restarts: Some(max_restarts),
+            ..self
+        }
+    }
+
+    /// Calls the given function after every start of the process,
+    /// for example to take its standard streams.
+    pub fn with_spawn_hook<F>(self, spawn_hook: F) -> Self
+    where
+        F: Fn(&mut Child) + Send + Sync + 'static,
+    {
+        Self {
+            spawn_hook: Some(Arc::new(spawn_hook)),
+            ..self
+        }
+    }
+
+    /// Shows a message to the user whenever the process crashes or the supervisor gives up.
+    pub fn with_alerts(self, client: Arc<dyn LanguageClient>) -> Self {
+        Self {
+            client: Some(client),
+            ..self
+        }
+    }
+
+    /// Translates the alerts into the locale of the client of the given session.
+    pub fn with_context(self, context: ServerContext) -> Self {
+        Self { context, ..self }
+    }
+
+    /// Sets the clock that measures the uptime of the process and the backoff.
+    ///
+    /// The process itself is always polled in real time.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Starts the process and watches it until the returned handle is stopped.
+    pub fn start(self) -> SupervisorHandle {
+        let handle = SupervisorHandle::default();
+        let watcher = handle.clone();
+        thread::spawn(move || self.watch(&watcher));
+        handle
+    }
+
+    fn watch(&self, handle: &SupervisorHandle) {
+        let mut backoff = self.initial_backoff;
+        loop {
+            let started_at = self.clock.now();
+            let exit_code = match (self.command)().spawn() {
+                Ok(mut child) => {
+                    if let Some(spawn_hook) = &self.spawn_hook {
+                        spawn_hook(&mut child);
+                    }
+
+                    handle.update(|health| health.running = true);
+                    let exit_code = match wait(&mut child, &handle.stopped) {
+                        Some(exit_code) => exit_code,
+                        None => {
+                            handle.update(|health| health.running = false);
+                            return;
+                        }
+                    };
+                    handle.update(|health| {
+                        health.running = false;
+                        health.last_exit_code = exit_code;
+                    });
+                    exit_code
+                }
+                Err(why) => {
+                    log::error!("{}: failed to start the process: {}", self.name, why);
+                    None
+                }
+            };
+
+            let restarts = handle.health().restarts;
+            let given_up = match self.max_restarts {
+                Some(max_restarts) => restarts >= max_restarts,
+                None => false,
+            };
+
+            if given_up {
+                handle.update(|health| health.given_up = true);
+                let message = self
+                    .context
+                    .localize(i18n::SUPERVISOR_GIVEN_UP, &[&self.name]);
+                self.alert(MessageType::Error, message);
+                return;
+            }
+
+            let message = match exit_code {
+                Some(code) => self
+                    .context
+                    .localize(i18n::SUPERVISOR_EXITED, &[&self.name, &code.to_string()]),
+                None => self
+                    .context
+                    .localize(i18n::SUPERVISOR_CRASHED, &[&self.name]),
+            };
+            self.alert(MessageType::Warning, message);
+
+            if self.clock.now() - started_at > self.max_backoff {
+                backoff = self.initial_backoff;
+            }
+
+            if !sleep(self.clock.as_ref(), backoff, &handle.stopped) {
+                return;
+            }
+            backoff = cmp::min(backoff * 2, self.max_backoff);
+            handle.update(|health| health.restarts += 1);
+        }
+    }
+
+    fn alert(&self, typ: MessageType, message: String) {
+        log::warn!("{}", message);
+        if let Some(client) = &self.client {
+            let params = ShowMessageParams { typ, message };
+            futures::executor::block_on(client.show_message(params));
+        }
+    }
+}
+
+/// A handle to a running [`Supervisor`](struct.Supervisor.html).
+#[derive(Debug, Clone, Default)]
+pub struct SupervisorHandle {
+    health: Arc<Mutex<ProcessHealth>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl SupervisorHandle {
+    /// Returns the current health of the process.
+    pub fn health(&self) -> ProcessHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Kills the process and stops restarting it.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn update<F: FnOnce(&mut ProcessHealth)>(&self, update: F) {
+        update(&mut self.health.lock().unwrap());
+    }
+}
+
+// Waits until the process exits and returns its exit code.
+// Returns `None` if the process has been killed because the supervisor has been stopped.
+fn wait(child: &mut Child, stopped: &AtomicBool) -> Option<Option<i32>> {
+    loop {
+        if stopped.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status.code()),
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(_) => return Some(None),
+        }
+    }
+}
+
+// Sleeps for the given duration and returns `false` if the supervisor has been stopped in the meantime.
+fn sleep(clock: &dyn Clock, duration: Duration, stopped: &AtomicBool) -> bool {
+    let deadline = clock.now() + duration;
+    while !stopped.load(Ordering::SeqCst) {
+        let now = clock.now();
+        if now >= deadline {
+            return true;
+        }
+        futures::executor::block_on(clock.sleep(cmp::min(deadline - now, POLL_INTERVAL)));
+    }
+    false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn wait_for<F: Fn(&ProcessHealth) -> bool>(handle: &SupervisorHandle, condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition(&handle.health()) {
+            assert!(
+                Instant::now() < deadline,
+                "timed out: {:?}",
+                handle.health()
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn restart_until_given_up() {
+        let handle = Supervisor::new("foo".into(), || {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg("exit 3");
+            command
+        })
+        .with_backoff(Duration::from_millis(1), Duration::from_millis(10))
+        .with_max_restarts(2)
+        .start();
+
+        wait_for(&handle, |health| health.given_up);
+        let health = handle.health();
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_exit_code, Some(3));
+        assert!(!health.running);
+    }
+
+    #[test]
+    fn stop_kills_process() {
+        let handle = Supervisor::new("foo".into(), || {
+            let mut command = Command::new("sleep");
+            command.arg("60");
+            command
+        })
+        .start();
+
+        wait_for(&handle, |health| health.running);
+        handle.stop();
+        wait_for(&handle, |health| !health.running);
+        assert_eq!(handle.health().restarts, 0);
+    }
+}
@@ -0,0 +1,866 @@
+//! Replays recorded sessions against a language server and compares the output with golden files.
+//!
+//! A golden file contains the messages of a session in the order in which they are exchanged,
+//! one message per line:
+//!
+//! - `--> {...}`: A message that is sent to the server.
+//! - `<-- {...}`: A message that the server is expected to write after receiving the previous message.
+//! - `# ...`: A comment, which is attached to the next message that is sent to the server.
+//!
+//! The session runs on a single-threaded executor until the server has processed all messages,
+//! so the output does not depend on timing. Responses to the requests of the server can be sent
+//! like any other message, because the ids of these requests are deterministic.
+//!
+//! If the environment variable `UPDATE_GOLDEN` is set, mismatching golden files are rewritten
+//! with the actual output instead of failing the test.
+//!
+//! Traces that have been written by a [`RecordingMiddleware`](../struct.RecordingMiddleware.html),
+//! e.g. attached to a bug report, are loaded with [`GoldenSession::load_trace`](struct.GoldenSession.html#method.load_trace)
+//! and replayed like golden files, so the reported behavior can be reproduced in a test.
+//!
+//! A session can also be replayed against two versions of a server with
+//! [`GoldenSession::diff`](struct.GoldenSession.html#method.diff), which reports the messages
+//! where they diverge, so a refactoring can be checked without maintaining the expected output.
+//!
+//! Handlers can also be tested without a golden file by exchanging typed messages
+//! with a [`TestService`](struct.TestService.html), or called directly with a
+//! [`MockLanguageClient`](struct.MockLanguageClient.html) that records the messages to the client.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use language_server::{async_trait::async_trait, testing::GoldenSession, types::*, *};
+//! use std::sync::Arc;
+//!
+//! struct Server;
+//!
+//! #[async_trait]
+//! impl LanguageServer for Server {
+//!     async fn initialize(
+//!         &self,
+//!         _params: InitializeParams,
+//!         _client: Arc<dyn LanguageClient>,
+//!     ) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//! }
+//!
+//! GoldenSession::load("tests/golden/initialize.session")
+//!     .expect("failed to load the session")
+//!     .assert_server(Arc::new(Server));
+//! ```
+use crate::{
+    jsonrpc::{Id, Message, Notification, Request, Response, Result},
+    LanguageClient, LanguageServer, LanguageService, MethodKind, RawClient,
+};
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc,
+    executor::{LocalPool, LocalSpawner},
+    io::{AsyncRead, AsyncWrite},
+    stream::Stream,
+    task::{Context, LocalSpawnExt, Poll},
+    Future, FutureExt,
+};
+use lsp_types::{notification, request};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    env, fs, io,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+const INPUT_PREFIX: &str = "--> ";
+const OUTPUT_PREFIX: &str = "<-- ";
+const LENGTH_HEADER: &str = "Content-Length: ";
+
+/// A session that has been loaded from a golden file.
+#[derive(Debug, Clone)]
+pub struct GoldenSession {
+    path: PathBuf,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    comments: Vec<String>,
+    input: Value,
+    outputs: Vec<Value>,
+}
+
+impl GoldenSession {
+    /// Parses the golden file at the given path.
+    pub fn load<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let text = fs::read_to_string(&path)?;
+        Self::parse(path, text.lines())
+    }
+
+    /// Parses a trace that has been written by a [`RecordingMiddleware`](../struct.RecordingMiddleware.html).
+    ///
+    /// The timestamps are ignored, so the session can be replayed deterministically.
+    /// The messages that the server has written become the expected output.
+    pub fn load_trace<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let text = fs::read_to_string(&path)?;
+        Self::parse(path, text.lines().map(strip_timestamp))
+    }
+
+    fn parse<'a, I>(path: PathBuf, lines: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut steps: Vec<Step> = Vec::new();
+        let mut comments = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let invalid = |message: &str| {
+                let message = format!("{}:{}: {}", path.display(), number + 1, message);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            } else if line.starts_with('#') {
+                comments.push(line.to_owned());
+            } else if line.get(..INPUT_PREFIX.len()) == Some(INPUT_PREFIX) {
+                let input = serde_json::from_str(&line[INPUT_PREFIX.len()..])
+                    .map_err(|why| invalid(&why.to_string()))?;
+                steps.push(Step {
+                    comments: comments.split_off(0),
+                    input,
+                    outputs: Vec::new(),
+                });
+            } else if line.get(..OUTPUT_PREFIX.len()) == Some(OUTPUT_PREFIX) {
+                let output = serde_json::from_str(&line[OUTPUT_PREFIX.len()..])
+                    .map_err(|why| invalid(&why.to_string()))?;
+                steps
+                    .last_mut()
+                    .ok_or_else(|| invalid("output before the first input"))?
+                    .outputs
+                    .push(output);
+            } else {
+                return Err(invalid("expected `-->`, `<--` or `#`"));
+            }
+        }
+
+        Ok(Self { path, steps })
+    }
+
+    /// Replays the session against a `LanguageService` with the default configuration
+    /// and asserts that the output matches the golden file.
+    pub fn assert_server<S>(&self, server: Arc<S>)
+    where
+        S: LanguageServer + Send + Sync + 'static,
+    {
+        self.assert(|input, output, executor| {
+            LanguageService::builder()
+                .input(input)
+                .output(output)
+                .executor(executor)
+                .server(server)
+                .build()
+                .listen()
+        });
+    }
+
+    /// Replays the session against a `LanguageService` with the default configuration
+    /// and returns the messages that have been written after each input.
+    pub fn replay<S>(&self, server: Arc<S>) -> Vec<Vec<Value>>
+    where
+        S: LanguageServer + Send + Sync + 'static,
+    {
+        self.run(|input, output, executor| {
+            LanguageService::builder()
+                .input(input)
+                .output(output)
+                .executor(executor)
+                .server(server)
+                .build()
+                .listen()
+        })
+    }
+
+    /// Replays the session against the service that is created by the given function
+    /// and asserts that the output matches the golden file.
+    pub fn assert<F, T>(&self, service: F)
+    where
+        F: FnOnce(SessionInput, SessionOutput, LocalSpawner) -> T,
+        T: Future + 'static,
+    {
+        let outputs = self.run(service);
+        let expected = self.render(self.steps.iter().map(|step| &step.outputs));
+        let actual = self.render(outputs.iter());
+        if expected == actual {
+            return;
+        }
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&self.path, actual).expect("failed to update the golden file");
+        } else {
+            panic!(
+                "the output does not match {} (set UPDATE_GOLDEN=1 to update it)\n\n--- expected\n{}\n+++ actual\n{}",
+                self.path.display(),
+                expected,
+                actual
+            );
+        }
+    }
+
+    /// Replays the session against the service that is created by the given function
+    /// and returns the messages that have been written after each input.
+    pub fn run<F, T>(&self, service: F) -> Vec<Vec<Value>>
+    where
+        F: FnOnce(SessionInput, SessionOutput, LocalSpawner) -> T,
+        T: Future + 'static,
+    {
+        let mut pool = LocalPool::new();
+        let (input_tx, input_rx) = mpsc::unbounded();
+        let output = SessionOutput::default();
+        let buffer = Arc::clone(&output.buffer);
+        let service = service(SessionInput::new(input_rx), output, pool.spawner());
+        pool.spawner()
+            .spawn_local(service.map(drop))
+            .expect("failed to spawn the service");
+
+        let mut outputs = Vec::new();
+        for step in &self.steps {
+            input_tx.unbounded_send(frame(&step.input)).unwrap();
+            pool.run_until_stalled();
+            outputs.push(take_messages(&buffer));
+        }
+
+        drop(input_tx);
+        pool.run_until_stalled();
+        let remaining = take_messages(&buffer);
+        match outputs.last_mut() {
+            Some(last) => last.extend(remaining),
+            None => outputs.push(remaining),
+        }
+        outputs
+    }
+
+    /// Replays the session against an old and a new version of a server
+    /// and returns the steps in which their output differs.
+    pub fn diff<S, T>(&self, old: Arc<S>, new: Arc<T>) -> SessionDiff
+    where
+        S: LanguageServer + Send + Sync + 'static,
+        T: LanguageServer + Send + Sync + 'static,
+    {
+        let old_outputs = self.run(|input, output, executor| {
+            LanguageService::builder()
+                .input(input)
+                .output(output)
+                .executor(executor)
+                .server(old)
+                .build()
+                .listen()
+        });
+
+        let new_outputs = self.run(|input, output, executor| {
+            LanguageService::builder()
+                .input(input)
+                .output(output)
+                .executor(executor)
+                .server(new)
+                .build()
+                .listen()
+        });
+
+        let divergences = self
+            .steps
+            .iter()
+            .zip(old_outputs.into_iter().zip(new_outputs))
+            .enumerate()
+            .filter(|(_, (_, (old, new)))| old != new)
+            .map(|(index, (step, (old, new)))| Divergence {
+                step: index,
+                method: step
+                    .input
+                    .get("method")
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned),
+                input: step.input.clone(),
+                old,
+                new,
+            })
+            .collect();
+
+        SessionDiff { divergences }
+    }
+
+    fn render<'a, I>(&self, outputs: I) -> String
+    where
+        I: Iterator<Item = &'a Vec<Value>>,
+    {
+        let mut text = String::new();
+        for (step, outputs) in self.steps.iter().zip(outputs) {
+            for comment in &step.comments {
+                text.push_str(comment);
+                text.push('\n');
+            }
+
+            text.push_str(INPUT_PREFIX);
+            text.push_str(&step.input.to_string());
+            text.push('\n');
+            for output in outputs {
+                text.push_str(OUTPUT_PREFIX);
+                text.push_str(&output.to_string());
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+// Removes the elapsed seconds in front of a line of a recorded trace.
+fn strip_timestamp(line: &str) -> &str {
+    match line.find(' ') {
+        Some(index) if line[..index].parse::<f64>().is_ok() => &line[index + 1..],
+        _ => line,
+    }
+}
+
+/// A step of a replayed session in which two servers have written different messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// The index of the step, starting with the first message that is sent to the server.
+    pub step: usize,
+
+    /// The method of the message that has been sent to the server, if it is not a response.
+    pub method: Option<String>,
+
+    /// The message that has been sent to the server.
+    pub input: Value,
+
+    /// The messages that the old server has written after receiving the input.
+    pub old: Vec<Value>,
+
+    /// The messages that the new server has written after receiving the input.
+    pub new: Vec<Value>,
+}
+
+/// The result of replaying a session against two servers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionDiff {
+    /// The steps in which the output differs, in the order of the session.
+    pub divergences: Vec<Divergence>,
+}
+
+impl SessionDiff {
+    /// Returns `true` if both servers have written the same messages.
+    pub fn is_empty(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Groups the divergences by the method of the message that has been sent to the server.
+    /// Responses to the requests of the server are grouped under an empty method.
+    pub fn by_method(&self) -> BTreeMap<&str, Vec<&Divergence>> {
+        let mut methods: BTreeMap<&str, Vec<&Divergence>> = BTreeMap::new();
+        for divergence in &self.divergences {
+            let method = divergence.method.as_ref().map_or("", String::as_str);
+            methods.entry(method).or_default().push(divergence);
+        }
+        methods
+    }
+}
+
+// Removes the complete messages from the output buffer.
+fn take_messages(buffer: &Mutex<Vec<u8>>) -> Vec<Value> {
+    let mut buffer = buffer.lock().unwrap();
+    let text = String::from_utf8_lossy(&buffer).into_owned();
+    let mut messages = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(index) = rest.find("\r\n\r\n") {
+        let length: usize = rest[..index]
+            .lines()
+            .find_map(|header| {
+                let is_length = header.get(..LENGTH_HEADER.len()) == Some(LENGTH_HEADER);
+                if is_length {
+                    header[LENGTH_HEADER.len()..].trim().parse().ok()
+                } else {
+                    None
+                }
+            })
+            .expect("invalid header");
+
+        let body = &rest[index + 4..];
+        if body.len() < length {
+            break;
+        }
+
+        messages.push(serde_json::from_str(&body[..length]).expect("invalid message"));
+        rest = &body[length..];
+    }
+
+    let consumed = text.len() - rest.len();
+    buffer.drain(..consumed);
+    messages
+}
+
+/// Runs a service in memory and exchanges typed messages with it, so handlers can be tested
+/// without framing the messages by hand.
+///
+/// Like a [`GoldenSession`](struct.GoldenSession.html), the service runs on a single-threaded executor
+/// until it has processed the last message. The messages that the server writes are kept
+/// until they are taken with one of the `expect_*` methods.
+///
+/// # Example
+///
+/// ```no_run
+/// # use language_server::{async_trait::async_trait, testing::TestService, types::*, *};
+/// # use std::sync::Arc;
+/// # struct Server;
+/// # #[async_trait]
+/// # impl LanguageServer for Server {
+/// #     async fn initialize(
+/// #         &self,
+/// #         _params: InitializeParams,
+/// #         _client: Arc<dyn LanguageClient>,
+/// #     ) -> Result<InitializeResult> {
+/// #         Ok(InitializeResult::default())
+/// #     }
+/// # }
+/// let mut service = TestService::new(Arc::new(Server));
+/// let params: InitializeParams = serde_json::from_value(serde_json::json!({ "capabilities": {} })).unwrap();
+/// service.request::<request::Initialize>(params).unwrap();
+/// service.notify::<notification::Initialized>(InitializedParams {});
+/// let diagnostics = service.expect_notification::<notification::PublishDiagnostics>();
+/// ```
+pub struct TestService {
+    pool: LocalPool,
+    input_tx: mpsc::UnboundedSender<Vec<u8>>,
+    buffer: Arc<Mutex<Vec<u8>>>,
+    received: VecDeque<Message>,
+    next_id: u64,
+}
+
+impl TestService {
+    /// Runs a `LanguageService` with the default configuration.
+    pub fn new<S>(server: Arc<S>) -> Self
+    where
+        S: LanguageServer + Send + Sync + 'static,
+    {
+        Self::with_service(|input, output, executor| {
+            LanguageService::builder()
+                .input(input)
+                .output(output)
+                .executor(executor)
+                .server(server)
+                .build()
+                .listen()
+        })
+    }
+
+    /// Runs the service that is created by the given function.
+    pub fn with_service<F, T>(service: F) -> Self
+    where
+        F: FnOnce(SessionInput, SessionOutput, LocalSpawner) -> T,
+        T: Future + 'static,
+    {
+        let pool = LocalPool::new();
+        let (input_tx, input_rx) = mpsc::unbounded();
+        let output = SessionOutput::default();
+        let buffer = Arc::clone(&output.buffer);
+        let service = service(SessionInput::new(input_rx), output, pool.spawner());
+        pool.spawner()
+            .spawn_local(service.map(drop))
+            .expect("failed to spawn the service");
+
+        Self {
+            pool,
+            input_tx,
+            buffer,
+            received: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Sends a request and returns the result of its response.
+    ///
+    /// Panics if the server has not answered the request after processing all messages.
+    pub fn request<R: request::Request>(&mut self, params: R::Params) -> Result<R::Result> {
+        let id = self.send_request::<R>(params);
+        self.expect_response::<R>(id)
+    }
+
+    /// Sends a request without waiting for its response,
+    /// e.g. to answer a request of the server first.
+    pub fn send_request<R: request::Request>(&mut self, params: R::Params) -> Id {
+        let id = Id::Number(self.next_id);
+        self.next_id += 1;
+        let request = Request::new(R::METHOD.to_owned(), json!(params), id.clone());
+        self.send(&Message::Request(request));
+        id
+    }
+
+    /// Sends a notification.
+    pub fn notify<N: notification::Notification>(&mut self, params: N::Params) {
+        let notification = Notification::new(N::METHOD.to_owned(), json!(params));
+        self.send(&Message::Notification(notification));
+    }
+
+    /// Answers a request of the server with the given result.
+    pub fn respond<R: request::Request>(&mut self, id: Id, result: Result<R::Result>) {
+        let response = match result {
+            Ok(result) => Response::result(json!(result), id),
+            Err(error) => Response::error(error, Some(id)),
+        };
+        self.send(&Message::Response(response));
+    }
+
+    /// Takes the response to the request with the given id and returns its result.
+    ///
+    /// Panics if the server has not answered the request.
+    pub fn expect_response<R: request::Request>(&mut self, id: Id) -> Result<R::Result> {
+        let response = self
+            .take(|message| match message {
+                Message::Response(response) => response.id.as_ref() == Some(&id),
+                _ => false,
+            })
+            .unwrap_or_else(|| panic!("expected a response to {} ({:?})", R::METHOD, id));
+
+        match response {
+            Message::Response(response) => response.result_as(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Takes the oldest request of the given type that the server has sent
+    /// and returns its id and its parameters.
+    ///
+    /// Panics if the server has not sent such a request.
+    pub fn expect_request<R: request::Request>(&mut self) -> (Id, R::Params) {
+        let request = self
+            .take(|message| match message {
+                Message::Request(request) => request.method == R::METHOD,
+                _ => false,
+            })
+            .unwrap_or_else(|| panic!("expected a {} request", R::METHOD));
+
+        match request {
+            Message::Request(request) => {
+                let params = serde_json::from_value(request.params.unwrap_or_default())
+                    .expect("invalid request parameters");
+                (request.id, params)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Takes the oldest notification of the given type that the server has sent
+    /// and returns its parameters.
+    ///
+    /// Panics if the server has not sent such a notification.
+    pub fn expect_notification<N: notification::Notification>(&mut self) -> N::Params {
+        let notification = self
+            .take(|message| match message {
+                Message::Notification(notification) => notification.method == N::METHOD,
+                _ => false,
+            })
+            .unwrap_or_else(|| panic!("expected a {} notification", N::METHOD));
+
+        match notification {
+            Message::Notification(notification) => {
+                serde_json::from_value(notification.params.unwrap_or_default())
+                    .expect("invalid notification parameters")
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the messages that the server has written and that have not been taken yet.
+    pub fn received(&mut self) -> Vec<Message> {
+        self.collect();
+        self.received.iter().cloned().collect()
+    }
+
+    fn send(&mut self, message: &Message) {
+        self.input_tx
+            .unbounded_send(frame(&json!(message)))
+            .unwrap();
+        self.collect();
+    }
+
+    // Runs the service until it is stalled, e.g. after a `ManualClock` has been advanced,
+    // and stores the messages that it has written.
+    fn collect(&mut self) {
+        self.pool.run_until_stalled();
+        for message in take_messages(&self.buffer) {
+            let message = serde_json::from_value(message).expect("invalid message");
+            self.received.push_back(message);
+        }
+    }
+
+    fn take<P: Fn(&Message) -> bool>(&mut self, predicate: P) -> Option<Message> {
+        self.collect();
+        let index = self.received.iter().position(predicate)?;
+        self.received.remove(index)
+    }
+}
+
+impl Drop for TestService {
+    fn drop(&mut self) {
+        self.input_tx.close_channel();
+        self.pool.run_until_stalled();
+    }
+}
+
+impl std::fmt::Debug for TestService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestService")
+            .field("received", &self.received)
+            .field("next_id", &self.next_id)
+            .finish()
+    }
+}
+
+/// A message that has been sent to a [`MockLanguageClient`](struct.MockLanguageClient.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCall {
+    /// Whether the message is a request or a notification.
+    pub kind: MethodKind,
+
+    /// The method of the message.
+    pub method: String,
+
+    /// The parameters of the message.
+    pub params: Value,
+}
+
+/// A client that records the messages of the server and answers requests with scripted results,
+/// so handlers can be unit-tested without a service.
+///
+/// Requests without a scripted result are answered with `null`,
+/// which is the result of most requests that are sent to the client.
+///
+/// # Example
+///
+/// ```
+/// # use language_server::{testing::MockLanguageClient, types::*, *};
+/// # use futures::executor::block_on;
+/// # use std::sync::Arc;
+/// let client = Arc::new(MockLanguageClient::new());
+/// let action = MessageActionItem { title: "Retry".into() };
+/// client.respond::<request::ShowMessageRequest>(Ok(Some(action.clone())));
+///
+/// let params = ShowMessageRequestParams {
+///     typ: MessageType::Error,
+///     message: "Build failed".into(),
+///     actions: Some(vec![action.clone()]),
+/// };
+/// assert_eq!(block_on(client.show_message_request(params)), Ok(Some(action)));
+/// assert_eq!(client.requests::<request::ShowMessageRequest>().len(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockLanguageClient {
+    calls: Mutex<Vec<ClientCall>>,
+    results: Mutex<HashMap<String, VecDeque<Result<Value>>>>,
+}
+
+impl MockLanguageClient {
+    /// Creates a client without scripted results.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next request of the given type with the given result.
+    ///
+    /// Results of the same type are used in the order in which they have been scripted.
+    pub fn respond<R: request::Request>(&self, result: Result<R::Result>) {
+        self.results
+            .lock()
+            .unwrap()
+            .entry(R::METHOD.to_owned())
+            .or_default()
+            .push_back(result.map(|result| json!(result)));
+    }
+
+    /// Returns all messages that have been sent to the client in the order in which they have been sent.
+    pub fn calls(&self) -> Vec<ClientCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns the parameters of the requests of the given type.
+    pub fn requests<R: request::Request>(&self) -> Vec<R::Params> {
+        self.params(MethodKind::Request, R::METHOD)
+    }
+
+    /// Returns the parameters of the notifications of the given type.
+    pub fn notifications<N: notification::Notification>(&self) -> Vec<N::Params> {
+        self.params(MethodKind::Notification, N::METHOD)
+    }
+
+    /// Forgets the recorded messages.
+    pub fn clear(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    fn params<T: DeserializeOwned>(&self, kind: MethodKind, method: &str) -> Vec<T> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.kind == kind && call.method == method)
+            .map(|call| serde_json::from_value(call.params.clone()).expect("invalid parameters"))
+            .collect()
+    }
+
+    fn record(&self, kind: MethodKind, method: String, params: Value) {
+        self.calls.lock().unwrap().push(ClientCall {
+            kind,
+            method,
+            params,
+        });
+    }
+}
+
+#[async_trait]
+impl RawClient for MockLanguageClient {
+    async fn send_raw_request(&self, method: String, params: Value) -> Result<Value> {
+        let result = self
+            .results
+            .lock()
+            .unwrap()
+            .get_mut(&method)
+            .and_then(VecDeque::pop_front)
+            .unwrap_or(Ok(Value::Null));
+
+        self.record(MethodKind::Request, method, params);
+        result
+    }
+
+    async fn send_raw_notification(&self, method: String, params: Value) {
+        self.record(MethodKind::Notification, method, params);
+    }
+}
+
+impl LanguageClient for MockLanguageClient {}
+
+// Frames a message with the `Content-Length` header.
+fn frame(message: &Value) -> Vec<u8> {
+    let json = message.to_string();
+    format!("{}{}\r\n\r\n{}", LENGTH_HEADER, json.len(), json).into_bytes()
+}
+
+/// The input stream of a replayed session.
+#[derive(Debug)]
+pub struct SessionInput {
+    receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl SessionInput {
+    fn new(receiver: mpsc::UnboundedReceiver<Vec<u8>>) -> Self {
+        Self {
+            receiver,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl AsyncRead for SessionInput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.position == self.buffer.len() {
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    self.buffer = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let count = buf.len().min(self.buffer.len() - self.position);
+        let start = self.position;
+        buf[..count].copy_from_slice(&self.buffer[start..start + count]);
+        self.position += count;
+        Poll::Ready(Ok(count))
+    }
+}
+
+/// The output stream of a replayed session.
+#[derive(Debug, Default)]
+pub struct SessionOutput {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl AsyncWrite for SessionOutput {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.buffer.lock().unwrap().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_client_records_calls() {
+        use crate::jsonrpc::Error;
+        use futures::executor::block_on;
+        use lsp_types::*;
+
+        let client = MockLanguageClient::new();
+        client.respond::<request::WorkDoneProgressCreate>(Err(Error::internal_error("foo".into())));
+        let token = NumberOrString::Number(0);
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        assert!(block_on(client.work_done_progress_create(params.clone())).is_err());
+        assert!(block_on(client.work_done_progress_create(params)).is_ok());
+
+        let message = LogMessageParams {
+            typ: MessageType::Log,
+            message: "foo".into(),
+        };
+        block_on(client.log_message(message.clone()));
+        assert_eq!(
+            client.notifications::<notification::LogMessage>(),
+            vec![message]
+        );
+        assert_eq!(
+            client.requests::<request::WorkDoneProgressCreate>().len(),
+            2
+        );
+        assert_eq!(client.calls().len(), 3);
+    }
+
+    #[test]
+    fn strip_trace_timestamps() {
+        assert_eq!(strip_timestamp("1.234 --> {}"), "--> {}");
+        assert_eq!(strip_timestamp("--> {}"), "--> {}");
+        assert_eq!(strip_timestamp("# foo bar"), "# foo bar");
+    }
+
+    #[test]
+    fn take_complete_messages() {
+        let buffer = Mutex::new(b"Content-Length: 2\r\n\r\n{}Content-Length: 4\r\n\r\nnu".to_vec());
+        assert_eq!(take_messages(&buffer), vec![serde_json::json!({})]);
+        buffer.lock().unwrap().extend_from_slice(b"ll");
+        assert_eq!(take_messages(&buffer), vec![Value::Null]);
+        assert!(buffer.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{LanguageClient, ServerContext};
+use lsp_types::TraceOption;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+
+/// The parameters of the `$/setTrace` notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetTraceParams {
+    /// The new value that should be assigned to the trace setting.
+    pub value: TraceOption,
+}
+
+/// The parameters of the `$/logTrace` notification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogTraceParams {
+    /// The message to be logged.
+    pub message: String,
+
+    /// Additional information that can be computed if the trace setting is set to `verbose`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+/// Sends `$/logTrace` notifications with the verbosity that the client has requested,
+/// either with the `trace` field of the `initialize` request or with a `$/setTrace` notification.
+///
+/// Nothing is sent while the trace setting is `off`
+/// and the verbose part of a message is only computed if the setting is `verbose`.
+#[derive(Clone)]
+pub struct Tracer {
+    context: ServerContext,
+    client: Arc<dyn LanguageClient>,
+}
+
+impl Tracer {
+    /// Creates a tracer that reads the trace setting from the given context.
+    pub fn new(context: ServerContext, client: Arc<dyn LanguageClient>) -> Self {
+        Self { context, client }
+    }
+
+    /// Returns `true` if the client wants to receive trace messages.
+    pub fn is_enabled(&self) -> bool {
+        self.context.trace() != TraceOption::Off
+    }
+
+    /// Logs the given message if tracing is enabled.
+    pub async fn log(&self, message: String) {
+        self.log_verbose(message, String::new).await;
+    }
+
+    /// Logs the given message if tracing is enabled
+    /// and adds the result of the given function if the client has requested verbose output.
+    pub async fn log_verbose<F>(&self, message: String, verbose: F)
+    where
+        F: FnOnce() -> String,
+    {
+        let verbose = match self.context.trace() {
+            TraceOption::Off => return,
+            TraceOption::Messages => None,
+            TraceOption::Verbose => Some(verbose()).filter(|verbose| !verbose.is_empty()),
+        };
+
+        self.client
+            .log_trace(LogTraceParams { message, verbose })
+            .await;
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::LanguageClientImpl, jsonrpc::*};
+    use futures::{channel::mpsc, executor::block_on, StreamExt};
+    use serde_json::json;
+
+    fn set_trace(context: &ServerContext, value: TraceOption) {
+        let params = json!(SetTraceParams { value });
+        let notification = Notification::new("$/setTrace".into(), params);
+        context.on_incoming_message(&Message::Notification(notification));
+    }
+
+    #[test]
+    fn respect_trace_setting() {
+        let context = ServerContext::default();
+        let (tx, rx) = mpsc::channel(8);
+        let tracer = Tracer::new(context.clone(), Arc::new(LanguageClientImpl::new(tx)));
+
+        block_on(tracer.log("foo".into()));
+        set_trace(&context, TraceOption::Messages);
+        block_on(tracer.log_verbose("bar".into(), || "baz".into()));
+        set_trace(&context, TraceOption::Verbose);
+        block_on(tracer.log_verbose("qux".into(), || "quux".into()));
+        drop(tracer);
+
+        let params: Vec<_> = block_on(rx.collect::<Vec<_>>())
+            .into_iter()
+            .map(|message| match message {
+                Message::Notification(notification) => notification.params.unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            params,
+            vec![
+                json!({ "message": "bar" }),
+                json!({ "message": "qux", "verbose": "quux" })
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    i18n, jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext,
+};
+use async_trait::async_trait;
+use lsp_types::*;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// The trust state of the workspace.
+///
+/// Cloned instances share their state.
+#[derive(Debug, Clone)]
+pub struct WorkspaceTrust {
+    trusted: Arc<AtomicBool>,
+}
+
+impl WorkspaceTrust {
+    /// Creates a trust state with the given initial value.
+    pub fn new(trusted: bool) -> Self {
+        Self {
+            trusted: Arc::new(AtomicBool::new(trusted)),
+        }
+    }
+
+    /// Determines whether the workspace is trusted.
+    pub fn is_trusted(&self) -> bool {
+        self.trusted.load(Ordering::SeqCst)
+    }
+
+    /// Changes the trust state of the workspace.
+    pub fn set_trusted(&self, trusted: bool) {
+        self.trusted.store(trusted, Ordering::SeqCst);
+    }
+}
+
+/// Middleware that rejects selected requests while the workspace is not trusted
+/// and informs the user about it.
+///
+/// The trust state is read from the `initializationOptions` of the `initialize` request
+/// and from the settings of `workspace/didChangeConfiguration` notifications,
+/// using the given [JSON pointer](https://tools.ietf.org/html/rfc6901), e.g. `/trustedWorkspace`.
+pub struct TrustMiddleware {
+    trust: WorkspaceTrust,
+    setting: String,
+    methods: Vec<String>,
+}
+
+impl TrustMiddleware {
+    /// Creates a middleware that rejects the given methods while the workspace is not trusted.
+    pub fn new(trust: WorkspaceTrust, setting: String, methods: Vec<String>) -> Self {
+        Self {
+            trust,
+            setting,
+            methods,
+        }
+    }
+
+    fn update(&self, options: Option<&serde_json::Value>) {
+        if let Some(trusted) = options
+            .and_then(|options| options.pointer(&self.setting))
+            .and_then(|value| value.as_bool())
+        {
+            self.trust.set_trusted(trusted);
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for TrustMiddleware {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        _metadata: &MessageMetadata,
+        context: &ServerContext,
+        client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        match message {
+            Message::Request(request) if request.method == "initialize" => {
+                self.update(
+                    request
+                        .params
+                        .as_ref()
+                        .and_then(|params| params.get("initializationOptions")),
+                );
+            }
+            Message::Notification(notification)
+                if notification.method == "workspace/didChangeConfiguration" =>
+            {
+                self.update(
+                    notification
+                        .params
+                        .as_ref()
+                        .and_then(|params| params.get("settings")),
+                );
+            }
+            // Blocked requests are answered right away instead of being passed to the server.
+            Message::Request(request)
+                if !self.trust.is_trusted() && self.methods.contains(&request.method) =>
+            {
+                let message = context.localize(i18n::TRUST_DISABLED, &[&request.method]);
+                let error = Error {
+                    code: ErrorCode::InvalidRequest,
+                    message: message.clone(),
+                    data: None,
+                };
+
+                let params = ShowMessageParams {
+                    typ: MessageType::Warning,
+                    message,
+                };
+                client.show_message(params).await;
+                return MessageFlow::Respond(Response::error(error, Some(request.id.clone())));
+            }
+            _ => (),
+        }
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        _request: &Request,
+        _metadata: &MessageMetadata,
+        _response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+}
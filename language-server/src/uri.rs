@@ -0,0 +1,243 @@
+use crate::{jsonrpc::*, LanguageClient, MessageFlow, MessageMetadata, Middleware, ServerContext};
+use async_trait::async_trait;
+use lsp_types::Url;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+// The properties of the protocol types that contain a URI.
+const URI_PROPERTIES: &[&str] = &[
+    "uri",
+    "rootUri",
+    "targetUri",
+    "scopeUri",
+    "oldUri",
+    "newUri",
+];
+
+// The properties of the protocol types that map URIs to values, e.g. `WorkspaceEdit::changes`.
+const URI_MAP_PROPERTIES: &[&str] = &["changes"];
+
+/// Rewrites the URIs that are exchanged between the client and the server,
+/// e.g. to map paths inside of a container to paths on the host.
+pub trait UriMapper: Send + Sync {
+    /// Maps a URI of the client to the corresponding URI of the server.
+    ///
+    /// Returns `None` if the URI is not changed.
+    fn to_server(&self, uri: &Url) -> Option<Url>;
+
+    /// Maps a URI of the server to the corresponding URI of the client.
+    ///
+    /// Returns `None` if the URI is not changed.
+    fn to_client(&self, uri: &Url) -> Option<Url>;
+}
+
+/// Maps URIs by replacing a prefix, e.g. `file:///workspace/` on the client and `file:///home/user/project/` on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixUriMapper {
+    client_prefix: String,
+    server_prefix: String,
+}
+
+impl PrefixUriMapper {
+    /// Creates a mapper that replaces the client prefix with the server prefix and vice versa.
+    pub fn new(client_prefix: Url, server_prefix: Url) -> Self {
+        Self {
+            client_prefix: client_prefix.into(),
+            server_prefix: server_prefix.into(),
+        }
+    }
+
+    fn replace(uri: &Url, from: &str, to: &str) -> Option<Url> {
+        let uri = uri.as_str();
+        if uri.get(..from.len()) == Some(from) {
+            Url::parse(&format!("{}{}", to, &uri[from.len()..])).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl UriMapper for PrefixUriMapper {
+    fn to_server(&self, uri: &Url) -> Option<Url> {
+        Self::replace(uri, &self.client_prefix, &self.server_prefix)
+    }
+
+    fn to_client(&self, uri: &Url) -> Option<Url> {
+        Self::replace(uri, &self.server_prefix, &self.client_prefix)
+    }
+}
+
+/// Middleware that applies a [`UriMapper`](trait.UriMapper.html) to all incoming and outgoing messages,
+/// so the handlers do not need to know about the mapping.
+///
+/// URIs are detected by the name of the property that contains them
+/// (`uri`, `rootUri`, `targetUri`, `scopeUri`, `oldUri`, `newUri` and the keys of `changes`).
+pub struct UriMappingMiddleware<M> {
+    mapper: M,
+}
+
+impl<M: UriMapper> UriMappingMiddleware<M> {
+    /// Creates a middleware that uses the given mapper.
+    pub fn new(mapper: M) -> Self {
+        Self { mapper }
+    }
+
+    fn map_value<F>(value: &mut Value, map: &F)
+    where
+        F: Fn(&Url) -> Option<Url>,
+    {
+        match value {
+            Value::Array(values) => values
+                .iter_mut()
+                .for_each(|value| Self::map_value(value, map)),
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    if URI_PROPERTIES.contains(&key.as_str()) {
+                        Self::map_string(value, map);
+                    } else if URI_MAP_PROPERTIES.contains(&key.as_str()) {
+                        Self::map_keys(value, map);
+                    }
+                    Self::map_value(value, map);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn map_string<F>(value: &mut Value, map: &F)
+    where
+        F: Fn(&Url) -> Option<Url>,
+    {
+        if let Value::String(text) = value {
+            if let Some(uri) = Url::parse(text).ok().and_then(|uri| map(&uri)) {
+                *text = uri.into();
+            }
+        }
+    }
+
+    fn map_keys<F>(value: &mut Value, map: &F)
+    where
+        F: Fn(&Url) -> Option<Url>,
+    {
+        if let Value::Object(object) = value {
+            let entries: Map<String, Value> = std::mem::replace(object, Map::new())
+                .into_iter()
+                .map(
+                    |(key, value)| match Url::parse(&key).ok().and_then(|uri| map(&uri)) {
+                        Some(uri) => (uri.into(), value),
+                        None => (key, value),
+                    },
+                )
+                .collect();
+            *object = entries;
+        }
+    }
+}
+
+#[async_trait]
+impl<M: UriMapper> Middleware for UriMappingMiddleware<M> {
+    async fn on_incoming_message(
+        &self,
+        message: &mut Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        let map = |uri: &Url| self.mapper.to_server(uri);
+        match message {
+            Message::Request(Request {
+                params: Some(params),
+                ..
+            })
+            | Message::Notification(Notification {
+                params: Some(params),
+                ..
+            }) => Self::map_value(params, &map),
+            Message::Request(_) | Message::Notification(_) => {}
+            Message::Response(response) => {
+                if let Some(result) = &mut response.result {
+                    Self::map_value(result, &map);
+                }
+            }
+        }
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        _request: &Request,
+        _metadata: &MessageMetadata,
+        response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        if let Some(result) = &mut response.result {
+            Self::map_value(result, &|uri: &Url| self.mapper.to_client(uri));
+        }
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        if let Some(params) = &mut request.params {
+            Self::map_value(params, &|uri: &Url| self.mapper.to_client(uri));
+        }
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        if let Some(params) = &mut notification.params {
+            Self::map_value(params, &|uri: &Url| self.mapper.to_client(uri));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mapper() -> PrefixUriMapper {
+        PrefixUriMapper::new(
+            Url::parse("file:///workspace/").unwrap(),
+            Url::parse("file:///home/user/project/").unwrap(),
+        )
+    }
+
+    #[test]
+    fn prefix_mapper() {
+        let mapper = mapper();
+        let client = Url::parse("file:///workspace/foo.tex").unwrap();
+        let server = Url::parse("file:///home/user/project/foo.tex").unwrap();
+        assert_eq!(mapper.to_server(&client), Some(server.clone()));
+        assert_eq!(mapper.to_client(&server), Some(client));
+        assert_eq!(mapper.to_client(&Url::parse("file:///tmp").unwrap()), None);
+    }
+
+    #[test]
+    fn map_value_properties() {
+        let mut value = json!({
+            "textDocument": { "uri": "file:///workspace/foo.tex", "text": "file:///workspace/bar.tex" },
+            "edit": { "changes": { "file:///workspace/foo.tex": [] } },
+        });
+        let mapper = mapper();
+        UriMappingMiddleware::<PrefixUriMapper>::map_value(&mut value, &|uri: &Url| {
+            mapper.to_server(uri)
+        });
+
+        assert_eq!(
+            value,
+            json!({
+                "textDocument": { "uri": "file:///home/user/project/foo.tex", "text": "file:///workspace/bar.tex" },
+                "edit": { "changes": { "file:///home/user/project/foo.tex": [] } },
+            })
+        );
+    }
+}
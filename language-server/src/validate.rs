@@ -0,0 +1,88 @@
+use crate::jsonrpc::Error;
+#[cfg(feature = "validate")]
+use serde_json::json;
+
+// Converts a failed deserialization of the parameters of a request into an error response.
+#[cfg(not(feature = "validate"))]
+pub fn params_error(_method: &str, _error: serde_json::Error) -> Error {
+    Error::deserialize_error()
+}
+
+// Converts a failed deserialization of the parameters of a request into an error response
+// that explains which part of the parameter object does not match the expected structure.
+#[cfg(feature = "validate")]
+pub fn params_error(method: &str, error: serde_json::Error) -> Error {
+    let mut result = Error::invalid_params(format!(
+        "Invalid parameters for method \"{}\": {}",
+        method, error
+    ));
+    result.data = Some(json!({
+        "method": method,
+        "reason": error.to_string(),
+        "missingFields": missing_field(&error).into_iter().collect::<Vec<_>>(),
+    }));
+    result
+}
+
+// Handles a failed deserialization of the parameters of a notification.
+#[cfg(not(feature = "validate"))]
+pub fn notification_params_error(_method: &str, _error: serde_json::Error) {
+    panic!("{}", Error::deserialize_error().message);
+}
+
+// Handles a failed deserialization of the parameters of a notification.
+// Notifications cannot be answered, so the error is logged and the notification is dropped.
+#[cfg(feature = "validate")]
+pub fn notification_params_error(method: &str, error: serde_json::Error) {
+    log::error!("Invalid parameters for method \"{}\": {}", method, error);
+}
+
+#[cfg(feature = "validate")]
+fn missing_field(error: &serde_json::Error) -> Option<String> {
+    const PREFIX: &str = "missing field `";
+    let message = error.to_string();
+    if !message.starts_with(PREFIX) {
+        return None;
+    }
+
+    let field = &message[PREFIX.len()..];
+    let end = field.find('`')?;
+    Some(field[..end].to_owned())
+}
+
+#[cfg(all(test, feature = "validate"))]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::ErrorCode;
+    use lsp_types::TextDocumentPositionParams;
+
+    fn deserialize(params: serde_json::Value) -> serde_json::Error {
+        serde_json::from_value::<TextDocumentPositionParams>(params).unwrap_err()
+    }
+
+    #[test]
+    fn params_error_missing_field() {
+        let error = deserialize(json!({ "textDocument": { "uri": "file:///foo.tex" } }));
+        let error = params_error("textDocument/hover", error);
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert_eq!(
+            error.message,
+            "Invalid parameters for method \"textDocument/hover\": missing field `position`"
+        );
+
+        let data = error.data.unwrap();
+        assert_eq!(data["method"], "textDocument/hover");
+        assert_eq!(data["missingFields"], json!(["position"]));
+    }
+
+    #[test]
+    fn params_error_invalid_type() {
+        let error = deserialize(json!({
+            "textDocument": { "uri": "file:///foo.tex" },
+            "position": { "line": "1", "character": 2 }
+        }));
+        let error = params_error("textDocument/hover", error);
+        assert!(error.message.contains("invalid type: string \"1\""));
+        assert_eq!(error.data.unwrap()["missingFields"], json!([]));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{i18n, jsonrpc::*, CancellationToken, ServerContext};
+use futures::{
+    channel::oneshot,
+    future::{FutureExt, Shared},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Determines how a request is handled that arrives before the warm-up has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmUpPolicy {
+    /// Buffers the request until the warm-up has finished.
+    Wait,
+
+    /// Answers the request with a `ContentModified` error, which tells the client to retry it later.
+    Reject,
+
+    /// Handles the request immediately.
+    Proceed,
+}
+
+/// Runs the [`warm_up`](trait.LanguageServer.html#method.warm_up) hook of the server in the background
+/// once the client has sent the `initialized` notification.
+///
+/// The `initialize` request can return quickly, while expensive work like indexing the workspace
+/// is reported to the client as a work done progress with the given title.
+/// Requests that arrive before the warm-up has finished are handled according to their `WarmUpPolicy`.
+/// The `initialize` and `shutdown` requests are never held back
+/// and the `shutdown` request stops a warm-up that is still running.
+#[derive(Debug, Clone)]
+pub struct WarmUp {
+    title: String,
+    default: WarmUpPolicy,
+    methods: HashMap<String, WarmUpPolicy>,
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+    stop_token: CancellationToken,
+}
+
+impl WarmUp {
+    /// Creates a warm-up that buffers all requests until it has finished.
+    pub fn new(title: String) -> Self {
+        let (sender, receiver) = oneshot::channel();
+        Self {
+            title,
+            default: WarmUpPolicy::Wait,
+            methods: HashMap::new(),
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: receiver.shared(),
+            stop_token: CancellationToken::new(),
+        }
+    }
+
+    /// Sets the policy of the methods that have not been configured with `with_method`.
+    pub fn with_default(self, default: WarmUpPolicy) -> Self {
+        Self { default, ..self }
+    }
+
+    /// Sets the policy of the given request method, e.g. `textDocument/completion`.
+    pub fn with_method(mut self, method: String, policy: WarmUpPolicy) -> Self {
+        self.methods.insert(method, policy);
+        self
+    }
+
+    /// Returns `true` if the warm-up has finished.
+    pub fn is_finished(&self) -> bool {
+        self.sender.lock().unwrap().is_none()
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        &self.title
+    }
+
+    // Returns the policy of the given method while the warm-up is running.
+    pub(crate) fn policy(&self, method: &str) -> WarmUpPolicy {
+        if self.is_finished() || method == "initialize" || method == "shutdown" {
+            return WarmUpPolicy::Proceed;
+        }
+
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+
+    // Waits for the warm-up if necessary and returns `false` if the request has to be rejected.
+    pub(crate) async fn admit(&self, method: &str) -> bool {
+        match self.policy(method) {
+            WarmUpPolicy::Wait => {
+                let _ = self.receiver.clone().await;
+                true
+            }
+            WarmUpPolicy::Reject => false,
+            WarmUpPolicy::Proceed => true,
+        }
+    }
+
+    // Releases the requests that are waiting for the warm-up.
+    pub(crate) fn finish(&self) {
+        if let Some(sender) = self.sender.lock().unwrap().take() {
+            let _ = sender.send(());
+        }
+    }
+
+    // Stops the hook of the server and releases the waiting requests.
+    pub(crate) fn stop(&self) {
+        self.stop_token.cancel();
+        self.finish();
+    }
+
+    pub(crate) fn stop_token(&self) -> &CancellationToken {
+        &self.stop_token
+    }
+}
+
+// The response to a request that has been rejected during the warm-up.
+pub(crate) fn rejected(request: &Request, context: &ServerContext) -> Response {
+    let message = context.localize(i18n::WARM_UP_REJECTED, &[&request.method]);
+    Response::error(
+        Error::content_modified_error(message),
+        Some(request.id.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join;
+
+    #[test]
+    fn policy_per_method() {
+        let warm_up = WarmUp::new("foo".into())
+            .with_default(WarmUpPolicy::Reject)
+            .with_method("textDocument/hover".into(), WarmUpPolicy::Proceed);
+
+        assert_eq!(warm_up.policy("textDocument/hover"), WarmUpPolicy::Proceed);
+        assert_eq!(
+            warm_up.policy("textDocument/completion"),
+            WarmUpPolicy::Reject
+        );
+        assert_eq!(warm_up.policy("shutdown"), WarmUpPolicy::Proceed);
+
+        warm_up.finish();
+        assert!(warm_up.is_finished());
+        assert_eq!(
+            warm_up.policy("textDocument/completion"),
+            WarmUpPolicy::Proceed
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_until_finished() {
+        let warm_up = WarmUp::new("foo".into());
+        let finish = async { warm_up.finish() };
+        let (admitted, ()) = join(warm_up.admit("textDocument/hover"), finish).await;
+        assert!(admitted);
+    }
+}
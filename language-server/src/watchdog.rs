@@ -0,0 +1,132 @@
+use crate::{jsonrpc::Id, Clock};
+use futures::future::{select, Either};
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+/// A handler that has exceeded the budget of the [`Watchdog`](struct.Watchdog.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StuckHandler {
+    /// The id of the request.
+    pub id: Id,
+
+    /// The method of the request.
+    pub method: String,
+
+    /// The time that has elapsed since the handler has been started.
+    pub elapsed: Duration,
+}
+
+/// A function that is called with every handler that has exceeded the budget of the watchdog.
+pub type StuckHandlerHook = dyn Fn(&StuckHandler) + Send + Sync;
+
+/// Detects request handlers that exceed a wall-clock budget and logs a warning with their method,
+/// so hangs that users report as "server stopped responding" can be traced back to a handler.
+///
+/// The handler keeps running after the warning.
+/// A handler can stop its work with the token of [`CancellationToken::current`](struct.CancellationToken.html#method.current) instead.
+#[derive(Clone)]
+pub struct Watchdog {
+    budget: Duration,
+    hook: Option<Arc<StuckHandlerHook>>,
+}
+
+impl Watchdog {
+    /// Creates a watchdog that reports handlers that take longer than the given budget.
+    pub fn new(budget: Duration) -> Self {
+        Self { budget, hook: None }
+    }
+
+    /// Calls the given function with every handler that has exceeded the budget,
+    /// e.g. to send telemetry in addition to the warning.
+    pub fn with_hook(self, hook: Arc<StuckHandlerHook>) -> Self {
+        Self {
+            hook: Some(hook),
+            ..self
+        }
+    }
+
+    // Drives the handler of a request and reports it once it has exceeded the budget.
+    pub(crate) async fn watch<F: Future>(
+        &self,
+        id: &Id,
+        method: &str,
+        clock: &dyn Clock,
+        handler: F,
+    ) -> F::Output {
+        let started = clock.now();
+        let mut handler = Box::pin(handler);
+        match select(handler.as_mut(), clock.sleep(self.budget)).await {
+            Either::Left((output, _)) => output,
+            Either::Right(((), _)) => {
+                let stuck = StuckHandler {
+                    id: id.clone(),
+                    method: method.to_owned(),
+                    elapsed: clock.now() - started,
+                };
+
+                log::warn!(
+                    "The handler of {} ({:?}) has been running for {:?}",
+                    stuck.method,
+                    stuck.id,
+                    stuck.elapsed
+                );
+
+                if let Some(hook) = &self.hook {
+                    hook(&stuck);
+                }
+
+                handler.await
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use futures::{channel::oneshot, executor::LocalPool, task::LocalSpawnExt};
+    use std::sync::Mutex;
+
+    #[test]
+    fn report_stuck_handler() {
+        let stuck = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&stuck);
+        let watchdog = Watchdog::new(Duration::from_secs(5)).with_hook(Arc::new(move |handler| {
+            reported.lock().unwrap().push(handler.clone())
+        }));
+
+        let clock = ManualClock::new();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let mut pool = LocalPool::new();
+        let watched_clock = clock.clone();
+        pool.spawner()
+            .spawn_local(async move {
+                let handler = async { release_rx.await.unwrap() };
+                watchdog
+                    .watch(&Id::Number(0), "foo", &watched_clock, handler)
+                    .await;
+            })
+            .unwrap();
+
+        pool.run_until_stalled();
+        clock.advance(Duration::from_secs(6));
+        pool.run_until_stalled();
+        release_tx.send(()).unwrap();
+        pool.run();
+
+        let expected = StuckHandler {
+            id: Id::Number(0),
+            method: "foo".into(),
+            elapsed: Duration::from_secs(6),
+        };
+        assert_eq!(*stuck.lock().unwrap(), vec![expected]);
+    }
+}
@@ -1,8 +1,10 @@
+use async_executors::TokioTp;
 use futures::{
     executor::LocalPool,
-    future::{BoxFuture, FutureExt},
+    future::{select, BoxFuture, Either, FutureExt},
     io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt},
-    task::LocalSpawnExt,
+    stream::StreamExt,
+    task::{LocalSpawnExt, SpawnExt},
 };
 use indoc::indoc;
 use jsonrpc::{Notification, Request};
@@ -14,8 +16,14 @@ use language_server::{
 };
 use mockall::mock;
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
 use sluice::pipe::{pipe, PipeReader};
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    convert::TryFrom,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 mock! {
     pub LanguageServer {
@@ -94,7 +102,7 @@ fn simple_request_success() {
 
     executor
         .spawner()
-        .spawn_local(service.listen())
+        .spawn_local(service.listen().map(drop))
         .expect("failed to spawn server");
 
     executor.run_until(async move {
@@ -120,6 +128,80 @@ fn simple_request_success() {
     });
 }
 
+#[test]
+fn initialize_retry_success() {
+    let attempts = Arc::new(Mutex::new(0));
+    let mut server = MockLanguageServer::new();
+    server.expect_initialize().times(2).returning(move |_, _| {
+        let attempts = Arc::clone(&attempts);
+        async move {
+            let mut attempts = attempts.lock().unwrap();
+            *attempts += 1;
+            if *attempts == 1 {
+                Err(jsonrpc::Error::initialize_error("foo".into(), true))
+            } else {
+                Ok(InitializeResult::default())
+            }
+        }
+        .boxed()
+    });
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 75
+
+                    {"jsonrpc":"2.0","method":"initialize","id":0,"params":{"capabilities":{}}}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let error = jsonrpc::Error::initialize_error("foo".into(), true);
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 75
+
+                    {"jsonrpc":"2.0","method":"initialize","id":1,"params":{"capabilities":{}}}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let response = Response::result(
+            serde_json::to_value(InitializeResult::default()).unwrap(),
+            Id::Number(1),
+        );
+        read_message(&mut rx2, response).await;
+    });
+}
+
 #[test]
 fn notification_with_client_notification_success() {
     let mut server = MockLanguageServer::new();
@@ -147,7 +229,7 @@ fn notification_with_client_notification_success() {
 
     executor
         .spawner()
-        .spawn_local(service.listen())
+        .spawn_local(service.listen().map(drop))
         .expect("failed to spawn server");
 
     executor.run_until(async move {
@@ -209,7 +291,7 @@ fn request_with_client_request_success() {
 
     executor
         .spawner()
-        .spawn_local(service.listen())
+        .spawn_local(service.listen().map(drop))
         .expect("failed to spawn server");
 
     executor.run_until(async move {
@@ -257,3 +339,2649 @@ fn request_with_client_request_success() {
         read_message(&mut rx2, request).await;
     });
 }
+
+#[test]
+fn work_done_progress_cancel_success() {
+    let progress = ProgressRegistry::default();
+    let mut server = MockLanguageServer::new();
+    {
+        let progress = progress.clone();
+        server
+            .expect_shutdown()
+            .times(1)
+            .returning(move |_, client| {
+                let progress = progress.clone();
+                async move {
+                    let handle = progress.begin(client, "Indexing".into(), true).await?;
+                    handle.cancellation_token().cancelled().await;
+                    handle.end(Some("Cancelled".into())).await;
+                    Ok(())
+                }
+                .boxed()
+            });
+    }
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .progress(progress)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 58
+
+                    {"jsonrpc":"2.0","method":"shutdown","id":0,"params":null}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let request = Request::new(
+            "window/workDoneProgress/create".into(),
+            serde_json::to_value(WorkDoneProgressCreateParams {
+                token: NumberOrString::Number(0),
+            })
+            .unwrap(),
+            Id::Number(0),
+        );
+        read_message(&mut rx2, request).await;
+
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 38
+
+                    {"jsonrpc":"2.0","id":0,"result":null}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let notification = Notification::new(
+            "$/progress".into(),
+            serde_json::to_value(ProgressParams {
+                token: NumberOrString::Number(0),
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(
+                    WorkDoneProgressBegin {
+                        title: "Indexing".into(),
+                        cancellable: Some(true),
+                        message: None,
+                        percentage: None,
+                    },
+                )),
+            })
+            .unwrap(),
+        );
+        read_message(&mut rx2, notification).await;
+
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 80
+
+                    {"jsonrpc":"2.0","method":"window/workDoneProgress/cancel","params":{"token":0}}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let notification = Notification::new(
+            "$/progress".into(),
+            serde_json::to_value(ProgressParams {
+                token: NumberOrString::Number(0),
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: Some("Cancelled".into()),
+                })),
+            })
+            .unwrap(),
+        );
+        read_message(&mut rx2, notification).await;
+
+        let response = Response::result(serde_json::Value::Null, Id::Number(0));
+        read_message(&mut rx2, response).await;
+    });
+}
+
+#[test]
+fn middleware_language_scope_mapped_uris() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(|_, _| async move { Ok(()) }.boxed());
+
+    let mapper = PrefixUriMapper::new(
+        Url::parse("file:///client/").unwrap(),
+        Url::parse("file:///server/").unwrap(),
+    );
+    let middleware = Arc::new(MetadataMiddleware::default());
+    let scope = LanguageScope::new(vec!["latex".into()], middleware.clone());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![
+            Arc::new(UriMappingMiddleware::new(mapper)),
+            Arc::new(scope),
+        ])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({
+            "textDocument": {
+                "uri": "file:///client/foo.tex",
+                "languageId": "latex",
+                "version": 0,
+                "text": "",
+            }
+        });
+        let notification = Notification::new("textDocument/didOpen".into(), params);
+        write_message(&mut tx1, notification).await;
+
+        // The scope sees the mapped URI, which the context has recorded as well.
+        let params = json!({
+            "textDocument": { "uri": "file:///client/foo.tex" },
+            "position": { "line": 0, "character": 0 }
+        });
+        let request = Request::new("textDocument/hover".into(), params, Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error::method_not_found_error();
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(1))).await;
+    });
+
+    let sequences = middleware.sequences.lock().unwrap();
+    let methods: Vec<_> = sequences
+        .iter()
+        .map(|(method, _)| method.as_str())
+        .collect();
+    assert_eq!(methods, vec!["textDocument/hover", "shutdown"]);
+}
+
+#[derive(Default)]
+struct MetadataMiddleware {
+    sequences: Mutex<Vec<(String, u64)>>,
+    cancelled: Mutex<Vec<(Id, String)>>,
+}
+
+#[async_trait]
+impl Middleware for MetadataMiddleware {
+    async fn on_incoming_message(
+        &self,
+        _message: &mut jsonrpc::Message,
+        _metadata: &MessageMetadata,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        MessageFlow::Continue
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        request: &Request,
+        metadata: &MessageMetadata,
+        _response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        let mut sequences = self.sequences.lock().unwrap();
+        sequences.push((request.method.clone(), metadata.sequence));
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_request_cancelled(
+        &self,
+        id: &Id,
+        method: &str,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        let mut cancelled = self.cancelled.lock().unwrap();
+        cancelled.push((id.clone(), method.to_owned()));
+    }
+}
+
+#[test]
+fn middleware_message_metadata() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_initialized()
+        .times(1)
+        .returning(|_, _| async move {}.boxed());
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(|_, _| async move { Ok(()) }.boxed());
+
+    let middleware = Arc::new(MetadataMiddleware::default());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![middleware.clone()])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        tx1.write_all(
+            indoc!(
+                r#"
+                    Content-Length: 52
+
+                    {"jsonrpc":"2.0","method":"initialized","params":{}}Content-Length: 58
+
+                    {"jsonrpc":"2.0","method":"shutdown","id":0,"params":null}
+                "#
+            )
+            .trim()
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let response = Response::result(serde_json::Value::Null, Id::Number(0));
+        read_message(&mut rx2, response).await;
+    });
+
+    let sequences = middleware.sequences.lock().unwrap();
+    assert_eq!(*sequences, vec![("shutdown".to_owned(), 1)]);
+}
+
+#[test]
+fn middleware_language_scope() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(|_, _| async move { Ok(()) }.boxed());
+
+    let middleware = Arc::new(MetadataMiddleware::default());
+    let scope = LanguageScope::new(vec!["latex".into()], middleware.clone());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![Arc::new(scope)])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        for (id, (uri, language)) in [("file:///foo.tex", "latex"), ("file:///foo.bib", "bibtex")]
+            .iter()
+            .enumerate()
+        {
+            let params = json!({
+                "textDocument": { "uri": uri, "languageId": language, "version": 0, "text": "" }
+            });
+            let notification = Notification::new("textDocument/didOpen".into(), params);
+            write_message(&mut tx1, notification).await;
+
+            let params = json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 0 }
+            });
+            let id = Id::Number(id as u64);
+            let request = Request::new("textDocument/hover".into(), params, id.clone());
+            write_message(&mut tx1, request).await;
+            let error = jsonrpc::Error::method_not_found_error();
+            read_message(&mut rx2, Response::error(error, Some(id))).await;
+        }
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(2));
+        write_message(&mut tx1, request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(2))).await;
+    });
+
+    let sequences = middleware.sequences.lock().unwrap();
+    let methods: Vec<_> = sequences
+        .iter()
+        .map(|(method, _)| method.as_str())
+        .collect();
+    assert_eq!(methods, vec!["textDocument/hover", "shutdown"]);
+}
+
+struct GateMiddleware;
+
+#[async_trait]
+impl Middleware for GateMiddleware {
+    async fn on_incoming_message(
+        &self,
+        message: &mut jsonrpc::Message,
+        _metadata: &MessageMetadata,
+        context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) -> MessageFlow {
+        match message {
+            jsonrpc::Message::Request(request)
+                if request.method != "initialize"
+                    && context.state() != ServerState::Initialized =>
+            {
+                let error = jsonrpc::Error {
+                    code: jsonrpc::ErrorCode::ServerNotInitialized,
+                    message: "foo".into(),
+                    data: None,
+                };
+                MessageFlow::Respond(Response::error(error, None))
+            }
+            jsonrpc::Message::Notification(_) => MessageFlow::Drop,
+            _ => MessageFlow::Continue,
+        }
+    }
+
+    async fn on_outgoing_response(
+        &self,
+        _request: &Request,
+        _metadata: &MessageMetadata,
+        _response: &mut Response,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_request(
+        &self,
+        _request: &mut Request,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+
+    async fn on_outgoing_notification(
+        &self,
+        _notification: &mut Notification,
+        _context: &ServerContext,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+    }
+}
+
+#[test]
+fn middleware_short_circuit() {
+    // The mock panics if the server receives any message.
+    let server = MockLanguageServer::new();
+    let middleware = Arc::new(MetadataMiddleware::default());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![Arc::new(GateMiddleware), middleware.clone()])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let notification = Notification::new("initialized".into(), json!({}));
+        write_message(&mut tx1, notification).await;
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error {
+            code: jsonrpc::ErrorCode::ServerNotInitialized,
+            message: "foo".into(),
+            data: None,
+        };
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+    });
+
+    let sequences = middleware.sequences.lock().unwrap();
+    assert_eq!(*sequences, vec![("shutdown".to_owned(), 1)]);
+}
+
+#[test]
+fn middleware_short_circuit_shutdown() {
+    let server = MockLanguageServer::new();
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![Arc::new(GateMiddleware)])
+        .build();
+
+    let client = async move {
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error {
+            code: jsonrpc::ErrorCode::ServerNotInitialized,
+            message: "foo".into(),
+            data: None,
+        };
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+        let notification = Notification::new("exit".into(), json!(null));
+        write_message(&mut tx1, notification).await;
+        (tx1, rx2)
+    };
+
+    let (reason, _) = executor.run_until(futures::future::join(service.listen(), client));
+    assert_eq!(
+        reason.unwrap(),
+        ExitReason::Exited {
+            after_shutdown: false
+        }
+    );
+}
+
+enum BuildRequest {}
+
+impl request::Request for BuildRequest {
+    type Params = TextDocumentIdentifier;
+    type Result = String;
+    const METHOD: &'static str = "textDocument/build";
+}
+
+#[test]
+fn extension_methods() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(|_, _| async move { Ok(()) }.boxed());
+
+    let table = MethodTable::new().with_request::<BuildRequest, _, _>(|params, _| async move {
+        Ok(format!("built {}", params.uri))
+    });
+    assert_eq!(table.methods(), vec!["textDocument/build"]);
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .extensions(vec![table])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({ "uri": "file:///foo.tex" });
+        let request = Request::new("textDocument/build".into(), params, Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let result = json!("built file:///foo.tex");
+        read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(1))).await;
+    });
+}
+
+#[derive(Default)]
+struct LegacyHandler {
+    requests: Mutex<Vec<String>>,
+    notifications: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl RawHandler for LegacyHandler {
+    async fn handle_raw_request(
+        &self,
+        request: Request,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Response {
+        self.requests.lock().unwrap().push(request.method.clone());
+        Response::result(json!(request.method), request.id)
+    }
+
+    async fn handle_raw_notification(
+        &self,
+        notification: Notification,
+        _client: Arc<dyn LanguageClient>,
+    ) {
+        self.notifications.lock().unwrap().push(notification.method);
+    }
+}
+
+#[test]
+fn fallback_handler() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(|_, _| async move { Ok(()) }.boxed());
+
+    let fallback = Arc::new(LegacyHandler::default());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .fallback_handler(fallback.clone())
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let notification = Notification::new("legacy/notify".into(), json!(null));
+        write_message(&mut tx1, notification).await;
+        let request = Request::new("legacy/request".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        read_message(
+            &mut rx2,
+            Response::result(json!("legacy/request"), Id::Number(0)),
+        )
+        .await;
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(1))).await;
+    });
+
+    let notifications = fallback.notifications.lock().unwrap();
+    assert_eq!(*notifications, vec!["legacy/notify".to_owned()]);
+}
+
+#[test]
+fn middleware_request_cancelled() {
+    let (release_tx, release_rx) = futures::channel::oneshot::channel::<()>();
+    let release_rx = Mutex::new(Some(release_rx));
+    let mut server = MockLanguageServer::new();
+    server.expect_shutdown().times(1).returning(move |_, _| {
+        let release_rx = release_rx.lock().unwrap().take().unwrap();
+        async move {
+            release_rx.await.unwrap();
+            Ok(())
+        }
+        .boxed()
+    });
+
+    let middleware = Arc::new(MetadataMiddleware::default());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![middleware.clone()])
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let notification = Notification::new("$/cancelRequest".into(), json!({ "id": 0 }));
+        write_message(&mut tx1, notification).await;
+        let error = jsonrpc::Error::request_cancelled();
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+
+        // Notifications are processed in order, so the cancellation has been handled
+        // once the response to the next request arrives.
+        let notification = Notification::new("$/cancelRequest".into(), json!({ "id": 42 }));
+        write_message(&mut tx1, notification).await;
+        let request = Request::new("foo".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error::method_not_found_error();
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(1)))).await;
+
+        // The handler has been dropped together with the receiver.
+        assert!(release_tx.send(()).is_err());
+    });
+
+    let cancelled = middleware.cancelled.lock().unwrap();
+    assert_eq!(*cancelled, vec![(Id::Number(0), "shutdown".to_owned())]);
+}
+
+#[test]
+fn service_stop() {
+    let mut executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .listen();
+
+    handle.stop();
+    assert_eq!(
+        executor.run_until(handle.join()).unwrap(),
+        ExitReason::Stopped
+    );
+}
+
+#[test]
+fn service_abort() {
+    let mut executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .listen();
+
+    handle.controller().abort();
+    assert_eq!(executor.run_until(handle).unwrap(), ExitReason::Aborted);
+}
+
+#[test]
+fn service_disconnected() {
+    let mut executor = LocalPool::new();
+    let (rx1, tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .listen();
+
+    drop(tx1);
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::Disconnected
+    );
+}
+
+#[test]
+fn service_disconnected_closes_client_requests() {
+    let (result_tx, result_rx) = futures::channel::oneshot::channel();
+    let result_tx = Mutex::new(Some(result_tx));
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .times(1)
+        .returning(move |_, client| {
+            let result_tx = result_tx.lock().unwrap().take().unwrap();
+            async move {
+                let params = ShowMessageRequestParams {
+                    actions: None,
+                    message: "Hello World!".into(),
+                    typ: MessageType::Info,
+                };
+                let result = client.show_message_request(params).await;
+                result_tx.send(result).unwrap();
+                Ok(())
+            }
+            .boxed()
+        });
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build()
+        .listen();
+
+    let client = async move {
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+
+        let request = Request::new(
+            "window/showMessageRequest".into(),
+            json!({ "message": "Hello World!", "type": 3 }),
+            Id::Number(0),
+        );
+        read_message(&mut rx2, request).await;
+
+        // The input ends while the handler is waiting for the response of the client.
+        drop(tx1);
+        rx2
+    };
+    let (reason, _rx2) = executor.run_until(futures::future::join(handle, client));
+
+    assert_eq!(reason.unwrap(), ExitReason::Disconnected);
+    let error = executor.run_until(result_rx).unwrap().unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::ConnectionClosed);
+}
+
+#[cfg(feature = "websocket")]
+#[test]
+fn service_websocket_closed() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .framing(Framing::WebSocket)
+        .build()
+        .listen();
+
+    // A masked close frame without a payload ends the connection normally.
+    executor.run_until(async move {
+        tx1.write_all(&[0x88, 0x80, 1, 2, 3, 4]).await.unwrap();
+    });
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::Disconnected
+    );
+}
+
+#[test]
+fn service_spawned() {
+    let executor = TokioTp::try_from(&mut tokio::runtime::Builder::new()).unwrap();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.clone())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .spawn()
+        .unwrap();
+
+    let reason = executor.block_on(handle.shutdown()).unwrap();
+    assert_eq!(reason, ExitReason::Stopped);
+}
+
+#[test]
+fn service_exited() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_shutdown()
+        .returning(|_, _| async { Ok(()) }.boxed());
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build()
+        .listen();
+
+    executor
+        .spawner()
+        .spawn_local(async move {
+            let shutdown = Request::new("shutdown".into(), json!(null), Id::Number(0));
+            write_message(&mut tx1, shutdown).await;
+            read_message(&mut rx2, Response::result(json!(null), Id::Number(0))).await;
+            write_message(&mut tx1, Notification::new("exit".into(), json!(null))).await;
+
+            // Keeps the input open to make sure that the service stops because of the notification.
+            futures::future::pending::<()>().await;
+        })
+        .unwrap();
+
+    let reason = executor.run_until(handle).unwrap();
+    assert_eq!(
+        reason,
+        ExitReason::Exited {
+            after_shutdown: true
+        }
+    );
+    assert_eq!(reason.exit_code(), 0);
+}
+
+#[test]
+fn service_malformed_input() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .listen();
+
+    executor
+        .spawner()
+        .spawn_local(async move {
+            tx1.write_all(b"Content-Length: foo\r\n\r\n{}")
+                .await
+                .unwrap();
+        })
+        .unwrap();
+
+    match executor.run_until(handle) {
+        Err(ServiceError::Input(_)) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn service_parent_exited() {
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_initialize()
+        .returning(|_, _| async move { Ok(InitializeResult::default()) }.boxed());
+
+    let mut parent = std::process::Command::new("true").spawn().unwrap();
+    parent.wait().unwrap();
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+    let locale = Arc::new(Mutex::new(None));
+    let hook_locale = Arc::clone(&locale);
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .watch_parent_process(true)
+        .locale_hook(Arc::new(move |locale: &str| {
+            *hook_locale.lock().unwrap() = Some(locale.to_owned());
+        }))
+        .build()
+        .listen();
+
+    let params = json!({ "capabilities": {}, "processId": parent.id(), "locale": "de" });
+    let request = Request::new("initialize".into(), params, Id::Number(0));
+    let json = serde_json::to_string(&request).unwrap();
+    let message = format!("Content-Length: {}\r\n\r\n{}", json.len(), json);
+    executor
+        .run_until(tx1.write_all(message.as_bytes()))
+        .unwrap();
+
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::ParentExited
+    );
+    assert_eq!(*locale.lock().unwrap(), Some("de".to_owned()));
+}
+
+#[test]
+fn service_validate_executor_shutdown() {
+    let executor = LocalPool::new();
+    let spawner = executor.spawner();
+    drop(executor);
+
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(spawner)
+        .server(Arc::new(MockLanguageServer::new()))
+        .build()
+        .validate();
+
+    match result {
+        Err(BuildError::Executor(_)) => {}
+        _ => panic!("expected an executor error"),
+    }
+}
+
+#[test]
+fn service_validate_empty_response_order() {
+    let executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .response_order(ResponseOrder::ArrivalFor(Vec::new()))
+        .build()
+        .validate();
+
+    match result {
+        Err(BuildError::ResponseOrder(_)) => {}
+        _ => panic!("expected a response order error"),
+    }
+}
+
+#[test]
+fn service_validate_misplaced_wildcard() {
+    let executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .response_order(ResponseOrder::ArrivalFor(vec!["*/completion".into()]))
+        .build()
+        .validate();
+
+    match result {
+        Err(BuildError::ResponseOrder(_)) => {}
+        _ => panic!("expected a response order error"),
+    }
+}
+
+#[test]
+fn service_validate_zero_client_timeout() {
+    let executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .client_timeout(Duration::from_secs(0))
+        .build()
+        .validate();
+
+    match result {
+        Err(BuildError::ClientTimeout) => {}
+        _ => panic!("expected a client timeout error"),
+    }
+}
+
+#[test]
+fn service_validate_conflicting_ping() {
+    let executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .standby(Standby::new().with_ping("$/serverStatus".into()))
+        .status(ServerStatus::new(8))
+        .build()
+        .validate();
+
+    match result {
+        Err(BuildError::ConflictingMethod(method)) => assert_eq!(method, "$/serverStatus"),
+        _ => panic!("expected a conflicting method error"),
+    }
+}
+
+#[test]
+fn service_validate_valid() {
+    let executor = LocalPool::new();
+    let (rx1, _tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let result = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .response_order(ResponseOrder::ArrivalFor(vec!["textDocument/*".into()]))
+        .standby(Standby::new().with_ping("$/ping".into()))
+        .build()
+        .validate();
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn service_abort_cancels_pending_requests() {
+    let (started_tx, started_rx) = futures::channel::oneshot::channel();
+    let (guard_tx, guard_rx) = futures::channel::oneshot::channel::<()>();
+    let channels = Mutex::new(Some((started_tx, guard_tx)));
+    let mut server = MockLanguageServer::new();
+    server.expect_shutdown().times(1).returning(move |_, _| {
+        let (started_tx, guard_tx) = channels.lock().unwrap().take().unwrap();
+        async move {
+            let _guard_tx = guard_tx;
+            started_tx.send(()).unwrap();
+            futures::future::pending().await
+        }
+        .boxed()
+    });
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build()
+        .listen();
+
+    let controller = handle.controller();
+    let spawner = executor.spawner();
+    let reason = executor.run_until(async move {
+        spawner
+            .spawn_local(async move {
+                tx1.write_all(
+                    indoc!(
+                        r#"
+                            Content-Length: 58
+
+                            {"jsonrpc":"2.0","method":"shutdown","id":0,"params":null}
+                        "#
+                    )
+                    .trim()
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+
+                started_rx.await.unwrap();
+                controller.abort();
+            })
+            .unwrap();
+
+        handle.await
+    });
+
+    assert_eq!(reason.unwrap(), ExitReason::Aborted);
+    assert!(executor.run_until(guard_rx).is_err());
+}
+
+#[test]
+fn dropped_service_closes_client_requests() {
+    let (client_tx, client_rx) = futures::channel::oneshot::channel();
+    let client_tx = Mutex::new(Some(client_tx));
+    let mut server = MockLanguageServer::new();
+    server
+        .expect_initialize()
+        .returning(|_, _| async move { Ok(InitializeResult::default()) }.boxed());
+    server.expect_initialized().returning(move |_, client| {
+        let _ = client_tx.lock().unwrap().take().unwrap().send(client);
+        async move {}.boxed()
+    });
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build()
+        .listen();
+
+    let spawner = executor.spawner();
+    executor.run_until(async move {
+        let mut service = Box::pin(handle);
+        let params = json!({ "capabilities": {} });
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let notification = Notification::new("initialized".into(), json!({}));
+        write_message(&mut tx1, notification).await;
+
+        let client = match select(service.as_mut(), client_rx).await {
+            Either::Right((client, _)) => client.unwrap(),
+            Either::Left(_) => panic!("the service has exited"),
+        };
+
+        // The service is dropped while the request is waiting for its response.
+        let (result_tx, result_rx) = futures::channel::oneshot::channel();
+        let pending_client = Arc::clone(&client);
+        spawner
+            .spawn_local(async move {
+                let params = ShowMessageRequestParams {
+                    actions: None,
+                    message: "Hello World!".into(),
+                    typ: MessageType::Info,
+                };
+                let result = pending_client.show_message_request(params).await;
+                result_tx.send(result).unwrap();
+            })
+            .unwrap();
+
+        let result = serde_json::to_value(InitializeResult::default()).unwrap();
+        let request = Request::new(
+            "window/showMessageRequest".into(),
+            json!({ "message": "Hello World!", "type": 3 }),
+            Id::Number(0),
+        );
+        let output = async {
+            read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+            read_message(&mut rx2, request).await;
+        };
+        if let Either::Left(_) = select(service.as_mut(), Box::pin(output)).await {
+            panic!("the service has exited");
+        }
+
+        drop(service);
+        let error = result_rx.await.unwrap().unwrap_err();
+        assert_eq!(error.code, jsonrpc::ErrorCode::ConnectionClosed);
+
+        // Requests that are sent after the service has been dropped fail immediately.
+        let error = client
+            .work_done_progress_create(WorkDoneProgressCreateParams {
+                token: NumberOrString::Number(0),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, jsonrpc::ErrorCode::ConnectionClosed);
+    });
+}
+
+#[derive(Default)]
+struct LifecycleServer {
+    events: Mutex<Vec<&'static str>>,
+}
+
+#[async_trait]
+impl LanguageServer for LifecycleServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn shutdown(&self, _params: (), _client: Arc<dyn LanguageClient>) -> Result<()> {
+        self.events.lock().unwrap().push("shutdown");
+        Ok(())
+    }
+
+    async fn on_shutdown(&self) {
+        self.events.lock().unwrap().push("on_shutdown");
+    }
+
+    async fn on_exit(&self) {
+        self.events.lock().unwrap().push("on_exit");
+    }
+}
+
+#[test]
+fn lifecycle_hooks_called_once() {
+    let server = Arc::new(LifecycleServer::default());
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::clone(&server))
+        .build()
+        .listen();
+
+    let reason = executor.run_until(async move {
+        let service = async {
+            tx1.write_all(
+                indoc!(
+                    r#"
+                        Content-Length: 58
+
+                        {"jsonrpc":"2.0","method":"shutdown","id":0,"params":null}
+                    "#
+                )
+                .trim()
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+            let response = Response::result(serde_json::Value::Null, Id::Number(0));
+            read_message(&mut rx2, response).await;
+            drop(tx1);
+        };
+
+        futures::future::join(handle, service).await.0
+    });
+
+    assert_eq!(reason.unwrap(), ExitReason::Disconnected);
+    assert_eq!(
+        *server.events.lock().unwrap(),
+        vec!["shutdown", "on_shutdown", "on_exit"]
+    );
+}
+
+#[test]
+fn lifecycle_hooks_without_shutdown_request() {
+    let server = Arc::new(LifecycleServer::default());
+    let mut executor = LocalPool::new();
+    let (rx1, tx1) = pipe();
+    let (_rx2, tx2) = pipe();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::clone(&server))
+        .build()
+        .listen();
+
+    drop(tx1);
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::Disconnected
+    );
+    assert_eq!(
+        *server.events.lock().unwrap(),
+        vec!["on_shutdown", "on_exit"]
+    );
+}
+
+fn document_link_request<S>(server: S, lenient_defaults: bool, expected: Response)
+where
+    S: LanguageServer + Send + Sync + 'static,
+{
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .lenient_defaults(lenient_defaults)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({ "textDocument": { "uri": "file:///foo.tex" } });
+        let request = Request::new("textDocument/documentLink".into(), params, Id::Number(0));
+        let json = serde_json::to_string(&request).unwrap();
+        let message = format!("Content-Length: {}\r\n\r\n{}", json.len(), json);
+        tx1.write_all(message.as_bytes()).await.unwrap();
+        read_message(&mut rx2, expected).await;
+    });
+}
+
+#[test]
+fn unimplemented_request_method_not_found() {
+    let error = jsonrpc::Error::method_not_found_error();
+    let response = Response::error(error, Some(Id::Number(0)));
+    document_link_request(MockLanguageServer::new(), false, response);
+}
+
+#[test]
+fn unimplemented_request_lenient_defaults() {
+    let response = Response::result(json!([]), Id::Number(0));
+    document_link_request(MockLanguageServer::new(), true, response);
+}
+
+struct RejectingServer;
+
+#[async_trait]
+impl LanguageServer for RejectingServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn document_link(
+        &self,
+        _params: DocumentLinkParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Vec<DocumentLink>> {
+        Err(jsonrpc::Error::method_not_found_error())
+    }
+}
+
+#[test]
+fn implemented_request_lenient_defaults() {
+    let error = jsonrpc::Error::method_not_found_error();
+    let response = Response::error(error, Some(Id::Number(0)));
+    document_link_request(RejectingServer, true, response);
+}
+
+#[test]
+fn unimplemented_request_debug_method_not_found() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .debug_method_not_found(true)
+        .build();
+
+    let methods = service.supported_methods();
+    let link = SupportedMethod {
+        name: "textDocument/documentLink",
+        kind: MethodKind::Request,
+        enabled: true,
+    };
+    assert!(methods.contains(&link));
+    let will_rename = methods
+        .iter()
+        .find(|method| method.name == "workspace/willRenameFiles")
+        .unwrap();
+    assert_eq!(will_rename.enabled, cfg!(feature = "file-operations"));
+
+    let mut error = jsonrpc::Error::method_not_found_error();
+    let enabled: Vec<_> = methods.iter().filter(|method| method.enabled).collect();
+    let disabled: Vec<_> = methods.iter().filter(|method| !method.enabled).collect();
+    error.data = Some(json!({ "supportedMethods": enabled, "disabledMethods": disabled }));
+    let expected = Response::error(error, Some(Id::Number(0)));
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({ "textDocument": { "uri": "file:///foo.tex" } });
+        let request = Request::new("textDocument/documentLink".into(), params, Id::Number(0));
+        write_message(&mut tx1, request).await;
+        read_message(&mut rx2, expected).await;
+    });
+}
+
+#[test]
+fn strict_version_rejects_requests() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .strict_version(true)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({ "textDocument": { "uri": "file:///foo.tex" } });
+        let request = Request::new("textDocument/documentLink".into(), params, Id::Number(0));
+        let invalid = Request {
+            jsonrpc: "1.0".into(),
+            ..request.clone()
+        };
+        write_message(&mut tx1, invalid).await;
+        let error = jsonrpc::Error::invalid_request("Unsupported JSON-RPC version: 1.0".into());
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error::method_not_found_error();
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+    });
+}
+
+async fn write_message<T: Serialize>(writer: &mut sluice::pipe::PipeWriter, message: T) {
+    let json = serde_json::to_string(&message).unwrap();
+    let message = format!("Content-Length: {}\r\n\r\n{}", json.len(), json);
+    writer.write_all(message.as_bytes()).await.unwrap();
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut *self.0.lock().unwrap(), buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn recording_lifecycle_errors() {
+    let buffer = SharedBuffer::default();
+    let recorder =
+        RecordingMiddleware::new(Box::new(buffer.clone())).with_clock(Arc::new(ManualClock::new()));
+    let server = MockLanguageServer::new();
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .middlewares(vec![Arc::new(recorder)])
+        .strict_lifecycle(true)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    let response = executor.run_until(async move {
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let mut header = String::new();
+        rx2.read_line(&mut header).await.unwrap();
+        let length = header["Content-Length: ".len()..].trim().parse().unwrap();
+        rx2.read_line(&mut String::new()).await.unwrap();
+        let mut buf = vec![0; length];
+        rx2.read_exact(&mut buf).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    });
+
+    let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        trace,
+        format!(
+            "0.000 --> {}\n0.000 <-- {}\n",
+            r#"{"jsonrpc":"2.0","method":"shutdown","params":null,"id":0}"#, response
+        )
+    );
+}
+
+#[test]
+fn untrusted_workspace_blocks_methods() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let trust = WorkspaceTrust::new(false);
+    let middleware = TrustMiddleware::new(
+        trust.clone(),
+        "/trustedWorkspace".into(),
+        vec!["workspace/executeCommand".into()],
+    );
+
+    let fallback = Arc::new(LegacyHandler::default());
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .middlewares(vec![Arc::new(middleware)])
+        .fallback_handler(fallback.clone())
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({ "command": "build", "arguments": [] });
+        let request = Request::new("workspace/executeCommand".into(), params, Id::Number(0));
+        write_message(&mut tx1, &request).await;
+
+        let message = "workspace/executeCommand is disabled because the workspace is not trusted";
+        let notification = Notification::new(
+            "window/showMessage".into(),
+            json!({ "type": 2, "message": message }),
+        );
+        read_message(&mut rx2, notification).await;
+
+        let error = jsonrpc::Error {
+            code: jsonrpc::ErrorCode::InvalidRequest,
+            message: message.into(),
+            data: None,
+        };
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(0)))).await;
+
+        let params = json!({ "settings": { "trustedWorkspace": true } });
+        let notification = Notification::new("workspace/didChangeConfiguration".into(), params);
+        write_message(&mut tx1, &notification).await;
+        write_message(&mut tx1, &request).await;
+
+        let result = json!("workspace/executeCommand");
+        read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+    });
+
+    // The blocked request has not reached the fallback handler.
+    assert!(trust.is_trusted());
+    let requests = fallback.requests.lock().unwrap();
+    assert_eq!(*requests, vec!["workspace/executeCommand".to_owned()]);
+}
+
+struct DeadlockServer {
+    result: Arc<Mutex<Option<Result<Vec<WorkspaceFolder>>>>>,
+}
+
+#[async_trait]
+impl LanguageServer for DeadlockServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn did_open(&self, _params: DidOpenTextDocumentParams, client: Arc<dyn LanguageClient>) {
+        let result = client.workspace_folders(()).await;
+        *self.result.lock().unwrap() = Some(result);
+    }
+}
+
+#[test]
+fn deadlock_policy_fail() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let result = Arc::new(Mutex::new(None));
+    let server = DeadlockServer {
+        result: Arc::clone(&result),
+    };
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .deadlock_policy(DeadlockPolicy::Fail)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({
+            "textDocument": { "uri": "file:///foo.tex", "languageId": "latex", "version": 0, "text": "" }
+        });
+        let notification = Notification::new("textDocument/didOpen".into(), params);
+        write_message(&mut tx1, &notification).await;
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, &request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(0))).await;
+    });
+
+    assert!(result.lock().unwrap().take().unwrap().is_err());
+}
+
+struct FolderServer {
+    name: String,
+}
+
+#[async_trait]
+impl LanguageServer for FolderServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn hover(
+        &self,
+        _params: HoverParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<Hover>> {
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String(self.name.clone())),
+            range: None,
+        }))
+    }
+}
+
+#[test]
+fn server_factory_routes_documents() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(FolderServer {
+            name: "default".into(),
+        }))
+        .server_factory(Arc::new(|folder: &WorkspaceFolder| {
+            Arc::new(FolderServer {
+                name: folder.name.clone(),
+            })
+        }))
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let params = json!({
+            "capabilities": {},
+            "workspaceFolders": [
+                { "uri": "file:///foo", "name": "foo" },
+                { "uri": "file:///foo/bar", "name": "bar" },
+            ],
+        });
+        let request = Request::new("initialize".into(), params, Id::Number(0));
+        write_message(&mut tx1, &request).await;
+        let result = serde_json::to_value(InitializeResult::default()).unwrap();
+        read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+
+        let documents = vec![
+            ("file:///foo/foo.tex", "foo"),
+            ("file:///foo/bar/bar.tex", "bar"),
+            ("file:///baz.tex", "default"),
+        ];
+        for (id, (uri, name)) in documents.into_iter().enumerate() {
+            let id = Id::Number(id as u64 + 1);
+            let params = json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 0 },
+            });
+            let request = Request::new("textDocument/hover".into(), params, id.clone());
+            write_message(&mut tx1, &request).await;
+            read_message(&mut rx2, Response::result(json!({ "contents": name }), id)).await;
+        }
+    });
+}
+
+struct WarmUpServer {
+    ready: Mutex<Option<futures::channel::oneshot::Receiver<()>>>,
+}
+
+#[async_trait]
+impl LanguageServer for WarmUpServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn hover(
+        &self,
+        _params: HoverParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<Hover>> {
+        Ok(None)
+    }
+
+    async fn warm_up(&self, _progress: Option<&Progress>, _client: Arc<dyn LanguageClient>) {
+        let ready = self.ready.lock().unwrap().take();
+        if let Some(ready) = ready {
+            let _ = ready.await;
+        }
+    }
+}
+
+#[test]
+fn warm_up_holds_back_requests() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let (ready_tx, ready_rx) = futures::channel::oneshot::channel();
+    let server = WarmUpServer {
+        ready: Mutex::new(Some(ready_rx)),
+    };
+
+    let warm_up = WarmUp::new("Indexing".into())
+        .with_method("textDocument/completion".into(), WarmUpPolicy::Reject);
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .warm_up(warm_up.clone())
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new(
+            "initialize".into(),
+            json!({ "capabilities": {} }),
+            Id::Number(0),
+        );
+        write_message(&mut tx1, &request).await;
+        let result = serde_json::to_value(InitializeResult::default()).unwrap();
+        read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+
+        let notification = Notification::new("initialized".into(), json!({}));
+        write_message(&mut tx1, &notification).await;
+
+        let params = json!({
+            "textDocument": { "uri": "file:///foo.tex" },
+            "position": { "line": 0, "character": 0 },
+        });
+        let request = Request::new(
+            "textDocument/completion".into(),
+            params.clone(),
+            Id::Number(1),
+        );
+        write_message(&mut tx1, &request).await;
+        let error = jsonrpc::Error::content_modified_error(
+            "textDocument/completion is not available until the server has finished warming up"
+                .into(),
+        );
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(1)))).await;
+
+        let request = Request::new("textDocument/hover".into(), params, Id::Number(2));
+        write_message(&mut tx1, &request).await;
+        assert!(!warm_up.is_finished());
+        ready_tx.send(()).unwrap();
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(2))).await;
+        assert!(warm_up.is_finished());
+    });
+}
+
+#[test]
+fn shutdown_stops_warm_up() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    // The warm-up never finishes because the sender is kept alive.
+    let (_ready_tx, ready_rx) = futures::channel::oneshot::channel();
+    let server = WarmUpServer {
+        ready: Mutex::new(Some(ready_rx)),
+    };
+
+    let warm_up = WarmUp::new("Indexing".into());
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .warm_up(warm_up.clone())
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new(
+            "initialize".into(),
+            json!({ "capabilities": {} }),
+            Id::Number(0),
+        );
+        write_message(&mut tx1, &request).await;
+        let result = serde_json::to_value(InitializeResult::default()).unwrap();
+        read_message(&mut rx2, Response::result(result, Id::Number(0))).await;
+
+        let notification = Notification::new("initialized".into(), json!({}));
+        write_message(&mut tx1, &notification).await;
+        assert!(!warm_up.is_finished());
+
+        let request = Request::new("shutdown".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, &request).await;
+        read_message(&mut rx2, Response::result(json!(null), Id::Number(1))).await;
+        assert!(warm_up.is_finished());
+    });
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn golden_session_hover() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/hover.session");
+    testing::GoldenSession::load(path)
+        .expect("failed to load the session")
+        .assert_server(Arc::new(FolderServer {
+            name: "golden".into(),
+        }));
+}
+
+#[cfg(feature = "testing")]
+struct PromptServer;
+
+#[cfg(feature = "testing")]
+#[async_trait]
+impl LanguageServer for PromptServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams, client: Arc<dyn LanguageClient>) {
+        let uri = params.text_document.uri;
+        client
+            .publish_diagnostics(PublishDiagnosticsParams::new(uri, Vec::new(), None))
+            .await;
+    }
+
+    async fn hover(
+        &self,
+        _params: HoverParams,
+        client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<Hover>> {
+        let params = ShowMessageRequestParams {
+            typ: MessageType::Info,
+            message: "foo".into(),
+            actions: None,
+        };
+        let action = client.show_message_request(params).await?;
+        Ok(action.map(|action| Hover {
+            contents: HoverContents::Scalar(MarkedString::String(action.title)),
+            range: None,
+        }))
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_service_typed_messages() {
+    use request::{HoverRequest, Initialize, ShowMessageRequest};
+
+    let mut service = testing::TestService::new(Arc::new(PromptServer));
+    let params = serde_json::from_value(json!({ "capabilities": {} })).unwrap();
+    assert!(service.request::<Initialize>(params).is_ok());
+    service.notify::<notification::Initialized>(InitializedParams {});
+
+    let uri = Url::parse("file:///foo.tex").unwrap();
+    service.notify::<notification::DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(uri.clone(), "latex".into(), 0, String::new()),
+    });
+    let diagnostics = service.expect_notification::<notification::PublishDiagnostics>();
+    assert_eq!(diagnostics.uri, uri);
+
+    let id = service.send_request::<HoverRequest>(HoverParams {
+        text_document_position_params: TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(uri),
+            Position::new(0, 0),
+        ),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+    });
+    let (request_id, params) = service.expect_request::<ShowMessageRequest>();
+    assert_eq!(params.message, "foo");
+    let action = MessageActionItem {
+        title: "bar".into(),
+    };
+    service.respond::<ShowMessageRequest>(request_id, Ok(Some(action)));
+
+    let hover = service
+        .expect_response::<HoverRequest>(id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        hover.contents,
+        HoverContents::Scalar(MarkedString::String("bar".into()))
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn reference_servers() {
+    use request::{Completion, ExecuteCommand, Initialize};
+
+    let initialize = || serde_json::from_value(json!({ "capabilities": {} })).unwrap();
+    let position = TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(Url::parse("file:///foo.tex").unwrap()),
+        Position::new(0, 0),
+    );
+
+    let mut null = testing::TestService::new(Arc::new(servers::NullServer));
+    assert!(null.request::<Initialize>(initialize()).is_ok());
+    let error = null
+        .request::<Completion>(CompletionParams {
+            text_document_position: position,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+            context: None,
+        })
+        .unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::MethodNotFound);
+
+    let mut echo = testing::TestService::new(Arc::new(servers::EchoServer));
+    assert!(echo.request::<Initialize>(initialize()).is_ok());
+    let arguments = vec![json!({ "foo": "b\u{e4}r" }), json!([1, null])];
+    let result = echo.request::<ExecuteCommand>(ExecuteCommandParams {
+        command: servers::ECHO_COMMAND.into(),
+        arguments: arguments.clone(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+    });
+    assert_eq!(result, Ok(Some(json!(arguments))));
+
+    echo.notify::<notification::DidChangeConfiguration>(DidChangeConfigurationParams {
+        settings: json!({ "foo": 42 }),
+    });
+    let event = echo.expect_notification::<notification::TelemetryEvent>();
+    assert_eq!(event, json!({ "foo": 42 }));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn client_timeout_follows_clock() {
+    use request::{HoverRequest, ShowMessageRequest};
+
+    let clock = ManualClock::new();
+    let service_clock = clock.clone();
+    let mut service = testing::TestService::with_service(move |input, output, executor| {
+        LanguageService::builder()
+            .input(input)
+            .output(output)
+            .executor(executor)
+            .server(Arc::new(PromptServer))
+            .clock(Arc::new(service_clock))
+            .client_timeout(Duration::from_secs(5))
+            .build()
+            .listen()
+    });
+
+    let id = service.send_request::<HoverRequest>(HoverParams {
+        text_document_position_params: TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(Url::parse("file:///foo.tex").unwrap()),
+            Position::new(0, 0),
+        ),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+    });
+    let (request_id, _) = service.expect_request::<ShowMessageRequest>();
+    assert_eq!(clock.sleepers(), 1);
+
+    clock.advance(Duration::from_secs(5));
+    let error = service.expect_response::<HoverRequest>(id).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::RequestTimeout);
+    let params = service.expect_notification::<notification::Cancel>();
+    assert_eq!(json!(params.id), json!(request_id));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn strict_lifecycle() {
+    use request::{Initialize, Shutdown, WorkspaceSymbol};
+
+    let mut service = testing::TestService::with_service(|input, output, executor| {
+        LanguageService::builder()
+            .input(input)
+            .output(output)
+            .executor(executor)
+            .server(Arc::new(servers::NullServer))
+            .strict_lifecycle(true)
+            .build()
+            .listen()
+    });
+
+    let initialize = || serde_json::from_value(json!({ "capabilities": {} })).unwrap();
+    let symbols = || WorkspaceSymbolParams {
+        query: String::new(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams::default(),
+    };
+
+    let error = service.request::<WorkspaceSymbol>(symbols()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::ServerNotInitialized);
+    assert!(service.request::<Initialize>(initialize()).is_ok());
+    let error = service.request::<Initialize>(initialize()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::InvalidRequest);
+    assert!(service.request::<Shutdown>(()).is_ok());
+    let error = service.request::<Shutdown>(()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::InvalidRequest);
+}
+
+// Asks the client to confirm the initialization and fails if the client declines.
+#[cfg(feature = "testing")]
+struct ConfirmServer;
+
+#[cfg(feature = "testing")]
+#[async_trait]
+impl LanguageServer for ConfirmServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        let params = ShowMessageRequestParams {
+            actions: None,
+            message: "Initialize?".into(),
+            typ: MessageType::Info,
+        };
+        match client.show_message_request(params).await {
+            Ok(_) => Ok(InitializeResult::default()),
+            Err(_) => Err(jsonrpc::Error::initialize_error("declined".into(), true)),
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn strict_lifecycle_initializing() {
+    use request::{Initialize, ShowMessageRequest, Shutdown, WorkspaceSymbol};
+
+    let mut service = testing::TestService::with_service(|input, output, executor| {
+        LanguageService::builder()
+            .input(input)
+            .output(output)
+            .executor(executor)
+            .server(Arc::new(ConfirmServer))
+            .strict_lifecycle(true)
+            .build()
+            .listen()
+    });
+
+    let initialize = || serde_json::from_value(json!({ "capabilities": {} })).unwrap();
+    let symbols = || WorkspaceSymbolParams {
+        query: String::new(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams::default(),
+    };
+
+    let id = service.send_request::<Initialize>(initialize());
+    let (request_id, _) = service.expect_request::<ShowMessageRequest>();
+    let error = service.request::<Initialize>(initialize()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::InvalidRequest);
+    let error = service.request::<WorkspaceSymbol>(symbols()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::ServerNotInitialized);
+
+    let declined = Err(jsonrpc::Error::request_failed_error("declined".into()));
+    service.respond::<ShowMessageRequest>(request_id, declined);
+    assert!(service.expect_response::<Initialize>(id).is_err());
+
+    // The failed initialization can be retried.
+    let id = service.send_request::<Initialize>(initialize());
+    let (request_id, _) = service.expect_request::<ShowMessageRequest>();
+    service.respond::<ShowMessageRequest>(request_id, Ok(None));
+    assert!(service.expect_response::<Initialize>(id).is_ok());
+    assert!(service.request::<Shutdown>(()).is_ok());
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn strict_lifecycle_initialize_without_retry() {
+    use request::Initialize;
+
+    let mut server = MockLanguageServer::new();
+    server.expect_initialize().times(1).returning(|_, _| {
+        async move { Err(jsonrpc::Error::initialize_error("foo".into(), false)) }.boxed()
+    });
+    let server = Arc::new(server);
+    let mut service = testing::TestService::with_service(move |input, output, executor| {
+        LanguageService::builder()
+            .input(input)
+            .output(output)
+            .executor(executor)
+            .server(server)
+            .strict_lifecycle(true)
+            .build()
+            .listen()
+    });
+
+    let initialize = || serde_json::from_value(json!({ "capabilities": {} })).unwrap();
+    let error = service.request::<Initialize>(initialize()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::RequestFailed);
+    let error = service.request::<Initialize>(initialize()).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::InvalidRequest);
+}
+
+#[cfg(feature = "testing")]
+struct PanicServer;
+
+#[cfg(feature = "testing")]
+#[async_trait]
+impl LanguageServer for PanicServer {
+    async fn initialize(
+        &self,
+        _params: InitializeParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn hover(
+        &self,
+        _params: HoverParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Option<Hover>> {
+        panic!("foo")
+    }
+
+    async fn workspace_symbol(
+        &self,
+        _params: WorkspaceSymbolParams,
+        _client: Arc<dyn LanguageClient>,
+    ) -> Result<Vec<SymbolInformation>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn handler_panicked() {
+    use request::{HoverRequest, WorkspaceSymbol};
+
+    let mut service = testing::TestService::new(Arc::new(PanicServer));
+    let hover = serde_json::from_value(json!({
+        "textDocument": { "uri": "file:///foo.tex" },
+        "position": { "line": 0, "character": 0 }
+    }))
+    .unwrap();
+    let symbols = WorkspaceSymbolParams {
+        query: String::new(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams::default(),
+    };
+
+    let error = service.request::<HoverRequest>(hover).unwrap_err();
+    assert_eq!(error.code, jsonrpc::ErrorCode::InternalError);
+    assert!(error.message.contains("foo"));
+    assert_eq!(
+        service.request::<WorkspaceSymbol>(symbols).unwrap(),
+        Some(Vec::new())
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn replay_recorded_trace() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/hover.trace");
+    let session = testing::GoldenSession::load_trace(path).expect("failed to load the trace");
+    let outputs = session.replay(Arc::new(FolderServer {
+        name: "replayed".into(),
+    }));
+    assert_eq!(outputs.len(), 5);
+    assert_eq!(outputs[2][0]["result"]["contents"], "replayed");
+
+    session.assert_server(Arc::new(FolderServer {
+        name: "traced".into(),
+    }));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn golden_session_diff() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/hover.session");
+    let session = testing::GoldenSession::load(path).expect("failed to load the session");
+    let server = || Arc::new(FolderServer { name: "old".into() });
+    assert!(session.diff(server(), server()).is_empty());
+
+    let diff = session.diff(server(), Arc::new(FolderServer { name: "new".into() }));
+    let methods = diff.by_method();
+    assert_eq!(
+        methods.keys().collect::<Vec<_>>(),
+        vec![&"textDocument/hover"]
+    );
+    let divergence = methods["textDocument/hover"][0];
+    assert_eq!(divergence.step, 2);
+    assert_eq!(divergence.old[0]["result"]["contents"], "old");
+    assert_eq!(divergence.new[0]["result"]["contents"], "new");
+}
+
+#[test]
+fn serve_tcp_independent_connections() {
+    use std::io::{BufRead, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let servers = Arc::new(Mutex::new(0));
+    let factory_servers = Arc::clone(&servers);
+
+    let executor = TokioTp::try_from(&mut tokio::runtime::Builder::new()).unwrap();
+    let factory = move || {
+        *factory_servers.lock().unwrap() += 1;
+        let mut server = MockLanguageServer::new();
+        server
+            .expect_initialize()
+            .times(1)
+            .returning(|_, _| async move { Ok(InitializeResult::default()) }.boxed());
+        Arc::new(server)
+    };
+    executor
+        .spawn(serve_tcp(listener, executor.clone(), factory).map(drop))
+        .unwrap();
+
+    let (results_tx, results_rx) = futures::channel::mpsc::unbounded();
+    for _ in 0..2 {
+        let results_tx = results_tx.clone();
+        std::thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let request = Request::new(
+                "initialize".into(),
+                json!({ "capabilities": {} }),
+                Id::Number(0),
+            );
+            let json = serde_json::to_string(&request).unwrap();
+            write!(stream, "Content-Length: {}\r\n\r\n{}", json.len(), json).unwrap();
+
+            let mut reader = std::io::BufReader::new(stream);
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let length: usize = header["Content-Length: ".len()..].trim().parse().unwrap();
+            reader.read_line(&mut String::new()).unwrap();
+            let mut content = vec![0; length];
+            reader.read_exact(&mut content).unwrap();
+            let response: Response = serde_json::from_slice(&content).unwrap();
+            results_tx.unbounded_send(response).unwrap();
+        });
+    }
+    drop(results_tx);
+
+    let responses: Vec<_> = executor.block_on(results_rx.collect());
+    let result = serde_json::to_value(InitializeResult::default()).unwrap();
+    assert_eq!(
+        responses,
+        vec![
+            Response::result(result.clone(), Id::Number(0)),
+            Response::result(result, Id::Number(0))
+        ]
+    );
+    assert_eq!(*servers.lock().unwrap(), 2);
+}
+
+// Records every write, so tests can check how messages are combined.
+#[derive(Clone, Default)]
+struct RecordingWriter {
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl futures::AsyncWrite for RecordingWriter {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.writes.lock().unwrap().push(buf.to_vec());
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+fn queued_messages_are_written_at_once() {
+    let mut server = MockLanguageServer::new();
+    server.expect_initialized().returning(|_, client| {
+        async move {
+            let messages = (0..5).map(|i| {
+                let params = LogMessageParams {
+                    typ: MessageType::Log,
+                    message: i.to_string(),
+                };
+                client.log_message(params)
+            });
+            futures::future::join_all(messages).await;
+        }
+        .boxed()
+    });
+
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let writer = RecordingWriter::default();
+
+    let handle = LanguageService::builder()
+        .input(rx1)
+        .output(writer.clone())
+        .executor(executor.spawner())
+        .server(Arc::new(server))
+        .build()
+        .listen();
+
+    let notification = Notification::new("initialized".into(), json!({}));
+    executor.run_until(async move {
+        write_message(&mut tx1, notification).await;
+    });
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::Disconnected
+    );
+
+    let writes = writer.writes.lock().unwrap();
+    assert_eq!(writes.len(), 1);
+    let output = String::from_utf8_lossy(&writes[0]);
+    assert_eq!(output.matches("window/logMessage").count(), 5);
+}
+
+#[jsonrpc_client(ident = "CalculatorClientImpl")]
+#[async_trait]
+pub trait CalculatorClient: Send + Sync + 'static {
+    #[jsonrpc_method(name = "calculator/log", kind = "notification")]
+    async fn log(&self, params: String);
+}
+
+#[jsonrpc_server(client = "CalculatorClient")]
+#[async_trait]
+trait Calculator {
+    #[jsonrpc_method(name = "calculator/add", kind = "request")]
+    async fn add(&self, params: (i32, i32), client: Arc<dyn CalculatorClient>) -> Result<i32>;
+}
+
+#[jsonrpc_server(client = "CalculatorClient")]
+#[async_trait]
+trait ScientificCalculator {
+    #[jsonrpc_method(name = "calculator/sqrt", kind = "request")]
+    async fn sqrt(&self, params: f64, client: Arc<dyn CalculatorClient>) -> Result<f64>;
+}
+
+struct CalculatorServer;
+
+#[async_trait]
+impl Calculator for CalculatorServer {
+    async fn add(&self, (a, b): (i32, i32), client: Arc<dyn CalculatorClient>) -> Result<i32> {
+        client.log(format!("{} + {}", a, b)).await;
+        Ok(a + b)
+    }
+}
+
+#[test]
+fn jsonrpc_service_custom_protocol() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let server: Arc<dyn Calculator + Send + Sync> = Arc::new(CalculatorServer);
+    let service = JsonRpcService::builder()
+        .input(rx1)
+        .output(tx2)
+        .server(server)
+        .client(CalculatorClientImpl::new)
+        .executor(executor.spawner())
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new("calculator/add".into(), json!([1, 2]), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let notification = Notification::new("calculator/log".into(), json!("1 + 2"));
+        read_message(&mut rx2, notification).await;
+        read_message(&mut rx2, Response::result(json!(3), Id::Number(0))).await;
+
+        let request = Request::new("calculator/sub".into(), json!([1, 2]), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error::method_not_found_error();
+        read_message(&mut rx2, Response::error(error, Some(Id::Number(1)))).await;
+    });
+}
+
+// Adds the numbers once the gate has been opened.
+struct GatedCalculatorServer {
+    gate: Mutex<Option<futures::channel::oneshot::Receiver<()>>>,
+}
+
+#[async_trait]
+impl Calculator for GatedCalculatorServer {
+    async fn add(&self, (a, b): (i32, i32), _client: Arc<dyn CalculatorClient>) -> Result<i32> {
+        let gate = self.gate.lock().unwrap().take().unwrap();
+        gate.await.unwrap();
+        Ok(a + b)
+    }
+}
+
+#[test]
+fn jsonrpc_service_waits_for_handlers() {
+    let (gate_tx, gate_rx) = futures::channel::oneshot::channel();
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let server: Arc<dyn Calculator + Send + Sync> = Arc::new(GatedCalculatorServer {
+        gate: Mutex::new(Some(gate_rx)),
+    });
+    let service = JsonRpcService::builder()
+        .input(rx1)
+        .output(tx2)
+        .server(server)
+        .client(CalculatorClientImpl::new)
+        .executor(executor.spawner())
+        .build();
+
+    let mut handle = executor
+        .spawner()
+        .spawn_local_with_handle(service.listen())
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new("calculator/add".into(), json!([1, 2]), Id::Number(0));
+        write_message(&mut tx1, request).await;
+    });
+
+    // The input has ended while the handler is still running.
+    executor.run_until_stalled();
+    assert!((&mut handle).now_or_never().is_none());
+
+    gate_tx.send(()).unwrap();
+    assert_eq!(
+        executor.run_until(handle).unwrap(),
+        ExitReason::Disconnected
+    );
+    executor.run_until(read_message(
+        &mut rx2,
+        Response::result(json!(3), Id::Number(0)),
+    ));
+}
+
+#[test]
+fn jsonrpc_server_method_lists() {
+    let names = |methods: Vec<SupportedMethod>| -> Vec<_> {
+        methods.into_iter().map(|method| method.name).collect()
+    };
+    assert_eq!(
+        names(calculator_supported_methods()),
+        vec!["calculator/add"]
+    );
+    assert_eq!(
+        names(scientific_calculator_supported_methods()),
+        vec!["calculator/sqrt"]
+    );
+}
+
+#[test]
+fn server_info_describes_build() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .build_info(true)
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        let request = Request::new("$/serverInfo".into(), json!(null), Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let info = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": BuildInfo::new(Framing::ContentLength).features,
+            "protocolVersion": PROTOCOL_VERSION,
+            "transport": "contentLength",
+        });
+        read_message(&mut rx2, Response::result(info, Id::Number(0))).await;
+    });
+}
+
+#[test]
+fn server_status_reports_recent_errors() {
+    let mut executor = LocalPool::new();
+    let (rx1, mut tx1) = pipe();
+    let (mut rx2, tx2) = pipe();
+
+    let service = LanguageService::builder()
+        .input(rx1)
+        .output(tx2)
+        .executor(executor.spawner())
+        .server(Arc::new(MockLanguageServer::new()))
+        .status(ServerStatus::new(8))
+        .build();
+
+    executor
+        .spawner()
+        .spawn_local(service.listen().map(drop))
+        .expect("failed to spawn server");
+
+    executor.run_until(async move {
+        tx1.write_all(b"Content-Length: 3\r\n\r\nfoo")
+            .await
+            .unwrap();
+        let error = jsonrpc::Error::parse_error();
+        read_message(&mut rx2, Response::error(error, None)).await;
+
+        let params = json!({ "textDocument": { "uri": "file:///foo.tex" } });
+        let request = Request::new("textDocument/documentLink".into(), params, Id::Number(0));
+        write_message(&mut tx1, request).await;
+        let error = jsonrpc::Error::method_not_found_error();
+        read_message(
+            &mut rx2,
+            Response::error(error.clone(), Some(Id::Number(0))),
+        )
+        .await;
+
+        let request = Request::new("$/serverStatus".into(), json!(null), Id::Number(1));
+        write_message(&mut tx1, request).await;
+        let parse_error = serde_json::from_str::<jsonrpc::Message>("foo").unwrap_err();
+        let report = StatusReport {
+            handler_errors: 1,
+            parse_errors: 1,
+            dropped_messages: 0,
+            recent: vec![
+                Problem {
+                    sequence: 0,
+                    kind: ProblemKind::ParseError,
+                    method: None,
+                    message: parse_error.to_string(),
+                },
+                Problem {
+                    sequence: 1,
+                    kind: ProblemKind::HandlerError,
+                    method: Some("textDocument/documentLink".into()),
+                    message: error.message,
+                },
+            ],
+        };
+        read_message(&mut rx2, Response::result(json!(report), Id::Number(1))).await;
+    });
+}